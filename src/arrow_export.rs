@@ -0,0 +1,872 @@
+use arrow_array::{
+    ArrayRef,
+    BooleanArray,
+    Decimal128Array,
+    DurationNanosecondArray,
+    FixedSizeListArray,
+    Float32Array,
+    Float64Array,
+    Int16Array,
+    Int32Array,
+    Int64Array,
+    Int8Array,
+    ListArray,
+    NullArray,
+    RecordBatch,
+    StringArray,
+    StructArray,
+    TimestampNanosecondArray,
+    UInt16Array,
+    UInt32Array,
+    UInt64Array,
+    UInt8Array,
+};
+use arrow_buffer::{
+    NullBuffer,
+    OffsetBuffer,
+};
+use arrow_ipc::writer::{
+    FileWriter,
+    StreamWriter,
+};
+use arrow_schema::{
+    DataType,
+    Field,
+    Fields,
+    Schema,
+    TimeUnit,
+};
+use dynamic_vector::FieldType;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::{
+    Map,
+    Value,
+};
+use std::{
+    io::{
+        self,
+        Write,
+    },
+    ops::Range,
+    sync::Arc,
+};
+
+use crate::{
+    interchange::for_each_batch,
+    vector_engine::VectorEngine,
+};
+
+static NULL: Value = Value::Null;
+const UTC: &str = "UTC";
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+/// Arrow IPC flavour: the random-access file format (`.arrow`, Feather v2)
+/// or the streaming format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArrowFormat {
+    #[default]
+    File,
+    Stream,
+}
+
+/// Options of `VectorEngine::export_arrow`.
+#[derive(Debug, Clone)]
+pub struct ArrowOptions {
+    pub format: ArrowFormat,
+    /// Records per record batch, which bounds the memory an export holds.
+    pub batch_size: u64,
+    /// Column types, usually `T::describe()` for records deriving
+    /// `CheckDynamicSize`. Without it the types are inferred from the
+    /// serde output of the first batch: nullable columns, `Int64`, `UInt64`
+    /// or `Float64` numbers, and `Utf8` for values of mixed types.
+    pub schema: Option<FieldType>,
+}
+
+impl Default for ArrowOptions {
+    fn default() -> Self {
+        Self {
+            format: ArrowFormat::File,
+            batch_size: 65536,
+            schema: None,
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn timestamp_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Nanosecond, Some(UTC.into()))
+}
+
+fn described_field(name: &str, ty: &FieldType) -> Field {
+    let nullable = matches!(ty, FieldType::Option(_) | FieldType::Unit);
+    Field::new(name, described_type(ty), nullable)
+}
+
+fn described_fields<'a>(fields: impl Iterator<Item = (String, &'a FieldType)>) -> Fields {
+    fields
+        .map(|(name, ty)| described_field(&name, ty))
+        .collect()
+}
+
+/// Arrow type of a field described by the `dynamic-vector` derive. Enums
+/// become `Utf8`: the variant name, or the JSON of variants with data.
+fn described_type(ty: &FieldType) -> DataType {
+    match ty {
+        FieldType::Bool => DataType::Boolean,
+        FieldType::U8 => DataType::UInt8,
+        FieldType::U16 => DataType::UInt16,
+        FieldType::U32 => DataType::UInt32,
+        FieldType::U64 => DataType::UInt64,
+        FieldType::I8 => DataType::Int8,
+        FieldType::I16 => DataType::Int16,
+        FieldType::I32 => DataType::Int32,
+        FieldType::I64 => DataType::Int64,
+        FieldType::U128 | FieldType::I128 => DataType::Decimal128(38, 0),
+        FieldType::F32 => DataType::Float32,
+        FieldType::F64 => DataType::Float64,
+        FieldType::Char
+        | FieldType::String
+        | FieldType::FixedString(_)
+        | FieldType::Enum { .. } => DataType::Utf8,
+        FieldType::Unit => DataType::Null,
+        FieldType::Seq(item) | FieldType::FixedVec(item, _) => {
+            DataType::List(Arc::new(described_field("item", item)))
+        }
+        FieldType::Array(item, len) => {
+            DataType::FixedSizeList(Arc::new(described_field("item", item)), *len as i32)
+        }
+        FieldType::Option(inner) => described_type(inner),
+        FieldType::Tuple(items) => DataType::Struct(described_fields(
+            items.iter().enumerate().map(|(i, ty)| (i.to_string(), ty)),
+        )),
+        FieldType::Struct(schema) if schema.fields.is_empty() => DataType::Null,
+        FieldType::Struct(schema) => DataType::Struct(described_fields(
+            schema
+                .fields
+                .iter()
+                .map(|field| (field.name.clone(), &field.ty)),
+        )),
+        FieldType::Duration => DataType::Duration(TimeUnit::Nanosecond),
+        FieldType::Timestamp | FieldType::DateTime => timestamp_type(),
+    }
+}
+
+/// Rewrites the serde output of a described value into the shape
+/// [`build`] expects: structs and tuples as maps by field name, fixed
+/// strings as strings, fixed vectors without their length and padding,
+/// enums as strings.
+fn normalize(ty: &FieldType, value: Value) -> Value {
+    fn by_name<'a>(
+        names: impl Iterator<Item = (String, &'a FieldType)>,
+        value: Value,
+    ) -> Value {
+        let names: Vec<_> = names.collect();
+        let map = match value {
+            Value::Object(mut map) => names
+                .into_iter()
+                .map(|(name, ty)| {
+                    let value = map.remove(&name).unwrap_or(Value::Null);
+                    (name, normalize(ty, value))
+                })
+                .collect(),
+            Value::Array(items) => names
+                .into_iter()
+                .zip(items)
+                .map(|((name, ty), value)| (name, normalize(ty, value)))
+                .collect(),
+            Value::Null => return Value::Null,
+            // A newtype struct serializes as its only field.
+            value if names.len() == 1 => {
+                let (name, ty) = names.into_iter().next().unwrap();
+                Map::from_iter([(name, normalize(ty, value))])
+            }
+            value => return value,
+        };
+        Value::Object(map)
+    }
+    match (ty, value) {
+        (_, Value::Null) => Value::Null,
+        (FieldType::Option(inner), value) => normalize(inner, value),
+        (FieldType::Seq(item) | FieldType::Array(item, _), Value::Array(items)) => {
+            Value::Array(
+                items
+                    .into_iter()
+                    .map(|value| normalize(item, value))
+                    .collect(),
+            )
+        }
+        (FieldType::FixedVec(item, _), Value::Array(items)) => {
+            let len = items.first().and_then(Value::as_u64).unwrap_or(0) as usize;
+            Value::Array(
+                items
+                    .into_iter()
+                    .skip(1)
+                    .take(len)
+                    .map(|value| normalize(item, value))
+                    .collect(),
+            )
+        }
+        (FieldType::FixedString(_), Value::Array(bytes)) => {
+            let bytes: Vec<u8> = bytes
+                .iter()
+                .map(|byte| byte.as_u64().unwrap_or(0) as u8)
+                .collect();
+            let end = bytes
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(bytes.len());
+            Value::String(String::from_utf8_lossy(&bytes[..end]).into_owned())
+        }
+        (FieldType::Enum { .. }, Value::String(name)) => Value::String(name),
+        (FieldType::Enum { .. }, value) => Value::String(value.to_string()),
+        (FieldType::Tuple(items), value) => by_name(
+            items.iter().enumerate().map(|(i, ty)| (i.to_string(), ty)),
+            value,
+        ),
+        (FieldType::Struct(schema), value) => by_name(
+            schema
+                .fields
+                .iter()
+                .map(|field| (field.name.clone(), &field.ty)),
+            value,
+        ),
+        (_, value) => value,
+    }
+}
+
+fn rfc3339_nanos(text: &str) -> Option<i64> {
+    chrono::DateTime::parse_from_rfc3339(text)
+        .ok()?
+        .timestamp_nanos_opt()
+}
+
+fn nanos(value: &Value, secs: &str, nanos: &str) -> Option<i64> {
+    let secs = value.get(secs)?.as_i64()?;
+    secs.checked_mul(NANOS_PER_SEC)?
+        .checked_add(value.get(nanos)?.as_i64()?)
+}
+
+/// Whether `value` is a map with exactly the keys `keys`.
+fn has_keys(value: &Value, keys: [&str; 2]) -> bool {
+    value.as_object().is_some_and(|map| {
+        map.len() == keys.len() && keys.iter().all(|key| map.contains_key(*key))
+    })
+}
+
+/// Arrow type for values of one column, from their serde output.
+fn infer(values: &[&Value]) -> DataType {
+    let present: Vec<&Value> = values
+        .iter()
+        .copied()
+        .filter(|value| !value.is_null())
+        .collect();
+    let all = |test: fn(&Value) -> bool| present.iter().all(|value| test(value));
+    if present.is_empty() {
+        DataType::Utf8
+    } else if all(Value::is_boolean) {
+        DataType::Boolean
+    } else if all(Value::is_i64) {
+        DataType::Int64
+    } else if all(Value::is_u64) {
+        DataType::UInt64
+    } else if all(Value::is_number) {
+        DataType::Float64
+    } else if present
+        .iter()
+        .all(|value| value.as_str().and_then(rfc3339_nanos).is_some())
+    {
+        timestamp_type()
+    } else if all(Value::is_array) {
+        let items: Vec<&Value> = present
+            .iter()
+            .flat_map(|value| value.as_array().unwrap())
+            .collect();
+        DataType::List(Arc::new(Field::new("item", infer(&items), true)))
+    } else if present
+        .iter()
+        .all(|value| has_keys(value, ["secs_since_epoch", "nanos_since_epoch"]))
+    {
+        timestamp_type()
+    } else if present
+        .iter()
+        .all(|value| has_keys(value, ["secs", "nanos"]))
+    {
+        DataType::Duration(TimeUnit::Nanosecond)
+    } else if all(Value::is_object) {
+        let mut names: Vec<&String> = Vec::new();
+        for value in &present {
+            for name in value.as_object().unwrap().keys() {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        if names.is_empty() {
+            return DataType::Utf8;
+        }
+        DataType::Struct(
+            names
+                .into_iter()
+                .map(|name| {
+                    let column: Vec<&Value> = values
+                        .iter()
+                        .map(|value| value.get(name).unwrap_or(&NULL))
+                        .collect();
+                    Field::new(name, infer(&column), true)
+                })
+                .collect(),
+        )
+    } else {
+        DataType::Utf8
+    }
+}
+
+fn mismatch(path: &str, data_type: &DataType, value: &Value) -> io::Error {
+    invalid(format!("{}: expected {}, got {}", path, data_type, value))
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+fn validity(valid: Vec<bool>) -> Option<NullBuffer> {
+    if valid.iter().all(|&valid| valid) {
+        None
+    } else {
+        Some(NullBuffer::from(valid))
+    }
+}
+
+fn collect<T>(
+    values: &[&Value],
+    path: &str,
+    data_type: &DataType,
+    convert: impl Fn(&Value) -> Option<T>,
+) -> io::Result<Vec<Option<T>>> {
+    values
+        .iter()
+        .map(|value| match value {
+            Value::Null => Ok(None),
+            value => convert(value)
+                .map(Some)
+                .ok_or_else(|| mismatch(path, data_type, value)),
+        })
+        .collect()
+}
+
+fn int<T: TryFrom<i64>>(value: &Value) -> Option<T> {
+    T::try_from(value.as_i64()?).ok()
+}
+
+fn uint<T: TryFrom<u64>>(value: &Value) -> Option<T> {
+    T::try_from(value.as_u64()?).ok()
+}
+
+fn build_struct(
+    fields: &Fields,
+    values: &[&Value],
+    path: &str,
+) -> io::Result<StructArray> {
+    let mut valid = Vec::with_capacity(values.len());
+    for value in values {
+        match value {
+            Value::Null => valid.push(false),
+            Value::Object(_) => valid.push(true),
+            value => return Err(mismatch(path, &DataType::Struct(fields.clone()), value)),
+        }
+    }
+    let columns = fields
+        .iter()
+        .map(|field| {
+            let column: Vec<&Value> = values
+                .iter()
+                .map(|value| value.get(field.name()).unwrap_or(&NULL))
+                .collect();
+            build(field.data_type(), &column, &join(path, field.name()))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    StructArray::try_new(fields.clone(), columns, validity(valid))
+        .map_err(|e| invalid(format!("{}: {}", path, e)))
+}
+
+/// Arrow array of `data_type` holding `values`; null values are nulls.
+fn build(data_type: &DataType, values: &[&Value], path: &str) -> io::Result<ArrayRef> {
+    Ok(match data_type {
+        DataType::Null => Arc::new(NullArray::new(values.len())),
+        DataType::Boolean => Arc::new(BooleanArray::from(collect(
+            values,
+            path,
+            data_type,
+            Value::as_bool,
+        )?)),
+        DataType::Int8 => {
+            Arc::new(Int8Array::from(collect(values, path, data_type, int)?))
+        }
+        DataType::Int16 => {
+            Arc::new(Int16Array::from(collect(values, path, data_type, int)?))
+        }
+        DataType::Int32 => {
+            Arc::new(Int32Array::from(collect(values, path, data_type, int)?))
+        }
+        DataType::Int64 => {
+            Arc::new(Int64Array::from(collect(values, path, data_type, int)?))
+        }
+        DataType::UInt8 => {
+            Arc::new(UInt8Array::from(collect(values, path, data_type, uint)?))
+        }
+        DataType::UInt16 => {
+            Arc::new(UInt16Array::from(collect(values, path, data_type, uint)?))
+        }
+        DataType::UInt32 => {
+            Arc::new(UInt32Array::from(collect(values, path, data_type, uint)?))
+        }
+        DataType::UInt64 => {
+            Arc::new(UInt64Array::from(collect(values, path, data_type, uint)?))
+        }
+        DataType::Float32 => Arc::new(Float32Array::from(collect(
+            values,
+            path,
+            data_type,
+            |value| value.as_f64().map(|value| value as f32),
+        )?)),
+        DataType::Float64 => Arc::new(Float64Array::from(collect(
+            values,
+            path,
+            data_type,
+            Value::as_f64,
+        )?)),
+        DataType::Decimal128(precision, scale) => Arc::new(
+            Decimal128Array::from(collect(values, path, data_type, |value| {
+                value
+                    .as_i64()
+                    .map(i128::from)
+                    .or_else(|| value.as_u64().map(i128::from))
+            })?)
+            .with_precision_and_scale(*precision, *scale)
+            .map_err(io::Error::other)?,
+        ),
+        DataType::Utf8 => Arc::new(StringArray::from(collect(
+            values,
+            path,
+            data_type,
+            |value| match value {
+                Value::String(text) => Some(text.clone()),
+                value => Some(value.to_string()),
+            },
+        )?)),
+        DataType::Timestamp(TimeUnit::Nanosecond, timezone) => Arc::new(
+            TimestampNanosecondArray::from(collect(values, path, data_type, |value| {
+                match value {
+                    Value::String(text) => rfc3339_nanos(text),
+                    value => nanos(value, "secs_since_epoch", "nanos_since_epoch"),
+                }
+            })?)
+            .with_timezone_opt(timezone.clone()),
+        ),
+        DataType::Duration(TimeUnit::Nanosecond) => Arc::new(
+            DurationNanosecondArray::from(collect(values, path, data_type, |value| {
+                nanos(value, "secs", "nanos")
+            })?),
+        ),
+        DataType::List(item) => {
+            let mut lengths = Vec::with_capacity(values.len());
+            let mut valid = Vec::with_capacity(values.len());
+            let mut items = Vec::new();
+            for value in values {
+                match value {
+                    Value::Null => {
+                        lengths.push(0);
+                        valid.push(false);
+                    }
+                    Value::Array(list) => {
+                        lengths.push(list.len());
+                        valid.push(true);
+                        items.extend(list);
+                    }
+                    value => return Err(mismatch(path, data_type, value)),
+                }
+            }
+            let child = build(item.data_type(), &items, &format!("{}[]", path))?;
+            Arc::new(
+                ListArray::try_new(
+                    item.clone(),
+                    OffsetBuffer::from_lengths(lengths),
+                    child,
+                    validity(valid),
+                )
+                .map_err(|e| invalid(format!("{}: {}", path, e)))?,
+            )
+        }
+        DataType::FixedSizeList(item, size) => {
+            let mut valid = Vec::with_capacity(values.len());
+            let mut items = Vec::new();
+            for value in values {
+                match value {
+                    Value::Null => {
+                        valid.push(false);
+                        items.extend(std::iter::repeat_n(&NULL, *size as usize));
+                    }
+                    Value::Array(list) if list.len() == *size as usize => {
+                        valid.push(true);
+                        items.extend(list);
+                    }
+                    value => return Err(mismatch(path, data_type, value)),
+                }
+            }
+            let child = build(item.data_type(), &items, &format!("{}[]", path))?;
+            Arc::new(
+                FixedSizeListArray::try_new(item.clone(), *size, child, validity(valid))
+                    .map_err(|e| invalid(format!("{}: {}", path, e)))?,
+            )
+        }
+        DataType::Struct(fields) => Arc::new(build_struct(fields, values, path)?),
+        data_type => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{}: {} columns are not supported", path, data_type),
+            ))
+        }
+    })
+}
+
+/// Top-level records that are structs become one column per field;
+/// anything else is a single `value` column.
+fn schema_of(record: &DataType) -> Schema {
+    match record {
+        DataType::Struct(fields) => Schema::new(fields.clone()),
+        data_type => Schema::new(vec![Field::new("value", data_type.clone(), true)]),
+    }
+}
+
+fn record_batch(record: &DataType, values: &[Value]) -> io::Result<RecordBatch> {
+    let values: Vec<&Value> = values.iter().collect();
+    let (schema, columns) = match record {
+        DataType::Struct(fields) => {
+            let (fields, columns, _) = build_struct(fields, &values, "")?.into_parts();
+            (Schema::new(fields), columns)
+        }
+        data_type => (
+            schema_of(data_type),
+            vec![build(data_type, &values, "value")?],
+        ),
+    };
+    RecordBatch::try_new(Arc::new(schema), columns).map_err(|e| invalid(e.to_string()))
+}
+
+enum IpcWriter<W: Write> {
+    File(FileWriter<W>),
+    Stream(StreamWriter<W>),
+}
+
+impl<W: Write> IpcWriter<W> {
+    fn new(writer: W, format: ArrowFormat, schema: &Schema) -> io::Result<Self> {
+        Ok(match format {
+            ArrowFormat::File => IpcWriter::File(
+                FileWriter::try_new(writer, schema).map_err(io::Error::other)?,
+            ),
+            ArrowFormat::Stream => IpcWriter::Stream(
+                StreamWriter::try_new(writer, schema).map_err(io::Error::other)?,
+            ),
+        })
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> io::Result<()> {
+        match self {
+            IpcWriter::File(writer) => writer.write(batch),
+            IpcWriter::Stream(writer) => writer.write(batch),
+        }
+        .map_err(io::Error::other)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self {
+            IpcWriter::File(writer) => writer.finish(),
+            IpcWriter::Stream(writer) => writer.finish(),
+        }
+        .map_err(io::Error::other)
+    }
+}
+
+pub(crate) fn export_arrow<T, E, W>(
+    engine: &E,
+    writer: W,
+    range: Range<u64>,
+    options: &ArrowOptions,
+) -> io::Result<u64>
+where
+    T: Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+    E: VectorEngine<T> + ?Sized,
+    W: Write,
+{
+    let count = range.end.saturating_sub(range.start);
+    let mut writer = Some(writer);
+    // The record type and the IPC writer, opened with the first batch when
+    // the types are inferred.
+    let mut output: Option<(DataType, IpcWriter<W>)> = None;
+    for_each_batch(engine, range, options.batch_size, |first, records| {
+        let mut values = records
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(io::Error::other)?;
+        if let Some(ty) = &options.schema {
+            values = values
+                .into_iter()
+                .map(|value| normalize(ty, value))
+                .collect();
+        }
+        if output.is_none() {
+            let record = match &options.schema {
+                Some(ty) => described_type(ty),
+                None => infer(&values.iter().collect::<Vec<_>>()),
+            };
+            let ipc = IpcWriter::new(
+                writer.take().unwrap(),
+                options.format,
+                &schema_of(&record),
+            )?;
+            output = Some((record, ipc));
+        }
+        let (record, ipc) = output.as_mut().unwrap();
+        let batch = record_batch(record, &values).map_err(|e| {
+            invalid(format!(
+                "records {}..{}: {}",
+                first,
+                first + values.len() as u64,
+                e
+            ))
+        })?;
+        ipc.write(&batch)
+    })?;
+    let mut ipc = match output {
+        Some((_, ipc)) => ipc,
+        None => {
+            let schema = match &options.schema {
+                Some(ty) => schema_of(&described_type(ty)),
+                None => Schema::empty(),
+            };
+            IpcWriter::new(writer.take().unwrap(), options.format, &schema)?
+        }
+    };
+    ipc.finish()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        services::dynamic_vector_manage_service::DynamicVectorManageService,
+        test_support::TestDir,
+    };
+    use arrow_array::{
+        cast::AsArray,
+        types::{
+            Int64Type,
+            TimestampNanosecondType,
+            UInt32Type,
+            UInt64Type,
+        },
+        Array,
+    };
+    use arrow_ipc::reader::{
+        FileReader,
+        StreamReader,
+    };
+    use dynamic_vector::{
+        CheckDynamicSize,
+        Describe,
+        FixedString,
+    };
+    use std::time::{
+        Duration,
+        SystemTime,
+    };
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, CheckDynamicSize)]
+    pub struct Location(f32, f32);
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, CheckDynamicSize)]
+    pub struct Event {
+        id: u64,
+        at: SystemTime,
+        kind: FixedString<8>,
+        tags: Vec<String>,
+        samples: Vec<Vec<u32>>,
+        location: Option<Location>,
+        delta: i64,
+    }
+
+    fn event(i: u64) -> Event {
+        Event {
+            id: i,
+            at: SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000 + i, 7),
+            kind: FixedString::new(if i.is_multiple_of(2) { "click" } else { "view" })
+                .unwrap(),
+            tags: (0..i % 3).map(|tag| format!("t{}", tag)).collect(),
+            samples: vec![(0..i as u32 % 4).collect(); (i % 2) as usize],
+            location: (!i.is_multiple_of(3)).then(|| Location(i as f32, -(i as f32))),
+            delta: i as i64 - 500,
+        }
+    }
+
+    fn engine(dir: &TestDir) -> DynamicVectorManageService<Event> {
+        let (structure, data) = dir.collection();
+        let engine = DynamicVectorManageService::new(structure, data, 1024).unwrap();
+        engine.pushx((0..1000).map(event).collect());
+        engine
+    }
+
+    fn check_batches(batches: Vec<RecordBatch>, first: u64, described: bool) {
+        let mut index = first;
+        for batch in &batches {
+            assert!(batch.num_rows() <= 300);
+            let ids = batch.column_by_name("id").unwrap();
+            let delta = batch
+                .column_by_name("delta")
+                .unwrap()
+                .as_primitive::<Int64Type>();
+            let at = batch
+                .column_by_name("at")
+                .unwrap()
+                .as_primitive::<TimestampNanosecondType>();
+            let tags = batch.column_by_name("tags").unwrap().as_list::<i32>();
+            let samples = batch.column_by_name("samples").unwrap().as_list::<i32>();
+            let location = batch.column_by_name("location").unwrap();
+            for row in 0..batch.num_rows() {
+                let expected = event(index);
+                let id = match described {
+                    true => ids.as_primitive::<UInt64Type>().value(row),
+                    false => ids.as_primitive::<Int64Type>().value(row) as u64,
+                };
+                assert_eq!(id, index);
+                assert_eq!(delta.value(row), expected.delta);
+                assert_eq!(
+                    at.value(row),
+                    (1_700_000_000 + index as i64) * NANOS_PER_SEC + 7
+                );
+                assert_eq!(tags.value(row).len(), expected.tags.len());
+                let nested = samples.value(row);
+                assert_eq!(nested.len(), expected.samples.len());
+                assert_eq!(location.is_null(row), expected.location.is_none());
+                if described {
+                    if let Some(first) = expected.samples.first() {
+                        let inner = nested.as_list::<i32>().value(0);
+                        assert_eq!(
+                            inner.as_primitive::<UInt32Type>().values(),
+                            first.as_slice()
+                        );
+                    }
+                    let kind = batch.column_by_name("kind").unwrap().as_string::<i32>();
+                    assert_eq!(kind.value(row), expected.kind.as_str());
+                }
+                index += 1;
+            }
+        }
+        assert_eq!(index, 1000);
+    }
+
+    #[test]
+    fn test_export_arrow_described() {
+        let dir = TestDir::new("export_arrow_described");
+        let engine = engine(&dir);
+        let options = ArrowOptions {
+            batch_size: 300,
+            schema: Some(Event::describe()),
+            ..Default::default()
+        };
+        let mut file = Vec::new();
+        assert_eq!(
+            engine.export_arrow(&mut file, 100..1000, &options).unwrap(),
+            900
+        );
+
+        let reader = FileReader::try_new(io::Cursor::new(file), None).unwrap();
+        let schema = reader.schema();
+        assert_eq!(
+            schema.field_with_name("id").unwrap().data_type(),
+            &DataType::UInt64
+        );
+        assert!(!schema.field_with_name("id").unwrap().is_nullable());
+        assert_eq!(
+            schema.field_with_name("at").unwrap().data_type(),
+            &timestamp_type()
+        );
+        assert_eq!(
+            schema.field_with_name("kind").unwrap().data_type(),
+            &DataType::Utf8
+        );
+        let location = schema.field_with_name("location").unwrap();
+        assert!(location.is_nullable());
+        let DataType::Struct(fields) = location.data_type() else {
+            panic!("location is {}", location.data_type());
+        };
+        assert_eq!(fields[1].data_type(), &DataType::Float32);
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.len(), 3);
+        check_batches(batches, 100, true);
+    }
+
+    #[test]
+    fn test_export_arrow_inferred_stream() {
+        let dir = TestDir::new("export_arrow_inferred_stream");
+        let engine = engine(&dir);
+        let options = ArrowOptions {
+            format: ArrowFormat::Stream,
+            batch_size: 300,
+            schema: None,
+        };
+        let mut stream = Vec::new();
+        engine.export_arrow(&mut stream, 0..1000, &options).unwrap();
+        let reader = StreamReader::try_new(io::Cursor::new(stream), None).unwrap();
+        assert_eq!(
+            reader
+                .schema()
+                .field_with_name("delta")
+                .unwrap()
+                .data_type(),
+            &DataType::Int64
+        );
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.len(), 4);
+        check_batches(batches, 0, false);
+
+        let times = [
+            Value::from("2024-05-01T12:00:00+02:00"),
+            Value::Null,
+            Value::from("1970-01-01T00:00:01Z"),
+        ];
+        assert_eq!(infer(&times.iter().collect::<Vec<_>>()), timestamp_type());
+        let at =
+            build(&timestamp_type(), &times.iter().collect::<Vec<_>>(), "at").unwrap();
+        let at = at.as_primitive::<TimestampNanosecondType>();
+        assert_eq!(at.value(0), 1_714_557_600 * NANOS_PER_SEC);
+        assert!(at.is_null(1));
+        assert_eq!(at.value(2), NANOS_PER_SEC);
+
+        let mut empty = Vec::new();
+        assert_eq!(engine.export_arrow(&mut empty, 5..5, &options).unwrap(), 0);
+        assert_eq!(
+            StreamReader::try_new(io::Cursor::new(empty), None)
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,625 @@
+use serde::Serialize;
+use serde_json::{
+    json,
+    Value,
+};
+use std::{
+    io::{
+        self,
+        BufRead,
+        BufReader,
+        BufWriter,
+        Read,
+        Write,
+    },
+    process::ExitCode,
+};
+
+use vector_db_core::{
+    convert_headerless,
+    convert_static_headerless,
+    convert_to_compact_index,
+    decode_value,
+    repair,
+    CodecKind,
+    DynamicOptions,
+    FieldType,
+    HybridFileReader,
+    IndexFormat,
+    KeyProvider,
+    Layout,
+    LocalKeyProvider,
+    RawCollection,
+    RecordSchema,
+    RepairOptions,
+};
+
+const USAGE: &str = "\
+usage: vector-db <command> <index file> [<data file>] [options]
+
+A collection is static when only the index file is given and dynamic or
+hybrid when the data file is given too. Encrypted collections need
+--keys <key file>. Hybrid collections carry their schema in the header and
+support info, dump and verify.
+
+commands:
+  info      Header, length, file sizes and preallocated slack, as JSON.
+            For a hybrid collection, its slot size and schema.
+  dump      Print records as JSON lines. JSON records are printed as
+            stored and hybrid ones decoded with their schema; bincode
+            records are decoded with --schema <file>, a RecordSchema as
+            JSON, and printed as hex without one. info prints the schema of
+            a hybrid collection; for any type deriving CheckDynamicSize it
+            is serde_json of its schema().
+            [--range <start>..<end>] [--schema <file>]
+  verify    Check that every record can be read, and decodes with
+            --schema <file> or the hybrid schema. Fails when problems are
+            found.
+  compact   Cut preallocated slack off the files. With --compact-index <n>
+            a dynamic pair index is first converted to a compact index
+            checkpointed every n records.
+  export    Copy encoded records to a file. [--out <file>] [--range ...]
+  import    Append records from an export. [--from <file>]
+            A missing dynamic collection is created with the codec of the
+            export, [--framed] and [--compact-index <n>].
+  stats     Record count, sizes and a size histogram, as JSON.
+            [--range <start>..<end>]
+  repair    Check a dynamic collection after a crash and drop records at
+            the end that cannot be read. Prints a JSON report.
+            [--dry-run] [--report <file>]
+            [--renumber] also quarantines damaged records followed by
+            intact ones, renumbering the records after them.
+  upgrade   Add a header to an index file in the original headerless
+            layout, which the other commands refuse: [length][pairs] for a
+            dynamic collection, or [length][slots] for a static one given
+            --slot-size <n>, the size_of of its record type. The bincode
+            records are moved into [--new-slot-size <n>] byte slots, the
+            slot size the record type needs now.
+";
+
+/// First line of an export file; a JSON line with the codec follows, then
+/// the records as [length u64][bytes].
+const EXPORT_MAGIC: &str = "vector-db export 1";
+/// Records read or written at a time.
+const BATCH: u64 = 4096;
+
+/// Command line arguments after the command name: positional values and
+/// `--flag [value]` options.
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+const VALUED_OPTIONS: &[&str] = &[
+    "keys",
+    "report",
+    "range",
+    "schema",
+    "out",
+    "from",
+    "compact-index",
+    "slot-size",
+    "new-slot-size",
+];
+
+impl Args {
+    /// Fails on options outside `accepted`, the options of the command.
+    fn parse(
+        mut args: impl Iterator<Item = String>,
+        accepted: &[&str],
+    ) -> io::Result<Self> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            options: Vec::new(),
+        };
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if !accepted.contains(&name) => {
+                    return Err(invalid_input(format!("unknown option --{}", name)));
+                }
+                Some(name) if VALUED_OPTIONS.contains(&name) => {
+                    let value = args.next().ok_or_else(|| {
+                        invalid_input(format!("--{} needs a value", name))
+                    })?;
+                    parsed.options.push((name.to_string(), Some(value)));
+                }
+                Some(name) => parsed.options.push((name.to_string(), None)),
+                None => parsed.positional.push(arg),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| option == name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(option, _)| option == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    fn required(&self, name: &str) -> io::Result<&str> {
+        self.value(name)
+            .ok_or_else(|| invalid_input(format!("--{} is required", name)))
+    }
+
+    fn positional(&self, position: usize, name: &str) -> io::Result<&str> {
+        self.positional
+            .get(position)
+            .map(String::as_str)
+            .ok_or_else(|| invalid_input(format!("missing {}", name)))
+    }
+
+    fn number(&self, name: &str) -> io::Result<Option<u64>> {
+        self.value(name)
+            .map(|value| {
+                value.parse().map_err(|_| {
+                    invalid_input(format!("--{} expects a number, got {}", name, value))
+                })
+            })
+            .transpose()
+    }
+
+    fn keys(&self) -> io::Result<Option<LocalKeyProvider>> {
+        self.value("keys")
+            .map(LocalKeyProvider::from_file)
+            .transpose()
+    }
+
+    /// The collection when its index file has a hybrid header.
+    fn hybrid(&self) -> io::Result<Option<HybridFileReader>> {
+        let (Some(structure), Some(data)) =
+            (self.positional.first(), self.positional.get(1))
+        else {
+            return Ok(None);
+        };
+        match HybridFileReader::open(structure.clone(), data.clone()) {
+            Ok(_) if self.value("schema").is_some() => Err(invalid_input(
+                "hybrid collections are decoded with the schema in their header; \
+                     drop --schema",
+            )),
+            Ok(reader) => Ok(Some(reader)),
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn open(&self, keys: Option<&dyn KeyProvider>) -> io::Result<RawCollection> {
+        if self.hybrid()?.is_some() {
+            return Err(invalid_input(
+                "hybrid collections support info, dump and verify only",
+            ));
+        }
+        let structure = self.positional(0, "index file")?;
+        match self.positional.get(1) {
+            Some(data) => RawCollection::open_dynamic(structure, data, keys),
+            None => RawCollection::open_static(structure, keys),
+        }
+    }
+
+    /// `--range start..end`, either end optional, clamped to `length`.
+    fn range(&self, length: u64) -> io::Result<(u64, u64)> {
+        let Some(range) = self.value("range") else {
+            return Ok((0, length));
+        };
+        let bad = || invalid_input(format!("--range expects start..end, got {}", range));
+        let (start, end) = range.split_once("..").ok_or_else(bad)?;
+        let bound = |text: &str, default: u64| match text {
+            "" => Ok(default),
+            text => text.parse::<u64>().map_err(|_| bad()),
+        };
+        let end = bound(end, length)?.min(length);
+        Ok((bound(start, 0)?.min(end), end))
+    }
+
+    fn schema(&self) -> io::Result<Option<RecordSchema>> {
+        self.value("schema")
+            .map(|path| {
+                serde_json::from_slice(&std::fs::read(path)?)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .transpose()
+    }
+}
+
+fn invalid_input(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.into())
+}
+
+fn print_json(value: &impl Serialize) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(value).map_err(io::Error::other)?;
+    writeln!(io::stdout().lock(), "{}", json)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Turns encoded records into JSON where the codec or a schema allows it.
+struct Decoder {
+    codec: CodecKind,
+    schema: Option<FieldType>,
+}
+
+impl Decoder {
+    fn new(codec: CodecKind, schema: Option<RecordSchema>) -> io::Result<Self> {
+        if schema.is_some() && codec != CodecKind::Bincode {
+            return Err(invalid_input(format!(
+                "--schema decodes bincode records; this collection uses {:?}",
+                codec
+            )));
+        }
+        Ok(Self {
+            codec,
+            schema: schema.map(FieldType::Struct),
+        })
+    }
+
+    /// `None` when the records can only be shown as bytes.
+    fn decode(&self, bytes: &[u8]) -> Option<io::Result<Value>> {
+        match (&self.schema, self.codec) {
+            (Some(schema), _) => {
+                Some(decode_value(schema, bytes).map(|(value, _)| value))
+            }
+            (None, CodecKind::Json) => Some(
+                serde_json::from_slice(bytes)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            ),
+            (None, _) => None,
+        }
+    }
+}
+
+fn run_info(args: Args) -> io::Result<()> {
+    if let Some(hybrid) = args.hybrid()? {
+        return print_json(&json!({
+            "layout": "Hybrid",
+            "length": hybrid.len(),
+            "slot_size": hybrid.slot_size(),
+            "schema": hybrid.schema(),
+        }));
+    }
+    let keys = args.keys()?;
+    print_json(&args.open(keys.as_ref().map(|keys| keys as _))?.info()?)
+}
+
+fn run_dump(args: Args) -> io::Result<()> {
+    if let Some(hybrid) = args.hybrid()? {
+        let (start, end) = args.range(hybrid.len())?;
+        let mut out = BufWriter::new(io::stdout().lock());
+        for index in start..end {
+            let record = hybrid.read_value(index)?;
+            writeln!(out, "{}", json!({ "index": index, "record": record }))?;
+        }
+        return out.flush();
+    }
+    let keys = args.keys()?;
+    let collection = args.open(keys.as_ref().map(|keys| keys as _))?;
+    let decoder = Decoder::new(collection.codec(), args.schema()?)?;
+    let (start, end) = args.range(collection.len())?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut index = start;
+    while index < end {
+        let count = BATCH.min(end - index);
+        for (offset, bytes) in collection.read(index, count)?.iter().enumerate() {
+            let line = match decoder.decode(bytes) {
+                Some(record) => {
+                    json!({ "index": index + offset as u64, "record": record? })
+                }
+                None => json!({ "index": index + offset as u64, "bytes": hex(bytes) }),
+            };
+            writeln!(out, "{}", line)?;
+        }
+        index += count;
+    }
+    out.flush()
+}
+
+fn run_verify(args: Args) -> io::Result<()> {
+    if let Some(hybrid) = args.hybrid()? {
+        let damaged: Vec<Value> = (0..hybrid.len())
+            .filter_map(|index| {
+                let error = hybrid.read_value(index).err()?;
+                Some(json!({ "index": index, "reason": error.to_string() }))
+            })
+            .collect();
+        print_json(&json!({ "length": hybrid.len(), "damaged": damaged }))?;
+        if !damaged.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} damaged records", damaged.len()),
+            ));
+        }
+        return Ok(());
+    }
+    let keys = args.keys()?;
+    let keys = keys.as_ref().map(|keys| keys as &dyn KeyProvider);
+    let collection = args.open(keys)?;
+    let decoder = Decoder::new(collection.codec(), args.schema()?)?;
+    let check = |bytes: &[u8]| match decoder.decode(bytes) {
+        Some(result) => result.map(|_| ()),
+        None => Ok(()),
+    };
+    let report = collection.verify(keys, Some(&check))?;
+    print_json(&report)?;
+    if !report.is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} damaged records, {} missing index entries",
+                report.damaged.len(),
+                report.missing_entries
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn run_compact(args: Args) -> io::Result<()> {
+    let keys = args.keys()?;
+    let converted = match args.number("compact-index")? {
+        Some(interval) => {
+            if args.positional.len() < 2 {
+                return Err(invalid_input("--compact-index needs a dynamic collection"));
+            }
+            let interval = u32::try_from(interval)
+                .map_err(|_| invalid_input("--compact-index is too large"))?;
+            Some(convert_to_compact_index(
+                args.positional(0, "index file")?,
+                interval,
+            )?)
+        }
+        None => None,
+    };
+    let released = args.open(keys.as_ref().map(|keys| keys as _))?.trim()?;
+    print_json(&json!({ "converted_records": converted, "released_bytes": released }))
+}
+
+fn run_export(args: Args) -> io::Result<()> {
+    let keys = args.keys()?;
+    let collection = args.open(keys.as_ref().map(|keys| keys as _))?;
+    let (start, end) = args.range(collection.len())?;
+    let mut out = BufWriter::new(std::fs::File::create(args.required("out")?)?);
+    writeln!(out, "{}", EXPORT_MAGIC)?;
+    writeln!(out, "{}", json!({ "codec": collection.codec() }))?;
+    let mut index = start;
+    while index < end {
+        let count = BATCH.min(end - index);
+        for bytes in collection.read(index, count)? {
+            out.write_all(&(bytes.len() as u64).to_le_bytes())?;
+            out.write_all(&bytes)?;
+        }
+        index += count;
+    }
+    out.flush()?;
+    print_json(&json!({ "exported": end - start }))
+}
+
+/// Next record of an export, `None` at the end of the file. The buffer
+/// grows with the bytes actually read, so a damaged length fails with
+/// `InvalidData` instead of allocating what it claims.
+fn read_exported(input: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 8];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+    let len = u64::from_le_bytes(len);
+    let mut bytes = Vec::new();
+    input.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "export record of {} bytes is cut off after {} bytes",
+                len,
+                bytes.len()
+            ),
+        ));
+    }
+    Ok(Some(bytes))
+}
+
+fn run_import(args: Args) -> io::Result<()> {
+    let keys = args.keys()?;
+    let keys = keys.as_ref().map(|keys| keys as &dyn KeyProvider);
+    let mut input = BufReader::new(std::fs::File::open(args.required("from")?)?);
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    if line.trim_end() != EXPORT_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a vector-db export",
+        ));
+    }
+    line.clear();
+    input.read_line(&mut line)?;
+    let header: Value = serde_json::from_str(&line)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let codec: CodecKind = serde_json::from_value(header["codec"].clone())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let structure = args.positional(0, "index file")?;
+    let mut collection = match args.positional.get(1) {
+        Some(data) if !std::path::Path::new(structure).exists() => {
+            let interval = args.number("compact-index")?;
+            let index = match interval {
+                Some(interval) => IndexFormat::Compact {
+                    checkpoint_interval: u32::try_from(interval)
+                        .map_err(|_| invalid_input("--compact-index is too large"))?,
+                },
+                None => IndexFormat::Pairs,
+            };
+            let options = DynamicOptions {
+                codec,
+                index,
+                framed: args.flag("framed"),
+                ..Default::default()
+            };
+            RawCollection::create_dynamic(structure, data, options, keys)?
+        }
+        _ => args.open(keys)?,
+    };
+    if collection.codec() != codec {
+        return Err(invalid_input(format!(
+            "the export holds {:?} records but the collection uses {:?}",
+            codec,
+            collection.codec()
+        )));
+    }
+
+    let mut imported = 0;
+    loop {
+        let mut batch = Vec::new();
+        while (batch.len() as u64) < BATCH {
+            match read_exported(&mut input)? {
+                Some(bytes) => batch.push(bytes),
+                None => break,
+            }
+        }
+        if batch.is_empty() {
+            break;
+        }
+        imported += batch.len() as u64;
+        collection.append(batch)?;
+    }
+    print_json(&json!({ "imported": imported, "length": collection.len() }))
+}
+
+fn run_stats(args: Args) -> io::Result<()> {
+    let keys = args.keys()?;
+    let collection = args.open(keys.as_ref().map(|keys| keys as _))?;
+    let (start, end) = args.range(collection.len())?;
+    // Bucket i counts records of at most 16 << i bytes.
+    let mut histogram: Vec<u64> = Vec::new();
+    let (mut total, mut min, mut max) = (0u64, u64::MAX, 0u64);
+    let mut index = start;
+    while index < end {
+        let count = BATCH.min(end - index);
+        for bytes in collection.read(index, count)? {
+            let size = bytes.len() as u64;
+            total += size;
+            min = min.min(size);
+            max = max.max(size);
+            let bucket = (64 - size.saturating_sub(1).leading_zeros()).saturating_sub(4);
+            let bucket = bucket as usize;
+            if histogram.len() <= bucket {
+                histogram.resize(bucket + 1, 0);
+            }
+            histogram[bucket] += 1;
+        }
+        index += count;
+    }
+    let records = end - start;
+    let histogram: Vec<Value> = histogram
+        .iter()
+        .enumerate()
+        .map(|(bucket, count)| json!({ "up_to": 16u64 << bucket, "count": count }))
+        .collect();
+    let size_note = match collection.layout() {
+        Layout::Static => "whole slots, padding included",
+        Layout::Dynamic => "encoded records",
+    };
+    print_json(&json!({
+        "records": records,
+        "sizes_of": size_note,
+        "total_bytes": total,
+        "min": if records == 0 { 0 } else { min },
+        "max": max,
+        "mean": if records == 0 { 0.0 } else { total as f64 / records as f64 },
+        "histogram": histogram,
+    }))
+}
+
+fn run_repair(args: Args) -> io::Result<()> {
+    let structure = args.positional(0, "index file")?;
+    let data = args.positional(1, "data file")?;
+    let keys = args.keys()?;
+    let report = repair(
+        structure,
+        data,
+        RepairOptions {
+            keys: keys.as_ref().map(|keys| keys as _),
+            dry_run: args.flag("dry-run"),
+            renumber: args.flag("renumber"),
+        },
+    )?;
+    let json = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+    match args.value("report") {
+        Some(path) => std::fs::write(path, json + "\n")?,
+        None => println!("{}", json),
+    }
+    if !report.quarantined.is_empty() && report.renumbered.is_empty() {
+        eprintln!(
+            "damaged records keep their positions; pass --renumber to remove them \
+             and move the records after them down"
+        );
+    } else if !report.is_consistent() && !report.applied {
+        eprintln!("inconsistencies found; run without --dry-run to repair");
+    }
+    Ok(())
+}
+
+fn run_upgrade(args: Args) -> io::Result<()> {
+    let structure = args.positional(0, "index file")?;
+    let converted = match args.number("slot-size")? {
+        Some(from_slot_size) => {
+            let slot_size = args.number("new-slot-size")?.unwrap_or(from_slot_size);
+            convert_static_headerless(
+                structure,
+                from_slot_size,
+                slot_size,
+                CodecKind::Bincode,
+            )?
+        }
+        None if args.flag("new-slot-size") => {
+            return Err(invalid_input("--new-slot-size needs --slot-size"));
+        }
+        None => convert_headerless(structure)?,
+    };
+    print_json(&json!({ "converted_records": converted }))
+}
+
+type Command = fn(Args) -> io::Result<()>;
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let (command, accepted): (Command, &[&str]) = match args.next().as_deref() {
+        Some("info") => (run_info, &["keys"]),
+        Some("dump") => (run_dump, &["keys", "range", "schema"]),
+        Some("verify") => (run_verify, &["keys", "schema"]),
+        Some("compact") => (run_compact, &["keys", "compact-index"]),
+        Some("export") => (run_export, &["keys", "range", "out"]),
+        Some("import") => (run_import, &["keys", "from", "framed", "compact-index"]),
+        Some("stats") => (run_stats, &["keys", "range"]),
+        Some("repair") => (run_repair, &["keys", "dry-run", "renumber", "report"]),
+        Some("upgrade") => (run_upgrade, &["slot-size", "new-slot-size"]),
+        Some("help" | "--help" | "-h") => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        _ => {
+            eprint!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let args = match Args::parse(args, accepted) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("vector-db: {}", error);
+            eprint!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match command(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("vector-db: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -166,6 +166,8 @@ where
 
 #[cfg(test)]
 mod test {
+    use dynamic_vector::MaxEncodedSize;
+
     use super::*;
     use crate::services::{
         dynamic_vector_manage_service::DynamicVectorManageService,
@@ -175,7 +177,7 @@ mod test {
     const COUNT: usize = 100;
     const TURNS: usize = 5;
 
-    #[derive(Serialize, Deserialize, Default, Debug, Clone)]
+    #[derive(Serialize, Deserialize, Default, Debug, Clone, MaxEncodedSize)]
     pub struct StaticStruct {
         my_usize: usize,
         my_u64: u64,
@@ -313,7 +315,7 @@ mod test {
         let awake = Instant::now();
         let objs = read_cache_service.getting_lot(0, COUNT as u64);
         println!("get lot cache duration: {:?}", awake.elapsed());
-        assert_eq!(442 + COUNT, objs[COUNT as usize - 1].my_usize);
+        assert_eq!(442 + COUNT, objs[COUNT - 1].my_usize);
         assert_eq!(COUNT, objs.len());
         assert_eq!(COUNT, read_cache_service.get_length());
     }
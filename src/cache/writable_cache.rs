@@ -300,6 +300,8 @@ where
 
 #[cfg(test)]
 mod test {
+    use dynamic_vector::MaxEncodedSize;
+
     use super::*;
     use crate::services::{
         dynamic_vector_manage_service::DynamicVectorManageService,
@@ -308,7 +310,7 @@ mod test {
 
     const COUNT: usize = 1000;
 
-    #[derive(Serialize, Deserialize, Default, Debug, Clone)]
+    #[derive(Serialize, Deserialize, Default, Debug, Clone, MaxEncodedSize)]
     pub struct StaticStruct {
         my_usize: usize,
         my_u64: u64,
@@ -0,0 +1,146 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    io,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use crate::vector_engine::VectorEngine;
+
+/// Records fed to an index per call while it catches up with the engine.
+const CATCH_UP_BATCH_SIZE: u64 = 65536;
+
+/// A secondary structure maintained alongside the records of an engine.
+pub trait RecordIndex<T>: Send + Sync {
+    /// Number of leading records already covered by the index.
+    fn indexed_len(&self) -> u64;
+
+    /// Indexes `records`, which were stored as
+    /// `first_index..first_index + records.len()`. Records below
+    /// `indexed_len` may be passed again after a restart and must be
+    /// tolerated.
+    fn append(&self, first_index: u64, records: &[T]);
+
+    /// Persists any state kept in memory.
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Engine wrapper that keeps attached indexes up to date on every append.
+pub struct IndexedEngine<D, T>
+where
+    D: VectorEngine<T>,
+    T: Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+{
+    database: D,
+    indexes: Vec<Arc<dyn RecordIndex<T>>>,
+    append_lock: Mutex<()>,
+}
+
+impl<D, T> IndexedEngine<D, T>
+where
+    D: VectorEngine<T>,
+    T: Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+{
+    pub fn with_database(database: D) -> Self {
+        Self {
+            database,
+            indexes: Vec::new(),
+            append_lock: Mutex::new(()),
+        }
+    }
+
+    /// Attaches `index`, first feeding it every record it has not seen yet.
+    pub fn attach_index(&mut self, index: Arc<dyn RecordIndex<T>>) {
+        let total = self.database.len() as u64;
+        let mut next = index.indexed_len();
+        while next < total {
+            let count = CATCH_UP_BATCH_SIZE.min(total - next);
+            index.append(next, &self.database.pullx(next, count));
+            next += count;
+        }
+        self.indexes.push(index);
+    }
+
+    pub fn database(&self) -> &D {
+        &self.database
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        for index in &self.indexes {
+            index.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<D, T> VectorEngine<T> for IndexedEngine<D, T>
+where
+    D: VectorEngine<T>,
+    T: Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+{
+    fn new(
+        structural_repository: String,
+        dynamic_repository: String,
+        initial_file_size: u64,
+    ) -> Self {
+        Self::with_database(D::new(
+            structural_repository,
+            dynamic_repository,
+            initial_file_size,
+        ))
+    }
+
+    fn push(&self, obj: T) {
+        self.pushx(vec![obj]);
+    }
+
+    fn pushx(&self, objs: Vec<T>) {
+        if self.indexes.is_empty() {
+            self.database.pushx(objs);
+            return;
+        }
+        let _guard = self.append_lock.lock().unwrap();
+        let first_index = self.database.len() as u64;
+        self.database.pushx(objs.clone());
+        for index in &self.indexes {
+            index.append(first_index, &objs);
+        }
+    }
+
+    fn pull(&self, index: u64) -> T {
+        self.database.pull(index)
+    }
+
+    fn pullx(&self, index: u64, count: u64) -> Vec<T> {
+        self.database.pullx(index, count)
+    }
+
+    fn len(&self) -> usize {
+        self.database.len()
+    }
+}
@@ -0,0 +1,602 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::{
+    Map,
+    Value,
+};
+use std::{
+    io::{
+        self,
+        BufRead,
+        BufReader,
+        BufWriter,
+        Read,
+        Write,
+    },
+    ops::Range,
+};
+
+use crate::vector_engine::VectorEngine;
+
+/// Records read per `pullx` call while exporting.
+const EXPORT_BATCH: u64 = 1024;
+/// Records imported per `pushx` call, which bounds the memory an import
+/// holds no matter how large the input is.
+const IMPORT_BATCH: usize = 1024;
+/// Malformed lines kept in an [`ImportReport`]; later ones are only
+/// counted.
+const MAX_REPORTED_ERRORS: usize = 1000;
+/// Column of records that serialize to a single value rather than a map.
+const VALUE_COLUMN: &str = "value";
+
+/// A line of an import that could not be turned into a record.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LineError {
+    /// 1-based line the record starts on.
+    pub line: u64,
+    pub message: String,
+}
+
+/// Outcome of `import_jsonl` or `import_csv`. Malformed lines are skipped
+/// and reported here instead of aborting the import.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ImportReport {
+    pub imported: u64,
+    pub rejected: u64,
+    /// The first malformed lines, in order.
+    pub errors: Vec<LineError>,
+}
+
+impl ImportReport {
+    pub fn is_clean(&self) -> bool {
+        self.rejected == 0
+    }
+
+    fn reject(&mut self, line: u64, message: impl ToString) {
+        self.rejected += 1;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(LineError {
+                line,
+                message: message.to_string(),
+            });
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Calls `f` with consecutive batches of at most `batch_size` records in
+/// `range`, and the index of the first record of each batch.
+pub(crate) fn for_each_batch<T, E>(
+    engine: &E,
+    range: Range<u64>,
+    batch_size: u64,
+    mut f: impl FnMut(u64, Vec<T>) -> io::Result<()>,
+) -> io::Result<()>
+where
+    T: Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+    E: VectorEngine<T> + ?Sized,
+{
+    let length = engine.len() as u64;
+    if range.start > range.end || range.end > length {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "records {}..{} out of range of {} records",
+                range.start, range.end, length
+            ),
+        ));
+    }
+    let mut index = range.start;
+    while index < range.end {
+        let count = batch_size.max(1).min(range.end - index);
+        f(index, engine.pullx(index, count))?;
+        index += count;
+    }
+    Ok(())
+}
+
+/// Feeds parsed records to `pushx` in batches of [`IMPORT_BATCH`].
+struct Importer<'a, T, E: ?Sized> {
+    engine: &'a E,
+    batch: Vec<T>,
+    report: ImportReport,
+}
+
+impl<'a, T, E> Importer<'a, T, E>
+where
+    T: Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+    E: VectorEngine<T> + ?Sized,
+{
+    fn new(engine: &'a E) -> Self {
+        Self {
+            engine,
+            batch: Vec::with_capacity(IMPORT_BATCH),
+            report: ImportReport::default(),
+        }
+    }
+
+    fn push(&mut self, record: T) {
+        self.batch.push(record);
+        if self.batch.len() == IMPORT_BATCH {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.batch.is_empty() {
+            self.report.imported += self.batch.len() as u64;
+            self.engine.pushx(std::mem::take(&mut self.batch));
+        }
+    }
+
+    fn finish(mut self) -> ImportReport {
+        self.flush();
+        self.report
+    }
+}
+
+pub(crate) fn export_jsonl<T, E>(
+    engine: &E,
+    writer: impl Write,
+    range: Range<u64>,
+) -> io::Result<u64>
+where
+    T: Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+    E: VectorEngine<T> + ?Sized,
+{
+    let mut writer = BufWriter::new(writer);
+    let count = range.end.saturating_sub(range.start);
+    for_each_batch(engine, range, EXPORT_BATCH, |_, records| {
+        for record in records {
+            serde_json::to_writer(&mut writer, &record).map_err(io::Error::other)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(count)
+}
+
+pub(crate) fn import_jsonl<T, E>(
+    engine: &E,
+    reader: impl Read,
+) -> io::Result<ImportReport>
+where
+    T: Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+    E: VectorEngine<T> + ?Sized,
+{
+    let mut reader = BufReader::new(reader);
+    let mut importer = Importer::new(engine);
+    let mut line = Vec::new();
+    let mut number = 0;
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        number += 1;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        match serde_json::from_slice(&line) {
+            Ok(record) => importer.push(record),
+            Err(error) => importer.report.reject(number, error),
+        }
+    }
+    Ok(importer.finish())
+}
+
+/// Appends the leaves of `value` as (dotted column, value) pairs. Maps are
+/// flattened; arrays and other values are single cells.
+fn flatten(column: String, value: Value, cells: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let column = if column.is_empty() {
+                    key
+                } else {
+                    format!("{}.{}", column, key)
+                };
+                flatten(column, value, cells);
+            }
+        }
+        value if column.is_empty() => cells.push((VALUE_COLUMN.to_string(), value)),
+        value => cells.push((column, value)),
+    }
+}
+
+/// Text of a CSV cell. Strings are written as they are unless they would
+/// read back as another JSON value, in which case they are written as a
+/// JSON string literal; null is an empty cell; other values are JSON.
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text)
+            if !text.is_empty() && serde_json::from_str::<Value>(text).is_err() =>
+        {
+            text.clone()
+        }
+        value => value.to_string(),
+    }
+}
+
+/// Reverse of [`cell_text`].
+fn cell_value(text: &str) -> Value {
+    if text.is_empty() {
+        return Value::Null;
+    }
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+}
+
+/// Nests dotted columns back into maps. A map whose cells are all empty
+/// becomes null, so optional structs round-trip.
+fn unflatten(
+    header: &csv::StringRecord,
+    row: &csv::StringRecord,
+) -> Result<Value, String> {
+    fn insert(
+        map: &mut Map<String, Value>,
+        path: &[&str],
+        value: Value,
+    ) -> Result<(), ()> {
+        match path {
+            [key] => match map.insert(key.to_string(), value) {
+                None => Ok(()),
+                Some(_) => Err(()),
+            },
+            [key, rest @ ..] => match map
+                .entry(key.to_string())
+                .or_insert_with(|| Value::Object(Map::new()))
+            {
+                Value::Object(inner) => insert(inner, rest, value),
+                _ => Err(()),
+            },
+            [] => Err(()),
+        }
+    }
+    fn collapse(value: Value) -> Value {
+        match value {
+            Value::Object(map) => {
+                let map: Map<String, Value> = map
+                    .into_iter()
+                    .map(|(key, value)| (key, collapse(value)))
+                    .collect();
+                if map.values().all(Value::is_null) {
+                    Value::Null
+                } else {
+                    Value::Object(map)
+                }
+            }
+            value => value,
+        }
+    }
+
+    let mut record = Map::new();
+    for (column, text) in header.iter().zip(row.iter()) {
+        let path: Vec<&str> = column.split('.').collect();
+        insert(&mut record, &path, cell_value(text))
+            .map_err(|_| format!("column {} conflicts with another column", column))?;
+    }
+    Ok(Value::Object(
+        record
+            .into_iter()
+            .map(|(key, value)| (key, collapse(value)))
+            .collect(),
+    ))
+}
+
+/// Whether `column` is a field of the map at `parent`.
+fn is_within(column: &str, parent: &str) -> bool {
+    column.len() > parent.len()
+        && column.starts_with(parent)
+        && column.as_bytes()[parent.len()] == b'.'
+}
+
+/// Adds the column of a cell to `columns` unless it is there already. An
+/// absent optional struct is a single null cell, which gives way to the
+/// columns of its fields wherever a record has them; fields of one map are
+/// kept next to each other.
+fn add_column(columns: &mut Vec<String>, column: &str, value: &Value) {
+    if columns
+        .iter()
+        .any(|known| known == column || value.is_null() && is_within(known, column))
+    {
+        return;
+    }
+    if let Some(absent) = columns.iter().position(|known| is_within(column, known)) {
+        columns[absent] = column.to_string();
+        return;
+    }
+    let mut parent = column;
+    while let Some((prefix, _)) = parent.rsplit_once('.') {
+        if let Some(last) = columns.iter().rposition(|known| is_within(known, prefix)) {
+            columns.insert(last + 1, column.to_string());
+            return;
+        }
+        parent = prefix;
+    }
+    columns.push(column.to_string());
+}
+
+/// Writes the records in `range` as CSV. The columns are the union of
+/// those of the first batch of records, so a record missing an optional
+/// struct does not hide its columns.
+pub(crate) fn export_csv<T, E>(
+    engine: &E,
+    writer: impl Write,
+    range: Range<u64>,
+) -> io::Result<u64>
+where
+    T: Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+    E: VectorEngine<T> + ?Sized,
+{
+    let mut writer = csv::Writer::from_writer(writer);
+    let mut header: Option<Vec<String>> = None;
+    let count = range.end.saturating_sub(range.start);
+    for_each_batch(engine, range, EXPORT_BATCH, |first, records| {
+        let rows = records
+            .iter()
+            .map(|record| {
+                let mut cells = Vec::new();
+                flatten(
+                    String::new(),
+                    serde_json::to_value(record).map_err(io::Error::other)?,
+                    &mut cells,
+                );
+                Ok(cells)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let header = match &mut header {
+            Some(header) => header,
+            None => {
+                let mut columns = Vec::new();
+                for (column, value) in rows.iter().flatten() {
+                    add_column(&mut columns, column, value);
+                }
+                writer.write_record(&columns)?;
+                header.insert(columns)
+            }
+        };
+        for (offset, cells) in rows.into_iter().enumerate() {
+            let mut row = vec![String::new(); header.len()];
+            for (column, value) in cells {
+                match header.iter().position(|known| *known == column) {
+                    Some(position) => row[position] = cell_text(&value),
+                    // An optional struct that is absent here but present in
+                    // another record: its columns stay empty.
+                    None if value.is_null()
+                        && header.iter().any(|known| is_within(known, &column)) => {}
+                    None => {
+                        return Err(invalid(format!(
+                            "record {} has a field {} that no record of the first \
+                             exported batch has; export it as JSON Lines instead",
+                            first + offset as u64,
+                            column
+                        )))
+                    }
+                }
+            }
+            writer.write_record(&row)?;
+        }
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(count)
+}
+
+pub(crate) fn import_csv<T, E>(engine: &E, reader: impl Read) -> io::Result<ImportReport>
+where
+    T: Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+    E: VectorEngine<T> + ?Sized,
+{
+    let mut reader = csv::Reader::from_reader(reader);
+    let header = reader.headers().map_err(io::Error::other)?.clone();
+    let scalar = header.len() == 1 && &header[0] == VALUE_COLUMN;
+    let mut importer = Importer::new(engine);
+    let mut row = csv::StringRecord::new();
+    loop {
+        let line = reader.position().line();
+        match reader.read_record(&mut row) {
+            Ok(false) => break,
+            Ok(true) => {}
+            Err(error) if error.is_io_error() => return Err(io::Error::other(error)),
+            Err(error) => {
+                let line = error.position().map_or(line, |position| position.line());
+                importer.report.reject(line, error);
+                continue;
+            }
+        }
+        let line = row.position().map_or(line, |position| position.line());
+        let record = unflatten(&header, &row).and_then(|value| {
+            serde_json::from_value(value).or_else(|error| match scalar {
+                true => serde_json::from_value(cell_value(&row[0]))
+                    .map_err(|_| error.to_string()),
+                false => Err(error.to_string()),
+            })
+        });
+        match record {
+            Ok(record) => importer.push(record),
+            Err(message) => importer.report.reject(line, message),
+        }
+    }
+    Ok(importer.finish())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        services::dynamic_vector_manage_service::DynamicVectorManageService,
+        test_support::TestDir,
+        vector_engine::VectorEngine,
+    };
+    use serde::{
+        Deserialize,
+        Serialize,
+    };
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Author {
+        name: String,
+        karma: i64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Post {
+        id: u64,
+        title: String,
+        tags: Vec<String>,
+        author: Option<Author>,
+        score: f32,
+    }
+
+    fn post(i: u64) -> Post {
+        Post {
+            id: i,
+            // Titles that look like other JSON values must stay strings.
+            title: match i % 4 {
+                0 => format!("post, \"quoted\"\nnumber {}", i),
+                1 => i.to_string(),
+                2 => "true".to_string(),
+                _ => String::new(),
+            },
+            tags: (0..i % 3).map(|tag| format!("tag{}", tag)).collect(),
+            author: (i % 5 != 1).then(|| Author {
+                name: format!("author {}", i % 7),
+                karma: i as i64 - 50,
+            }),
+            score: i as f32 / 4.0,
+        }
+    }
+
+    fn engine(dir: &TestDir, name: &str) -> DynamicVectorManageService<Post> {
+        DynamicVectorManageService::new(
+            dir.path(&format!("{}_structure.bin", name)),
+            dir.path(&format!("{}_data.bin", name)),
+            1024,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_jsonl_and_csv_round_trip() {
+        let dir = TestDir::new("jsonl_and_csv_round_trip");
+        let source = engine(&dir, "source");
+        let posts: Vec<Post> = (0..2500).map(post).collect();
+        source.pushx(posts.clone());
+
+        let mut jsonl = Vec::new();
+        assert_eq!(source.export_jsonl(&mut jsonl, 100..2500).unwrap(), 2400);
+        let mut csv = Vec::new();
+        assert_eq!(source.export_csv(&mut csv, 0..2500).unwrap(), 2500);
+        assert!(source.export_jsonl(Vec::new(), 0..2501).is_err());
+
+        let from_jsonl = engine(&dir, "jsonl");
+        let report = from_jsonl.import_jsonl(jsonl.as_slice()).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.imported, 2400);
+        assert_eq!(from_jsonl.pullx(0, 2400), posts[100..]);
+
+        let from_csv = engine(&dir, "csv");
+        let report = from_csv.import_csv(csv.as_slice()).unwrap();
+        assert!(report.is_clean(), "{:?}", report.errors);
+        assert_eq!(from_csv.pullx(0, 2500), posts);
+    }
+
+    #[test]
+    fn test_csv_range_starting_at_absent_struct() {
+        let dir = TestDir::new("csv_range_starting_at_absent_struct");
+        let source = engine(&dir, "source");
+        let posts: Vec<Post> = (0..1100).map(post).collect();
+        source.pushx(posts.clone());
+
+        // post(1) has no author, yet its columns come from later records.
+        let mut csv = Vec::new();
+        assert_eq!(source.export_csv(&mut csv, 1..1100).unwrap(), 1099);
+        let header = String::from_utf8(csv.clone()).unwrap();
+        let mut columns: Vec<&str> = header.lines().next().unwrap().split(',').collect();
+        columns.sort();
+        assert_eq!(
+            columns,
+            [
+                "author.karma",
+                "author.name",
+                "id",
+                "score",
+                "tags",
+                "title"
+            ]
+        );
+
+        let from_csv = engine(&dir, "csv");
+        let report = from_csv.import_csv(csv.as_slice()).unwrap();
+        assert!(report.is_clean(), "{:?}", report.errors);
+        assert_eq!(from_csv.pullx(0, 1099), posts[1..]);
+    }
+
+    #[test]
+    fn test_import_reports_malformed_lines() {
+        let good = serde_json::to_string(&post(3)).unwrap();
+        let jsonl = format!("{}\n{{\"id\": 1\n\n{}\nnot json\n{}", good, good, good);
+        let dir = TestDir::new("import_reports_malformed_lines");
+        let target = engine(&dir, "malformed");
+        let report = target.import_jsonl(jsonl.as_bytes()).unwrap();
+        assert_eq!(report.imported, 3);
+        assert_eq!(report.rejected, 2);
+        let lines: Vec<u64> = report.errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, [2, 5]);
+        assert_eq!(target.len(), 3);
+
+        let csv = "id,title,tags,author.name,author.karma,score\n\
+                   1,a,[],ann,3,0.5\n\
+                   x,b,[],bob,4,1\n\
+                   3,\"multi\nline\",[],,,2\n\
+                   4,too,few\n";
+        let report = target.import_csv(csv.as_bytes()).unwrap();
+        assert_eq!(report.imported, 2);
+        let lines: Vec<u64> = report.errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, [3, 6]);
+        assert_eq!(target.pull(4).title, "multi\nline");
+        assert_eq!(target.pull(4).author, None);
+    }
+}
@@ -1,15 +1,88 @@
+#[cfg(feature = "arrow")]
+mod arrow_export;
 #[cfg(feature = "cache")]
 mod cache;
+mod indexed_engine;
+mod interchange;
+mod schema;
+mod search;
 mod vector_engine;
 
 mod services;
+#[cfg(test)]
+mod test_support;
+#[cfg(feature = "arrow")]
+pub use arrow_export::{
+    ArrowFormat,
+    ArrowOptions,
+};
 #[cfg(feature = "cache")]
 pub use cache::{
     ReadableCache,
     WritableCache,
 };
+pub use dynamic_vector::{
+    BytesDecodeError,
+    CapacityError,
+    CheckDynamicSize,
+    Describe,
+    DynamicSized,
+    DynamicVector,
+    FieldCodec,
+    FieldSchema,
+    FieldType,
+    FixedString,
+    FixedStringError,
+    FixedVec,
+    FromBytesVector,
+    HybridRecord,
+    MaxEncodedSize,
+    OverflowPolicy,
+    RecordSchema,
+    VariantSchema,
+    VectorCandidate,
+};
+pub use indexed_engine::{
+    IndexedEngine,
+    RecordIndex,
+};
+pub use interchange::{
+    ImportReport,
+    LineError,
+};
+pub use schema::{
+    decode_record,
+    decode_value,
+};
+pub use search::{
+    tokenize,
+    Bm25Index,
+    Embedder,
+    Fusion,
+    HashingEmbedder,
+    HybridHit,
+    HybridIndex,
+    IvfPqIndex,
+    IvfPqParams,
+    Metric,
+    Neighbor,
+    SearchFilter,
+    SparseIndex,
+    SparseVector,
+};
 pub use services::{
+    backup::*,
+    codec::*,
+    compression::*,
     dynamic_vector_manage_service::*,
+    encryption::*,
+    hybrid_vector_manage_service::*,
+    raw_collection::*,
+    repair::*,
+    replication::*,
+    shredding_vector_manage_service::*,
     static_vector_manage_service::*,
+    vector_column_service::*,
+    versioned_vector_manage_service::*,
 };
 pub use vector_engine::VectorEngine;
@@ -0,0 +1,361 @@
+use dynamic_vector::{
+    FieldSchema,
+    FieldType,
+    RecordSchema,
+};
+use serde_json::{
+    Map,
+    Value,
+};
+use std::io;
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn take(bytes: &[u8], len: usize) -> io::Result<&[u8]> {
+    bytes.get(..len).ok_or_else(|| {
+        invalid(format!(
+            "unexpected end of record: needed {} bytes, {} remaining",
+            len,
+            bytes.len()
+        ))
+    })
+}
+
+fn read_u32(bytes: &[u8]) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(take(bytes, 4)?.try_into().unwrap()))
+}
+
+fn read_u64(bytes: &[u8]) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(take(bytes, 8)?.try_into().unwrap()))
+}
+
+fn float(value: f64) -> Value {
+    serde_json::Number::from_f64(value)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+/// Decodes one bincode-encoded value described by `ty` from the start of
+/// `bytes`, returning it as JSON together with the number of bytes used.
+/// Structs, tuples and enums map to JSON the way `serde_json` would
+/// serialize the typed value; 128-bit integers that do not fit in 64 bits
+/// become strings.
+pub fn decode_value(ty: &FieldType, bytes: &[u8]) -> io::Result<(Value, usize)> {
+    let decoded = match ty {
+        FieldType::Bool => match take(bytes, 1)?[0] {
+            0 => (Value::Bool(false), 1),
+            1 => (Value::Bool(true), 1),
+            other => return Err(invalid(format!("invalid bool byte {}", other))),
+        },
+        FieldType::U8 => (Value::from(take(bytes, 1)?[0]), 1),
+        FieldType::U16 => (
+            Value::from(u16::from_le_bytes(take(bytes, 2)?.try_into().unwrap())),
+            2,
+        ),
+        FieldType::U32 => (Value::from(read_u32(bytes)?), 4),
+        FieldType::U64 => (Value::from(read_u64(bytes)?), 8),
+        FieldType::U128 => {
+            let value = u128::from_le_bytes(take(bytes, 16)?.try_into().unwrap());
+            match u64::try_from(value) {
+                Ok(value) => (Value::from(value), 16),
+                Err(_) => (Value::String(value.to_string()), 16),
+            }
+        }
+        FieldType::I8 => (Value::from(take(bytes, 1)?[0] as i8), 1),
+        FieldType::I16 => (
+            Value::from(i16::from_le_bytes(take(bytes, 2)?.try_into().unwrap())),
+            2,
+        ),
+        FieldType::I32 => (
+            Value::from(i32::from_le_bytes(take(bytes, 4)?.try_into().unwrap())),
+            4,
+        ),
+        FieldType::I64 => (
+            Value::from(i64::from_le_bytes(take(bytes, 8)?.try_into().unwrap())),
+            8,
+        ),
+        FieldType::I128 => {
+            let value = i128::from_le_bytes(take(bytes, 16)?.try_into().unwrap());
+            match i64::try_from(value) {
+                Ok(value) => (Value::from(value), 16),
+                Err(_) => (Value::String(value.to_string()), 16),
+            }
+        }
+        FieldType::F32 => (
+            float(f32::from_le_bytes(take(bytes, 4)?.try_into().unwrap()) as f64),
+            4,
+        ),
+        FieldType::F64 => (
+            float(f64::from_le_bytes(take(bytes, 8)?.try_into().unwrap())),
+            8,
+        ),
+        FieldType::Char => {
+            // bincode writes a char as its UTF-8 bytes without a length.
+            let width = match take(bytes, 1)?[0] {
+                0x00..=0x7f => 1,
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            let text = std::str::from_utf8(take(bytes, width)?)
+                .map_err(|e| invalid(e.to_string()))?;
+            (Value::String(text.to_string()), width)
+        }
+        FieldType::Unit => (Value::Null, 0),
+        FieldType::String | FieldType::DateTime => {
+            let len = read_u64(bytes)? as usize;
+            let text = std::str::from_utf8(take(&bytes[8..], len)?)
+                .map_err(|e| invalid(e.to_string()))?;
+            (Value::String(text.to_string()), 8 + len)
+        }
+        FieldType::Seq(item) => {
+            let count = read_u64(bytes)?;
+            let (items, used) = decode_sequence(item, count as usize, &bytes[8..])?;
+            (Value::Array(items), 8 + used)
+        }
+        FieldType::Array(item, count) => {
+            let (items, used) = decode_sequence(item, *count, bytes)?;
+            (Value::Array(items), used)
+        }
+        FieldType::Option(inner) => match take(bytes, 1)?[0] {
+            0 => (Value::Null, 1),
+            1 => {
+                let (value, used) = decode_value(inner, &bytes[1..])?;
+                (value, 1 + used)
+            }
+            other => return Err(invalid(format!("invalid Option tag {}", other))),
+        },
+        FieldType::Tuple(types) => {
+            let mut items = Vec::with_capacity(types.len());
+            let mut offset = 0;
+            for ty in types {
+                let (value, used) = decode_value(ty, &bytes[offset..])?;
+                items.push(value);
+                offset += used;
+            }
+            (Value::Array(items), offset)
+        }
+        FieldType::Duration => {
+            let secs = read_u64(bytes)?;
+            let nanos = read_u32(&bytes[8..])?;
+            let mut map = Map::new();
+            map.insert("secs".to_string(), Value::from(secs));
+            map.insert("nanos".to_string(), Value::from(nanos));
+            (Value::Object(map), 12)
+        }
+        FieldType::Timestamp => {
+            let secs = read_u64(bytes)? as i64;
+            let nanos = read_u32(&bytes[8..])?;
+            let mut map = Map::new();
+            map.insert("secs_since_epoch".to_string(), Value::from(secs));
+            map.insert("nanos_since_epoch".to_string(), Value::from(nanos));
+            (Value::Object(map), 12)
+        }
+        FieldType::FixedString(capacity) => {
+            let raw = take(bytes, *capacity)?;
+            let len = raw.iter().position(|b| *b == 0).unwrap_or(*capacity);
+            let text =
+                std::str::from_utf8(&raw[..len]).map_err(|e| invalid(e.to_string()))?;
+            (Value::String(text.to_string()), *capacity)
+        }
+        FieldType::FixedVec(item, capacity) => {
+            let len = read_u32(bytes)? as usize;
+            if len > *capacity {
+                return Err(invalid(format!(
+                    "FixedVec length {} exceeds capacity {}",
+                    len, capacity
+                )));
+            }
+            let (mut items, used) = decode_sequence(item, *capacity, &bytes[4..])?;
+            items.truncate(len);
+            (Value::Array(items), 4 + used)
+        }
+        FieldType::Struct(schema) => decode_fields(&schema.fields, bytes)?,
+        FieldType::Enum { name, variants } => {
+            let index = read_u32(bytes)? as usize;
+            let variant = variants.get(index).ok_or_else(|| {
+                invalid(format!("{} has no variant with index {}", name, index))
+            })?;
+            if variant.fields.is_empty() {
+                (Value::String(variant.name.clone()), 4)
+            } else {
+                let (value, used) = decode_fields(&variant.fields, &bytes[4..])?;
+                let mut map = Map::new();
+                map.insert(variant.name.clone(), value);
+                (Value::Object(map), 4 + used)
+            }
+        }
+    };
+    Ok(decoded)
+}
+
+fn decode_sequence(
+    item: &FieldType,
+    count: usize,
+    bytes: &[u8],
+) -> io::Result<(Vec<Value>, usize)> {
+    // The count comes from the file, so it only bounds the loop.
+    let mut items = Vec::with_capacity(count.min(bytes.len()));
+    let mut offset = 0;
+    for _ in 0..count {
+        let (value, used) = decode_value(item, &bytes[offset..])?;
+        items.push(value);
+        offset += used;
+    }
+    Ok((items, offset))
+}
+
+/// Tuple structs (fields named "0", "1", ...) become arrays, or the single
+/// value for a newtype; named fields become objects.
+fn fields_value(fields: &[FieldSchema], values: Vec<Value>) -> Value {
+    let is_tuple = fields
+        .iter()
+        .enumerate()
+        .all(|(i, field)| field.name == i.to_string());
+    if fields.is_empty() || !is_tuple {
+        let map = fields
+            .iter()
+            .map(|field| field.name.clone())
+            .zip(values)
+            .collect();
+        Value::Object(map)
+    } else if fields.len() == 1 {
+        values.into_iter().next().unwrap()
+    } else {
+        Value::Array(values)
+    }
+}
+
+fn decode_fields(fields: &[FieldSchema], bytes: &[u8]) -> io::Result<(Value, usize)> {
+    let mut values = Vec::with_capacity(fields.len());
+    let mut offset = 0;
+    for field in fields {
+        let (value, used) = decode_value(&field.ty, &bytes[offset..])?;
+        values.push(value);
+        offset += used;
+    }
+    Ok((fields_value(fields, values), offset))
+}
+
+/// Decodes a record stored in hybrid layout: the fixed fields padded to
+/// their `encoded_size` in `fixed`, and one bincode buffer per dynamic
+/// field in declaration order.
+pub fn decode_record(
+    schema: &RecordSchema,
+    fixed: &[u8],
+    dynamic: &[Vec<u8>],
+) -> io::Result<Value> {
+    let mut values = Vec::with_capacity(schema.fields.len());
+    let mut fixed_offset = 0;
+    let mut dynamic = dynamic.iter();
+    for field in &schema.fields {
+        let value = if field.dynamic {
+            let bytes = dynamic.next().ok_or_else(|| {
+                invalid(format!("missing dynamic field {}", field.name))
+            })?;
+            decode_value(&field.ty, bytes)?.0
+        } else {
+            let slot = fixed
+                .get(fixed_offset..fixed_offset + field.encoded_size)
+                .ok_or_else(|| {
+                    invalid(format!("fixed field {} out of slot", field.name))
+                })?;
+            fixed_offset += field.encoded_size;
+            decode_value(&field.ty, slot)?.0
+        };
+        values.push(value);
+    }
+    Ok(fields_value(&schema.fields, values))
+}
+
+#[cfg(test)]
+mod test {
+    use dynamic_vector::{
+        CheckDynamicSize,
+        Describe,
+        HybridRecord,
+        VectorCandidate,
+    };
+    use serde::{
+        Deserialize,
+        Serialize,
+    };
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, CheckDynamicSize)]
+    pub struct Pair(u8, i16);
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, CheckDynamicSize)]
+    pub enum Event {
+        Ping,
+        Move(f32, f32),
+        Say { text: String },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, CheckDynamicSize)]
+    pub struct Entry {
+        id: u64,
+        tags: Vec<String>,
+        pair: Pair,
+        score: Option<f64>,
+        grid: [u16; 2],
+        letter: char,
+        events: Vec<Event>,
+    }
+
+    fn entry() -> Entry {
+        Entry {
+            id: 7,
+            tags: vec!["a".to_string(), "bc".to_string()],
+            pair: Pair(1, -2),
+            score: Some(0.5),
+            grid: [3, 4],
+            letter: 'ß',
+            events: vec![
+                Event::Ping,
+                Event::Move(1.0, -1.0),
+                Event::Say {
+                    text: "hi".to_string(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_decode_value_matches_serde_json() {
+        let entry = entry();
+        let bytes = bincode::serialize(&entry).unwrap();
+        let (value, used) = decode_value(&Entry::describe(), &bytes).unwrap();
+        assert_eq!(used, bytes.len());
+        assert_eq!(value, serde_json::to_value(&entry).unwrap());
+    }
+
+    #[test]
+    fn test_decode_record_from_hybrid_parts() {
+        let entry = entry();
+        let schema = Entry::schema();
+        assert_eq!(schema.fields.len(), 7);
+        assert!(schema.fields[1].dynamic);
+        assert_eq!(schema.fields[3].encoded_size, 9);
+
+        let (fixed, dynamic) = entry.split_fields();
+        let value = decode_record(&schema, &fixed, &dynamic).unwrap();
+        assert_eq!(value, serde_json::to_value(&entry).unwrap());
+        assert!(decode_record(&schema, &fixed[..4], &dynamic).is_err());
+    }
+
+    #[test]
+    fn test_decode_timestamp_before_epoch() {
+        let before = std::time::UNIX_EPOCH - std::time::Duration::new(1, 250_000_000);
+        let bytes = before.to_bytes_vector();
+        let (value, used) = decode_value(&FieldType::Timestamp, &bytes).unwrap();
+        assert_eq!(used, 12);
+        assert_eq!(
+            value,
+            serde_json::json!({ "secs_since_epoch": -2, "nanos_since_epoch": 750_000_000 })
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{
+        self,
+        BufReader,
+        BufWriter,
+        Write,
+    },
+};
+
+use crate::search::{
+    distance::{
+        Neighbor,
+        TopK,
+    },
+    embedder::tokenize,
+};
+
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+/// In-memory inverted index scored with Okapi BM25. Documents are
+/// identified by their record index and must be added in order.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct Bm25Index {
+    postings: HashMap<String, Vec<(u64, u32)>>,
+    document_lengths: Vec<u32>,
+    total_length: u64,
+}
+
+impl Bm25Index {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of documents indexed so far.
+    pub fn len(&self) -> u64 {
+        self.document_lengths.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.document_lengths.is_empty()
+    }
+
+    pub fn add(&mut self, index: u64, text: &str) {
+        assert_eq!(index, self.len(), "documents must be added in record order");
+        let tokens = tokenize(text);
+        let mut frequencies: HashMap<String, u32> = HashMap::new();
+        for token in &tokens {
+            *frequencies.entry(token.clone()).or_default() += 1;
+        }
+        for (term, frequency) in frequencies {
+            self.postings
+                .entry(term)
+                .or_default()
+                .push((index, frequency));
+        }
+        self.document_lengths.push(tokens.len() as u32);
+        self.total_length += tokens.len() as u64;
+    }
+
+    /// Best `k` documents for `query`. Hits are returned as neighbors whose
+    /// distance is the negated BM25 score, so smaller is better as for the
+    /// vector indexes.
+    pub fn search(&self, query: &str, k: usize) -> Vec<Neighbor> {
+        let document_count = self.len() as f32;
+        if document_count == 0.0 {
+            return Vec::new();
+        }
+        let average_length = self.total_length as f32 / document_count;
+
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+
+        let mut scores: HashMap<u64, f32> = HashMap::new();
+        for term in terms {
+            let Some(postings) = self.postings.get(&term) else {
+                continue;
+            };
+            let document_frequency = postings.len() as f32;
+            let idf = (1.0
+                + (document_count - document_frequency + 0.5)
+                    / (document_frequency + 0.5))
+                .ln();
+            for (index, frequency) in postings {
+                let frequency = *frequency as f32;
+                let length = self.document_lengths[*index as usize] as f32;
+                let normalization = K1 * (1.0 - B + B * length / average_length);
+                *scores.entry(*index).or_default() +=
+                    idf * frequency * (K1 + 1.0) / (frequency + normalization);
+            }
+        }
+
+        let mut top = TopK::new(k);
+        for (index, score) in scores {
+            top.push(index, -score);
+        }
+        top.into_sorted_vec()
+    }
+
+    pub fn save(&self, path: String) -> io::Result<()> {
+        let temp_path = format!("{}.tmp", path);
+        {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            bincode::serialize_into(&mut writer, self).map_err(io::Error::other)?;
+            writer.flush()?;
+        }
+        std::fs::rename(temp_path, path)
+    }
+
+    pub fn open(path: String) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        bincode::deserialize_from(reader)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bm25_ranking() {
+        let mut index = Bm25Index::new();
+        index.add(0, "the cat sat on the mat");
+        index.add(1, "dogs and cats");
+        index.add(2, "a cat and another cat chasing the cat");
+        index.add(3, "nothing relevant here");
+
+        let result = index.search("cat", 10);
+        let ranked: Vec<u64> = result.iter().map(|n| n.index).collect();
+        assert_eq!(ranked, vec![2, 0]);
+        assert!(result[0].distance < result[1].distance);
+        assert!(index.search("unknown", 10).is_empty());
+    }
+}
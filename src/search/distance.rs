@@ -0,0 +1,161 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+};
+
+/// Distance function used to compare embeddings. Smaller is always closer:
+/// inner product and cosine similarity are negated so every index can rank
+/// candidates the same way.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    L2,
+    InnerProduct,
+    Cosine,
+}
+
+impl Metric {
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::L2 => l2_squared(a, b),
+            Metric::InnerProduct => -dot(a, b),
+            Metric::Cosine => {
+                let norm = (dot(a, a) * dot(b, b)).sqrt();
+                if norm == 0.0 {
+                    1.0
+                } else {
+                    1.0 - dot(a, b) / norm
+                }
+            }
+        }
+    }
+}
+
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    assert_eq!(a.len(), b.len(), "vector dimensions do not match");
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+pub fn l2_squared(a: &[f32], b: &[f32]) -> f32 {
+    assert_eq!(a.len(), b.len(), "vector dimensions do not match");
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| {
+            let diff = x - y;
+            diff * diff
+        })
+        .sum()
+}
+
+pub fn normalize(vector: &mut [f32]) {
+    let norm = dot(vector, vector).sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// A search hit: the record index in the underlying engine and its distance
+/// to the query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbor {
+    pub index: u64,
+    pub distance: f32,
+}
+
+impl Eq for Neighbor {}
+
+impl Ord for Neighbor {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then_with(|| self.index.cmp(&other.index))
+    }
+}
+
+impl PartialOrd for Neighbor {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Keeps the `k` closest neighbors seen so far.
+pub(crate) struct TopK {
+    k: usize,
+    heap: BinaryHeap<Neighbor>,
+}
+
+impl TopK {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            heap: BinaryHeap::with_capacity(k + 1),
+        }
+    }
+
+    pub fn push(&mut self, index: u64, distance: f32) {
+        if self.k == 0 {
+            return;
+        }
+        let neighbor = Neighbor { index, distance };
+        if self.heap.len() < self.k {
+            self.heap.push(neighbor);
+        } else if self.heap.peek().is_some_and(|worst| neighbor < *worst) {
+            // Ties on distance are broken by index so results are stable
+            // regardless of scan order.
+            self.heap.pop();
+            self.heap.push(neighbor);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Distance of the k-th neighbor once `k` neighbors have been collected.
+    pub fn threshold(&self) -> Option<f32> {
+        if self.heap.len() < self.k {
+            None
+        } else {
+            self.heap.peek().map(|n| n.distance)
+        }
+    }
+
+    pub fn merge(mut self, other: TopK) -> Self {
+        for neighbor in other.heap {
+            self.push(neighbor.index, neighbor.distance);
+        }
+        self
+    }
+
+    pub fn into_sorted_vec(self) -> Vec<Neighbor> {
+        self.heap.into_sorted_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let a = [1.0, 0.0];
+        let b = [0.0, 2.0];
+        assert_eq!(Metric::L2.distance(&a, &b), 5.0);
+        assert_eq!(Metric::InnerProduct.distance(&a, &[3.0, 1.0]), -3.0);
+        assert!((Metric::Cosine.distance(&a, &b) - 1.0).abs() < 1e-6);
+        assert!(Metric::Cosine.distance(&b, &[0.0, 5.0]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_top_k() {
+        let mut top = TopK::new(3);
+        for (i, d) in [5.0, 1.0, 4.0, 2.0, 3.0].iter().enumerate() {
+            top.push(i as u64, *d);
+        }
+        let result: Vec<u64> = top.into_sorted_vec().iter().map(|n| n.index).collect();
+        assert_eq!(result, vec![1, 3, 4]);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::search::distance::normalize;
+
+/// Turns records and free-text queries into dense vectors of a fixed
+/// dimension. Records and queries must land in the same space.
+pub trait Embedder<T>: Send + Sync {
+    fn dimension(&self) -> usize;
+    fn embed_record(&self, record: &T) -> Vec<f32>;
+    fn embed_query(&self, query: &str) -> Vec<f32>;
+}
+
+/// Deterministic bag-of-words embedder based on feature hashing. It needs
+/// no model and produces the same vectors on every platform, which makes it
+/// a stand-in for tests and for deployments without an embedding service.
+pub struct HashingEmbedder<T> {
+    dimension: usize,
+    text: Box<dyn Fn(&T) -> String + Send + Sync>,
+}
+
+impl<T> HashingEmbedder<T> {
+    /// `text` extracts the searchable text of a record.
+    pub fn new<F>(dimension: usize, text: F) -> Self
+    where
+        F: Fn(&T) -> String + Send + Sync + 'static,
+    {
+        assert!(dimension > 0, "dimension must be positive");
+        Self {
+            dimension,
+            text: Box::new(text),
+        }
+    }
+
+    fn embed_text(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0f32; self.dimension];
+        for token in tokenize(text) {
+            let hash = fnv1a(token.as_bytes());
+            let bucket = (hash % self.dimension as u64) as usize;
+            // The top bit picks the sign so colliding tokens tend to cancel
+            // out instead of piling up.
+            vector[bucket] += if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        }
+        normalize(&mut vector);
+        vector
+    }
+}
+
+impl<T> Embedder<T> for HashingEmbedder<T> {
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn embed_record(&self, record: &T) -> Vec<f32> {
+        self.embed_text(&(self.text)(record))
+    }
+
+    fn embed_query(&self, query: &str) -> Vec<f32> {
+        self.embed_text(query)
+    }
+}
+
+/// Lower-cased alphanumeric words. CJK ideographs are emitted one per token
+/// since those scripts do not separate words with spaces.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            tokens.push(c.to_string());
+        } else if c.is_alphanumeric() {
+            current.extend(c.to_lowercase());
+        } else if !current.is_empty() {
+            tokens.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{ac00}'..='\u{d7af}'
+        | '\u{f900}'..='\u{faff}')
+}
+
+/// 64-bit FNV-1a; stable across Rust releases, unlike `DefaultHasher`, so
+/// stored embeddings stay valid.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::search::distance::dot;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("Hello, World! 这是第1条消息"),
+            vec!["hello", "world", "这", "是", "第", "1", "条", "消", "息"]
+        );
+    }
+
+    #[test]
+    fn test_hashing_embedder_is_deterministic() {
+        let embedder = HashingEmbedder::new(64, |text: &String| text.clone());
+        let a = embedder.embed_record(&"the quick brown fox".to_string());
+        let b = embedder.embed_query("The QUICK brown fox");
+        assert_eq!(a, b);
+        assert!((dot(&a, &a) - 1.0).abs() < 1e-6);
+
+        let related = embedder.embed_query("quick fox");
+        let unrelated = embedder.embed_query("database index");
+        assert!(dot(&a, &related) > dot(&a, &unrelated));
+    }
+}
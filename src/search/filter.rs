@@ -0,0 +1,121 @@
+use std::ops::Range;
+
+use crate::search::kmeans::SplitMix64;
+
+/// Below this estimated fraction of matching records a filtered search
+/// visits the matching records directly instead of filtering while it scans.
+const PRE_FILTER_SELECTIVITY: f64 = 0.01;
+/// Records probed when estimating the selectivity of a predicate.
+const SELECTIVITY_SAMPLE: usize = 1024;
+
+/// Restricts a vector search to a subset of record indices.
+pub enum SearchFilter<'a> {
+    All,
+    /// Records `start..end`. Since records are append-only, time ranges
+    /// usually map to a contiguous index range.
+    Range(Range<u64>),
+    /// Pre-computed candidate set, e.g. from a secondary index. Build it with
+    /// `SearchFilter::candidates` so it is sorted and free of duplicates.
+    Candidates(Vec<u64>),
+    /// Arbitrary metadata predicate on the record index.
+    Predicate(Box<dyn Fn(u64) -> bool + Send + Sync + 'a>),
+}
+
+impl<'a> SearchFilter<'a> {
+    pub fn candidates(mut indices: Vec<u64>) -> Self {
+        indices.sort_unstable();
+        indices.dedup();
+        SearchFilter::Candidates(indices)
+    }
+
+    pub fn predicate<F>(predicate: F) -> Self
+    where
+        F: Fn(u64) -> bool + Send + Sync + 'a,
+    {
+        SearchFilter::Predicate(Box::new(predicate))
+    }
+
+    pub fn matches(&self, index: u64) -> bool {
+        match self {
+            SearchFilter::All => true,
+            SearchFilter::Range(range) => range.contains(&index),
+            SearchFilter::Candidates(indices) => indices.binary_search(&index).is_ok(),
+            SearchFilter::Predicate(predicate) => predicate(index),
+        }
+    }
+
+    /// Estimated fraction of `0..population` accepted by the filter.
+    /// Predicates are estimated from a fixed random sample.
+    pub fn selectivity(&self, population: u64) -> f64 {
+        if population == 0 {
+            return 0.0;
+        }
+        match self {
+            SearchFilter::All => 1.0,
+            SearchFilter::Range(_) | SearchFilter::Candidates(_) => {
+                self.count_hint(population) as f64 / population as f64
+            }
+            SearchFilter::Predicate(predicate) => {
+                let sample =
+                    SplitMix64::new(population).sample(population, SELECTIVITY_SAMPLE);
+                let accepted = sample.iter().filter(|index| predicate(**index)).count();
+                accepted as f64 / sample.len() as f64
+            }
+        }
+    }
+
+    fn count_hint(&self, population: u64) -> u64 {
+        match self {
+            SearchFilter::Range(range) => {
+                range.end.min(population).saturating_sub(range.start)
+            }
+            SearchFilter::Candidates(indices) => {
+                indices.partition_point(|index| *index < population) as u64
+            }
+            _ => population,
+        }
+    }
+
+    /// Whether visiting the matching records directly is cheaper than
+    /// filtering during a scan.
+    pub(crate) fn prefers_pre_filter(&self, population: u64) -> bool {
+        !matches!(self, SearchFilter::All)
+            && self.selectivity(population) < PRE_FILTER_SELECTIVITY
+    }
+
+    /// Every accepted index below `population`, in ascending order.
+    pub(crate) fn matching(&self, population: u64) -> Vec<u64> {
+        match self {
+            SearchFilter::Range(range) => {
+                (range.start..range.end.min(population)).collect()
+            }
+            SearchFilter::Candidates(indices) => {
+                indices[..self.count_hint(population) as usize].to_vec()
+            }
+            _ => (0..population)
+                .filter(|index| self.matches(*index))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_selectivity() {
+        assert_eq!(SearchFilter::All.selectivity(100), 1.0);
+        assert_eq!(SearchFilter::Range(90..200).selectivity(100), 0.1);
+        let candidates = SearchFilter::candidates(vec![5, 3, 3, 500]);
+        assert_eq!(candidates.selectivity(100), 0.02);
+        assert_eq!(candidates.matching(100), vec![3, 5]);
+        assert!(candidates.prefers_pre_filter(1000));
+
+        let even = SearchFilter::predicate(|index| index % 2 == 0);
+        let estimate = even.selectivity(100_000);
+        assert!((estimate - 0.5).abs() < 0.1, "{}", estimate);
+        assert!(!even.prefers_pre_filter(100_000));
+        assert_eq!(even.matching(5), vec![0, 2, 4]);
+    }
+}
@@ -0,0 +1,371 @@
+use rayon::prelude::*;
+use std::{
+    collections::HashMap,
+    io,
+    sync::Mutex,
+};
+
+use crate::{
+    indexed_engine::RecordIndex,
+    search::{
+        bm25::Bm25Index,
+        distance::{
+            Metric,
+            Neighbor,
+        },
+        embedder::Embedder,
+    },
+    services::vector_column_service::{
+        VectorColumnService,
+        VectorEncoding,
+    },
+};
+
+/// Each ranking contributes this many candidates per requested hit before
+/// fusion.
+const CANDIDATE_FACTOR: usize = 4;
+const MIN_CANDIDATES: usize = 32;
+
+/// How the lexical and the vector rankings are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fusion {
+    /// Reciprocal rank fusion: each ranking adds `1 / (k + rank)`.
+    ReciprocalRank { k: f32 },
+    /// Weighted sum of the min-max normalized scores of both rankings.
+    Weighted { lexical: f32, vector: f32 },
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Fusion::ReciprocalRank { k: 60.0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HybridHit {
+    pub index: u64,
+    /// Fused score, larger is better.
+    pub score: f32,
+    /// Zero-based position in the BM25 ranking, if the record was retrieved.
+    pub lexical_rank: Option<usize>,
+    /// Zero-based position in the embedding ranking, if retrieved.
+    pub vector_rank: Option<usize>,
+}
+
+impl HybridHit {
+    fn unranked(index: u64) -> Self {
+        Self {
+            index,
+            score: 0.0,
+            lexical_rank: None,
+            vector_rank: None,
+        }
+    }
+}
+
+/// BM25 plus embedding index over the records of an engine. Attach it to
+/// an `IndexedEngine` so records are tokenized and embedded on append.
+///
+/// Files: `<path>.vectors` holds the embeddings (one fixed-width slot per
+/// record) and `<path>.bm25` the inverted index, written on `save` or
+/// `flush`. Dropping the index saves it on a best-effort basis; call `save`
+/// to see errors.
+pub struct HybridIndex<T, E>
+where
+    E: Embedder<T>,
+{
+    embedder: E,
+    text: Box<dyn Fn(&T) -> String + Send + Sync>,
+    vectors: VectorColumnService,
+    lexical: Mutex<Bm25Index>,
+    lexical_path: String,
+}
+
+impl<T, E> HybridIndex<T, E>
+where
+    T: Sync,
+    E: Embedder<T>,
+{
+    /// `text` extracts the searchable text of a record for BM25.
+    pub fn open<F>(path: String, embedder: E, text: F) -> io::Result<Self>
+    where
+        F: Fn(&T) -> String + Send + Sync + 'static,
+    {
+        let vectors = VectorColumnService::new(
+            format!("{}.vectors", path),
+            embedder.dimension(),
+            VectorEncoding::F32,
+            1024,
+        )?;
+        let lexical_path = format!("{}.bm25", path);
+        let lexical = if std::path::Path::new(&lexical_path).exists() {
+            Bm25Index::open(lexical_path.clone())?
+        } else {
+            Bm25Index::new()
+        };
+        Ok(Self {
+            embedder,
+            text: Box::new(text),
+            vectors,
+            lexical: Mutex::new(lexical),
+            lexical_path,
+        })
+    }
+
+    /// Writes the BM25 index to `<path>.bm25`. Embeddings are written as
+    /// records are appended.
+    pub fn save(&self) -> io::Result<()> {
+        self.lexical
+            .lock()
+            .map_err(|_| io::Error::other("lexical index lock poisoned"))?
+            .save(self.lexical_path.clone())
+    }
+
+    pub fn lexical_search(&self, query: &str, k: usize) -> Vec<Neighbor> {
+        self.lexical.lock().unwrap().search(query, k)
+    }
+
+    /// Nearest records to the embedded query by cosine distance.
+    pub fn vector_search(&self, query: &str, k: usize) -> Vec<Neighbor> {
+        self.vectors
+            .knn(&self.embedder.embed_query(query), k, Metric::Cosine)
+    }
+
+    /// Best `k` records for `query` according to both rankings.
+    pub fn search(&self, query: &str, k: usize, fusion: Fusion) -> Vec<HybridHit> {
+        let candidates = (k * CANDIDATE_FACTOR).max(MIN_CANDIDATES);
+        let (lexical, vector) = rayon::join(
+            || self.lexical_search(query, candidates),
+            || self.vector_search(query, candidates),
+        );
+
+        let mut hits: HashMap<u64, HybridHit> = HashMap::new();
+        // Both rankings come back as distances; turn them into scores.
+        let lexical_scores = normalized_scores(&lexical, fusion, |d| -d);
+        let vector_scores = normalized_scores(&vector, fusion, |d| 1.0 - d);
+        for (rank, (neighbor, score)) in lexical.iter().zip(lexical_scores).enumerate() {
+            let entry = hits
+                .entry(neighbor.index)
+                .or_insert_with(|| HybridHit::unranked(neighbor.index));
+            entry.lexical_rank = Some(rank);
+            entry.score += match fusion {
+                Fusion::ReciprocalRank { k } => 1.0 / (k + rank as f32 + 1.0),
+                Fusion::Weighted { lexical, .. } => lexical * score,
+            };
+        }
+        for (rank, (neighbor, score)) in vector.iter().zip(vector_scores).enumerate() {
+            let entry = hits
+                .entry(neighbor.index)
+                .or_insert_with(|| HybridHit::unranked(neighbor.index));
+            entry.vector_rank = Some(rank);
+            entry.score += match fusion {
+                Fusion::ReciprocalRank { k } => 1.0 / (k + rank as f32 + 1.0),
+                Fusion::Weighted { vector, .. } => vector * score,
+            };
+        }
+
+        let mut hits: Vec<HybridHit> = hits.into_values().collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.index.cmp(&b.index)));
+        hits.truncate(k);
+        hits
+    }
+}
+
+/// Min-max normalized scores for weighted fusion; unused by rank fusion.
+fn normalized_scores<F>(neighbors: &[Neighbor], fusion: Fusion, score: F) -> Vec<f32>
+where
+    F: Fn(f32) -> f32,
+{
+    if let Fusion::ReciprocalRank { .. } = fusion {
+        return vec![0.0; neighbors.len()];
+    }
+    let scores: Vec<f32> = neighbors.iter().map(|n| score(n.distance)).collect();
+    let min = scores.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    scores
+        .into_iter()
+        .map(|s| {
+            if max > min {
+                (s - min) / (max - min)
+            } else {
+                1.0
+            }
+        })
+        .collect()
+}
+
+impl<T, E> RecordIndex<T> for HybridIndex<T, E>
+where
+    T: Send + Sync,
+    E: Embedder<T>,
+{
+    fn indexed_len(&self) -> u64 {
+        self.vectors
+            .get_length()
+            .min(self.lexical.lock().unwrap().len())
+    }
+
+    fn append(&self, first_index: u64, records: &[T]) {
+        let vectors: Vec<Vec<f32>> = records
+            .par_iter()
+            .map(|record| self.embedder.embed_record(record))
+            .collect();
+        self.vectors.write_bulk(first_index, &vectors);
+
+        let mut lexical = self.lexical.lock().unwrap();
+        for (offset, record) in records.iter().enumerate() {
+            let index = first_index + offset as u64;
+            if index >= lexical.len() {
+                lexical.add(index, &(self.text)(record));
+            }
+        }
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.save()
+    }
+}
+
+impl<T, E> Drop for HybridIndex<T, E>
+where
+    E: Embedder<T>,
+{
+    fn drop(&mut self) {
+        if let Ok(lexical) = self.lexical.get_mut() {
+            let _ = lexical.save(self.lexical_path.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{
+        Deserialize,
+        Serialize,
+    };
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        indexed_engine::IndexedEngine,
+        search::embedder::HashingEmbedder,
+        services::dynamic_vector_manage_service::DynamicVectorManageService,
+        test_support::TestDir,
+        vector_engine::VectorEngine,
+    };
+
+    #[derive(Serialize, Deserialize, Default, Debug, Clone)]
+    pub struct ChatMessage {
+        sender: String,
+        content: String,
+    }
+
+    /// Structure, data and index base paths under `dir`.
+    fn paths(dir: &TestDir) -> (String, String, String) {
+        (
+            dir.path("test.index"),
+            dir.path("test.data"),
+            dir.path("test"),
+        )
+    }
+
+    fn open_index(
+        path: &str,
+    ) -> Arc<HybridIndex<ChatMessage, HashingEmbedder<ChatMessage>>> {
+        let embedder = HashingEmbedder::new(64, |m: &ChatMessage| m.content.clone());
+        Arc::new(
+            HybridIndex::open(path.to_string(), embedder, |m: &ChatMessage| {
+                m.content.clone()
+            })
+            .unwrap(),
+        )
+    }
+
+    fn message(content: &str) -> ChatMessage {
+        ChatMessage {
+            sender: "alice".to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_hybrid_search_indexes_on_append() {
+        let dir = TestDir::new("hybrid_search_indexes_on_append");
+        let (structure, data, path) = paths(&dir);
+        let index = open_index(&path);
+        let mut engine: IndexedEngine<
+            DynamicVectorManageService<ChatMessage>,
+            ChatMessage,
+        > = VectorEngine::new(structure, data, 1024);
+        engine.attach_index(index.clone());
+
+        engine.pushx(vec![
+            message("the deployment failed with a timeout"),
+            message("lunch at noon?"),
+            message("deployment succeeded after retry"),
+            message("timeout again on the build server"),
+        ]);
+        engine.push(message("see you tomorrow"));
+        assert_eq!(index.indexed_len(), 5);
+
+        let hits = index.search("deployment timeout", 2, Fusion::default());
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].index, 0);
+        assert!(hits[0].lexical_rank.is_some() && hits[0].vector_rank.is_some());
+
+        let weighted = Fusion::Weighted {
+            lexical: 1.0,
+            vector: 0.0,
+        };
+        let hits = index.search("lunch", 1, weighted);
+        assert_eq!(hits[0].index, 1);
+    }
+
+    #[test]
+    fn test_hybrid_index_catches_up_after_reopen() {
+        let dir = TestDir::new("hybrid_index_catches_up_after_reopen");
+        let (structure, data, path) = paths(&dir);
+        {
+            let mut engine: IndexedEngine<
+                DynamicVectorManageService<ChatMessage>,
+                ChatMessage,
+            > = VectorEngine::new(structure.clone(), data.clone(), 1024);
+            engine.attach_index(open_index(&path));
+            engine.pushx(vec![message("first message"), message("second message")]);
+        }
+        {
+            // Appended while no index was attached.
+            let engine = DynamicVectorManageService::<ChatMessage>::new(
+                structure.clone(),
+                data.clone(),
+                1024,
+            )
+            .unwrap();
+            engine.save(message("third message about rust"));
+        }
+
+        let index = open_index(&path);
+        assert_eq!(index.indexed_len(), 2);
+        let mut engine: IndexedEngine<
+            DynamicVectorManageService<ChatMessage>,
+            ChatMessage,
+        > = VectorEngine::new(structure, data, 1024);
+        engine.attach_index(index.clone());
+        assert_eq!(index.indexed_len(), 3);
+        assert_eq!(index.search("rust", 1, Fusion::default())[0].index, 2);
+    }
+
+    #[test]
+    fn test_save_reports_errors() {
+        let dir = TestDir::new("hybrid_save_reports_errors");
+        let (_, _, path) = paths(&dir);
+        let index = open_index(&path);
+        index.append(0, &[message("kept in memory")]);
+        std::fs::create_dir(format!("{}.bm25.tmp", path)).unwrap();
+        assert!(index.save().is_err());
+        assert!(index.flush().is_err());
+        // Dropping still succeeds, without the index file.
+        drop(index);
+        assert!(!std::path::Path::new(&format!("{}.bm25", path)).exists());
+    }
+}
@@ -0,0 +1,841 @@
+use rayon::prelude::*;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    fs::File,
+    io::{
+        self,
+        BufReader,
+        BufWriter,
+        Write,
+    },
+};
+
+use crate::{
+    search::{
+        distance::{
+            dot,
+            l2_squared,
+            normalize,
+            Metric,
+            Neighbor,
+            TopK,
+        },
+        filter::SearchFilter,
+        kmeans::{
+            kmeans,
+            nearest_centroid,
+            SplitMix64,
+        },
+    },
+    vector_engine::VectorEngine,
+};
+
+/// Number of records pulled from the engine at a time while indexing.
+const SYNC_BATCH_SIZE: u64 = 65536;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IvfPqParams {
+    /// Number of coarse clusters (inverted lists).
+    pub nlist: usize,
+    /// Number of sub-vectors each embedding is split into; must divide the
+    /// dimension. Every record costs this many bytes in the index.
+    pub subquantizers: usize,
+    /// Codewords per sub-quantizer, at most 256 so a code fits in a byte.
+    pub codebook_size: usize,
+    /// Upper bound on the number of embeddings used for training.
+    pub training_sample: usize,
+    pub iterations: usize,
+    pub metric: Metric,
+    pub seed: u64,
+}
+
+impl Default for IvfPqParams {
+    fn default() -> Self {
+        Self {
+            nlist: 256,
+            subquantizers: 8,
+            codebook_size: 256,
+            training_sample: 65536,
+            iterations: 20,
+            metric: Metric::L2,
+            seed: 42,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+struct InvertedList {
+    ids: Vec<u64>,
+    codes: Vec<u8>,
+}
+
+/// Inverted-file index with product-quantized residuals. Only the coarse
+/// centroids, the PQ codebooks and `subquantizers` bytes per record are kept
+/// in memory; full embeddings stay in the underlying engine and are only
+/// fetched when re-ranking.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IvfPqIndex {
+    params: IvfPqParams,
+    dimension: usize,
+    codebook_size: usize,
+    centroids: Vec<f32>,
+    codebooks: Vec<f32>,
+    lists: Vec<InvertedList>,
+    indexed_len: u64,
+}
+
+impl IvfPqIndex {
+    /// Trains coarse centroids and PQ codebooks from `samples`. The returned
+    /// index is empty; use `add`, `add_batch` or `sync` to fill it.
+    pub fn train(params: IvfPqParams, samples: &[Vec<f32>]) -> Self {
+        assert!(!samples.is_empty(), "cannot train an index without samples");
+        assert!(params.nlist > 0, "nlist must be positive");
+        assert!(
+            params.codebook_size > 0 && params.codebook_size <= 256,
+            "codebook_size must be within 1..=256"
+        );
+        let dimension = samples[0].len();
+        assert!(
+            params.subquantizers > 0 && dimension.is_multiple_of(params.subquantizers),
+            "dimension {} is not divisible by {} subquantizers",
+            dimension,
+            params.subquantizers
+        );
+
+        let data: Vec<f32> = samples
+            .iter()
+            .flat_map(|sample| {
+                assert_eq!(sample.len(), dimension, "inconsistent sample dimension");
+                Self::prepare(params.metric, sample)
+            })
+            .collect();
+
+        let mut rng = SplitMix64::new(params.seed);
+        let centroids =
+            kmeans(&data, dimension, params.nlist, params.iterations, &mut rng);
+
+        let residuals: Vec<f32> = data
+            .par_chunks_exact(dimension)
+            .flat_map_iter(|vector| {
+                let list = nearest_centroid(&centroids, dimension, vector);
+                let centroid = &centroids[list * dimension..(list + 1) * dimension];
+                vector
+                    .iter()
+                    .zip(centroid)
+                    .map(|(x, c)| x - c)
+                    .collect::<Vec<f32>>()
+            })
+            .collect();
+
+        let sub_dimension = dimension / params.subquantizers;
+        let codebooks: Vec<f32> = (0..params.subquantizers)
+            .into_par_iter()
+            .flat_map_iter(|j| {
+                let sub_data: Vec<f32> = residuals
+                    .chunks_exact(dimension)
+                    .flat_map(|r| r[j * sub_dimension..(j + 1) * sub_dimension].to_vec())
+                    .collect();
+                let mut rng = SplitMix64::new(params.seed.wrapping_add(j as u64 + 1));
+                kmeans(
+                    &sub_data,
+                    sub_dimension,
+                    params.codebook_size,
+                    params.iterations,
+                    &mut rng,
+                )
+            })
+            .collect();
+
+        Self {
+            lists: vec![InvertedList::default(); params.nlist],
+            codebook_size: params.codebook_size,
+            params,
+            dimension,
+            centroids,
+            codebooks,
+            indexed_len: 0,
+        }
+    }
+
+    /// Trains from a random sample of the records stored in `engine`.
+    /// `embed` extracts the embedding of a record.
+    pub fn train_from_engine<D, T, F>(engine: &D, params: IvfPqParams, embed: F) -> Self
+    where
+        D: VectorEngine<T> + Sync,
+        T: Serialize
+            + for<'de> Deserialize<'de>
+            + 'static
+            + std::fmt::Debug
+            + Clone
+            + Send
+            + Sync,
+        F: Fn(&T) -> Vec<f32> + Sync,
+    {
+        let mut rng = SplitMix64::new(params.seed);
+        let samples: Vec<Vec<f32>> = rng
+            .sample(engine.len() as u64, params.training_sample)
+            .into_par_iter()
+            .map(|index| embed(&engine.pull(index)))
+            .collect();
+        Self::train(params, &samples)
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.params.metric
+    }
+
+    /// Number of vectors stored in the index.
+    pub fn len(&self) -> usize {
+        self.lists.iter().map(|list| list.ids.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// One past the highest record index added so far; `sync` resumes from
+    /// here.
+    pub fn indexed_len(&self) -> u64 {
+        self.indexed_len
+    }
+
+    pub fn add(&mut self, index: u64, vector: &[f32]) {
+        self.add_batch(index, &[vector.to_vec()]);
+    }
+
+    /// Adds `vectors` as records `first_index..first_index + vectors.len()`
+    /// using the already trained quantizers.
+    pub fn add_batch(&mut self, first_index: u64, vectors: &[Vec<f32>]) {
+        let encoded: Vec<(usize, Vec<u8>)> = vectors
+            .par_iter()
+            .map(|vector| self.encode(vector))
+            .collect();
+        for (offset, (list, code)) in encoded.into_iter().enumerate() {
+            let list = &mut self.lists[list];
+            list.ids.push(first_index + offset as u64);
+            list.codes.extend(code);
+        }
+        self.indexed_len = self.indexed_len.max(first_index + vectors.len() as u64);
+    }
+
+    /// Indexes every record appended to `engine` since the last sync.
+    /// Returns the number of newly indexed records.
+    pub fn sync<D, T, F>(&mut self, engine: &D, embed: F) -> u64
+    where
+        D: VectorEngine<T>,
+        T: Serialize
+            + for<'de> Deserialize<'de>
+            + 'static
+            + std::fmt::Debug
+            + Clone
+            + Send
+            + Sync,
+        F: Fn(&T) -> Vec<f32> + Sync,
+    {
+        let total = engine.len() as u64;
+        let start = self.indexed_len;
+        while self.indexed_len < total {
+            let count = SYNC_BATCH_SIZE.min(total - self.indexed_len);
+            let vectors: Vec<Vec<f32>> = engine
+                .pullx(self.indexed_len, count)
+                .par_iter()
+                .map(&embed)
+                .collect();
+            self.add_batch(self.indexed_len, &vectors);
+        }
+        self.indexed_len.saturating_sub(start)
+    }
+
+    /// Approximate `k` nearest neighbors, scanning the `nprobe` closest
+    /// inverted lists. Distances are estimated from the PQ codes.
+    pub fn search(&self, query: &[f32], k: usize, nprobe: usize) -> Vec<Neighbor> {
+        self.search_filtered(query, k, nprobe, &SearchFilter::All)
+    }
+
+    /// Approximate search restricted to records accepted by `filter`.
+    /// Rejected entries are skipped while scanning, and more lists are probed
+    /// (doubling `nprobe`) until `k` matches are found or every list has been
+    /// scanned.
+    pub fn search_filtered(
+        &self,
+        query: &[f32],
+        k: usize,
+        nprobe: usize,
+        filter: &SearchFilter,
+    ) -> Vec<Neighbor> {
+        assert_eq!(query.len(), self.dimension, "query dimension mismatch");
+        let query = Self::prepare(self.params.metric, query);
+        let lists = self.probe(&query, self.params.nlist);
+
+        let mut top = TopK::new(k);
+        let mut scanned = 0;
+        let mut target = nprobe.clamp(1, lists.len());
+        loop {
+            top = lists[scanned..target]
+                .par_iter()
+                .map(|list| self.scan_list(&query, *list, k, filter))
+                .reduce(|| TopK::new(k), TopK::merge)
+                .merge(top);
+            scanned = target;
+            if top.len() >= k || scanned == lists.len() {
+                return top.into_sorted_vec();
+            }
+            target = (target * 2).min(lists.len());
+        }
+    }
+
+    /// Approximate `k` nearest neighbors of every query in `queries`, as
+    /// `search` returns them. Queries probing the same inverted list are
+    /// scored together in a single pass over its codes, and lists are
+    /// scanned in parallel. Queries with fewer than `k` results probe more
+    /// lists, doubling `nprobe` each round as in `search_filtered`.
+    pub fn knn_batch(
+        &self,
+        queries: &[Vec<f32>],
+        k: usize,
+        nprobe: usize,
+    ) -> Vec<Vec<Neighbor>> {
+        let nlist = self.params.nlist;
+        let queries: Vec<Vec<f32>> = queries
+            .iter()
+            .map(|query| {
+                assert_eq!(query.len(), self.dimension, "query dimension mismatch");
+                Self::prepare(self.params.metric, query)
+            })
+            .collect();
+        let orders: Vec<Vec<usize>> = queries
+            .par_iter()
+            .map(|query| self.probe(query, nlist))
+            .collect();
+
+        let mut tops: Vec<TopK> = queries.iter().map(|_| TopK::new(k)).collect();
+        let mut scanned = vec![0; queries.len()];
+        let mut targets = vec![nprobe.clamp(1, nlist); queries.len()];
+        let mut pending: Vec<usize> = (0..queries.len()).collect();
+        while !pending.is_empty() {
+            let mut probing: Vec<Vec<usize>> = vec![Vec::new(); nlist];
+            for &q in &pending {
+                for &list in &orders[q][scanned[q]..targets[q]] {
+                    probing[list].push(q);
+                }
+            }
+            let partials: Vec<(usize, TopK)> = probing
+                .par_iter()
+                .enumerate()
+                .filter(|(_, group)| !group.is_empty())
+                .flat_map_iter(|(list, group)| {
+                    let group_queries: Vec<&[f32]> =
+                        group.iter().map(|q| queries[*q].as_slice()).collect();
+                    group.iter().copied().zip(self.scan_list_batch(
+                        &group_queries,
+                        list,
+                        k,
+                    ))
+                })
+                .collect();
+            for (q, top) in partials {
+                tops[q] = std::mem::replace(&mut tops[q], TopK::new(0)).merge(top);
+            }
+            pending.retain(|&q| {
+                scanned[q] = targets[q];
+                if tops[q].len() >= k || scanned[q] == nlist {
+                    return false;
+                }
+                targets[q] = (targets[q] * 2).min(nlist);
+                true
+            });
+        }
+        tops.into_iter().map(TopK::into_sorted_vec).collect()
+    }
+
+    /// Every record in the `nprobe` closest lists whose estimated distance
+    /// to `query` is at most `radius`, nearest first. Records in lists that
+    /// are not probed are missed, as with `search`.
+    pub fn range_search(
+        &self,
+        query: &[f32],
+        radius: f32,
+        nprobe: usize,
+    ) -> Vec<Neighbor> {
+        assert_eq!(query.len(), self.dimension, "query dimension mismatch");
+        let query = Self::prepare(self.params.metric, query);
+        let mut neighbors: Vec<Neighbor> = self
+            .probe(&query, nprobe)
+            .par_iter()
+            .flat_map_iter(|list| {
+                let mut within = Vec::new();
+                self.visit_list(&query, *list, &SearchFilter::All, |index, distance| {
+                    if distance <= radius {
+                        within.push(Neighbor { index, distance });
+                    }
+                });
+                within
+            })
+            .collect();
+        neighbors.sort();
+        neighbors
+    }
+
+    /// Like `search`, but re-ranks the best `rerank` candidates (at least
+    /// `k`) with exact distances computed from the records in `engine`.
+    pub fn search_exact<D, T, F>(
+        &self,
+        engine: &D,
+        embed: F,
+        query: &[f32],
+        k: usize,
+        nprobe: usize,
+        rerank: usize,
+    ) -> Vec<Neighbor>
+    where
+        D: VectorEngine<T> + Sync,
+        T: Serialize
+            + for<'de> Deserialize<'de>
+            + 'static
+            + std::fmt::Debug
+            + Clone
+            + Send
+            + Sync,
+        F: Fn(&T) -> Vec<f32> + Sync,
+    {
+        self.search_exact_filtered(
+            engine,
+            embed,
+            query,
+            k,
+            nprobe,
+            rerank,
+            &SearchFilter::All,
+        )
+    }
+
+    /// Filtered search with exact distances. Selective filters are
+    /// pre-filtered: every matching indexed record is fetched from `engine`
+    /// and compared exactly. Otherwise the index is searched with the filter
+    /// applied during the scan and the candidates are re-ranked.
+    #[allow(clippy::too_many_arguments)]
+    pub fn search_exact_filtered<D, T, F>(
+        &self,
+        engine: &D,
+        embed: F,
+        query: &[f32],
+        k: usize,
+        nprobe: usize,
+        rerank: usize,
+        filter: &SearchFilter,
+    ) -> Vec<Neighbor>
+    where
+        D: VectorEngine<T> + Sync,
+        T: Serialize
+            + for<'de> Deserialize<'de>
+            + 'static
+            + std::fmt::Debug
+            + Clone
+            + Send
+            + Sync,
+        F: Fn(&T) -> Vec<f32> + Sync,
+    {
+        let candidates: Vec<u64> = if filter.prefers_pre_filter(self.indexed_len) {
+            filter.matching(self.indexed_len)
+        } else {
+            self.search_filtered(query, rerank.max(k), nprobe, filter)
+                .into_iter()
+                .map(|candidate| candidate.index)
+                .collect()
+        };
+        let mut neighbors: Vec<Neighbor> = candidates
+            .into_par_iter()
+            .map(|index| Neighbor {
+                index,
+                distance: self
+                    .params
+                    .metric
+                    .distance(query, &embed(&engine.pull(index))),
+            })
+            .collect();
+        neighbors.sort();
+        neighbors.truncate(k);
+        neighbors
+    }
+
+    pub fn save(&self, path: String) -> io::Result<()> {
+        let temp_path = format!("{}.tmp", path);
+        {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            bincode::serialize_into(&mut writer, self).map_err(io::Error::other)?;
+            writer.flush()?;
+        }
+        std::fs::rename(temp_path, path)
+    }
+
+    pub fn open(path: String) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        bincode::deserialize_from(reader)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn prepare(metric: Metric, vector: &[f32]) -> Vec<f32> {
+        let mut vector = vector.to_vec();
+        if metric == Metric::Cosine {
+            normalize(&mut vector);
+        }
+        vector
+    }
+
+    fn sub_dimension(&self) -> usize {
+        self.dimension / self.params.subquantizers
+    }
+
+    fn centroid(&self, list: usize) -> &[f32] {
+        &self.centroids[list * self.dimension..(list + 1) * self.dimension]
+    }
+
+    fn codeword(&self, subquantizer: usize, code: usize) -> &[f32] {
+        let sub_dimension = self.sub_dimension();
+        let start = (subquantizer * self.codebook_size + code) * sub_dimension;
+        &self.codebooks[start..start + sub_dimension]
+    }
+
+    fn encode(&self, vector: &[f32]) -> (usize, Vec<u8>) {
+        assert_eq!(vector.len(), self.dimension, "vector dimension mismatch");
+        let vector = Self::prepare(self.params.metric, vector);
+        let list = nearest_centroid(&self.centroids, self.dimension, &vector);
+        let residual: Vec<f32> = vector
+            .iter()
+            .zip(self.centroid(list))
+            .map(|(x, c)| x - c)
+            .collect();
+
+        let sub_dimension = self.sub_dimension();
+        let code = residual
+            .chunks_exact(sub_dimension)
+            .enumerate()
+            .map(|(j, sub_vector)| {
+                let sub_codebook = &self.codebooks[j * self.codebook_size * sub_dimension
+                    ..(j + 1) * self.codebook_size * sub_dimension];
+                nearest_centroid(sub_codebook, sub_dimension, sub_vector) as u8
+            })
+            .collect();
+        (list, code)
+    }
+
+    fn coarse_distance(&self, query: &[f32], list: usize) -> f32 {
+        match self.params.metric {
+            Metric::InnerProduct => -dot(query, self.centroid(list)),
+            Metric::L2 | Metric::Cosine => l2_squared(query, self.centroid(list)),
+        }
+    }
+
+    fn probe(&self, query: &[f32], nprobe: usize) -> Vec<usize> {
+        let mut lists: Vec<(usize, f32)> = (0..self.params.nlist)
+            .map(|list| (list, self.coarse_distance(query, list)))
+            .collect();
+        lists.sort_by(|a, b| a.1.total_cmp(&b.1));
+        lists
+            .into_iter()
+            .take(nprobe.max(1))
+            .map(|(list, _)| list)
+            .collect()
+    }
+
+    /// Precomputed per-codeword distances for one probed list, followed by
+    /// the constant term shared by all of its entries.
+    fn distance_table(&self, query: &[f32], list: usize) -> (Vec<f32>, f32) {
+        let sub_dimension = self.sub_dimension();
+        let m = self.params.subquantizers;
+        match self.params.metric {
+            Metric::InnerProduct => {
+                let table = (0..m)
+                    .flat_map(|j| {
+                        let sub_query =
+                            &query[j * sub_dimension..(j + 1) * sub_dimension];
+                        (0..self.codebook_size)
+                            .map(move |code| -dot(sub_query, self.codeword(j, code)))
+                    })
+                    .collect();
+                (table, -dot(query, self.centroid(list)))
+            }
+            Metric::L2 | Metric::Cosine => {
+                let residual: Vec<f32> = query
+                    .iter()
+                    .zip(self.centroid(list))
+                    .map(|(q, c)| q - c)
+                    .collect();
+                let table = (0..m)
+                    .flat_map(|j| {
+                        let sub_query =
+                            residual[j * sub_dimension..(j + 1) * sub_dimension].to_vec();
+                        (0..self.codebook_size).map(move |code| {
+                            l2_squared(&sub_query, self.codeword(j, code))
+                        })
+                    })
+                    .collect();
+                (table, 0.0)
+            }
+        }
+    }
+
+    fn scan_list(
+        &self,
+        query: &[f32],
+        list: usize,
+        k: usize,
+        filter: &SearchFilter,
+    ) -> TopK {
+        let mut top = TopK::new(k);
+        self.visit_list(query, list, filter, |id, distance| top.push(id, distance));
+        top
+    }
+
+    /// Calls `visit` with the estimated distance of every entry of `list`
+    /// accepted by `filter`.
+    fn visit_list<F>(
+        &self,
+        query: &[f32],
+        list: usize,
+        filter: &SearchFilter,
+        mut visit: F,
+    ) where
+        F: FnMut(u64, f32),
+    {
+        let (table, base) = self.distance_table(query, list);
+        let m = self.params.subquantizers;
+        let inverted_list = &self.lists[list];
+        for (id, code) in inverted_list
+            .ids
+            .iter()
+            .zip(inverted_list.codes.chunks_exact(m))
+        {
+            if !filter.matches(*id) {
+                continue;
+            }
+            visit(*id, self.code_distance(&table, base, code));
+        }
+    }
+
+    /// Scans `list` once for all of `queries`, scoring each entry against
+    /// every query before moving on to the next one.
+    fn scan_list_batch(&self, queries: &[&[f32]], list: usize, k: usize) -> Vec<TopK> {
+        let tables: Vec<(Vec<f32>, f32)> = queries
+            .iter()
+            .map(|query| self.distance_table(query, list))
+            .collect();
+        let mut tops: Vec<TopK> = queries.iter().map(|_| TopK::new(k)).collect();
+        let inverted_list = &self.lists[list];
+        for (id, code) in inverted_list
+            .ids
+            .iter()
+            .zip(inverted_list.codes.chunks_exact(self.params.subquantizers))
+        {
+            for ((table, base), top) in tables.iter().zip(tops.iter_mut()) {
+                top.push(*id, self.code_distance(table, *base, code));
+            }
+        }
+        tops
+    }
+
+    /// Estimated distance of one PQ code from a list's distance table.
+    fn code_distance(&self, table: &[f32], base: f32, code: &[u8]) -> f32 {
+        let mut distance = base;
+        for (j, &c) in code.iter().enumerate() {
+            distance += table[j * self.codebook_size + c as usize];
+        }
+        if self.params.metric == Metric::Cosine {
+            // Unit vectors: |a - b|^2 = 2 (1 - cos)
+            distance /= 2.0;
+        }
+        distance
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        services::dynamic_vector_manage_service::DynamicVectorManageService,
+        test_support::TestDir,
+    };
+
+    #[derive(Serialize, Deserialize, Default, Debug, Clone)]
+    pub struct Document {
+        id: u64,
+        embedding: Vec<f32>,
+    }
+
+    fn embedding(i: u64) -> Vec<f32> {
+        let mut rng = SplitMix64::new(i);
+        let cluster = (i % 8) as f32 * 4.0;
+        (0..16)
+            .map(|_| cluster + (rng.next_u64() % 1000) as f32 / 1000.0)
+            .collect()
+    }
+
+    fn params() -> IvfPqParams {
+        IvfPqParams {
+            nlist: 8,
+            subquantizers: 4,
+            codebook_size: 32,
+            training_sample: 500,
+            iterations: 10,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_search_finds_own_cluster() {
+        let samples: Vec<Vec<f32>> = (0..400).map(embedding).collect();
+        let mut index = IvfPqIndex::train(params(), &samples);
+        index.add_batch(0, &samples);
+        assert_eq!(index.len(), 400);
+
+        let result = index.search(&embedding(3), 10, 2);
+        assert_eq!(result.len(), 10);
+        assert!(result.iter().all(|n| n.index % 8 == 3));
+        assert!(result.windows(2).all(|w| w[0].distance <= w[1].distance));
+    }
+
+    #[test]
+    fn test_knn_batch_and_range_search() {
+        let samples: Vec<Vec<f32>> = (0..400).map(embedding).collect();
+        let mut index = IvfPqIndex::train(params(), &samples);
+        index.add_batch(0, &samples);
+
+        let queries: Vec<Vec<f32>> = [3, 4, 11, 250].into_iter().map(embedding).collect();
+        let batch = index.knn_batch(&queries, 10, 2);
+        assert_eq!(batch.len(), 4);
+        for (query, result) in queries.iter().zip(&batch) {
+            assert_eq!(result, &index.search(query, 10, 2));
+        }
+        // Two lists hold about 100 records, so every query has to widen.
+        let batch = index.knn_batch(&queries, 150, 2);
+        for (query, result) in queries.iter().zip(&batch) {
+            assert_eq!(result.len(), 150);
+            assert_eq!(result, &index.search(query, 150, 2));
+        }
+
+        let all = index.search(&embedding(3), 400, 1);
+        let radius = all[20].distance;
+        let within = index.range_search(&embedding(3), radius, 1);
+        assert!(within.len() > 20);
+        assert!(within.iter().all(|n| n.distance <= radius));
+        assert_eq!(within[..21], all[..21]);
+    }
+
+    #[test]
+    fn test_sync_and_rerank_with_engine() {
+        let dir = TestDir::new("sync_and_rerank_with_engine");
+        let (structure, data) = dir.collection();
+        let engine =
+            DynamicVectorManageService::<Document>::new(structure, data, 1024).unwrap();
+        let documents: Vec<Document> = (0..300)
+            .map(|id| Document {
+                id,
+                embedding: embedding(id),
+            })
+            .collect();
+        engine.save_bulk(documents);
+
+        let embed = |doc: &Document| doc.embedding.clone();
+        let mut index = IvfPqIndex::train_from_engine(&engine, params(), embed);
+        assert_eq!(index.sync(&engine, embed), 300);
+
+        engine.save(Document {
+            id: 300,
+            embedding: embedding(300),
+        });
+        assert_eq!(index.sync(&engine, embed), 1);
+        assert_eq!(index.indexed_len(), 301);
+
+        let query = embedding(300);
+        let result = index.search_exact(&engine, embed, &query, 5, 8, 50);
+        assert_eq!(result[0].index, 300);
+        assert_eq!(result[0].distance, 0.0);
+    }
+
+    #[test]
+    fn test_filtered_search_returns_k() {
+        let dir = TestDir::new("filtered_search_returns_k");
+        let (structure, data) = dir.collection();
+        let engine =
+            DynamicVectorManageService::<Document>::new(structure, data, 1024).unwrap();
+        let documents: Vec<Document> = (0..400)
+            .map(|id| Document {
+                id,
+                embedding: embedding(id),
+            })
+            .collect();
+        engine.save_bulk(documents);
+        let embed = |doc: &Document| doc.embedding.clone();
+        let mut index = IvfPqIndex::train_from_engine(&engine, params(), embed);
+        index.sync(&engine, embed);
+
+        // Only cluster 5 matches, but the query sits in cluster 3: one probe
+        // is not enough and the search has to widen.
+        let filter = SearchFilter::predicate(|i| i % 8 == 5);
+        let result = index.search_filtered(&embedding(3), 10, 1, &filter);
+        assert_eq!(result.len(), 10);
+        assert!(result.iter().all(|n| n.index % 8 == 5));
+
+        let result = index.search_exact_filtered(
+            &engine,
+            embed,
+            &embedding(3),
+            10,
+            1,
+            20,
+            &filter,
+        );
+        assert_eq!(result.len(), 10);
+        assert!(result.iter().all(|n| n.index % 8 == 5));
+
+        let filter = SearchFilter::candidates(vec![11, 17, 300]);
+        let result = index.search_exact_filtered(
+            &engine,
+            embed,
+            &embedding(17),
+            10,
+            1,
+            20,
+            &filter,
+        );
+        assert_eq!(result.len(), 3);
+        assert_eq!(
+            result[0],
+            Neighbor {
+                index: 17,
+                distance: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_save_and_open() {
+        let dir = TestDir::new("save_and_open");
+        let path = dir.path("index");
+        let samples: Vec<Vec<f32>> = (0..200).map(embedding).collect();
+        let mut index = IvfPqIndex::train(
+            IvfPqParams {
+                metric: Metric::Cosine,
+                ..params()
+            },
+            &samples,
+        );
+        index.add_batch(0, &samples);
+        index.save(path.clone()).unwrap();
+
+        let reopened = IvfPqIndex::open(path).unwrap();
+        assert_eq!(reopened.len(), 200);
+        assert_eq!(reopened.metric(), Metric::Cosine);
+        assert_eq!(
+            index.search(&embedding(5), 3, 4),
+            reopened.search(&embedding(5), 3, 4)
+        );
+    }
+}
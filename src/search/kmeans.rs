@@ -0,0 +1,146 @@
+use rayon::prelude::*;
+
+use crate::search::distance::l2_squared;
+
+/// Small deterministic generator (splitmix64) so training is reproducible
+/// for a given seed without pulling in an RNG dependency.
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    /// Picks `count` distinct values out of `0..population`.
+    pub fn sample(&mut self, population: u64, count: usize) -> Vec<u64> {
+        if count as u64 >= population {
+            return (0..population).collect();
+        }
+        let mut picked = std::collections::HashSet::with_capacity(count);
+        while picked.len() < count {
+            picked.insert(self.below(population));
+        }
+        let mut picked: Vec<u64> = picked.into_iter().collect();
+        picked.sort_unstable();
+        picked
+    }
+}
+
+/// Index of the centroid closest to `vector`.
+pub(crate) fn nearest_centroid(
+    centroids: &[f32],
+    dimension: usize,
+    vector: &[f32],
+) -> usize {
+    centroids
+        .chunks_exact(dimension)
+        .enumerate()
+        .map(|(i, centroid)| (i, l2_squared(centroid, vector)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+        .expect("no centroids")
+}
+
+/// Lloyd's k-means over `data` (row-major, `dimension` columns). Returns
+/// `k * dimension` centroid coordinates. Clusters that end up empty are
+/// re-seeded from a random training point.
+pub(crate) fn kmeans(
+    data: &[f32],
+    dimension: usize,
+    k: usize,
+    iterations: usize,
+    rng: &mut SplitMix64,
+) -> Vec<f32> {
+    let count = data.len() / dimension;
+    assert!(count > 0, "k-means needs at least one training vector");
+
+    let mut centroids: Vec<f32> = rng
+        .sample(count as u64, k)
+        .into_iter()
+        .flat_map(|i| {
+            let i = i as usize;
+            data[i * dimension..(i + 1) * dimension].to_vec()
+        })
+        .collect();
+    // Fewer training points than clusters: duplicate points to fill up.
+    while centroids.len() < k * dimension {
+        let i = rng.below(count as u64) as usize;
+        centroids.extend_from_slice(&data[i * dimension..(i + 1) * dimension]);
+    }
+
+    for _ in 0..iterations {
+        let assignments: Vec<usize> = data
+            .par_chunks_exact(dimension)
+            .map(|vector| nearest_centroid(&centroids, dimension, vector))
+            .collect();
+
+        let mut sums = vec![0f64; k * dimension];
+        let mut counts = vec![0usize; k];
+        for (vector, &cluster) in data.chunks_exact(dimension).zip(&assignments) {
+            counts[cluster] += 1;
+            for (sum, x) in sums[cluster * dimension..(cluster + 1) * dimension]
+                .iter_mut()
+                .zip(vector)
+            {
+                *sum += *x as f64;
+            }
+        }
+
+        for cluster in 0..k {
+            let target = &mut centroids[cluster * dimension..(cluster + 1) * dimension];
+            if counts[cluster] == 0 {
+                let i = rng.below(count as u64) as usize;
+                target.copy_from_slice(&data[i * dimension..(i + 1) * dimension]);
+            } else {
+                for (c, sum) in target
+                    .iter_mut()
+                    .zip(&sums[cluster * dimension..(cluster + 1) * dimension])
+                {
+                    *c = (*sum / counts[cluster] as f64) as f32;
+                }
+            }
+        }
+    }
+
+    centroids
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_kmeans_separates_clusters() {
+        let mut data = Vec::new();
+        for i in 0..50 {
+            let jitter = i as f32 * 0.001;
+            data.extend_from_slice(&[jitter, jitter]);
+            data.extend_from_slice(&[10.0 + jitter, 10.0 - jitter]);
+        }
+        let mut rng = SplitMix64::new(7);
+        let centroids = kmeans(&data, 2, 2, 10, &mut rng);
+        let a = nearest_centroid(&centroids, 2, &[0.0, 0.0]);
+        let b = nearest_centroid(&centroids, 2, &[10.0, 10.0]);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_sample_is_distinct() {
+        let mut rng = SplitMix64::new(1);
+        let picked = rng.sample(100, 30);
+        assert_eq!(picked.len(), 30);
+        assert!(picked.windows(2).all(|w| w[0] < w[1]));
+    }
+}
@@ -0,0 +1,36 @@
+mod bm25;
+mod distance;
+mod embedder;
+mod filter;
+mod hybrid;
+mod ivf_pq;
+mod kmeans;
+mod sparse;
+
+pub(crate) use self::distance::TopK;
+pub use self::{
+    bm25::Bm25Index,
+    distance::{
+        Metric,
+        Neighbor,
+    },
+    embedder::{
+        tokenize,
+        Embedder,
+        HashingEmbedder,
+    },
+    filter::SearchFilter,
+    hybrid::{
+        Fusion,
+        HybridHit,
+        HybridIndex,
+    },
+    ivf_pq::{
+        IvfPqIndex,
+        IvfPqParams,
+    },
+    sparse::{
+        SparseIndex,
+        SparseVector,
+    },
+};
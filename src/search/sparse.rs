@@ -0,0 +1,568 @@
+use rayon::prelude::*;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{
+        self,
+        BufReader,
+        BufWriter,
+        Write,
+    },
+    sync::Mutex,
+};
+
+use crate::{
+    indexed_engine::RecordIndex,
+    search::distance::{
+        Neighbor,
+        TopK,
+    },
+    services::dynamic_vector_manage_service::DynamicVectorManageService,
+};
+
+/// Stored vectors read per batch while rebuilding postings on open.
+const REBUILD_BATCH_SIZE: u64 = 65536;
+
+/// Sparse embedding as parallel, term-sorted arrays of term ids and weights.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct SparseVector {
+    terms: Vec<u32>,
+    weights: Vec<f32>,
+}
+
+impl SparseVector {
+    /// Builds a vector from `(term, weight)` pairs in any order. Weights of
+    /// repeated terms are summed and zero weights dropped.
+    pub fn from_pairs(pairs: impl IntoIterator<Item = (u32, f32)>) -> Self {
+        let mut pairs: Vec<(u32, f32)> = pairs.into_iter().collect();
+        pairs.sort_by_key(|(term, _)| *term);
+        let mut vector = SparseVector::default();
+        for (term, weight) in pairs {
+            if vector.terms.last() == Some(&term) {
+                *vector.weights.last_mut().unwrap() += weight;
+            } else {
+                vector.terms.push(term);
+                vector.weights.push(weight);
+            }
+        }
+        let (terms, weights) = vector
+            .terms
+            .into_iter()
+            .zip(vector.weights)
+            .filter(|(_, weight)| *weight != 0.0)
+            .unzip();
+        SparseVector { terms, weights }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, f32)> + '_ {
+        self.terms.iter().cloned().zip(self.weights.iter().cloned())
+    }
+
+    /// Number of non-zero terms.
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn dot(&self, other: &SparseVector) -> f32 {
+        let (mut i, mut j, mut sum) = (0, 0, 0.0);
+        while i < self.terms.len() && j < other.terms.len() {
+            match self.terms[i].cmp(&other.terms[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    sum += self.weights[i] * other.weights[j];
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        sum
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+struct PostingList {
+    indices: Vec<u64>,
+    weights: Vec<f32>,
+    max_weight: f32,
+    min_weight: f32,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+struct Postings {
+    lists: HashMap<u32, PostingList>,
+    indexed_len: u64,
+}
+
+impl Postings {
+    fn add(&mut self, index: u64, vector: &SparseVector) {
+        for (term, weight) in vector.iter() {
+            let list = self.lists.entry(term).or_default();
+            if list.indices.is_empty() {
+                list.max_weight = weight;
+                list.min_weight = weight;
+            }
+            list.indices.push(index);
+            list.weights.push(weight);
+            list.max_weight = list.max_weight.max(weight);
+            list.min_weight = list.min_weight.min(weight);
+        }
+        self.indexed_len = self.indexed_len.max(index + 1);
+    }
+
+    /// Document-at-a-time WAND: a record is only scored once the summed
+    /// upper bounds of the lists positioned on it can beat the current k-th
+    /// best score.
+    ///
+    /// Terms that can only lower a score never move the pivot; they are kept
+    /// as scoring cursors whose contributions are added to every record
+    /// that gets scored.
+    fn top_k(&self, query: &SparseVector, k: usize) -> Vec<Neighbor> {
+        let (mut cursors, mut penalties): (Vec<Cursor>, Vec<Cursor>) = query
+            .iter()
+            .filter_map(|(term, query_weight)| {
+                let list = self.lists.get(&term)?;
+                let upper_bound =
+                    (query_weight * list.max_weight).max(query_weight * list.min_weight);
+                Some(Cursor {
+                    list,
+                    query_weight,
+                    upper_bound,
+                    position: 0,
+                })
+            })
+            .partition(|cursor| cursor.upper_bound > 0.0);
+
+        let mut top = TopK::new(k);
+        if k == 0 {
+            return Vec::new();
+        }
+        loop {
+            cursors.retain(|cursor| cursor.current().is_some());
+            cursors.sort_by_key(|cursor| cursor.current());
+            // A record must beat the current k-th score to enter the result.
+            let threshold = top.threshold().map_or(0.0, |distance| -distance);
+
+            let mut bound = 0.0;
+            let Some(pivot) = cursors.iter().position(|cursor| {
+                bound += cursor.upper_bound;
+                bound > threshold
+            }) else {
+                break;
+            };
+            let pivot_index = cursors[pivot].current().unwrap();
+
+            if cursors[0].current() == Some(pivot_index) {
+                let mut score = 0.0;
+                for cursor in cursors.iter_mut() {
+                    if cursor.current() != Some(pivot_index) {
+                        break;
+                    }
+                    score += cursor.query_weight * cursor.list.weights[cursor.position];
+                    cursor.position += 1;
+                }
+                for cursor in penalties.iter_mut() {
+                    cursor.advance_to(pivot_index);
+                    if cursor.current() == Some(pivot_index) {
+                        score +=
+                            cursor.query_weight * cursor.list.weights[cursor.position];
+                    }
+                }
+                if score > 0.0 {
+                    top.push(pivot_index, -score);
+                }
+            } else {
+                for cursor in cursors[..pivot].iter_mut() {
+                    cursor.advance_to(pivot_index);
+                }
+            }
+        }
+        top.into_sorted_vec()
+    }
+}
+
+/// Position in one query term's posting list during a WAND traversal.
+struct Cursor<'a> {
+    list: &'a PostingList,
+    query_weight: f32,
+    upper_bound: f32,
+    position: usize,
+}
+
+impl Cursor<'_> {
+    fn current(&self) -> Option<u64> {
+        self.list.indices.get(self.position).copied()
+    }
+
+    fn advance_to(&mut self, target: u64) {
+        self.position +=
+            self.list.indices[self.position..].partition_point(|i| *i < target);
+    }
+}
+
+/// Inverted index over learned sparse embeddings (term id -> weight),
+/// answering top-k dot-product queries with WAND pruning.
+///
+/// Vectors are stored per record index in `<path>.sparse` /
+/// `<path>.sparse-data`; the posting lists are cached in `<path>.postings`
+/// by `save` or `flush`, and rebuilt from the stored vectors when missing or
+/// behind. Dropping the index saves them on a best-effort basis.
+pub struct SparseIndex<T> {
+    extract: Box<dyn Fn(&T) -> SparseVector + Send + Sync>,
+    storage: DynamicVectorManageService<SparseVector>,
+    postings: Mutex<Postings>,
+    postings_path: String,
+}
+
+impl<T> SparseIndex<T> {
+    /// `extract` computes the sparse embedding of a record.
+    pub fn open<F>(path: String, extract: F) -> io::Result<Self>
+    where
+        F: Fn(&T) -> SparseVector + Send + Sync + 'static,
+    {
+        let storage = DynamicVectorManageService::new(
+            format!("{}.sparse", path),
+            format!("{}.sparse-data", path),
+            1024,
+        )?;
+        let postings_path = format!("{}.postings", path);
+        let mut postings = if std::path::Path::new(&postings_path).exists() {
+            let reader = BufReader::new(File::open(&postings_path)?);
+            bincode::deserialize_from(reader)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        } else {
+            Postings::default()
+        };
+        let stored = storage.get_length();
+        if postings.indexed_len > stored {
+            postings = Postings::default();
+        }
+        while postings.indexed_len < stored {
+            let first = postings.indexed_len;
+            let count = REBUILD_BATCH_SIZE.min(stored - first);
+            for (offset, vector) in storage.load_bulk(first, count).iter().enumerate() {
+                postings.add(first + offset as u64, vector);
+            }
+            postings.indexed_len = first + count;
+        }
+        Ok(Self {
+            extract: Box::new(extract),
+            storage,
+            postings: Mutex::new(postings),
+            postings_path,
+        })
+    }
+
+    /// Number of records covered by the index.
+    pub fn len(&self) -> u64 {
+        self.postings.lock().unwrap().indexed_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sparse embedding stored for record `index`.
+    pub fn get(&self, index: u64) -> SparseVector {
+        self.storage.load(index)
+    }
+
+    /// Stores and indexes `vectors` as the next records.
+    pub fn add_vectors(&self, vectors: Vec<SparseVector>) {
+        if vectors.is_empty() {
+            return;
+        }
+        let mut postings = self.postings.lock().unwrap();
+        let first = postings.indexed_len;
+        for (offset, vector) in vectors.iter().enumerate() {
+            postings.add(first + offset as u64, vector);
+        }
+        postings.indexed_len = first + vectors.len() as u64;
+        self.storage.save_bulk(vectors);
+    }
+
+    /// Records with the `k` largest positive dot products with `query`, as
+    /// neighbors whose distance is the negated dot product.
+    pub fn search(&self, query: &SparseVector, k: usize) -> Vec<Neighbor> {
+        self.postings.lock().unwrap().top_k(query, k)
+    }
+
+    /// Top-`k` results for every query in `queries`. Each query runs its own
+    /// WAND traversal; the traversals run in parallel against a single
+    /// snapshot of the posting lists.
+    pub fn knn_batch(&self, queries: &[SparseVector], k: usize) -> Vec<Vec<Neighbor>> {
+        let postings = self.postings.lock().unwrap();
+        let postings = &*postings;
+        queries
+            .par_iter()
+            .map(|query| postings.top_k(query, k))
+            .collect()
+    }
+
+    /// Every record whose distance to `query` is at most `radius`, nearest
+    /// first. Distances are negated dot products, so `radius = -s` returns
+    /// the records scoring at least `s`.
+    pub fn range_search(&self, query: &SparseVector, radius: f32) -> Vec<Neighbor> {
+        let postings = self.postings.lock().unwrap();
+        let mut scores: HashMap<u64, f32> = HashMap::new();
+        for (term, query_weight) in query.iter() {
+            let Some(list) = postings.lists.get(&term) else {
+                continue;
+            };
+            for (index, weight) in list.indices.iter().zip(&list.weights) {
+                *scores.entry(*index).or_default() += query_weight * weight;
+            }
+        }
+        let mut neighbors: Vec<Neighbor> = scores
+            .into_iter()
+            .map(|(index, score)| Neighbor {
+                index,
+                distance: -score,
+            })
+            .filter(|neighbor| neighbor.distance <= radius)
+            .collect();
+        neighbors.sort();
+        neighbors
+    }
+
+    /// Writes the posting lists to `<path>.postings`.
+    pub fn save(&self) -> io::Result<()> {
+        let postings = self
+            .postings
+            .lock()
+            .map_err(|_| io::Error::other("postings lock poisoned"))?;
+        self.save_postings(&postings)
+    }
+
+    fn save_postings(&self, postings: &Postings) -> io::Result<()> {
+        let temp_path = format!("{}.tmp", self.postings_path);
+        {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            bincode::serialize_into(&mut writer, postings).map_err(io::Error::other)?;
+            writer.flush()?;
+        }
+        std::fs::rename(temp_path, &self.postings_path)
+    }
+}
+
+impl<T: Sync> RecordIndex<T> for SparseIndex<T> {
+    fn indexed_len(&self) -> u64 {
+        self.len()
+    }
+
+    fn append(&self, first_index: u64, records: &[T]) {
+        let indexed = self.len();
+        assert!(
+            first_index <= indexed,
+            "sparse index is missing records {}..{}",
+            indexed,
+            first_index
+        );
+        let skip = ((indexed - first_index) as usize).min(records.len());
+        let vectors: Vec<SparseVector> = records[skip..]
+            .par_iter()
+            .map(|record| (self.extract)(record))
+            .collect();
+        self.add_vectors(vectors);
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.save()
+    }
+}
+
+impl<T> Drop for SparseIndex<T> {
+    fn drop(&mut self) {
+        if let Ok(postings) = self.postings.get_mut() {
+            let postings = std::mem::take(postings);
+            let _ = self.save_postings(&postings);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        indexed_engine::IndexedEngine,
+        search::kmeans::SplitMix64,
+        test_support::TestDir,
+        vector_engine::VectorEngine,
+    };
+
+    #[derive(Serialize, Deserialize, Default, Debug, Clone)]
+    pub struct Passage {
+        id: u64,
+        terms: Vec<(u32, f32)>,
+    }
+
+    /// Structure, data and index base paths under `dir`.
+    fn paths(dir: &TestDir) -> (String, String, String) {
+        (
+            dir.path("test.index"),
+            dir.path("test.data"),
+            dir.path("test"),
+        )
+    }
+
+    fn passage(id: u64) -> Passage {
+        let mut rng = SplitMix64::new(id);
+        let terms = (0..8)
+            .map(|_| (rng.below(64) as u32, (rng.below(1000) + 1) as f32 / 1000.0))
+            .collect();
+        Passage { id, terms }
+    }
+
+    fn extract(passage: &Passage) -> SparseVector {
+        SparseVector::from_pairs(passage.terms.clone())
+    }
+
+    #[test]
+    fn test_sparse_vector() {
+        let a = SparseVector::from_pairs(vec![(5, 1.0), (1, 2.0), (5, 0.5), (9, 0.0)]);
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![(1, 2.0), (5, 1.5)]);
+        let b = SparseVector::from_pairs(vec![(5, 2.0), (7, 1.0)]);
+        assert_eq!(a.dot(&b), 3.0);
+    }
+
+    #[test]
+    fn test_wand_matches_exhaustive_search() {
+        let dir = TestDir::new("wand_matches_exhaustive_search");
+        let (structure, data, path) = paths(&dir);
+        let index = Arc::new(SparseIndex::open(path, extract).unwrap());
+        let mut engine: IndexedEngine<DynamicVectorManageService<Passage>, Passage> =
+            VectorEngine::new(structure, data, 1024);
+        engine.attach_index(index.clone());
+        engine.pushx((0..500).map(passage).collect());
+        engine.push(passage(500));
+        assert_eq!(index.len(), 501);
+        assert_eq!(index.get(500), extract(&passage(500)));
+
+        for q in [3, 77, 500] {
+            let query = extract(&passage(q));
+            let mut expected: Vec<Neighbor> = (0..501)
+                .map(|i| Neighbor {
+                    index: i,
+                    distance: -query.dot(&extract(&passage(i))),
+                })
+                .filter(|n| n.distance < 0.0)
+                .collect();
+            expected.sort();
+            expected.truncate(10);
+            let result = index.search(&query, 10);
+            assert_eq!(
+                result.iter().map(|n| n.index).collect::<Vec<_>>(),
+                expected.iter().map(|n| n.index).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_wand_with_mixed_sign_weights() {
+        let dir = TestDir::new("wand_with_mixed_sign_weights");
+        let (_, _, path) = paths(&dir);
+        let index = SparseIndex::<Passage>::open(path, extract).unwrap();
+        // Weights are multiples of 1/8 so every dot product is exact and ties
+        // are broken the same way in both searches. Stored weights of terms
+        // 8 and up are positive, so negative query weights on them can only
+        // lower a score.
+        let vector = |seed: u64, positive_from: u64| {
+            let mut rng = SplitMix64::new(seed);
+            SparseVector::from_pairs((0..6).map(|_| {
+                let term = rng.below(16);
+                let weight = rng.below(16) as f32 / 8.0 - 1.0;
+                let weight = if term >= positive_from {
+                    weight.abs() + 0.125
+                } else {
+                    weight
+                };
+                (term as u32, weight)
+            }))
+        };
+        let vectors: Vec<SparseVector> = (0..400).map(|i| vector(i, 8)).collect();
+        index.add_vectors(vectors.clone());
+
+        for q in 1000..1050 {
+            let query = vector(q, 16);
+            let mut expected: Vec<Neighbor> = vectors
+                .iter()
+                .enumerate()
+                .map(|(i, v)| Neighbor {
+                    index: i as u64,
+                    distance: -query.dot(v),
+                })
+                .filter(|n| n.distance < 0.0)
+                .collect();
+            expected.sort();
+            expected.truncate(10);
+            assert_eq!(index.search(&query, 10), expected);
+        }
+    }
+
+    #[test]
+    fn test_knn_batch_and_range_search() {
+        let dir = TestDir::new("knn_batch_and_range_search");
+        let (_, _, path) = paths(&dir);
+        let index = SparseIndex::<Passage>::open(path, extract).unwrap();
+        index.add_vectors((0..300).map(|i| extract(&passage(i))).collect());
+
+        let queries: Vec<SparseVector> = [1, 2, 299].map(|i| extract(&passage(i))).into();
+        let batch = index.knn_batch(&queries, 7);
+        for (query, result) in queries.iter().zip(&batch) {
+            assert_eq!(result, &index.search(query, 7));
+        }
+
+        let all = index.search(&queries[0], 300);
+        let radius = all[9].distance;
+        let within = index.range_search(&queries[0], radius);
+        assert!(within.iter().all(|n| n.distance <= radius));
+        let indices = |neighbors: &[Neighbor]| -> Vec<u64> {
+            neighbors.iter().map(|n| n.index).collect()
+        };
+        assert_eq!(indices(&within[..9]), indices(&all[..9]));
+    }
+
+    #[test]
+    fn test_postings_rebuilt_on_open() {
+        let dir = TestDir::new("postings_rebuilt_on_open");
+        let (_, _, path) = paths(&dir);
+        let query = extract(&passage(42));
+        let expected = {
+            let index = SparseIndex::<Passage>::open(path.clone(), extract).unwrap();
+            index.add_vectors((0..100).map(|i| extract(&passage(i))).collect());
+            index.search(&query, 5)
+        };
+        assert_eq!(expected[0].index, 42);
+
+        std::fs::remove_file(format!("{}.postings", path)).unwrap();
+        let index = SparseIndex::<Passage>::open(path, extract).unwrap();
+        assert_eq!(index.len(), 100);
+        assert_eq!(index.search(&query, 5), expected);
+    }
+
+    #[test]
+    fn test_save_reports_errors() {
+        let dir = TestDir::new("sparse_save_reports_errors");
+        let (_, _, path) = paths(&dir);
+        let index = SparseIndex::<Passage>::open(path.clone(), extract).unwrap();
+        index.add_vectors((0..10).map(|i| extract(&passage(i))).collect());
+        std::fs::create_dir(format!("{}.postings.tmp", path)).unwrap();
+        assert!(index.save().is_err());
+        drop(index);
+        assert!(!std::path::Path::new(&format!("{}.postings", path)).exists());
+
+        // The posting lists are rebuilt from the stored vectors instead.
+        let index = SparseIndex::<Passage>::open(path, extract).unwrap();
+        assert_eq!(index.len(), 10);
+    }
+}
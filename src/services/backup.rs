@@ -0,0 +1,639 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    fs::{
+        self,
+        File,
+        OpenOptions,
+    },
+    io::{
+        self,
+        BufReader,
+        Read,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::services::{
+    dynamic_vector_manage_service::{
+        read_index,
+        replace_structure_file,
+        DynamicHeader,
+    },
+    file_access_service::FileAccessService,
+    string_repository::StringRepository,
+};
+
+/// Written last, so a directory without it holds no complete snapshot.
+const MANIFEST: &str = "snapshot.json";
+const INCREMENT_EXTENSION: &str = "increment";
+
+/// One file of a collection as of a capture: the bytes from `from` to
+/// `size`, and `patches` over the regions appends may rewrite in place
+/// after the capture.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Segment {
+    pub(crate) from: u64,
+    pub(crate) size: u64,
+    pub(crate) patches: Vec<(u64, Vec<u8>)>,
+}
+
+/// A committed state of a dynamic collection, the tails starting at record
+/// `since_len`. Only bytes outside the patches are read after the capture,
+/// and appends only write past `size` or inside the patches, so the copy is
+/// consistent while appends continue.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Capture {
+    pub(crate) since_len: u64,
+    pub(crate) length: u64,
+    pub(crate) structure: Segment,
+    pub(crate) data: Segment,
+}
+
+/// What a snapshot or an incremental backup wrote.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BackupInfo {
+    /// Records the backup restores up to.
+    pub length: u64,
+    /// First record it holds, 0 for a snapshot.
+    pub since_len: u64,
+    /// Bytes copied from the collection files.
+    pub copied_bytes: u64,
+    /// Snapshot manifest or increment file written.
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    length: u64,
+    structure_file: String,
+    data_file: String,
+}
+
+fn file_name(path: &str) -> io::Result<String> {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} has no file name", path),
+            )
+        })
+}
+
+fn already_exists(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// A live collection file read from `from`, padded with zeros past its end
+/// so a frame that was never written reads as empty.
+pub(crate) fn padded_from(path: &str, from: u64) -> io::Result<impl Read> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(from))?;
+    Ok(file.chain(io::repeat(0)))
+}
+
+/// Writes `segment` into `target`: the tail read from `tail`, then the
+/// patches, then cuts the file at the segment size.
+fn apply(target: &mut File, segment: &Segment, tail: &mut impl Read) -> io::Result<u64> {
+    let len = segment.size - segment.from;
+    target.seek(SeekFrom::Start(segment.from))?;
+    let copied = io::copy(&mut tail.take(len), target)?;
+    if copied != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "backup file is truncated",
+        ));
+    }
+    for (offset, bytes) in &segment.patches {
+        target.seek(SeekFrom::Start(*offset))?;
+        target.write_all(bytes)?;
+    }
+    target.set_len(segment.size)?;
+    Ok(copied)
+}
+
+/// Copies both files as of `capture` into `dest_dir` under their own
+/// names, then writes the manifest.
+pub(crate) fn snapshot(
+    capture: Capture,
+    structure_file_path: &str,
+    string_file_path: &str,
+    dest_dir: &str,
+) -> io::Result<BackupInfo> {
+    let dir = Path::new(dest_dir);
+    let manifest = Manifest {
+        length: capture.length,
+        structure_file: file_name(structure_file_path)?,
+        data_file: file_name(string_file_path)?,
+    };
+    if manifest.structure_file == manifest.data_file {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "structure and data files have the same name",
+        ));
+    }
+    fs::create_dir_all(dir)?;
+    for name in [MANIFEST, &manifest.structure_file, &manifest.data_file] {
+        already_exists(&dir.join(name))?;
+    }
+
+    let mut copied_bytes = 0;
+    for (source, name, segment) in [
+        (
+            structure_file_path,
+            &manifest.structure_file,
+            capture.structure,
+        ),
+        (string_file_path, &manifest.data_file, capture.data),
+    ] {
+        let segment = Segment { from: 0, ..segment };
+        let mut target = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dir.join(name))?;
+        copied_bytes += apply(&mut target, &segment, &mut padded_from(source, 0)?)?;
+        target.sync_all()?;
+    }
+
+    let path = dir.join(MANIFEST);
+    let partial = dir.join(format!("{}.partial", MANIFEST));
+    fs::write(&partial, serde_json::to_vec_pretty(&manifest)?)?;
+    fs::rename(&partial, &path)?;
+    Ok(BackupInfo {
+        length: capture.length,
+        since_len: 0,
+        copied_bytes,
+        path: path.to_string_lossy().to_string(),
+    })
+}
+
+/// Writes the tails of both files as of `capture` to
+/// `<since>-<length>.increment` in `dest_dir`, laid out as
+/// [header size u64][bincode capture][structure tail][data tail].
+pub(crate) fn incremental_backup(
+    capture: Capture,
+    structure_file_path: &str,
+    string_file_path: &str,
+    dest_dir: &str,
+) -> io::Result<BackupInfo> {
+    let dir = Path::new(dest_dir);
+    fs::create_dir_all(dir)?;
+    let name = format!("{:020}-{:020}", capture.since_len, capture.length);
+    let path = dir.join(format!("{}.{}", name, INCREMENT_EXTENSION));
+    already_exists(&path)?;
+    let partial = dir.join(format!("{}.partial", name));
+
+    let write = || -> io::Result<u64> {
+        let mut file = File::create(&partial)?;
+        let header = bincode::serialize(&capture).map_err(io::Error::other)?;
+        file.write_all(&(header.len() as u64).to_le_bytes())?;
+        file.write_all(&header)?;
+        let mut copied_bytes = 0;
+        for (source, segment) in [
+            (structure_file_path, &capture.structure),
+            (string_file_path, &capture.data),
+        ] {
+            copied_bytes += io::copy(
+                &mut padded_from(source, segment.from)?.take(segment.size - segment.from),
+                &mut file,
+            )?;
+        }
+        file.sync_all()?;
+        Ok(copied_bytes)
+    };
+    let copied_bytes = match write() {
+        Ok(copied_bytes) => copied_bytes,
+        Err(error) => {
+            let _ = fs::remove_file(&partial);
+            return Err(error);
+        }
+    };
+    fs::rename(&partial, &path)?;
+    Ok(BackupInfo {
+        length: capture.length,
+        since_len: capture.since_len,
+        copied_bytes,
+        path: path.to_string_lossy().to_string(),
+    })
+}
+
+/// (since, length, path) of the increments in `dir`, in order.
+fn increments(dir: &Path) -> io::Result<Vec<(u64, u64, PathBuf)>> {
+    let mut increments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_none_or(|extension| extension != INCREMENT_EXTENSION)
+        {
+            continue;
+        }
+        let range = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.split_once('-'))
+            .and_then(|(since, length)| {
+                Some((since.parse().ok()?, length.parse().ok()?))
+            });
+        if let Some((since, length)) = range {
+            increments.push((since, length, path));
+        }
+    }
+    increments.sort();
+    Ok(increments)
+}
+
+fn apply_increment(
+    path: &Path,
+    structure_file_path: &str,
+    string_file_path: &str,
+) -> io::Result<()> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut header_size = [0u8; 8];
+    file.read_exact(&mut header_size)?;
+    let mut header = vec![0u8; u64::from_le_bytes(header_size) as usize];
+    file.read_exact(&mut header)?;
+    let capture: Capture = bincode::deserialize(&header)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    for (target, segment) in [
+        (structure_file_path, &capture.structure),
+        (string_file_path, &capture.data),
+    ] {
+        let mut target = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(target)?;
+        apply(&mut target, segment, &mut file)?;
+    }
+    Ok(())
+}
+
+/// Cuts a restored collection back to its first `length` records. A block
+/// data file keeps the bytes of the dropped records, which nothing indexes
+/// any more.
+fn truncate(
+    structure_file_path: &str,
+    string_file_path: &str,
+    length: u64,
+) -> io::Result<()> {
+    let structure = FileAccessService::new(structure_file_path.to_string(), 1024);
+    let header = structure.read_header::<DynamicHeader>()?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no header", structure_file_path),
+        )
+    })?;
+    let locations = read_index(&structure, &header, length)?;
+    replace_structure_file(structure_file_path, &header, &locations)?;
+    if header.options.blocks.is_none() {
+        StringRepository::new(string_file_path.to_string(), 1024)
+            .truncate(locations.last().map_or(0, |&(_, end)| end))?;
+    }
+    Ok(())
+}
+
+/// Restores a dynamic collection from `backup_dir` into new files: the
+/// snapshot if there is one, then the increments that follow it in order.
+/// With `up_to_len` it stops at that many records, for a restore to a
+/// point in time between backups. Returns the number of records restored.
+///
+/// Fails with `InvalidData` when the increments leave a gap and with
+/// `InvalidInput` when the backups hold fewer than `up_to_len` records.
+/// The target files must not exist; they are removed again on error.
+pub fn restore(
+    backup_dir: &str,
+    structure_file_path: &str,
+    string_file_path: &str,
+    up_to_len: Option<u64>,
+) -> io::Result<u64> {
+    for path in [structure_file_path, string_file_path] {
+        already_exists(Path::new(path))?;
+    }
+    let result =
+        restore_files(backup_dir, structure_file_path, string_file_path, up_to_len);
+    if result.is_err() {
+        let _ = fs::remove_file(structure_file_path);
+        let _ = fs::remove_file(string_file_path);
+    }
+    result
+}
+
+fn restore_files(
+    backup_dir: &str,
+    structure_file_path: &str,
+    string_file_path: &str,
+    up_to_len: Option<u64>,
+) -> io::Result<u64> {
+    let dir = Path::new(backup_dir);
+    let manifest_path = dir.join(MANIFEST);
+    let mut restored = if manifest_path.exists() {
+        let manifest: Manifest = serde_json::from_slice(&fs::read(&manifest_path)?)?;
+        fs::copy(dir.join(&manifest.structure_file), structure_file_path)?;
+        fs::copy(dir.join(&manifest.data_file), string_file_path)?;
+        Some(manifest.length)
+    } else {
+        None
+    };
+
+    let target = up_to_len.unwrap_or(u64::MAX);
+    for (since, length, path) in increments(dir)? {
+        let current = restored.unwrap_or(0);
+        if restored.is_some() {
+            if current >= target {
+                break;
+            }
+            if length <= current {
+                continue;
+            }
+        }
+        if since > current {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} holds no backup of records {}..{}",
+                    backup_dir, current, since
+                ),
+            ));
+        }
+        apply_increment(&path, structure_file_path, string_file_path)?;
+        restored = Some(length);
+    }
+
+    let restored = restored.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} holds no snapshot or increments", backup_dir),
+        )
+    })?;
+    match up_to_len {
+        Some(up_to_len) if up_to_len > restored => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "backups hold {} records, fewer than {}",
+                restored, up_to_len
+            ),
+        )),
+        Some(up_to_len) if up_to_len < restored => {
+            truncate(structure_file_path, string_file_path, up_to_len)?;
+            Ok(up_to_len)
+        }
+        _ => Ok(restored),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        services::{
+            compression::BlockOptions,
+            dynamic_vector_manage_service::{
+                DynamicOptions,
+                DynamicVectorManageService,
+                IndexFormat,
+            },
+        },
+        test_support::TestDir,
+    };
+    use std::sync::Arc;
+
+    type Record = (u64, String);
+
+    fn record(i: u64) -> Record {
+        (i, "x".repeat(i as usize % 13))
+    }
+
+    fn layouts() -> Vec<(&'static str, DynamicOptions)> {
+        vec![
+            ("Pairs", DynamicOptions::default()),
+            (
+                "Compact",
+                DynamicOptions {
+                    index: IndexFormat::Compact {
+                        checkpoint_interval: 16,
+                    },
+                    ..Default::default()
+                },
+            ),
+            (
+                "Blocks",
+                DynamicOptions {
+                    blocks: Some(BlockOptions {
+                        block_size: 512,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ),
+        ]
+    }
+
+    /// Structure, data and backup directory paths under `dir`.
+    fn fresh(dir: &TestDir) -> (String, String, String) {
+        (
+            dir.path("Dynamic.bin"),
+            dir.path("StringDynamic.bin"),
+            dir.path("backups"),
+        )
+    }
+
+    fn open(
+        structure: &str,
+        data: &str,
+        options: &DynamicOptions,
+    ) -> DynamicVectorManageService<Record> {
+        DynamicVectorManageService::with_options(
+            structure.to_string(),
+            data.to_string(),
+            1024,
+            options.clone(),
+        )
+        .unwrap()
+    }
+
+    fn assert_records(service: &DynamicVectorManageService<Record>, length: u64) {
+        assert_eq!(service.get_length(), length);
+        let expected: Vec<Record> = (0..length).map(record).collect();
+        assert_eq!(service.load_bulk(0, length), expected);
+    }
+
+    #[test]
+    fn test_snapshot_during_appends() {
+        for (name, options) in layouts() {
+            let dir = TestDir::new(&format!("snapshot_{}", name));
+            let (structure, data, backups) = fresh(&dir);
+            let service = Arc::new(open(&structure, &data, &options));
+            service.save_bulk((0..300).map(record).collect());
+
+            let writer = {
+                let service = service.clone();
+                std::thread::spawn(move || {
+                    for i in 300..1300 {
+                        if i % 100 == 0 {
+                            service.save_bulk((i..i + 50).map(record).collect());
+                        } else if i % 100 < 50 {
+                            continue;
+                        } else {
+                            service.save(record(i));
+                        }
+                    }
+                })
+            };
+            let snapshots: Vec<(String, BackupInfo)> = (0..4)
+                .map(|i| {
+                    let dir = format!("{}/{}", backups, i);
+                    let info = service.snapshot(&dir).unwrap();
+                    (dir, info)
+                })
+                .collect();
+            writer.join().unwrap();
+            assert_eq!(
+                service.snapshot(&snapshots[0].0).unwrap_err().kind(),
+                io::ErrorKind::AlreadyExists
+            );
+
+            for (dir, info) in snapshots {
+                assert!(info.length >= 300);
+                let snapshot = open(
+                    &format!("{}/Dynamic.bin", dir),
+                    &format!("{}/StringDynamic.bin", dir),
+                    &options,
+                );
+                assert_records(&snapshot, info.length);
+            }
+            assert_records(&service, 1300);
+        }
+    }
+
+    #[test]
+    fn test_increments_across_sealed_blocks() {
+        let dir = TestDir::new("increments_across_sealed_blocks");
+        let (structure, data, backups) = fresh(&dir);
+        let options = DynamicOptions {
+            blocks: Some(BlockOptions {
+                block_size: 32,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let service = open(&structure, &data, &options);
+        service.incremental_backup(&backups, 0).unwrap();
+        for i in 0..40 {
+            service.save(record(i));
+            service.incremental_backup(&backups, i).unwrap();
+        }
+        let (restored_structure, restored_data) = (
+            format!("{}.restored", structure),
+            format!("{}.restored", data),
+        );
+        restore(&backups, &restored_structure, &restored_data, None).unwrap();
+        assert_records(&open(&restored_structure, &restored_data, &options), 40);
+    }
+
+    #[test]
+    fn test_incremental_backup_and_restore() {
+        for (name, options) in layouts() {
+            let dir = TestDir::new(&format!("restore_{}", name));
+            let (structure, data, backups) = fresh(&dir);
+            let service = open(&structure, &data, &options);
+            service.save_bulk((0..500).map(record).collect());
+            service.snapshot(&backups).unwrap();
+            for i in 500..800 {
+                service.save(record(i));
+            }
+            let first = service.incremental_backup(&backups, 500).unwrap();
+            assert_eq!((first.since_len, first.length), (500, 800));
+            service.save_bulk((800..1000).map(record).collect());
+            service.incremental_backup(&backups, 800).unwrap();
+            assert_eq!(
+                service
+                    .incremental_backup(&backups, 1001)
+                    .unwrap_err()
+                    .kind(),
+                io::ErrorKind::InvalidInput
+            );
+
+            let restored = |suffix: &str| {
+                (
+                    format!("{}.{}", structure, suffix),
+                    format!("{}.{}", data, suffix),
+                )
+            };
+            let (full_structure, full_data) = restored("full");
+            assert_eq!(
+                restore(&backups, &full_structure, &full_data, None).unwrap(),
+                1000
+            );
+            assert_records(&open(&full_structure, &full_data, &options), 1000);
+            assert_eq!(
+                restore(&backups, &full_structure, &full_data, None)
+                    .unwrap_err()
+                    .kind(),
+                io::ErrorKind::AlreadyExists
+            );
+
+            let (partial_structure, partial_data) = restored("partial");
+            assert_eq!(
+                restore(&backups, &partial_structure, &partial_data, Some(650)).unwrap(),
+                650
+            );
+            let partial = open(&partial_structure, &partial_data, &options);
+            assert_records(&partial, 650);
+            partial.save_bulk((650..700).map(record).collect());
+            partial.save(record(700));
+            assert_records(&partial, 701);
+
+            let (missing_structure, missing_data) = restored("missing");
+            assert_eq!(
+                restore(&backups, &missing_structure, &missing_data, Some(1200))
+                    .unwrap_err()
+                    .kind(),
+                io::ErrorKind::InvalidInput
+            );
+            assert!(!Path::new(&missing_structure).exists());
+
+            // Increments from the first record restore without a snapshot.
+            let increments_only = format!("{}-increments", backups);
+            service.incremental_backup(&increments_only, 0).unwrap();
+            service.save(record(1000));
+            service.incremental_backup(&increments_only, 1000).unwrap();
+            let (chained_structure, chained_data) = restored("chained");
+            assert_eq!(
+                restore(&increments_only, &chained_structure, &chained_data, None)
+                    .unwrap(),
+                1001
+            );
+            assert_records(&open(&chained_structure, &chained_data, &options), 1001);
+
+            fs::remove_file(format!(
+                "{}/{:020}-{:020}.increment",
+                increments_only, 0, 1000
+            ))
+            .unwrap();
+            let (gap_structure, gap_data) = restored("gap");
+            assert_eq!(
+                restore(&increments_only, &gap_structure, &gap_data, None)
+                    .unwrap_err()
+                    .kind(),
+                io::ErrorKind::InvalidData
+            );
+        }
+    }
+}
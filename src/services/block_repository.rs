@@ -0,0 +1,501 @@
+use std::{
+    collections::{
+        HashMap,
+        VecDeque,
+    },
+    io,
+    mem::size_of,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use crate::services::{
+    compression::BlockOptions,
+    encryption::PayloadCipher,
+    file_access_service::FileAccessService,
+};
+
+const SEALED_END_SIZE: usize = size_of::<u64>();
+/// Stored length u32, raw length u32, state u8.
+const FRAME_HEADER_SIZE: usize = 9;
+/// Decompressed blocks kept in memory for random reads.
+const CACHED_BLOCKS: usize = 64;
+
+const OPEN: u8 = 0;
+const SEALED_RAW: u8 = 1;
+const SEALED_COMPRESSED: u8 = 2;
+/// Open block being sealed in place. Its stored length is replaced by the
+/// distance from the end of the frame header to the sealed copy, a
+/// complete frame past both the open frame and the sealed one.
+const SEALING: u8 = 3;
+
+/// Location of a record: the file offset of its block frame, and its
+/// offset and length in the decompressed block packed into one u64. This
+/// is the pair the dynamic structure file stores in place of the
+/// (start, end) offsets of an uncompressed data file.
+fn pack_location(offset_in_block: u32, len: u32) -> u64 {
+    ((offset_in_block as u64) << 32) | len as u64
+}
+
+fn unpack_location(packed: u64) -> (usize, usize) {
+    ((packed >> 32) as usize, packed as u32 as usize)
+}
+
+struct BlockState {
+    /// End of the sealed blocks, where the open block's frame starts.
+    sealed_end: u64,
+    /// Records appended since the last block was sealed; also on disk,
+    /// uncompressed, in the open frame.
+    open: Vec<u8>,
+}
+
+/// Small LRU of decompressed blocks keyed by frame offset.
+struct BlockCache {
+    blocks: HashMap<u64, Arc<Vec<u8>>>,
+    order: VecDeque<u64>,
+}
+
+impl BlockCache {
+    fn get(&mut self, offset: u64) -> Option<Arc<Vec<u8>>> {
+        let block = self.blocks.get(&offset)?.clone();
+        self.order.retain(|cached| *cached != offset);
+        self.order.push_back(offset);
+        Some(block)
+    }
+
+    fn insert(&mut self, offset: u64, block: Arc<Vec<u8>>) {
+        if self.blocks.insert(offset, block).is_none() {
+            self.order.push_back(offset);
+        }
+        while self.order.len() > CACHED_BLOCKS {
+            let evicted = self.order.pop_front().unwrap();
+            self.blocks.remove(&evicted);
+        }
+    }
+}
+
+/// Data file that packs records into blocks compressed as a unit.
+///
+/// File layout: [sealed end u64][frame]..., where each frame is
+/// [stored length u32][raw length u32][state u8][payload]. The last frame
+/// is the open block, kept uncompressed so appends stay cheap; it is
+/// compressed in place once it would grow past the block size. The sealed
+/// frame is first written past the open one and the open frame marked
+/// `SEALING`, so a crash while it is copied into place is finished on the
+/// next open instead of losing the block.
+///
+/// With a cipher every payload is encrypted after compression, so the open
+/// block is re-encrypted as a whole on each append instead of growing in
+/// place.
+pub struct BlockRepository {
+    file_access: FileAccessService,
+    options: BlockOptions,
+    cipher: Option<PayloadCipher>,
+    state: Mutex<BlockState>,
+    cache: Mutex<BlockCache>,
+}
+
+impl BlockRepository {
+    pub fn new(
+        file_path: String,
+        initial_size_if_not_exists: u64,
+        options: BlockOptions,
+        cipher: Option<PayloadCipher>,
+    ) -> io::Result<Self> {
+        let file_access = FileAccessService::new(file_path, initial_size_if_not_exists);
+        let marker = file_access.read_in_file(0, SEALED_END_SIZE);
+        let mut sealed_end =
+            u64::from_le_bytes(marker.try_into().unwrap()).max(SEALED_END_SIZE as u64);
+
+        // A crash between sealing a block and moving the marker leaves the
+        // sealed frame where the open one was expected.
+        let open = loop {
+            let (stored_len, raw_len, state) =
+                Self::read_frame_header(&file_access, sealed_end);
+            match state {
+                SEALING => Self::finish_seal(&file_access, sealed_end, stored_len)?,
+                _ if stored_len == 0 => break Vec::new(),
+                OPEN => {
+                    let stored = file_access.read_in_file(
+                        sealed_end + FRAME_HEADER_SIZE as u64,
+                        stored_len as usize,
+                    );
+                    let open = match &cipher {
+                        Some(cipher) => cipher.open(&stored)?,
+                        None => stored,
+                    };
+                    if open.len() != raw_len as usize {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("open block at offset {} is truncated", sealed_end),
+                        ));
+                    }
+                    break open;
+                }
+                SEALED_RAW | SEALED_COMPRESSED => {
+                    sealed_end += (FRAME_HEADER_SIZE + stored_len as usize) as u64;
+                }
+                other => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid block state {} at offset {}", other, sealed_end),
+                    ))
+                }
+            }
+        };
+
+        Ok(Self {
+            file_access,
+            options,
+            cipher,
+            state: Mutex::new(BlockState { sealed_end, open }),
+            cache: Mutex::new(BlockCache {
+                blocks: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        self.file_access.path()
+    }
+
+    /// Bytes of the file in use: the sealed blocks and the open frame.
+    pub fn used_size(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        self.open_frame_end(&state)
+    }
+
+    /// End of the open frame as stored, which an encrypted block makes
+    /// longer than the records in it.
+    fn open_frame_end(&self, state: &BlockState) -> u64 {
+        let (stored_len, _, _) =
+            Self::read_frame_header(&self.file_access, state.sealed_end);
+        state.sealed_end + (FRAME_HEADER_SIZE + stored_len as usize) as u64
+    }
+
+    /// Byte ranges that appends rewrite in place, with their current
+    /// contents: the sealed end marker and the open frame, which is
+    /// rewritten or sealed over as records arrive.
+    pub fn rewritable_regions(&self) -> Vec<(u64, Vec<u8>)> {
+        let state = self.state.lock().unwrap();
+        let end = self.open_frame_end(&state);
+        let file_size = std::fs::metadata(self.file_access.path())
+            .expect("Unable to get file metadata")
+            .len();
+        vec![
+            (0, state.sealed_end.to_le_bytes().to_vec()),
+            (
+                state.sealed_end,
+                self.file_access.read_in_file(
+                    state.sealed_end,
+                    (end.min(file_size) - state.sealed_end) as usize,
+                ),
+            ),
+        ]
+    }
+
+    fn read_frame_header(file_access: &FileAccessService, offset: u64) -> (u32, u32, u8) {
+        let file_size = std::fs::metadata(file_access.path())
+            .expect("Unable to get file metadata")
+            .len();
+        if offset + FRAME_HEADER_SIZE as u64 > file_size {
+            return (0, 0, OPEN);
+        }
+        let header = file_access.read_in_file(offset, FRAME_HEADER_SIZE);
+        (
+            u32::from_le_bytes(header[0..4].try_into().unwrap()),
+            u32::from_le_bytes(header[4..8].try_into().unwrap()),
+            header[8],
+        )
+    }
+
+    fn frame_header(stored_len: usize, raw_len: usize, state: u8) -> Vec<u8> {
+        let mut header = Vec::with_capacity(FRAME_HEADER_SIZE);
+        header.extend_from_slice(&(stored_len as u32).to_le_bytes());
+        header.extend_from_slice(&(raw_len as u32).to_le_bytes());
+        header.push(state);
+        header
+    }
+
+    /// Copies the sealed frame of a block marked `SEALING` over its open
+    /// frame. The frame header is written last, ending the `SEALING` state
+    /// once the rest is on disk.
+    fn finish_seal(
+        file_access: &FileAccessService,
+        offset: u64,
+        copy_distance: u32,
+    ) -> io::Result<()> {
+        let copy = offset + (FRAME_HEADER_SIZE + copy_distance as usize) as u64;
+        let (stored_len, _, state) = Self::read_frame_header(file_access, copy);
+        let file_size = std::fs::metadata(file_access.path())?.len();
+        let copy_end = copy + (FRAME_HEADER_SIZE + stored_len as usize) as u64;
+        if !matches!(state, SEALED_RAW | SEALED_COMPRESSED) || copy_end > file_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("sealed copy of the block at offset {} is missing", offset),
+            ));
+        }
+        let frame = file_access.read_in_file(copy, (copy_end - copy) as usize);
+        let mut rest = frame[FRAME_HEADER_SIZE..].to_vec();
+        // Writing the empty open frame with the sealed one keeps stale raw
+        // bytes from being read back as a frame header.
+        rest.extend_from_slice(&Self::frame_header(0, 0, OPEN));
+        file_access.write_in_file(offset + FRAME_HEADER_SIZE as u64, &rest);
+        file_access.sync()?;
+        file_access.write_in_file(offset, &frame[..FRAME_HEADER_SIZE]);
+        file_access.sync()
+    }
+
+    /// Compresses the open block in place and starts an empty one after it.
+    fn seal(&self, state: &mut BlockState) {
+        let raw = std::mem::take(&mut state.open);
+        let compressed = self
+            .options
+            .compression
+            .compress(&raw)
+            .expect("Block compression failed");
+        let (frame_state, payload) = if compressed.len() < raw.len() {
+            (SEALED_COMPRESSED, compressed)
+        } else {
+            (SEALED_RAW, raw.clone())
+        };
+        let payload = match &self.cipher {
+            Some(cipher) => cipher.seal(&payload),
+            None => payload,
+        };
+        let mut frame = Self::frame_header(payload.len(), raw.len(), frame_state);
+        frame.extend_from_slice(&payload);
+        let block_offset = state.sealed_end;
+        let (open_stored_len, open_raw_len, _) =
+            Self::read_frame_header(&self.file_access, block_offset);
+
+        // The open frame stays intact until its sealed copy is on disk and
+        // the frame is marked as being sealed. The copy goes past both the
+        // open frame and the empty one written after the sealed frame.
+        let copy_distance = (open_stored_len as usize).max(frame.len());
+        assert!(
+            copy_distance <= u32::MAX as usize,
+            "block of {} bytes is too large to seal",
+            copy_distance
+        );
+        self.file_access.write_in_file(
+            block_offset + (FRAME_HEADER_SIZE + copy_distance) as u64,
+            &frame,
+        );
+        self.file_access.sync().expect("Unable to sync block");
+        self.file_access.write_in_file(
+            block_offset,
+            &Self::frame_header(copy_distance, open_raw_len as usize, SEALING),
+        );
+        self.file_access.sync().expect("Unable to sync block");
+        Self::finish_seal(&self.file_access, block_offset, copy_distance as u32)
+            .expect("Unable to seal block");
+
+        state.sealed_end += frame.len() as u64;
+        self.file_access
+            .write_in_file(0, &state.sealed_end.to_le_bytes());
+
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(block_offset, Arc::new(raw));
+    }
+
+    /// Appends `records` and returns the (block offset, packed location)
+    /// pair of each one.
+    pub fn append(&self, records: Vec<Vec<u8>>) -> Vec<(u64, u64)> {
+        let block_size = self.options.block_size as usize;
+        let mut state = self.state.lock().unwrap();
+        let mut locations = Vec::with_capacity(records.len());
+        let mut flushed = state.open.len();
+        for record in records {
+            assert!(
+                record.len() <= u32::MAX as usize,
+                "record of {} bytes does not fit in a block",
+                record.len()
+            );
+            if !state.open.is_empty() && state.open.len() + record.len() > block_size {
+                self.seal(&mut state);
+                flushed = 0;
+            }
+            locations.push((
+                state.sealed_end,
+                pack_location(state.open.len() as u32, record.len() as u32),
+            ));
+            state.open.extend_from_slice(&record);
+        }
+
+        let open_len = state.open.len();
+        if open_len > flushed {
+            match &self.cipher {
+                Some(cipher) => {
+                    let sealed = cipher.seal(&state.open);
+                    let mut frame = Self::frame_header(sealed.len(), open_len, OPEN);
+                    frame.extend_from_slice(&sealed);
+                    self.file_access.write_in_file(state.sealed_end, &frame);
+                }
+                None => {
+                    self.file_access.write_in_file(
+                        state.sealed_end + (FRAME_HEADER_SIZE + flushed) as u64,
+                        &state.open[flushed..],
+                    );
+                    self.file_access.write_in_file(
+                        state.sealed_end,
+                        &Self::frame_header(open_len, open_len, OPEN),
+                    );
+                }
+            }
+        }
+        locations
+    }
+
+    fn load_block(&self, offset: u64) -> io::Result<Arc<Vec<u8>>> {
+        if let Some(block) = self.cache.lock().unwrap().get(offset) {
+            return Ok(block);
+        }
+        let (stored_len, raw_len, state) =
+            Self::read_frame_header(&self.file_access, offset);
+        let stored = self
+            .file_access
+            .read_in_file(offset + FRAME_HEADER_SIZE as u64, stored_len as usize);
+        let stored = match &self.cipher {
+            Some(cipher) => cipher.open(&stored)?,
+            None => stored,
+        };
+        let raw = match state {
+            SEALED_RAW => stored,
+            SEALED_COMPRESSED => self
+                .options
+                .compression
+                .decompress(&stored, raw_len as usize)?,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("no sealed block at offset {}", offset),
+                ))
+            }
+        };
+        let block = Arc::new(raw);
+        self.cache.lock().unwrap().insert(offset, block.clone());
+        Ok(block)
+    }
+
+    /// Reads the records at `locations`, as returned by `append`.
+    pub fn load(&self, locations: &[(u64, u64)]) -> io::Result<Vec<Vec<u8>>> {
+        let mut records = Vec::with_capacity(locations.len());
+        for &(block_offset, packed) in locations {
+            let (start, len) = unpack_location(packed);
+            let record = {
+                let state = self.state.lock().unwrap();
+                if block_offset == state.sealed_end {
+                    state.open.get(start..start + len).map(<[u8]>::to_vec)
+                } else {
+                    drop(state);
+                    let block = self.load_block(block_offset)?;
+                    block.get(start..start + len).map(<[u8]>::to_vec)
+                }
+            };
+            records.push(record.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("record out of bounds in block at offset {}", block_offset),
+                )
+            })?);
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::TestDir;
+
+    #[test]
+    fn test_blocks_survive_reopen() {
+        let dir = TestDir::new("blocks_survive_reopen");
+        let path = dir.path("test_block_repository.bin");
+        let options = BlockOptions {
+            block_size: 256,
+            ..Default::default()
+        };
+        let records: Vec<Vec<u8>> = (0..100)
+            .map(|i| format!("log line {:03}: request served", i).into_bytes())
+            .collect();
+        let locations = {
+            let repository =
+                BlockRepository::new(path.clone(), 1024, options.clone(), None).unwrap();
+            let mut locations = repository.append(records[..60].to_vec());
+            for record in &records[60..] {
+                locations.extend(repository.append(vec![record.clone()]));
+            }
+            assert_eq!(repository.load(&locations).unwrap(), records);
+            locations
+        };
+        let blocks: std::collections::HashSet<u64> =
+            locations.iter().map(|(block, _)| *block).collect();
+        assert!(blocks.len() > 10);
+
+        let repository = BlockRepository::new(path.clone(), 1024, options, None).unwrap();
+        assert_eq!(repository.load(&locations).unwrap(), records);
+        assert_eq!(repository.load(&locations[97..98]).unwrap()[0], records[97]);
+        let more = repository.append(vec![b"after reopen".to_vec()]);
+        assert_eq!(repository.load(&more).unwrap()[0], b"after reopen");
+        assert!(std::fs::metadata(&path).unwrap().len() < 4096);
+    }
+
+    #[test]
+    fn test_seal_finished_after_crash() {
+        let dir = TestDir::new("seal_finished_after_crash");
+        let path = dir.path("blocks.bin");
+        let options = BlockOptions {
+            block_size: 256,
+            ..Default::default()
+        };
+        let records: Vec<Vec<u8>> = (0..8)
+            .map(|i| format!("log line {:03}: request served", i).into_bytes())
+            .collect();
+        let open = |path: &str| {
+            BlockRepository::new(path.to_string(), 1024, options.clone(), None).unwrap()
+        };
+        let locations = open(&path).append(records.clone());
+        let before = std::fs::read(&path).unwrap();
+
+        // Let a real seal produce the sealed frame of the block.
+        open(&path).append(vec![vec![b'x'; 200]]);
+        let sealed = std::fs::read(&path).unwrap();
+        let block = SEALED_END_SIZE;
+        let open_stored_len =
+            u32::from_le_bytes(before[block..block + 4].try_into().unwrap()) as usize;
+        let sealed_len = FRAME_HEADER_SIZE
+            + u32::from_le_bytes(sealed[block..block + 4].try_into().unwrap()) as usize;
+        let frame = &sealed[block..block + sealed_len];
+        assert_eq!(frame[8], SEALED_COMPRESSED);
+
+        // Crash while the sealed copy was being written over the open frame:
+        // the copy and the mark are on disk, the frame is half overwritten.
+        let mut crashed = before.clone();
+        let distance = open_stored_len.max(sealed_len);
+        let copy = block + FRAME_HEADER_SIZE + distance;
+        if crashed.len() < copy + sealed_len {
+            crashed.resize(copy + sealed_len, 0);
+        }
+        crashed[copy..copy + sealed_len].copy_from_slice(frame);
+        crashed[block..block + FRAME_HEADER_SIZE].copy_from_slice(
+            &BlockRepository::frame_header(distance, open_stored_len, SEALING),
+        );
+        let torn = block + FRAME_HEADER_SIZE + sealed_len / 2;
+        crashed[block + FRAME_HEADER_SIZE..torn].fill(0xee);
+        std::fs::write(&path, &crashed).unwrap();
+
+        let repository = open(&path);
+        assert_eq!(repository.load(&locations).unwrap(), records);
+        let more = repository.append(vec![b"after recovery".to_vec()]);
+        assert_eq!(more[0].0, (block + sealed_len) as u64);
+        assert_eq!(repository.load(&more).unwrap()[0], b"after recovery");
+        assert_eq!(open(&path).load(&locations).unwrap(), records);
+    }
+}
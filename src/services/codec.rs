@@ -0,0 +1,176 @@
+use bincode::Options;
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+    Serialize,
+};
+use std::io;
+
+/// Serialization format of the records an engine writes. Engines store the
+/// `CodecKind` of their codec in the file header and always decode a file
+/// with the codec recorded there.
+pub trait Codec {
+    const KIND: CodecKind;
+
+    fn encode<T: Serialize>(value: &T) -> io::Result<Vec<u8>>;
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T>;
+}
+
+/// bincode with fixed-width integers, the format the engines have always
+/// written. Trailing bytes are ignored so padded slots decode.
+pub struct BincodeCodec;
+
+/// `serde_json` text, for files that should be readable by hand. Its size
+/// is unbounded, so only the dynamic layout accepts it.
+pub struct JsonCodec;
+
+/// bincode with variable-length integers: small numbers take a single
+/// byte. Trailing bytes are ignored so padded slots decode.
+pub struct VarintCodec;
+
+fn varint_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_varint_encoding()
+        .allow_trailing_bytes()
+}
+
+fn invalid(error: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+impl Codec for BincodeCodec {
+    const KIND: CodecKind = CodecKind::Bincode;
+
+    fn encode<T: Serialize>(value: &T) -> io::Result<Vec<u8>> {
+        bincode::serialize(value).map_err(io::Error::other)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+        bincode::deserialize(bytes).map_err(invalid)
+    }
+}
+
+impl Codec for JsonCodec {
+    const KIND: CodecKind = CodecKind::Json;
+
+    fn encode<T: Serialize>(value: &T) -> io::Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(io::Error::other)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+        serde_json::from_slice(bytes).map_err(invalid)
+    }
+}
+
+impl Codec for VarintCodec {
+    const KIND: CodecKind = CodecKind::Varint;
+
+    fn encode<T: Serialize>(value: &T) -> io::Result<Vec<u8>> {
+        varint_options().serialize(value).map_err(io::Error::other)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+        varint_options().deserialize(bytes).map_err(invalid)
+    }
+}
+
+/// Codec identifier stored in file headers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CodecKind {
+    #[default]
+    Bincode,
+    Json,
+    Varint,
+}
+
+impl CodecKind {
+    pub fn encode<T: Serialize>(self, value: &T) -> io::Result<Vec<u8>> {
+        match self {
+            CodecKind::Bincode => BincodeCodec::encode(value),
+            CodecKind::Json => JsonCodec::encode(value),
+            CodecKind::Varint => VarintCodec::encode(value),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> io::Result<T> {
+        match self {
+            CodecKind::Bincode => BincodeCodec::decode(bytes),
+            CodecKind::Json => JsonCodec::decode(bytes),
+            CodecKind::Varint => VarintCodec::decode(bytes),
+        }
+    }
+
+    /// Largest encoding of a value whose fixed-width bincode encoding is at
+    /// most `bincode_size` bytes, or `None` when the codec has no bound.
+    /// A varint takes at most one byte more than half again its fixed
+    /// width (9 bytes for a u64, 3 for a u16).
+    pub fn max_encoded_size(self, bincode_size: usize) -> Option<usize> {
+        match self {
+            CodecKind::Bincode => Some(bincode_size),
+            CodecKind::Json => None,
+            CodecKind::Varint => Some(bincode_size + bincode_size.div_ceil(2)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use dynamic_vector::MaxEncodedSize;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, MaxEncodedSize)]
+    pub struct Reading {
+        id: u64,
+        delta: i16,
+        flag: bool,
+        value: Option<u32>,
+        kind: Kind,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, MaxEncodedSize)]
+    pub enum Kind {
+        Idle,
+        Level(u16),
+    }
+
+    #[test]
+    fn test_codecs_round_trip_within_bound() {
+        let large = Reading {
+            id: u64::MAX,
+            delta: i16::MIN,
+            flag: true,
+            value: Some(u32::MAX),
+            kind: Kind::Level(u16::MAX),
+        };
+        let small = Reading {
+            id: 1,
+            delta: -1,
+            flag: false,
+            value: None,
+            kind: Kind::Idle,
+        };
+        for kind in [CodecKind::Bincode, CodecKind::Json, CodecKind::Varint] {
+            for reading in [&large, &small] {
+                let bytes = kind.encode(reading).unwrap();
+                assert_eq!(&kind.decode::<Reading>(&bytes).unwrap(), reading);
+                if let Some(bound) = kind.max_encoded_size(Reading::MAX_ENCODED_SIZE) {
+                    assert!(bytes.len() <= bound, "{:?}: {}", kind, bytes.len());
+                }
+            }
+        }
+        assert!(
+            VarintCodec::encode(&small).unwrap().len()
+                < BincodeCodec::encode(&small).unwrap().len()
+        );
+        let json = JsonCodec::encode(&small).unwrap();
+        assert!(String::from_utf8(json).unwrap().starts_with("{\"id\":1,"));
+        assert_eq!(
+            JsonCodec::decode::<Reading>(b"not json")
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use crate::services::file_access_service::FileAccessService;
+
+/// Start offset u64, record count u32, varint bytes u32.
+const CHUNK_HEADER_SIZE: usize = 16;
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+/// Decodes the varint at the start of `bytes`, returning it and its size.
+fn read_varint(bytes: &[u8]) -> (u64, usize) {
+    let mut value = 0u64;
+    for (i, byte) in bytes.iter().enumerate() {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return (value, i + 1);
+        }
+    }
+    panic!("truncated varint in compact index");
+}
+
+fn chunk_header(start: u64, count: u32, byte_len: u32) -> [u8; CHUNK_HEADER_SIZE] {
+    let mut header = [0u8; CHUNK_HEADER_SIZE];
+    header[..8].copy_from_slice(&start.to_le_bytes());
+    header[8..12].copy_from_slice(&count.to_le_bytes());
+    header[12..].copy_from_slice(&byte_len.to_le_bytes());
+    header
+}
+
+/// Checkpoint of one chunk, kept in memory.
+struct Chunk {
+    first_index: u64,
+    /// File offset of the chunk header.
+    offset: u64,
+    start: u64,
+    count: u32,
+    byte_len: u32,
+    /// End offset of the last record, where the next contiguous record
+    /// starts.
+    end: u64,
+}
+
+/// Record locations stored as chunks of varint record lengths, each chunk
+/// headed by the absolute start offset of its first record. Records in the
+/// data file are contiguous, so a chunk replaces 16 bytes of (start, end)
+/// per record with a one or two byte length. Chunks hold up to
+/// `checkpoint_interval` records; a gap between records also starts a new
+/// chunk.
+///
+/// Layout after the structure file header:
+/// [start u64][count u32][byte length u32][varint lengths]...
+///
+/// The chunk checkpoints are kept in memory, so a lookup is a binary
+/// search followed by one read of at most one chunk.
+pub(crate) struct CompactIndex {
+    data_offset: u64,
+    checkpoint_interval: u32,
+    chunks: Vec<Chunk>,
+}
+
+impl CompactIndex {
+    /// Loads the checkpoints of the first `length` records, stopping early
+    /// at the end of the file or at a chunk that was never written.
+    ///
+    /// Appends rewrite the last chunk's header before the length marker
+    /// moves, so after a crash it may count records past `length`; the
+    /// chunk is clamped to `length` and the next append overwrites the
+    /// rest.
+    pub(crate) fn open(
+        file_access: &FileAccessService,
+        data_offset: u64,
+        length: u64,
+        checkpoint_interval: u32,
+    ) -> Self {
+        let mut index = Self {
+            data_offset,
+            checkpoint_interval,
+            chunks: Vec::new(),
+        };
+        let file_size = std::fs::metadata(file_access.path())
+            .expect("Unable to get file metadata")
+            .len();
+        let mut offset = data_offset;
+        let mut first_index = 0;
+        while first_index < length && offset + CHUNK_HEADER_SIZE as u64 <= file_size {
+            let header = file_access.read_in_file(offset, CHUNK_HEADER_SIZE);
+            let start = u64::from_le_bytes(header[..8].try_into().unwrap());
+            let count = u32::from_le_bytes(header[8..12].try_into().unwrap());
+            let byte_len = u32::from_le_bytes(header[12..].try_into().unwrap());
+            let chunk_end = offset + (CHUNK_HEADER_SIZE + byte_len as usize) as u64;
+            if count == 0 || chunk_end > file_size {
+                break;
+            }
+            let lengths = file_access
+                .read_in_file(offset + CHUNK_HEADER_SIZE as u64, byte_len as usize);
+            let count = (count as u64).min(length - first_index) as u32;
+            let mut end = start;
+            let mut position = 0;
+            for _ in 0..count {
+                let (len, used) = read_varint(&lengths[position..]);
+                end += len;
+                position += used;
+            }
+            index.chunks.push(Chunk {
+                first_index,
+                offset,
+                start,
+                count,
+                byte_len: position as u32,
+                end,
+            });
+            first_index += count as u64;
+            offset = chunk_end;
+        }
+        index
+    }
+
+    pub(crate) fn len(&self) -> u64 {
+        self.chunks
+            .last()
+            .map_or(0, |chunk| chunk.first_index + chunk.count as u64)
+    }
+
+    /// Bytes the index occupies after the header.
+    pub(crate) fn size(&self) -> u64 {
+        self.chunks.last().map_or(0, |chunk| {
+            chunk.offset + (CHUNK_HEADER_SIZE + chunk.byte_len as usize) as u64
+                - self.data_offset
+        })
+    }
+
+    /// File offset of the chunk holding record `index`, or the end of the
+    /// index when `index` is its length.
+    pub(crate) fn chunk_offset(&self, index: u64) -> u64 {
+        if index >= self.len() {
+            return self.data_offset + self.size();
+        }
+        let position = self
+            .chunks
+            .partition_point(|chunk| chunk.first_index <= index)
+            - 1;
+        self.chunks[position].offset
+    }
+
+    /// The last chunk's header, which appends rewrite in place, with its
+    /// current contents.
+    pub(crate) fn rewritable_region(&self) -> Option<(u64, Vec<u8>)> {
+        self.chunks.last().map(|chunk| {
+            (
+                chunk.offset,
+                chunk_header(chunk.start, chunk.count, chunk.byte_len).to_vec(),
+            )
+        })
+    }
+
+    /// Appends the (start, end) locations of the next records.
+    pub(crate) fn append(
+        &mut self,
+        file_access: &FileAccessService,
+        locations: &[(u64, u64)],
+    ) {
+        if locations.is_empty() {
+            return;
+        }
+        let extended = self.chunks.len().checked_sub(1);
+        let write_from = self.data_offset + self.size();
+        let mut bytes = Vec::new();
+        for &(start, end) in locations {
+            let open = self.chunks.last().filter(|chunk| {
+                chunk.count < self.checkpoint_interval && chunk.end == start
+            });
+            if open.is_none() {
+                let (first_index, offset) = match self.chunks.last() {
+                    Some(last) => (
+                        last.first_index + last.count as u64,
+                        last.offset + (CHUNK_HEADER_SIZE + last.byte_len as usize) as u64,
+                    ),
+                    None => (0, self.data_offset),
+                };
+                // Placeholder; rewritten below once the chunk is complete.
+                bytes.extend_from_slice(&[0u8; CHUNK_HEADER_SIZE]);
+                self.chunks.push(Chunk {
+                    first_index,
+                    offset,
+                    start,
+                    count: 0,
+                    byte_len: 0,
+                    end: start,
+                });
+            }
+            let chunk = self.chunks.last_mut().unwrap();
+            let before = bytes.len();
+            write_varint(&mut bytes, end - start);
+            chunk.count += 1;
+            chunk.byte_len += (bytes.len() - before) as u32;
+            chunk.end = end;
+        }
+
+        // New chunk headers sit inside `bytes`; fill them in before writing.
+        let first_new = extended.map_or(0, |last| last + 1);
+        for chunk in &self.chunks[first_new..] {
+            let at = (chunk.offset - write_from) as usize;
+            bytes[at..at + CHUNK_HEADER_SIZE].copy_from_slice(&chunk_header(
+                chunk.start,
+                chunk.count,
+                chunk.byte_len,
+            ));
+        }
+        file_access.write_in_file(write_from, &bytes);
+        if let Some(last) = extended {
+            let chunk = &self.chunks[last];
+            file_access.write_in_file(
+                chunk.offset,
+                &chunk_header(chunk.start, chunk.count, chunk.byte_len),
+            );
+        }
+    }
+
+    /// (start, end) locations of records `index..index + count`.
+    pub(crate) fn read(
+        &self,
+        file_access: &FileAccessService,
+        index: u64,
+        count: u64,
+    ) -> Vec<(u64, u64)> {
+        assert!(
+            index + count <= self.len(),
+            "records {}..{} out of range of {} indexed records",
+            index,
+            index + count,
+            self.len()
+        );
+        let mut locations = Vec::with_capacity(count as usize);
+        let mut chunk_position = self
+            .chunks
+            .partition_point(|chunk| chunk.first_index <= index)
+            - 1;
+        while (locations.len() as u64) < count {
+            let chunk = &self.chunks[chunk_position];
+            let lengths = file_access.read_in_file(
+                chunk.offset + CHUNK_HEADER_SIZE as u64,
+                chunk.byte_len as usize,
+            );
+            let mut start = chunk.start;
+            let mut position = 0;
+            for record in chunk.first_index..chunk.first_index + chunk.count as u64 {
+                let (len, used) = read_varint(&lengths[position..]);
+                position += used;
+                if record >= index {
+                    locations.push((start, start + len));
+                    if locations.len() as u64 == count {
+                        break;
+                    }
+                }
+                start += len;
+            }
+            chunk_position += 1;
+        }
+        locations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::TestDir;
+
+    #[test]
+    fn test_compact_index_append_and_read() {
+        let dir = TestDir::new("compact_index_append_and_read");
+        let path = dir.path("test_compact_index.bin");
+        let file_access = FileAccessService::new(path.clone(), 1024);
+        let data_offset = 24;
+
+        // Contiguous records with one gap, lengths up to two varint bytes.
+        let mut locations = Vec::new();
+        let mut start = 8;
+        for i in 0..1000u64 {
+            if i == 500 {
+                start += 77;
+            }
+            let len = (i * 37) % 300;
+            locations.push((start, start + len));
+            start += len;
+        }
+
+        let mut index = CompactIndex::open(&file_access, data_offset, 0, 64);
+        index.append(&file_access, &locations[..10]);
+        for location in &locations[10..20] {
+            index.append(&file_access, &[*location]);
+        }
+        index.append(&file_access, &locations[20..]);
+        assert_eq!(index.len(), 1000);
+        assert!(index.size() < 1000 * 16 / 6);
+        assert_eq!(index.read(&file_access, 0, 1000), locations);
+
+        let reopened = CompactIndex::open(&file_access, data_offset, 1000, 64);
+        assert_eq!(reopened.size(), index.size());
+        assert_eq!(reopened.read(&file_access, 499, 3), locations[499..502]);
+        assert_eq!(reopened.read(&file_access, 999, 1), locations[999..]);
+        assert_eq!(reopened.read(&file_access, 63, 66), locations[63..129]);
+    }
+
+    #[test]
+    fn test_compact_index_clamped_after_crash() {
+        let dir = TestDir::new("compact_index_clamped_after_crash");
+        let path = dir.path("test_compact_index.bin");
+        let file_access = FileAccessService::new(path.clone(), 1024);
+        let data_offset = 24;
+        let locations: Vec<(u64, u64)> = (0..40u64)
+            .map(|i| (8 + i * 200, 8 + (i + 1) * 200))
+            .collect();
+
+        let mut index = CompactIndex::open(&file_access, data_offset, 0, 16);
+        index.append(&file_access, &locations[..10]);
+        // Crash after the chunk header moved past the published length:
+        // the open chunk now claims 16 records and a second one follows.
+        index.append(&file_access, &locations[10..20]);
+
+        let mut reopened = CompactIndex::open(&file_access, data_offset, 10, 16);
+        assert_eq!(reopened.len(), 10);
+        assert_eq!(reopened.read(&file_access, 0, 10), locations[..10]);
+
+        // Records written again after recovery take different locations.
+        let retried: Vec<(u64, u64)> = (0..30u64)
+            .map(|i| (2008 + i * 3, 2008 + (i + 1) * 3))
+            .collect();
+        reopened.append(&file_access, &retried);
+        let expected = [&locations[..10], &retried[..]].concat();
+        assert_eq!(reopened.read(&file_access, 0, 40), expected);
+
+        let reopened = CompactIndex::open(&file_access, data_offset, 40, 16);
+        assert_eq!(reopened.len(), 40);
+        assert_eq!(reopened.read(&file_access, 0, 40), expected);
+    }
+}
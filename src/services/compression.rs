@@ -0,0 +1,175 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::io;
+
+#[cfg(feature = "zstd")]
+use crate::services::codec::CodecKind;
+
+/// Compression applied to each sealed block of a dynamic data file. The
+/// choice is recorded in the structure file header together with the block
+/// size, so a collection always reopens with the compression it was
+/// written with.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Blocks are stored as written.
+    None,
+    /// LZ4 block format from the pure-Rust `lz4_flex`.
+    #[default]
+    Lz4,
+    /// zstd at `level`, optionally primed with a dictionary trained from
+    /// sample records, which helps most when records are small.
+    #[cfg(feature = "zstd")]
+    Zstd {
+        level: i32,
+        dictionary: Option<Vec<u8>>,
+    },
+}
+
+fn invalid(error: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+impl Compression {
+    pub fn compress(&self, raw: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(raw.to_vec()),
+            Compression::Lz4 => Ok(lz4_flex::block::compress(raw)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd {
+                level,
+                dictionary: Some(dictionary),
+            } => {
+                zstd::bulk::Compressor::with_dictionary(*level, dictionary)?.compress(raw)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd {
+                level,
+                dictionary: None,
+            } => zstd::bulk::compress(raw, *level),
+        }
+    }
+
+    /// `raw_len` is the size of the block before compression.
+    pub fn decompress(&self, stored: &[u8], raw_len: usize) -> io::Result<Vec<u8>> {
+        let raw = match self {
+            Compression::None => stored.to_vec(),
+            Compression::Lz4 => {
+                lz4_flex::block::decompress(stored, raw_len).map_err(invalid)?
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd {
+                dictionary: Some(dictionary),
+                ..
+            } => zstd::bulk::Decompressor::with_dictionary(dictionary)?
+                .decompress(stored, raw_len)
+                .map_err(invalid)?,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd {
+                dictionary: None, ..
+            } => zstd::bulk::decompress(stored, raw_len).map_err(invalid)?,
+        };
+        if raw.len() != raw_len {
+            return Err(invalid(format!(
+                "block decompressed to {} bytes, expected {}",
+                raw.len(),
+                raw_len
+            )));
+        }
+        Ok(raw)
+    }
+
+    /// zstd compression with a dictionary of at most `max_dictionary_size`
+    /// bytes trained on `samples`, encoded with `codec` as they will be
+    /// stored. zstd needs a reasonably large and varied sample set and
+    /// fails with an error otherwise.
+    #[cfg(feature = "zstd")]
+    pub fn train_zstd<T: Serialize>(
+        level: i32,
+        codec: CodecKind,
+        samples: &[T],
+        max_dictionary_size: usize,
+    ) -> io::Result<Self> {
+        let samples = samples
+            .iter()
+            .map(|sample| codec.encode(sample))
+            .collect::<io::Result<Vec<Vec<u8>>>>()?;
+        let dictionary = zstd::dict::from_samples(&samples, max_dictionary_size)?;
+        Ok(Compression::Zstd {
+            level,
+            dictionary: Some(dictionary),
+        })
+    }
+}
+
+/// Groups records into blocks of about `block_size` raw bytes that are
+/// compressed as a unit. Larger blocks compress better; smaller ones make a
+/// random read decompress less.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BlockOptions {
+    pub compression: Compression,
+    pub block_size: u32,
+}
+
+impl Default for BlockOptions {
+    fn default() -> Self {
+        Self {
+            compression: Compression::default(),
+            block_size: 64 * 1024,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample_text() -> Vec<u8> {
+        (0..200)
+            .map(|i| format!("user {} said: the build is green again ", i % 7))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    #[test]
+    fn test_compression_round_trip() {
+        let raw = sample_text();
+        let all = [
+            Compression::None,
+            Compression::Lz4,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd {
+                level: 3,
+                dictionary: None,
+            },
+        ];
+        for compression in all {
+            let stored = compression.compress(&raw).unwrap();
+            if compression != Compression::None {
+                assert!(stored.len() < raw.len() / 4, "{:?}", compression);
+            }
+            assert_eq!(compression.decompress(&stored, raw.len()).unwrap(), raw);
+            assert!(compression.decompress(&stored, raw.len() + 1).is_err());
+        }
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_dictionary() {
+        let samples: Vec<String> = (0..1000)
+            .map(|i| {
+                format!(
+                    "{{\"sender\":\"user-{}\",\"text\":\"message {}\"}}",
+                    i % 13,
+                    i
+                )
+            })
+            .collect();
+        let compression =
+            Compression::train_zstd(3, CodecKind::Json, &samples, 4096).unwrap();
+        let raw = CodecKind::Json.encode(&samples[42]).unwrap();
+        let stored = compression.compress(&raw).unwrap();
+        assert_eq!(compression.decompress(&stored, raw.len()).unwrap(), raw);
+    }
+}
@@ -4,6 +4,7 @@ use serde::{
     Serialize,
 };
 use std::{
+    borrow::Cow,
     io::{
         self,
     },
@@ -12,24 +13,139 @@ use std::{
     sync::{
         Arc,
         Mutex,
+        RwLock,
     },
-    time::Instant,
 };
 
 use crate::services::{
+    backup::{
+        self,
+        BackupInfo,
+        Capture,
+        Segment,
+    },
+    block_repository::BlockRepository,
+    codec::CodecKind,
+    compact_index::CompactIndex,
+    compression::BlockOptions,
+    encryption::{
+        KeyProvider,
+        PayloadCipher,
+    },
     file_access_service::FileAccessService,
-    string_repository::StringRepository,
+    file_swap::{
+        finish_swap,
+        swap_in,
+    },
+    repair::{
+        decodes_as,
+        repair_with,
+        RepairOptions,
+        RepairReport,
+    },
+    string_repository::{
+        frame_record,
+        unframe_record,
+        StringRepository,
+    },
 };
 
 const LENGTH_MARKER_SIZE: usize = size_of::<u64>();
 
+/// How a dynamic collection stores its records. Stored as the header of
+/// the structure file, so options only apply when the files are created
+/// and a collection always reopens the way it was written.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DynamicOptions {
+    pub codec: CodecKind,
+    /// Compress records in blocks; `None` stores each record as encoded.
+    pub blocks: Option<BlockOptions>,
+    pub index: IndexFormat,
+    /// Frame each record with a length, a checksum and a sync marker, so
+    /// the index can be rebuilt from the data file with [`rebuild_index`].
+    /// Only for uncompressed data files.
+    pub framed: bool,
+}
+
+/// Layout of the record locations in the structure file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IndexFormat {
+    /// A (start, end) pair of u64 offsets per record.
+    #[default]
+    Pairs,
+    /// Varint record lengths with the absolute start offset checkpointed
+    /// every `checkpoint_interval` records, usually 4-8x smaller than
+    /// pairs. A lookup decodes at most one interval of lengths. Not
+    /// available for compressed blocks, whose locations are not contiguous.
+    Compact { checkpoint_interval: u32 },
+}
+
+fn check_options(options: &DynamicOptions) -> io::Result<()> {
+    match options.index {
+        IndexFormat::Compact { .. } if options.blocks.is_some() => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "compact index is not supported with compressed blocks",
+        )),
+        IndexFormat::Compact {
+            checkpoint_interval: 0,
+        } => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "checkpoint interval must be positive",
+        )),
+        _ if options.framed && options.blocks.is_some() => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "framed records are not supported with compressed blocks",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Records per read/write batch while re-encrypting a collection.
+const REENCRYPT_BATCH: u64 = 4096;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct DynamicHeader {
+    pub(crate) options: DynamicOptions,
+    /// Key the data file is encrypted with, `None` when it is plaintext.
+    pub(crate) key_id: Option<String>,
+}
+
+enum DataFile {
+    Plain(StringRepository),
+    Blocks(BlockRepository),
+}
+
+/// Record store that encodes each record into the data file and keeps its
+/// location in the structure file: the (start, end) offsets in a plain
+/// data file, or the block offset and packed position in the block of a
+/// compressed one.
+///
+/// Encrypted collections seal every record, or every block of a compressed
+/// data file, with ChaCha20-Poly1305 and a random nonce.
+///
+/// File layout: [length u64][magic u32][version u32][header size u64][bincode
+/// header][offset pairs], or compact index chunks with
+/// [`IndexFormat::Compact`]. Files in the headerless `[length][pairs]` layout
+/// of earlier versions are refused until [`convert_headerless`] is run.
 pub struct DynamicVectorManageService<T>
 where
     T: Serialize + for<'de> Deserialize<'de> + Send,
 {
     length: Arc<Mutex<u64>>,
     structure_file: Mutex<FileAccessService>,
-    string_repository: StringRepository,
+    data_file: DataFile,
+    options: DynamicOptions,
+    key_id: Option<String>,
+    /// Encrypts single records of a plain data file; block data files
+    /// encrypt whole blocks instead.
+    record_cipher: Option<PayloadCipher>,
+    data_offset: u64,
+    /// Held across the data write and the index append, so compact index
+    /// entries are appended in data file order.
+    compact_index: Option<Mutex<CompactIndex>>,
+    /// Held for reading by every append and for writing while a backup
+    /// captures the files, so no append is half written at that point.
+    appends: RwLock<()>,
     _marker: PhantomData<T>,
 }
 
@@ -43,15 +159,143 @@ where
         + Send
         + Sync,
 {
+    /// Opens the store with bincode records, or with the codec recorded in
+    /// the file header when the file already exists.
     pub fn new(
         structure_file_path: String,
         string_file_path: String,
         initial_size_if_not_exists: u64,
     ) -> io::Result<Self> {
+        Self::with_codec(
+            structure_file_path,
+            string_file_path,
+            initial_size_if_not_exists,
+            CodecKind::Bincode,
+        )
+    }
+
+    /// Opens the store, recording `codec` in the header of a new file. An
+    /// existing file keeps the codec it was created with.
+    pub fn with_codec(
+        structure_file_path: String,
+        string_file_path: String,
+        initial_size_if_not_exists: u64,
+        codec: CodecKind,
+    ) -> io::Result<Self> {
+        Self::with_options(
+            structure_file_path,
+            string_file_path,
+            initial_size_if_not_exists,
+            DynamicOptions {
+                codec,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Opens the store, recording `options` in the header of a new file.
+    /// An existing file keeps the options it was created with. Encrypted
+    /// files fail with `PermissionDenied`; open them with
+    /// [`with_encryption`](Self::with_encryption).
+    pub fn with_options(
+        structure_file_path: String,
+        string_file_path: String,
+        initial_size_if_not_exists: u64,
+        options: DynamicOptions,
+    ) -> io::Result<Self> {
+        Self::open(
+            structure_file_path,
+            string_file_path,
+            initial_size_if_not_exists,
+            options,
+            None,
+        )
+    }
+
+    /// Opens an encrypted store. A new file is encrypted with the current
+    /// key of `keys`; an existing one with the key recorded in its header,
+    /// which `keys` must still provide. Plaintext files fail with
+    /// `InvalidInput`; encrypt them with [`reencrypt`](Self::reencrypt).
+    pub fn with_encryption(
+        structure_file_path: String,
+        string_file_path: String,
+        initial_size_if_not_exists: u64,
+        options: DynamicOptions,
+        keys: &dyn KeyProvider,
+    ) -> io::Result<Self> {
+        let service = Self::open(
+            structure_file_path,
+            string_file_path,
+            initial_size_if_not_exists,
+            options,
+            Some(keys),
+        )?;
+        if service.key_id.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "collection is not encrypted",
+            ));
+        }
+        Ok(service)
+    }
+
+    pub(crate) fn open(
+        structure_file_path: String,
+        string_file_path: String,
+        initial_size_if_not_exists: u64,
+        options: DynamicOptions,
+        keys: Option<&dyn KeyProvider>,
+    ) -> io::Result<Self> {
+        finish_swap(&structure_file_path, &string_file_path)?;
         let structure_file_access =
             FileAccessService::new(structure_file_path, initial_size_if_not_exists);
-        let string_repository =
-            StringRepository::new(string_file_path.clone(), initial_size_if_not_exists);
+
+        let header = match structure_file_access.read_header::<DynamicHeader>()? {
+            Some(existing) => existing,
+            None => {
+                check_options(&options)?;
+                let header = DynamicHeader {
+                    options,
+                    key_id: keys.map(|keys| keys.current_key_id()).transpose()?,
+                };
+                structure_file_access.write_header(&header)?;
+                header
+            }
+        };
+        let data_offset = FileAccessService::data_offset(&header)?;
+        let cipher = match (&header.key_id, keys) {
+            (Some(key_id), Some(keys)) => Some(PayloadCipher::new(key_id, keys)?),
+            (Some(key_id), None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!(
+                        "{} is encrypted with key {}",
+                        structure_file_access.path(),
+                        key_id
+                    ),
+                ));
+            }
+            (None, _) => None,
+        };
+        let DynamicHeader { options, key_id } = header;
+        let (data_file, record_cipher) = match &options.blocks {
+            Some(blocks) => (
+                DataFile::Blocks(BlockRepository::new(
+                    string_file_path,
+                    initial_size_if_not_exists,
+                    blocks.clone(),
+                    cipher,
+                )?),
+                None,
+            ),
+            None => (
+                DataFile::Plain(StringRepository::new(
+                    string_file_path,
+                    initial_size_if_not_exists,
+                )),
+                cipher,
+            ),
+        };
         let length = {
             let buffer = structure_file_access.read_in_file(0, LENGTH_MARKER_SIZE);
 
@@ -61,14 +305,186 @@ where
 
             Arc::new(Mutex::new(length))
         };
+        let compact_index = match options.index {
+            IndexFormat::Pairs => None,
+            IndexFormat::Compact {
+                checkpoint_interval,
+            } => Some(Mutex::new(CompactIndex::open(
+                &structure_file_access,
+                data_offset,
+                *length.lock().unwrap(),
+                checkpoint_interval,
+            ))),
+        };
         Ok(Self {
             length,
             structure_file: Mutex::new(structure_file_access),
-            string_repository,
+            data_file,
+            options,
+            key_id,
+            record_cipher,
+            data_offset,
+            compact_index,
+            appends: RwLock::new(()),
             _marker: PhantomData,
         })
     }
 
+    /// Codec recorded in the file header.
+    pub fn codec(&self) -> CodecKind {
+        self.options.codec
+    }
+
+    /// Options recorded in the file header.
+    pub fn options(&self) -> &DynamicOptions {
+        &self.options
+    }
+
+    /// Id of the key the collection is encrypted with.
+    pub fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    /// Rewrites a collection so it is encrypted with the current key of
+    /// `keys`, decrypting it with the key in its header; plaintext
+    /// collections become encrypted. The collection must not be open
+    /// elsewhere. Returns the number of records written; on error the
+    /// original files are left in place. If the process stops while the
+    /// rewritten files are being renamed over the originals, the next open
+    /// finishes the renames.
+    pub fn reencrypt(
+        structure_file_path: &str,
+        string_file_path: &str,
+        keys: &dyn KeyProvider,
+    ) -> io::Result<u64> {
+        if !std::path::Path::new(structure_file_path).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} does not exist", structure_file_path),
+            ));
+        }
+        let source = Self::open(
+            structure_file_path.to_string(),
+            string_file_path.to_string(),
+            1024,
+            DynamicOptions::default(),
+            Some(keys),
+        )?;
+        let length = source.get_length();
+        let structure_tmp = format!("{}.reencrypting", structure_file_path);
+        let string_tmp = format!("{}.reencrypting", string_file_path);
+        for path in [&structure_tmp, &string_tmp] {
+            if std::path::Path::new(path).exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        let result = Self::open(
+            structure_tmp.clone(),
+            string_tmp.clone(),
+            1024,
+            source.options.clone(),
+            Some(keys),
+        )
+        .map(|target| {
+            let mut index = 0;
+            while index < length {
+                let count = REENCRYPT_BATCH.min(length - index);
+                target.save_bulk(source.load_bulk(index, count));
+                index += count;
+            }
+        });
+        if let Err(error) = result {
+            let _ = std::fs::remove_file(&structure_tmp);
+            let _ = std::fs::remove_file(&string_tmp);
+            return Err(error);
+        }
+        drop(source);
+        swap_in(structure_file_path, string_file_path, ".reencrypting")?;
+        Ok(length)
+    }
+
+    /// Like [`repair`](crate::repair), also dropping records that do not
+    /// deserialize as `T`.
+    pub fn repair(
+        structure_file_path: &str,
+        string_file_path: &str,
+        options: RepairOptions,
+    ) -> io::Result<RepairReport> {
+        repair_with(
+            structure_file_path,
+            string_file_path,
+            options,
+            Some(&decodes_as::<T>),
+        )
+    }
+
+    /// Copies both files as of the committed length into `dest_dir` while
+    /// appends continue; appends wait only while the length is captured.
+    /// Restore it with [`restore`](crate::restore).
+    pub fn snapshot(&self, dest_dir: &str) -> io::Result<BackupInfo> {
+        backup::snapshot(
+            self.capture(0)?,
+            &self.structure_file_path(),
+            self.data_file_path(),
+            dest_dir,
+        )
+    }
+
+    /// Copies the records appended since the collection had `since_len`
+    /// records into `dest_dir`, next to a snapshot or earlier increments.
+    pub fn incremental_backup(
+        &self,
+        dest_dir: &str,
+        since_len: u64,
+    ) -> io::Result<BackupInfo> {
+        backup::incremental_backup(
+            self.capture(since_len)?,
+            &self.structure_file_path(),
+            self.data_file_path(),
+            dest_dir,
+        )
+    }
+
+    fn encode_record(&self, obj: &T) -> Vec<u8> {
+        self.seal_payload(self.codec().encode(obj).expect("Serialization failed"))
+    }
+
+    /// Encrypts and frames codec output as the options require.
+    fn seal_payload(&self, bytes: Vec<u8>) -> Vec<u8> {
+        let bytes = match &self.record_cipher {
+            Some(cipher) => cipher.seal(&bytes),
+            None => bytes,
+        };
+        if self.options.framed {
+            frame_record(&bytes)
+        } else {
+            bytes
+        }
+    }
+
+    fn decode_record(&self, bytes: &[u8]) -> T {
+        self.codec()
+            .decode(&self.open_payload(bytes))
+            .expect("deserialization failed")
+    }
+
+    /// Codec output of a stored record; the reverse of `seal_payload`.
+    fn open_payload<'a>(&self, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+        let bytes = if self.options.framed {
+            unframe_record(bytes).expect("Corrupted record")
+        } else {
+            bytes
+        };
+        match &self.record_cipher {
+            Some(cipher) => Cow::Owned(cipher.open(bytes).expect("Decryption failed")),
+            None => Cow::Borrowed(bytes),
+        }
+    }
+
+    fn pair_offset(&self, index: u64) -> u64 {
+        self.data_offset + index * 2 * LENGTH_MARKER_SIZE as u64
+    }
+
     pub fn get_length(&self) -> u64 {
         let structure_file_guard = self.structure_file.lock().unwrap();
         let buffer = structure_file_guard.read_in_file(0, LENGTH_MARKER_SIZE);
@@ -85,57 +501,36 @@ where
     }
 
     fn save_dynamic(&self, obj: T) -> (u64, u64) {
-        let bytes = bincode::serialize(&obj).expect("Serialization failed");
-        let (start_offset, end_offset) = self
-            .string_repository
-            .write_string_content_and_get_offset(bytes);
-
-        (start_offset, end_offset)
+        let bytes = self.encode_record(&obj);
+        match &self.data_file {
+            DataFile::Plain(string_repository) => {
+                string_repository.write_string_content_and_get_offset(bytes)
+            }
+            DataFile::Blocks(block_repository) => block_repository.append(vec![bytes])[0],
+        }
     }
 
     pub fn save_dynamic_bulk(&self, objs: Vec<T>) -> Vec<(u64, u64)> {
-        let start = Instant::now();
-
-        let (bytes, length_list): (Vec<u8>, Vec<u64>) = objs
-            .par_iter()
-            .map(|obj| {
-                let serialized = bincode::serialize(&obj).expect("Serialization failed");
-                let len = serialized.len() as u64;
-                (serialized, len)
-            })
-            .fold(
-                || (Vec::new(), Vec::new()),
-                |mut acc, (serialized, len)| {
-                    acc.0.extend(serialized);
-                    acc.1.push(len);
-                    acc
-                },
-            )
-            .reduce(
-                || (Vec::new(), Vec::new()),
-                |(mut bytes1, mut lengths1), (bytes2, lengths2)| {
-                    bytes1.extend(bytes2);
-                    lengths1.extend(lengths2);
-                    (bytes1, lengths1)
-                },
-            );
+        let records: Vec<Vec<u8>> =
+            objs.par_iter().map(|obj| self.encode_record(obj)).collect();
+        self.write_sealed_bulk(records)
+    }
 
-        let collect_length_list_duration = start.elapsed();
-        println!(
-            "collect length list took: {:?}",
-            collect_length_list_duration
-        );
+    /// Writes records already passed through `seal_payload` to the data
+    /// file and returns their locations.
+    fn write_sealed_bulk(&self, records: Vec<Vec<u8>>) -> Vec<(u64, u64)> {
+        let string_repository = match &self.data_file {
+            DataFile::Plain(string_repository) => string_repository,
+            DataFile::Blocks(block_repository) => return block_repository.append(records),
+        };
+        let length_list: Vec<u64> =
+            records.iter().map(|record| record.len() as u64).collect();
+        let bytes = records.concat();
 
-        let (start_offset, _) = self
-            .string_repository
-            .write_string_content_and_get_offset(bytes);
-        let write_vector_content_duration = start.elapsed();
-        println!(
-            "persist vector content  on  dist took: {:?}",
-            write_vector_content_duration - collect_length_list_duration
-        );
+        let (start_offset, _) =
+            string_repository.write_string_content_and_get_offset(bytes);
 
-        let offsets_list = length_list
+        length_list
             .into_iter()
             .scan(start_offset, |current_offset, length| {
                 let start = *current_offset;
@@ -143,22 +538,19 @@ where
                 *current_offset = end;
                 Some((start, end))
             })
-            .collect::<Vec<(u64, u64)>>();
-
-        let collect_offsets_duration = start.elapsed();
-        println!(
-            "collect offsets took: {:?}",
-            collect_offsets_duration - write_vector_content_duration
-        );
-
-        offsets_list
+            .collect::<Vec<(u64, u64)>>()
     }
 
     fn load_dynamic(&self, start_offset: u64, end_offset: u64) -> T {
-        let bytes: Vec<u8> = self
-            .string_repository
-            .load_string_content(start_offset, end_offset);
-        let obj: T = bincode::deserialize(&bytes).expect("deserialization failed");
+        let bytes: Vec<u8> = match &self.data_file {
+            DataFile::Plain(string_repository) => string_repository
+                .load_string_content(start_offset, end_offset - start_offset),
+            DataFile::Blocks(block_repository) => block_repository
+                .load(&[(start_offset, end_offset)])
+                .expect("Unable to read block")
+                .remove(0),
+        };
+        let obj: T = self.decode_record(&bytes);
         obj
     }
 
@@ -166,46 +558,68 @@ where
         &self,
         start_offset_and_end_offset_list: Vec<(u64, u64)>,
     ) -> Vec<T> {
+        self.load_sealed_bulk(start_offset_and_end_offset_list)
+            .par_iter()
+            .map(|obj| self.decode_record(obj))
+            .collect()
+    }
+
+    /// Stored bytes of the records at the given locations.
+    fn load_sealed_bulk(
+        &self,
+        start_offset_and_end_offset_list: Vec<(u64, u64)>,
+    ) -> Vec<Vec<u8>> {
+        let string_repository = match &self.data_file {
+            DataFile::Plain(string_repository) => string_repository,
+            DataFile::Blocks(block_repository) => {
+                return block_repository
+                    .load(&start_offset_and_end_offset_list)
+                    .expect("Unable to read block");
+            }
+        };
         let start_offset = start_offset_and_end_offset_list[0].0;
         let end_offset = start_offset_and_end_offset_list
             [start_offset_and_end_offset_list.len() - 1]
             .1;
-        let bytes: Vec<u8> = self
-            .string_repository
-            .load_string_content(start_offset, end_offset);
-        let length_list: Vec<u64> = start_offset_and_end_offset_list
-            .par_iter()
-            .map(|obj| obj.1 - obj.0)
-            .collect();
+        let bytes: Vec<u8> = string_repository
+            .load_string_content(start_offset, end_offset - start_offset);
 
-        let mut start = 0;
-        let byte_vectors: Vec<Vec<u8>> = length_list
+        // Records are sliced by offset rather than by length, since a
+        // rebuilt index skips damaged frames and leaves gaps.
+        let byte_vectors: Vec<Vec<u8>> = start_offset_and_end_offset_list
             .into_iter()
-            .map(|length| {
-                let length = length as usize;
-
-                assert!(
-                    start + length <= bytes.len(),
-                    "Invalid length_list or bytes!"
+            .map(|(start, end)| {
+                let (start, end) = (
+                    (start - start_offset) as usize,
+                    (end - start_offset) as usize,
                 );
 
-                let segment = bytes[start..start + length].to_vec();
-
-                start += length;
+                assert!(end <= bytes.len(), "Invalid length_list or bytes!");
 
-                segment
+                bytes[start..end].to_vec()
             })
             .collect();
 
-        let objs: Vec<T> = byte_vectors
-            .par_iter()
-            .map(|obj| bincode::deserialize(obj).expect("Serialization failed"))
-            .collect();
+        byte_vectors
+    }
 
-        objs
+    /// Appends `locations` to the compact index, then publishes them by
+    /// moving the length marker.
+    fn append_compact(&self, compact_index: &mut CompactIndex, locations: &[(u64, u64)]) {
+        compact_index.append(&self.structure_file.lock().unwrap(), locations);
+        let mut length = self.length.lock().unwrap();
+        *length += locations.len() as u64;
+        self.save_length(*length);
     }
 
     pub fn save(&self, obj: T) {
+        let _appends = self.appends.read().unwrap();
+        if let Some(compact_index) = &self.compact_index {
+            let mut compact_index = compact_index.lock().unwrap();
+            let location = self.save_dynamic(obj);
+            self.append_compact(&mut compact_index, &[location]);
+            return;
+        }
         let index_to_write = {
             let mut length = self.length.lock().unwrap();
             let index = *length;
@@ -213,8 +627,7 @@ where
             self.save_length(*length);
             index
         };
-        let file_offset =
-            index_to_write * LENGTH_MARKER_SIZE as u64 * 2 + LENGTH_MARKER_SIZE as u64;
+        let file_offset = self.pair_offset(index_to_write);
         let (start_offset, end_offset) = self.save_dynamic(obj);
         let mut bytes_offset: Vec<u8> = start_offset.to_le_bytes().to_vec();
         let bytes_total_length: Vec<u8> = end_offset.to_le_bytes().to_vec();
@@ -224,8 +637,15 @@ where
     }
 
     pub fn load(&self, index: u64) -> T {
-        let file_offset =
-            2 * LENGTH_MARKER_SIZE as u64 * index + LENGTH_MARKER_SIZE as u64;
+        if let Some(compact_index) = &self.compact_index {
+            let (start_offset, end_offset) = compact_index.lock().unwrap().read(
+                &self.structure_file.lock().unwrap(),
+                index,
+                1,
+            )[0];
+            return self.load_dynamic(start_offset, end_offset);
+        }
+        let file_offset = self.pair_offset(index);
 
         let file_guard = self.structure_file.lock().unwrap();
         let marker_data: Vec<u8> =
@@ -236,12 +656,16 @@ where
         let start_offset = u64::from_le_bytes(start_offset_bytes.try_into().unwrap());
         let end_offset = u64::from_le_bytes(end_offset_bytes.try_into().unwrap());
 
-        let obj: T = self.load_dynamic(start_offset, end_offset - start_offset);
+        let obj: T = self.load_dynamic(start_offset, end_offset);
 
         obj
     }
 
     pub fn _save_bulk1(&self, objs: Vec<T>) {
+        if self.compact_index.is_some() {
+            return self.save_bulk(objs);
+        }
+        let _appends = self.appends.read().unwrap();
         let (index_to_write, _length) = {
             let count = objs.len();
             let mut length = self.length.lock().unwrap();
@@ -250,16 +674,8 @@ where
             self.save_length(*length);
             (index, *length)
         };
-        let file_offset =
-            index_to_write * LENGTH_MARKER_SIZE as u64 * 2 + LENGTH_MARKER_SIZE as u64;
-        let start = Instant::now();
+        let file_offset = self.pair_offset(index_to_write);
         let start_offset_and_end_offset: Vec<(u64, u64)> = self.save_dynamic_bulk(objs);
-        let save_dynamic_duration = start.elapsed();
-        println!(
-            "save {} dynamic objs  total cost: {:?}",
-            start_offset_and_end_offset.len(),
-            save_dynamic_duration
-        );
         let offset_buffer: Vec<u8> = start_offset_and_end_offset
             .par_iter()
             .map(|obj| {
@@ -273,39 +689,35 @@ where
             })
             .flatten()
             .collect::<Vec<u8>>();
-        let collect_offsets_duration = start.elapsed();
-        println!(
-            "collect offsets bytes took: {:?}",
-            collect_offsets_duration - save_dynamic_duration
-        );
         let file_guard = self.structure_file.lock().unwrap();
         file_guard.write_in_file(file_offset, &offset_buffer);
-        let write_offsets_duration = start.elapsed();
-        println!(
-            "write offsets took: {:?}",
-            write_offsets_duration - collect_offsets_duration
-        );
     }
 
     pub fn save_bulk(&self, objs: Vec<T>) {
+        let records: Vec<Vec<u8>> =
+            objs.par_iter().map(|obj| self.encode_record(obj)).collect();
+        self.save_sealed_bulk(records);
+    }
+
+    fn save_sealed_bulk(&self, records: Vec<Vec<u8>>) {
+        let _appends = self.appends.read().unwrap();
+        if let Some(compact_index) = &self.compact_index {
+            let mut compact_index = compact_index.lock().unwrap();
+            let locations = self.write_sealed_bulk(records);
+            self.append_compact(&mut compact_index, &locations);
+            return;
+        }
         let (index_to_write, _length) = {
-            let count = objs.len();
+            let count = records.len();
             let mut length = self.length.lock().unwrap();
             let index = *length;
             *length += count as u64;
             self.save_length(*length);
             (index, *length)
         };
-        let file_offset =
-            index_to_write * LENGTH_MARKER_SIZE as u64 * 2 + LENGTH_MARKER_SIZE as u64;
-        let start = Instant::now();
-        let start_offset_and_end_offset: Vec<(u64, u64)> = self.save_dynamic_bulk(objs);
-        let save_dynamic_duration = start.elapsed();
-        println!(
-            "save {} dynamic objs  total cost: {:?}",
-            start_offset_and_end_offset.len(),
-            save_dynamic_duration
-        );
+        let file_offset = self.pair_offset(index_to_write);
+        let start_offset_and_end_offset: Vec<(u64, u64)> =
+            self.write_sealed_bulk(records);
         let offset_buffer: Vec<u8> = start_offset_and_end_offset
             .into_par_iter()
             .flat_map(|(start_offset, end_offset)| {
@@ -315,23 +727,23 @@ where
                 local_buffer.to_vec()
             })
             .collect();
-        let collect_offsets_duration = start.elapsed();
-        println!(
-            "collect offsets bytes took: {:?}",
-            collect_offsets_duration - save_dynamic_duration
-        );
         let file_guard = self.structure_file.lock().unwrap();
         file_guard.write_in_file(file_offset, &offset_buffer);
-        let write_offsets_duration = start.elapsed();
-        println!(
-            "write offsets took: {:?}",
-            write_offsets_duration - collect_offsets_duration
-        );
     }
 
     pub fn load_bulk(&self, index: u64, count: u64) -> Vec<T> {
-        let file_offset =
-            2 * LENGTH_MARKER_SIZE as u64 * index + LENGTH_MARKER_SIZE as u64;
+        self.load_dynamic_bulk(self.read_locations(index, count))
+    }
+
+    fn read_locations(&self, index: u64, count: u64) -> Vec<(u64, u64)> {
+        if let Some(compact_index) = &self.compact_index {
+            return compact_index.lock().unwrap().read(
+                &self.structure_file.lock().unwrap(),
+                index,
+                count,
+            );
+        }
+        let file_offset = self.pair_offset(index);
         let file_guard = self.structure_file.lock().unwrap();
         let marker_data: Vec<u8> =
             file_guard.read_in_file(file_offset, 2 * LENGTH_MARKER_SIZE * count as usize);
@@ -353,15 +765,368 @@ where
                 (part1, part2)
             })
             .collect();
-        let objs: Vec<T> = self.load_dynamic_bulk(start_offset_and_end_offset_list);
 
-        objs
+        start_offset_and_end_offset_list
+    }
+
+    /// Records `index..index + count` as encoded by the codec, without
+    /// deserializing them.
+    pub(crate) fn load_encoded_bulk(&self, index: u64, count: u64) -> Vec<Vec<u8>> {
+        self.load_sealed_bulk(self.read_locations(index, count))
+            .par_iter()
+            .map(|bytes| self.open_payload(bytes).into_owned())
+            .collect()
+    }
+
+    /// Appends records already encoded with the collection's codec.
+    pub(crate) fn save_encoded_bulk(&self, records: Vec<Vec<u8>>) {
+        let records = records
+            .into_par_iter()
+            .map(|bytes| self.seal_payload(bytes))
+            .collect();
+        self.save_sealed_bulk(records);
+    }
+
+    /// Bytes of the structure file and of the data file in use; the files
+    /// grow ahead of their contents.
+    pub(crate) fn used_sizes(&self) -> (u64, u64) {
+        let structure = match &self.compact_index {
+            Some(compact_index) => {
+                self.data_offset + compact_index.lock().unwrap().size()
+            }
+            None => self.pair_offset(self.get_length()),
+        };
+        let data = match &self.data_file {
+            DataFile::Plain(string_repository) => string_repository.used_size(),
+            DataFile::Blocks(block_repository) => block_repository.used_size(),
+        };
+        (structure, data)
+    }
+
+    /// Waits for the appends in progress, then records the committed
+    /// length, the bytes of both files in use and the regions later appends
+    /// rewrite in place. The tails start at record `since_len`.
+    pub(crate) fn capture(&self, since_len: u64) -> io::Result<Capture> {
+        let _appends = self.appends.write().unwrap();
+        let length = self.get_length();
+        if since_len > length {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "backup from record {} past the length {}",
+                    since_len, length
+                ),
+            ));
+        }
+        let (structure_size, data_size) = self.used_sizes();
+
+        // The header and length marker, and the last compact index chunk
+        // header, which appends rewrite.
+        let mut structure_patches = vec![(
+            0,
+            self.structure_file
+                .lock()
+                .unwrap()
+                .read_in_file(0, self.data_offset as usize),
+        )];
+        let structure_from = match &self.compact_index {
+            Some(compact_index) => {
+                let compact_index = compact_index.lock().unwrap();
+                structure_patches.extend(compact_index.rewritable_region());
+                compact_index.chunk_offset(since_len)
+            }
+            None => self.pair_offset(since_len),
+        };
+
+        let data_from = match &self.data_file {
+            // Appending record `since_len` may have sealed the block of the
+            // record before it, so the tail starts at that block.
+            DataFile::Blocks(_) if since_len > 0 => {
+                self.read_locations(since_len - 1, 1)[0].0
+            }
+            _ if since_len == length => data_size,
+            DataFile::Plain(string_repository) => {
+                string_repository.file_offset(self.read_locations(since_len, 1)[0].0)
+            }
+            DataFile::Blocks(_) => self.read_locations(since_len, 1)[0].0,
+        };
+        let data_patches = match &self.data_file {
+            DataFile::Plain(string_repository) => string_repository.rewritable_regions(),
+            DataFile::Blocks(block_repository) => block_repository.rewritable_regions(),
+        };
+
+        Ok(Capture {
+            since_len,
+            length,
+            structure: Segment {
+                from: structure_from,
+                size: structure_size,
+                patches: structure_patches,
+            },
+            data: Segment {
+                from: data_from,
+                size: data_size,
+                patches: data_patches,
+            },
+        })
     }
+
+    pub(crate) fn data_file_path(&self) -> &str {
+        match &self.data_file {
+            DataFile::Plain(string_repository) => string_repository.path(),
+            DataFile::Blocks(block_repository) => block_repository.path(),
+        }
+    }
+
+    pub(crate) fn structure_file_path(&self) -> String {
+        self.structure_file.lock().unwrap().path().to_string()
+    }
+}
+
+/// Rewrites the pair index of a plaintext or encrypted collection with
+/// uncompressed records as a compact index, leaving the data file as it
+/// is. The collection must not be open elsewhere. Returns the number of
+/// records converted; on error the original file is left in place.
+pub fn convert_to_compact_index(
+    structure_file_path: &str,
+    checkpoint_interval: u32,
+) -> io::Result<u64> {
+    if !std::path::Path::new(structure_file_path).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not exist", structure_file_path),
+        ));
+    }
+    let source = FileAccessService::new(structure_file_path.to_string(), 1024);
+    let header = source.read_header::<DynamicHeader>()?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no header", structure_file_path),
+        )
+    })?;
+    if header.options.index != IndexFormat::Pairs {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "collection already uses a compact index",
+        ));
+    }
+    let pairs_offset = FileAccessService::data_offset(&header)?;
+    let length = u64::from_le_bytes(
+        source
+            .read_in_file(0, LENGTH_MARKER_SIZE)
+            .try_into()
+            .unwrap(),
+    );
+    let locations: Vec<(u64, u64)> = source
+        .read_in_file(pairs_offset, 2 * LENGTH_MARKER_SIZE * length as usize)
+        .chunks_exact(16)
+        .map(|pair| {
+            (
+                u64::from_le_bytes(pair[0..8].try_into().unwrap()),
+                u64::from_le_bytes(pair[8..16].try_into().unwrap()),
+            )
+        })
+        .collect();
+    let header = DynamicHeader {
+        options: DynamicOptions {
+            index: IndexFormat::Compact {
+                checkpoint_interval,
+            },
+            ..header.options
+        },
+        key_id: header.key_id,
+    };
+    check_options(&header.options)?;
+    replace_structure_file(structure_file_path, &header, &locations)?;
+    Ok(length)
+}
+
+/// Adds a header to a structure file in the original headerless layout,
+/// `[length u64][pairs]`, which opening refuses with `InvalidData`. Such
+/// collections hold bincode records in a plain data file, which is used as
+/// it is. The collection must not be open elsewhere. Returns the number of
+/// records converted; on error the original file is left in place.
+pub fn convert_headerless(structure_file_path: &str) -> io::Result<u64> {
+    if !std::path::Path::new(structure_file_path).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not exist", structure_file_path),
+        ));
+    }
+    let source = FileAccessService::new(structure_file_path.to_string(), 1024);
+    if source.has_header()? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} already has a header", structure_file_path),
+        ));
+    }
+    let file_size = std::fs::metadata(structure_file_path)?.len();
+    if file_size < LENGTH_MARKER_SIZE as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no length marker", structure_file_path),
+        ));
+    }
+    let length = u64::from_le_bytes(
+        source
+            .read_in_file(0, LENGTH_MARKER_SIZE)
+            .try_into()
+            .unwrap(),
+    );
+    let pair_size = 2 * LENGTH_MARKER_SIZE as u64;
+    if length > (file_size - LENGTH_MARKER_SIZE as u64) / pair_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} is too short for the {} records its length marker claims",
+                structure_file_path, length
+            ),
+        ));
+    }
+    let locations: Vec<(u64, u64)> = source
+        .read_in_file(LENGTH_MARKER_SIZE as u64, (length * pair_size) as usize)
+        .chunks_exact(pair_size as usize)
+        .map(|pair| {
+            (
+                u64::from_le_bytes(pair[0..8].try_into().unwrap()),
+                u64::from_le_bytes(pair[8..16].try_into().unwrap()),
+            )
+        })
+        .collect();
+    let header = DynamicHeader {
+        options: DynamicOptions::default(),
+        key_id: None,
+    };
+    replace_structure_file(structure_file_path, &header, &locations)?;
+    Ok(length)
+}
+
+/// Locations of the first `length` records of a structure file, or of as
+/// many as its index holds.
+pub(crate) fn read_index(
+    structure: &FileAccessService,
+    header: &DynamicHeader,
+    length: u64,
+) -> io::Result<Vec<(u64, u64)>> {
+    let data_offset = FileAccessService::data_offset(header)?;
+    Ok(match header.options.index {
+        IndexFormat::Pairs => {
+            let file_size = std::fs::metadata(structure.path())?.len();
+            let pair_size = 2 * LENGTH_MARKER_SIZE as u64;
+            let available = file_size.saturating_sub(data_offset) / pair_size;
+            let count = length.min(available);
+            structure
+                .read_in_file(data_offset, (count * pair_size) as usize)
+                .chunks_exact(pair_size as usize)
+                .map(|pair| {
+                    (
+                        u64::from_le_bytes(pair[0..8].try_into().unwrap()),
+                        u64::from_le_bytes(pair[8..16].try_into().unwrap()),
+                    )
+                })
+                .collect()
+        }
+        IndexFormat::Compact {
+            checkpoint_interval,
+        } => {
+            let index =
+                CompactIndex::open(structure, data_offset, length, checkpoint_interval);
+            let count = length.min(index.len());
+            index.read(structure, 0, count)
+        }
+    })
+}
+
+/// Writes a structure file holding `header` and the index of `locations`
+/// next to `structure_file_path`, then renames it over the original.
+pub(crate) fn replace_structure_file(
+    structure_file_path: &str,
+    header: &DynamicHeader,
+    locations: &[(u64, u64)],
+) -> io::Result<()> {
+    let tmp = format!("{}.rewriting", structure_file_path);
+    if std::path::Path::new(&tmp).exists() {
+        std::fs::remove_file(&tmp)?;
+    }
+    let target = FileAccessService::new(tmp.clone(), 1024);
+    let result = target.write_header(header).and_then(|()| {
+        let data_offset = FileAccessService::data_offset(header)?;
+        match header.options.index {
+            IndexFormat::Pairs => {
+                let pairs: Vec<u8> = locations
+                    .iter()
+                    .flat_map(|(start, end)| {
+                        start.to_le_bytes().into_iter().chain(end.to_le_bytes())
+                    })
+                    .collect();
+                target.write_in_file(data_offset, &pairs);
+            }
+            IndexFormat::Compact {
+                checkpoint_interval,
+            } => CompactIndex::open(&target, data_offset, 0, checkpoint_interval)
+                .append(&target, locations),
+        }
+        target.write_in_file(0, &(locations.len() as u64).to_le_bytes());
+        Ok(())
+    });
+    if let Err(error) = result {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(error);
+    }
+    std::fs::rename(&tmp, structure_file_path)
+}
+
+/// Regenerates the structure file of a framed collection by scanning its
+/// data file for intact records, for when the index was lost or damaged.
+/// Damaged records are dropped and the rest keep their order. The header
+/// of the existing structure file is kept when it can still be read;
+/// otherwise `options` and `key_id` must describe how the collection was
+/// created. The collection must not be open elsewhere. Returns the number
+/// of records recovered.
+pub fn rebuild_index(
+    structure_file_path: &str,
+    string_file_path: &str,
+    options: &DynamicOptions,
+    key_id: Option<&str>,
+) -> io::Result<u64> {
+    if !std::path::Path::new(string_file_path).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not exist", string_file_path),
+        ));
+    }
+    let existing = if std::path::Path::new(structure_file_path).exists() {
+        FileAccessService::new(structure_file_path.to_string(), 1024)
+            .read_header::<DynamicHeader>()
+            .ok()
+            .flatten()
+    } else {
+        None
+    };
+    let header = existing.unwrap_or_else(|| DynamicHeader {
+        options: options.clone(),
+        key_id: key_id.map(str::to_string),
+    });
+    if !header.options.framed || header.options.blocks.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only collections of framed records can be rebuilt",
+        ));
+    }
+    check_options(&header.options)?;
+
+    let locations =
+        StringRepository::new(string_file_path.to_string(), 1024).recover_frames();
+    replace_structure_file(structure_file_path, &header, &locations)?;
+    Ok(locations.len() as u64)
 }
 
 #[cfg(test)]
 mod test {
+    use std::time::Instant;
+
     use super::*;
+    use crate::test_support::TestDir;
 
     const COUNT: usize = 1000;
 
@@ -375,7 +1140,7 @@ mod test {
 
     fn remove_file(path: &str) {
         if std::path::Path::new(&path).exists() {
-            std::fs::remove_file(&path).expect("Unable to remove file");
+            std::fs::remove_file(path).expect("Unable to remove file");
         }
     }
 
@@ -515,4 +1280,491 @@ mod test {
         let length = read_service.get_length();
         println!("length: {}", length);
     }
+
+    #[test]
+    fn test_load_bulk_from_offset() {
+        let dir = TestDir::new("load_bulk_from_offset");
+        let (structure, data) = dir.collection();
+        let service =
+            DynamicVectorManageService::<ExampleStruct>::new(structure, data, 1024)
+                .unwrap();
+        let objs: Vec<ExampleStruct> = (0..COUNT)
+            .map(|i| ExampleStruct {
+                id: i,
+                my_vec: vec![i; 8],
+                ..Default::default()
+            })
+            .collect();
+        service.save_bulk(objs);
+
+        // Ranges ending at the last record must read `end - start` bytes;
+        // reading `end` bytes from `start` runs past the end of the data file.
+        for start in [1, COUNT / 2, COUNT - 10, COUNT - 1] {
+            let ids: Vec<usize> = service
+                .load_bulk(start as u64, (COUNT - start) as u64)
+                .iter()
+                .map(|obj| obj.id)
+                .collect();
+            assert_eq!(ids, (start..COUNT).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_headerless_file_refused_until_converted() {
+        let dir = TestDir::new("headerless_file_refused_until_converted");
+        let (structure, data) = dir.collection();
+        let objs: Vec<ExampleStruct> = (0..10)
+            .map(|i| ExampleStruct {
+                id: i,
+                my_vec: vec![i; i],
+                ..Default::default()
+            })
+            .collect();
+        // The original layout: [length][pairs], with the first record at 0.
+        {
+            let strings = StringRepository::new(data.clone(), 1024);
+            let file = FileAccessService::new(structure.clone(), 1024);
+            for (i, obj) in objs.iter().enumerate() {
+                let (start, end) = strings.write_string_content_and_get_offset(
+                    bincode::serialize(obj).unwrap(),
+                );
+                let pair: Vec<u8> = start
+                    .to_le_bytes()
+                    .into_iter()
+                    .chain(end.to_le_bytes())
+                    .collect();
+                file.write_in_file((LENGTH_MARKER_SIZE + 16 * i) as u64, &pair);
+            }
+            file.write_in_file(0, &(objs.len() as u64).to_le_bytes());
+        }
+        let original = std::fs::read(&structure).unwrap();
+        let refused = DynamicVectorManageService::<ExampleStruct>::new(
+            structure.clone(),
+            data.clone(),
+            1024,
+        );
+        assert_eq!(refused.err().unwrap().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(std::fs::read(&structure).unwrap(), original);
+
+        assert_eq!(convert_headerless(&structure).unwrap(), 10);
+        assert_eq!(
+            convert_headerless(&structure).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        let service =
+            DynamicVectorManageService::<ExampleStruct>::new(structure, data, 1024)
+                .unwrap();
+        assert_eq!(service.get_length(), 10);
+        let ids: Vec<usize> = service.load_bulk(0, 10).iter().map(|obj| obj.id).collect();
+        assert_eq!(ids, (0..10).collect::<Vec<_>>());
+        assert_eq!(service.load(9).my_vec, objs[9].my_vec);
+    }
+
+    #[test]
+    fn test_codec_recorded_in_header() {
+        let dir = TestDir::new("codec_recorded_in_header");
+        let (structure, data) = dir.collection();
+        let objs: Vec<ExampleStruct> = (0..10)
+            .map(|i| ExampleStruct {
+                id: i,
+                my_vec: vec![i; i],
+                ..Default::default()
+            })
+            .collect();
+        {
+            let service = DynamicVectorManageService::<ExampleStruct>::with_codec(
+                structure.clone(),
+                data.clone(),
+                1024,
+                CodecKind::Json,
+            )
+            .unwrap();
+            service.save_bulk(objs[..5].to_vec());
+            for obj in &objs[5..] {
+                service.save(obj.clone());
+            }
+        }
+        let text = String::from_utf8_lossy(&std::fs::read(&data).unwrap()).to_string();
+        assert!(text.contains(r#"{"id":3,"my_vec":[3,3,3]"#));
+
+        // `new` reopens with the codec from the header, not bincode.
+        let service =
+            DynamicVectorManageService::<ExampleStruct>::new(structure, data, 1024)
+                .unwrap();
+        assert_eq!(service.codec(), CodecKind::Json);
+        assert_eq!(service.get_length(), 10);
+        assert_eq!(service.load(7).my_vec, objs[7].my_vec);
+        assert_eq!(service.load_bulk(0, 10).len(), 10);
+    }
+
+    #[test]
+    fn test_compressed_blocks() {
+        let dir = TestDir::new("compressed_blocks");
+        let path = |name: &str| dir.path(name);
+        let objs: Vec<ExampleStruct> = (0..COUNT)
+            .map(|i| ExampleStruct {
+                id: i,
+                my_vec: vec![i % 10; 20],
+                ..Default::default()
+            })
+            .collect();
+        let options = DynamicOptions {
+            codec: CodecKind::Bincode,
+            blocks: Some(BlockOptions {
+                block_size: 4096,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        {
+            let service = DynamicVectorManageService::<ExampleStruct>::with_options(
+                path("DynamicBlocks.bin"),
+                path("StringDynamicBlocks.bin"),
+                1024,
+                options.clone(),
+            )
+            .unwrap();
+            service.save_bulk(objs[..COUNT - 10].to_vec());
+            for obj in &objs[COUNT - 10..] {
+                service.save(obj.clone());
+            }
+            assert_eq!(service.load(COUNT as u64 - 1).id, COUNT - 1);
+            let plain = DynamicVectorManageService::<ExampleStruct>::new(
+                path("DynamicPlain.bin"),
+                path("StringDynamicPlain.bin"),
+                1024,
+            )
+            .unwrap();
+            plain.save_bulk(objs.clone());
+        }
+        let compressed_size = std::fs::metadata(path("StringDynamicBlocks.bin"))
+            .unwrap()
+            .len();
+        let plain_size = std::fs::metadata(path("StringDynamicPlain.bin"))
+            .unwrap()
+            .len();
+        assert!(compressed_size * 4 < plain_size);
+
+        let service = DynamicVectorManageService::<ExampleStruct>::new(
+            path("DynamicBlocks.bin"),
+            path("StringDynamicBlocks.bin"),
+            1024,
+        )
+        .unwrap();
+        assert_eq!(service.options(), &options);
+        for index in [0, 517, 3, COUNT - 1] {
+            assert_eq!(service.load(index as u64).my_vec, objs[index].my_vec);
+        }
+        let loaded = service.load_bulk(0, COUNT as u64);
+        assert_eq!(
+            loaded.iter().map(|obj| obj.id).collect::<Vec<_>>(),
+            (0..COUNT).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_encryption_and_key_rotation() {
+        use crate::services::encryption::LocalKeyProvider;
+
+        let dir = TestDir::new("encryption_and_key_rotation");
+        let path = |name: &str| dir.path(name);
+        let old_keys =
+            LocalKeyProvider::new("k1", [("k1".to_string(), [1u8; 32])]).unwrap();
+        let rotated = LocalKeyProvider::new(
+            "k2",
+            [("k1".to_string(), [1u8; 32]), ("k2".to_string(), [2u8; 32])],
+        )
+        .unwrap();
+        let new_keys =
+            LocalKeyProvider::new("k2", [("k2".to_string(), [2u8; 32])]).unwrap();
+        let objs: Vec<ExampleStruct> = (0..50)
+            .map(|i| ExampleStruct {
+                id: i,
+                my_vec: vec![0x5eed; 4],
+                ..Default::default()
+            })
+            .collect();
+
+        let blocks = Some(BlockOptions {
+            block_size: 256,
+            ..Default::default()
+        });
+        for (name, blocks) in [("Plain", None), ("Blocks", blocks)] {
+            let structure = path(&format!("DynamicEncrypted{}.bin", name));
+            let data = path(&format!("StringDynamicEncrypted{}.bin", name));
+            let options = DynamicOptions {
+                codec: CodecKind::Bincode,
+                blocks,
+                ..Default::default()
+            };
+            {
+                let service =
+                    DynamicVectorManageService::<ExampleStruct>::with_encryption(
+                        structure.clone(),
+                        data.clone(),
+                        1024,
+                        options,
+                        &old_keys,
+                    )
+                    .unwrap();
+                service.save_bulk(objs[..40].to_vec());
+                for obj in &objs[40..] {
+                    service.save(obj.clone());
+                }
+                assert_eq!(service.load(45).id, 45);
+            }
+            let raw = std::fs::read(&data).unwrap();
+            assert!(!raw.windows(8).any(|w| w == 0x5eedu64.to_le_bytes()));
+
+            let plain = DynamicVectorManageService::<ExampleStruct>::new(
+                structure.clone(),
+                data.clone(),
+                1024,
+            );
+            assert_eq!(plain.err().unwrap().kind(), io::ErrorKind::PermissionDenied);
+            let reopened = DynamicVectorManageService::<ExampleStruct>::with_encryption(
+                structure.clone(),
+                data.clone(),
+                1024,
+                DynamicOptions::default(),
+                &rotated,
+            )
+            .unwrap();
+            assert_eq!(reopened.key_id(), Some("k1"));
+            assert_eq!(reopened.load_bulk(0, 50)[17].my_vec, objs[17].my_vec);
+            drop(reopened);
+
+            assert_eq!(
+                DynamicVectorManageService::<ExampleStruct>::reencrypt(
+                    &structure, &data, &rotated
+                )
+                .unwrap(),
+                50
+            );
+            let service = DynamicVectorManageService::<ExampleStruct>::with_encryption(
+                structure,
+                data,
+                1024,
+                DynamicOptions::default(),
+                &new_keys,
+            )
+            .unwrap();
+            assert_eq!(service.key_id(), Some("k2"));
+            assert_eq!(service.options().blocks.is_some(), name == "Blocks");
+            assert_eq!(service.load(49).id, 49);
+        }
+    }
+
+    #[test]
+    fn test_interrupted_swap_finished_on_open() {
+        let dir = TestDir::new("interrupted_swap_finished_on_open");
+        let (structure, data) = dir.collection();
+        let save = |structure: String, data: String, ids: std::ops::Range<usize>| {
+            let service =
+                DynamicVectorManageService::<ExampleStruct>::new(structure, data, 1024)
+                    .unwrap();
+            service.save_bulk(
+                ids.map(|id| ExampleStruct {
+                    id,
+                    my_vec: vec![id; 3],
+                    ..Default::default()
+                })
+                .collect(),
+            );
+        };
+        let ids = |structure: &str, data: &str| -> Vec<usize> {
+            let service = DynamicVectorManageService::<ExampleStruct>::new(
+                structure.to_string(),
+                data.to_string(),
+                1024,
+            )
+            .unwrap();
+            let length = service.get_length();
+            service
+                .load_bulk(0, length)
+                .iter()
+                .map(|obj| obj.id)
+                .collect()
+        };
+        let copy = |path: &str| format!("{}.reencrypting", path);
+        save(structure.clone(), data.clone(), 0..20);
+
+        // Copies without a marker are an unfinished rewrite and are ignored.
+        save(copy(&structure), copy(&data), 100..130);
+        assert_eq!(ids(&structure, &data), (0..20).collect::<Vec<_>>());
+
+        // Stopped after the data file was renamed over the original.
+        std::fs::write(format!("{}.swapping", structure), ".reencrypting").unwrap();
+        std::fs::rename(copy(&data), &data).unwrap();
+        assert_eq!(ids(&structure, &data), (100..130).collect::<Vec<_>>());
+        for path in [copy(&structure), format!("{}.swapping", structure)] {
+            assert!(!std::path::Path::new(&path).exists());
+        }
+    }
+
+    #[test]
+    fn test_compact_index() {
+        let dir = TestDir::new("compact_index");
+        let path = |name: &str| dir.path(name);
+        let objs: Vec<ExampleStruct> = (0..COUNT)
+            .map(|i| ExampleStruct {
+                id: i,
+                my_vec: vec![i; i % 7],
+                ..Default::default()
+            })
+            .collect();
+        let options = DynamicOptions {
+            index: IndexFormat::Compact {
+                checkpoint_interval: 128,
+            },
+            ..Default::default()
+        };
+        {
+            let service = DynamicVectorManageService::<ExampleStruct>::with_options(
+                path("DynamicCompact.bin"),
+                path("StringDynamicCompact.bin"),
+                1024,
+                options.clone(),
+            )
+            .unwrap();
+            service.save_bulk(objs[..COUNT - 10].to_vec());
+            for obj in &objs[COUNT - 10..] {
+                service.save(obj.clone());
+            }
+            assert_eq!(service.load(COUNT as u64 - 1).id, COUNT - 1);
+
+            let pairs = DynamicVectorManageService::<ExampleStruct>::new(
+                path("DynamicConverted.bin"),
+                path("StringDynamicConverted.bin"),
+                1024,
+            )
+            .unwrap();
+            pairs.save_bulk(objs.clone());
+        }
+        let service = DynamicVectorManageService::<ExampleStruct>::new(
+            path("DynamicCompact.bin"),
+            path("StringDynamicCompact.bin"),
+            1024,
+        )
+        .unwrap();
+        assert_eq!(service.options(), &options);
+        assert_eq!(service.get_length(), COUNT as u64);
+        for index in [0, 127, 128, 517, COUNT - 1] {
+            assert_eq!(service.load(index as u64).my_vec, objs[index].my_vec);
+        }
+        let loaded = service.load_bulk(100, 800);
+        assert_eq!(loaded[0].id, 100);
+        assert_eq!(loaded[799].my_vec, objs[899].my_vec);
+        let compact_size = service
+            .compact_index
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .size();
+        assert!(compact_size * 4 < COUNT as u64 * 16);
+
+        assert_eq!(
+            convert_to_compact_index(&path("DynamicConverted.bin"), 128).unwrap(),
+            COUNT as u64
+        );
+        assert!(convert_to_compact_index(&path("DynamicConverted.bin"), 128).is_err());
+        let converted = DynamicVectorManageService::<ExampleStruct>::new(
+            path("DynamicConverted.bin"),
+            path("StringDynamicConverted.bin"),
+            1024,
+        )
+        .unwrap();
+        assert_eq!(converted.options(), &options);
+        assert_eq!(converted.load(517).my_vec, objs[517].my_vec);
+        converted.save(objs[0].clone());
+        assert_eq!(converted.load_bulk(0, COUNT as u64 + 1)[COUNT].id, 0);
+
+        let rejected = DynamicVectorManageService::<ExampleStruct>::with_options(
+            path("DynamicCompactBlocks.bin"),
+            path("StringDynamicCompactBlocks.bin"),
+            1024,
+            DynamicOptions {
+                blocks: Some(BlockOptions::default()),
+                ..options
+            },
+        );
+        assert_eq!(rejected.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_rebuild_index() {
+        let dir = TestDir::new("rebuild_index");
+        let (structure, data) = dir.collection();
+        let objs: Vec<ExampleStruct> = (0..100)
+            .map(|i| ExampleStruct {
+                id: i,
+                my_vec: vec![i; i % 5],
+                ..Default::default()
+            })
+            .collect();
+        let options = DynamicOptions {
+            codec: CodecKind::Json,
+            framed: true,
+            ..Default::default()
+        };
+        {
+            let service = DynamicVectorManageService::<ExampleStruct>::with_options(
+                structure.clone(),
+                data.clone(),
+                1024,
+                options.clone(),
+            )
+            .unwrap();
+            service.save_bulk(objs[..60].to_vec());
+            for obj in &objs[60..] {
+                service.save(obj.clone());
+            }
+        }
+
+        // Index lost entirely: the header comes from `options`.
+        remove_file(&structure);
+        assert_eq!(
+            rebuild_index(&structure, &data, &options, None).unwrap(),
+            100
+        );
+        let service = DynamicVectorManageService::<ExampleStruct>::new(
+            structure.clone(),
+            data.clone(),
+            1024,
+        )
+        .unwrap();
+        assert_eq!(service.options(), &options);
+        assert_eq!(service.load(73).my_vec, objs[73].my_vec);
+        drop(service);
+
+        // A damaged record is dropped and the rest keep their order.
+        let mut raw = std::fs::read(&data).unwrap();
+        let at = raw
+            .windows(8)
+            .position(|window| window == br#""id":40,"#)
+            .unwrap();
+        raw[at + 5] = b'9';
+        std::fs::write(&data, raw).unwrap();
+        let unused = DynamicOptions::default();
+        assert_eq!(rebuild_index(&structure, &data, &unused, None).unwrap(), 99);
+        let service = DynamicVectorManageService::<ExampleStruct>::new(
+            structure.clone(),
+            data.clone(),
+            1024,
+        )
+        .unwrap();
+        let ids: Vec<usize> = service.load_bulk(0, 99).iter().map(|obj| obj.id).collect();
+        assert_eq!(ids, (0..100).filter(|id| *id != 40).collect::<Vec<_>>());
+        service.save(objs[40].clone());
+        assert_eq!(service.load(99).id, 40);
+
+        let unframed = dir.path("unframed.bin");
+        assert_eq!(
+            rebuild_index(&unframed, &data, &unused, None)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
 }
@@ -0,0 +1,216 @@
+use chacha20poly1305::{
+    aead::{
+        Aead,
+        AeadCore,
+        KeyInit,
+        OsRng,
+    },
+    ChaCha20Poly1305,
+    Key,
+    Nonce,
+};
+use std::{
+    collections::HashMap,
+    io,
+};
+
+pub const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+/// Bytes an encrypted payload adds: the nonce before it and the
+/// authentication tag after it.
+pub const ENCRYPTION_OVERHEAD: usize = NONCE_SIZE + TAG_SIZE;
+
+/// Source of the keys records are encrypted with. Engines store the id of
+/// the key a file was written with in its header and ask for that key when
+/// the file is reopened, so a provider has to keep serving old keys until
+/// their files have been re-encrypted with the current one.
+pub trait KeyProvider: Send + Sync {
+    /// Id of the key new files are encrypted with.
+    fn current_key_id(&self) -> io::Result<String>;
+
+    /// Fails with `NotFound` when the provider does not know `key_id`.
+    fn key(&self, key_id: &str) -> io::Result<[u8; KEY_SIZE]>;
+}
+
+/// In-memory key ring loaded from a key file or an environment variable.
+///
+/// The text format is a list of `id:hex-key` entries separated by
+/// whitespace or commas, `#` starting a comment; the last entry is the
+/// current key, so rotating means appending a new one.
+pub struct LocalKeyProvider {
+    keys: HashMap<String, [u8; KEY_SIZE]>,
+    current: String,
+}
+
+fn invalid_key(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn decode_hex_key(hex: &str) -> io::Result<[u8; KEY_SIZE]> {
+    if hex.len() != KEY_SIZE * 2 {
+        return Err(invalid_key(format!(
+            "key must be {} hex characters, got {}",
+            KEY_SIZE * 2,
+            hex.len()
+        )));
+    }
+    if !hex.is_ascii() {
+        return Err(invalid_key("key is not valid hex"));
+    }
+    let mut key = [0u8; KEY_SIZE];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+            .map_err(|_| invalid_key("key is not valid hex"))?;
+    }
+    Ok(key)
+}
+
+impl LocalKeyProvider {
+    /// Fails with `InvalidInput` when `current` is not one of `keys`.
+    pub fn new(
+        current: &str,
+        keys: impl IntoIterator<Item = (String, [u8; KEY_SIZE])>,
+    ) -> io::Result<Self> {
+        let keys: HashMap<_, _> = keys.into_iter().collect();
+        if !keys.contains_key(current) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("current key {} is not in the key ring", current),
+            ));
+        }
+        Ok(Self {
+            keys,
+            current: current.to_string(),
+        })
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut keys = HashMap::new();
+        let mut current = None;
+        let entries = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|entry| !entry.is_empty());
+        for entry in entries {
+            let (id, hex) = entry
+                .split_once(':')
+                .ok_or_else(|| invalid_key(format!("expected id:key, got {}", entry)))?;
+            keys.insert(id.to_string(), decode_hex_key(hex)?);
+            current = Some(id.to_string());
+        }
+        let current = current.ok_or_else(|| invalid_key("no keys given"))?;
+        Ok(Self { keys, current })
+    }
+
+    pub fn from_file(path: &str) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Reads the key list from the environment variable `name`.
+    pub fn from_env(name: &str) -> io::Result<Self> {
+        let text = std::env::var(name).map_err(|e| {
+            io::Error::new(io::ErrorKind::NotFound, format!("{}: {}", name, e))
+        })?;
+        Self::parse(&text)
+    }
+}
+
+impl KeyProvider for LocalKeyProvider {
+    fn current_key_id(&self) -> io::Result<String> {
+        Ok(self.current.clone())
+    }
+
+    fn key(&self, key_id: &str) -> io::Result<[u8; KEY_SIZE]> {
+        self.keys.get(key_id).copied().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("unknown key {}", key_id))
+        })
+    }
+}
+
+/// ChaCha20-Poly1305 with a random nonce per payload. A sealed payload is
+/// [nonce][ciphertext][tag].
+pub(crate) struct PayloadCipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl PayloadCipher {
+    pub(crate) fn new(key_id: &str, keys: &dyn KeyProvider) -> io::Result<Self> {
+        Ok(Self::from_key(&keys.key(key_id)?))
+    }
+
+    pub(crate) fn from_key(key: &[u8; KEY_SIZE]) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+        }
+    }
+
+    /// Fresh random key from the operating system's generator.
+    pub(crate) fn generate_key() -> [u8; KEY_SIZE] {
+        ChaCha20Poly1305::generate_key(&mut OsRng).into()
+    }
+
+    pub(crate) fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .expect("Encryption failed");
+        let mut sealed = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// Fails with `InvalidData` when the payload was altered or sealed with
+    /// another key.
+    pub(crate) fn open(&self, sealed: &[u8]) -> io::Result<Vec<u8>> {
+        if sealed.len() < ENCRYPTION_OVERHEAD {
+            return Err(invalid_key("encrypted payload is truncated"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| invalid_key("payload failed authentication"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEYS: &str = "
+        # rotated 2024-01
+        old:000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f
+        new:1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100
+    ";
+
+    #[test]
+    fn test_local_key_provider_and_cipher() {
+        let keys = LocalKeyProvider::parse(KEYS).unwrap();
+        assert_eq!(keys.current_key_id().unwrap(), "new");
+        assert_eq!(keys.key("old").unwrap()[31], 0x1f);
+        assert_eq!(
+            keys.key("gone").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(LocalKeyProvider::parse("old:00ff").is_err());
+        assert!(LocalKeyProvider::parse("# nothing").is_err());
+
+        let old = PayloadCipher::new("old", &keys).unwrap();
+        let new = PayloadCipher::new("new", &keys).unwrap();
+        let sealed = old.seal(b"meet at noon");
+        assert_eq!(sealed.len(), 12 + ENCRYPTION_OVERHEAD);
+        assert_ne!(old.seal(b"meet at noon"), sealed);
+        assert_eq!(old.open(&sealed).unwrap(), b"meet at noon");
+        assert!(new.open(&sealed).is_err());
+
+        let mut tampered = sealed.clone();
+        tampered[NONCE_SIZE] ^= 1;
+        assert_eq!(
+            old.open(&tampered).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -1,20 +1,35 @@
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
 use std::{
     fs::{
         File,
         OpenOptions,
     },
     io::{
+        self,
         Read,
         Seek,
         SeekFrom,
         Write,
     },
+    mem::size_of,
     sync::{
         Arc,
         Mutex,
     },
 };
 
+/// Identifies a structure file with a header, stored after the length
+/// marker.
+const HEADER_MAGIC: [u8; 4] = *b"VDBH";
+/// Version of the headered layout; files with any other version are
+/// refused.
+const FORMAT_VERSION: u32 = 1;
+/// Length marker, magic, format version and header size marker.
+const HEADER_PREFIX_SIZE: usize = 3 * size_of::<u64>();
+
 pub struct FileAccessService {
     path: String,
     current_size: Arc<Mutex<u64>>,
@@ -37,6 +52,10 @@ impl FileAccessService {
         }
     }
 
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
     pub fn write_in_file(&self, offset: u64, data: &[u8]) {
         let mut current_size = self.current_size.lock().unwrap();
 
@@ -82,6 +101,91 @@ impl FileAccessService {
         buffer
     }
 
+    /// Flushes written data to disk.
+    pub fn sync(&self) -> io::Result<()> {
+        OpenOptions::new().write(true).open(&self.path)?.sync_data()
+    }
+
+    /// Reads the bincode header of a structure file laid out as
+    /// [length u64][magic][version u32][header size u64][header][data].
+    /// Returns `None` when nothing has been written to the file yet. Files
+    /// without the magic, such as the original headerless
+    /// [length][records] layout, and unknown versions fail with
+    /// `InvalidData`.
+    pub fn read_header<H: DeserializeOwned>(&self) -> io::Result<Option<H>> {
+        let file_size = std::fs::metadata(&self.path)?.len();
+        let prefix = self.read_in_file(0, HEADER_PREFIX_SIZE.min(file_size as usize));
+        if prefix.iter().all(|byte| *byte == 0) {
+            return Ok(None);
+        }
+        if prefix.len() < HEADER_PREFIX_SIZE || prefix[8..12] != HEADER_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} has no header; files in the headerless [length][records] \
+                     layout must be converted first",
+                    self.path
+                ),
+            ));
+        }
+        let version = u32::from_le_bytes(prefix[12..16].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has unsupported format version {}", self.path, version),
+            ));
+        }
+        let header_size = u64::from_le_bytes(prefix[16..24].try_into().unwrap());
+        if header_size > file_size - HEADER_PREFIX_SIZE as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("header of {} is truncated", self.path),
+            ));
+        }
+        let bytes = self.read_in_file(HEADER_PREFIX_SIZE as u64, header_size as usize);
+        bincode::deserialize(&bytes)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes `header` after the length marker of a file that holds no
+    /// records and no header yet; anything else fails with `AlreadyExists`
+    /// rather than being overwritten. The magic is written last, so an
+    /// interrupted write leaves a file that still reads as empty.
+    pub fn write_header<H: Serialize>(&self, header: &H) -> io::Result<()> {
+        let bytes = bincode::serialize(header).map_err(io::Error::other)?;
+        let file_size = std::fs::metadata(&self.path)?.len();
+        let prefix = self.read_in_file(0, HEADER_PREFIX_SIZE.min(file_size as usize));
+        if prefix.iter().any(|byte| *byte != 0) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is not empty", self.path),
+            ));
+        }
+        self.write_in_file(HEADER_PREFIX_SIZE as u64, &bytes);
+        let mut prefix = Vec::with_capacity(HEADER_PREFIX_SIZE - size_of::<u64>());
+        prefix.extend_from_slice(&HEADER_MAGIC);
+        prefix.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        prefix.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        self.write_in_file(size_of::<u64>() as u64, &prefix);
+        Ok(())
+    }
+
+    /// Whether the file starts with the header magic, whatever its format
+    /// version.
+    pub fn has_header(&self) -> io::Result<bool> {
+        if std::fs::metadata(&self.path)?.len() < HEADER_PREFIX_SIZE as u64 {
+            return Ok(false);
+        }
+        Ok(self.read_in_file(8, HEADER_MAGIC.len()) == HEADER_MAGIC)
+    }
+
+    /// Offset of the data that follows `header`.
+    pub fn data_offset<H: Serialize>(header: &H) -> io::Result<u64> {
+        let header_size = bincode::serialized_size(header).map_err(io::Error::other)?;
+        Ok(HEADER_PREFIX_SIZE as u64 + header_size)
+    }
+
     fn get_updated_current_file_size(&self) -> u64 {
         let current_size = std::fs::metadata(&self.path)
             .expect("Unable to get file metadata")
@@ -0,0 +1,60 @@
+use std::{
+    fs::File,
+    io::{
+        self,
+        Write,
+    },
+    path::Path,
+};
+
+/// Suffix of the marker that records an unfinished swap of a collection's
+/// structure and data files.
+const MARKER_SUFFIX: &str = ".swapping";
+
+pub(crate) fn sync_parent(path: &str) -> io::Result<()> {
+    let parent = Path::new(path)
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    File::open(parent)?.sync_all()
+}
+
+/// Renames the rewritten copies `<structure><suffix>` and `<data><suffix>`
+/// over the originals. The copies are synced first, then a marker next to
+/// the structure file records `suffix` until both renames are durable, so
+/// [`finish_swap`] can complete a swap interrupted between the two renames.
+pub(crate) fn swap_in(structure: &str, data: &str, suffix: &str) -> io::Result<()> {
+    for path in [structure, data] {
+        File::open(format!("{}{}", path, suffix))?.sync_all()?;
+        sync_parent(path)?;
+    }
+    let marker = format!("{}{}", structure, MARKER_SUFFIX);
+    let mut file = File::create(&marker)?;
+    file.write_all(suffix.as_bytes())?;
+    file.sync_all()?;
+    sync_parent(&marker)?;
+    finish_swap(structure, data)
+}
+
+/// Completes a swap started by [`swap_in`] if its marker is present. Copies
+/// left without a marker belong to a rewrite that never finished; the
+/// originals stay in use and the next rewrite replaces the copies.
+pub(crate) fn finish_swap(structure: &str, data: &str) -> io::Result<()> {
+    let marker = format!("{}{}", structure, MARKER_SUFFIX);
+    let suffix = match std::fs::read_to_string(&marker) {
+        Ok(suffix) => suffix,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    // A marker torn before it was synced names no copy, and no rename has
+    // happened yet.
+    for path in [data, structure] {
+        let copy = format!("{}{}", path, suffix);
+        if !suffix.is_empty() && Path::new(&copy).exists() {
+            std::fs::rename(&copy, path)?;
+            sync_parent(path)?;
+        }
+    }
+    std::fs::remove_file(&marker)?;
+    sync_parent(&marker)
+}
@@ -0,0 +1,561 @@
+use dynamic_vector::{
+    HybridRecord,
+    RecordSchema,
+};
+use rayon::prelude::*;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    io::{
+        self,
+    },
+    marker::PhantomData,
+    mem::size_of,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use crate::{
+    schema::decode_record,
+    services::{
+        file_access_service::FileAccessService,
+        string_repository::StringRepository,
+    },
+};
+
+const LENGTH_MARKER_SIZE: usize = size_of::<u64>();
+/// Start and end offset in the string repository for each dynamic field.
+const OFFSET_PAIR_SIZE: usize = 2 * size_of::<u64>();
+
+/// Slot layout and record schema, stored after the length marker so the
+/// file can be checked on reopen and decoded without the Rust type.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct HybridHeader {
+    slot_size: u64,
+    schema: RecordSchema,
+}
+
+impl HybridHeader {
+    fn fixed_size(&self) -> usize {
+        self.schema
+            .fields
+            .iter()
+            .map(|field| field.encoded_size)
+            .sum()
+    }
+
+    /// Storage class and slot width of each field, in order. Renaming a
+    /// field keeps the layout; changing its type or order does not.
+    fn layout(&self) -> Vec<(bool, usize)> {
+        self.schema
+            .fields
+            .iter()
+            .map(|field| (field.dynamic, field.encoded_size))
+            .collect()
+    }
+}
+
+fn read_length(file_access: &FileAccessService) -> u64 {
+    let buffer = file_access.read_in_file(0, LENGTH_MARKER_SIZE);
+
+    assert!(buffer.len() >= 8, "Buffer length must be at least 8 bytes.");
+
+    u64::from_le_bytes(buffer[0..8].try_into().unwrap())
+}
+
+fn dynamic_offsets(fixed_size: usize, slot: &[u8]) -> Vec<(u64, u64)> {
+    slot[fixed_size..]
+        .chunks_exact(OFFSET_PAIR_SIZE)
+        .map(|pair| {
+            (
+                u64::from_le_bytes(pair[0..8].try_into().unwrap()),
+                u64::from_le_bytes(pair[8..16].try_into().unwrap()),
+            )
+        })
+        .collect()
+}
+
+/// Record store that keeps the fixed-size fields of each record in a
+/// fixed-width slot of the structure file and the `String`/`Vec` fields in
+/// the string repository. Each slot holds the fixed fields followed by the
+/// (start, end) offsets of every dynamic field, so fixed fields are read
+/// with a single positioned read.
+///
+/// File layout: [length u64][magic u32][version u32][header size u64]
+/// [bincode header][fixed-size slots]. The header carries the schema generated by
+/// `CheckDynamicSize`.
+pub struct HybridVectorManageService<T>
+where
+    T: HybridRecord + Send,
+{
+    length: Arc<Mutex<u64>>,
+    structure_file: Mutex<FileAccessService>,
+    string_repository: StringRepository,
+    schema: RecordSchema,
+    data_offset: u64,
+    _marker: PhantomData<T>,
+}
+
+impl<T> HybridVectorManageService<T>
+where
+    T: HybridRecord + Send + Sync,
+{
+    /// Opens the store, writing the slot size and schema of `T` into a new
+    /// file. Reopening a file whose field layout differs from `T` fails with
+    /// `InvalidData`.
+    pub fn new(
+        structure_file_path: String,
+        string_file_path: String,
+        initial_size_if_not_exists: u64,
+    ) -> io::Result<Self> {
+        let structure_file_access =
+            FileAccessService::new(structure_file_path, initial_size_if_not_exists);
+        let string_repository =
+            StringRepository::new(string_file_path, initial_size_if_not_exists);
+
+        let header = HybridHeader {
+            slot_size: Self::slot_size() as u64,
+            schema: T::schema(),
+        };
+        let header = match structure_file_access.read_header::<HybridHeader>()? {
+            Some(existing)
+                if existing.slot_size != header.slot_size
+                    || existing.layout() != header.layout() =>
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} was created for {} with a different field layout than {}",
+                        structure_file_access.path(),
+                        existing.schema.name,
+                        std::any::type_name::<T>()
+                    ),
+                ));
+            }
+            Some(existing) => existing,
+            None => {
+                structure_file_access.write_header(&header)?;
+                header
+            }
+        };
+        let data_offset = FileAccessService::data_offset(&header)?;
+
+        let length = Arc::new(Mutex::new(read_length(&structure_file_access)));
+        Ok(Self {
+            length,
+            structure_file: Mutex::new(structure_file_access),
+            string_repository,
+            schema: header.schema,
+            data_offset,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Schema stored in the file header when the file was created.
+    pub fn schema(&self) -> &RecordSchema {
+        &self.schema
+    }
+
+    pub fn get_length(&self) -> u64 {
+        *self.length.lock().unwrap()
+    }
+
+    fn save_length(&self, length: u64) {
+        let file_guard = self.structure_file.lock().unwrap();
+        file_guard.write_in_file(0, &length.to_le_bytes());
+    }
+
+    fn slot_size() -> usize {
+        T::fixed_size() + T::dynamic_field_count() * OFFSET_PAIR_SIZE
+    }
+
+    fn slot_offset(&self, index: u64) -> u64 {
+        self.data_offset + index * Self::slot_size() as u64
+    }
+
+    pub fn add(&self, obj: T) {
+        self.add_bulk(vec![obj]);
+    }
+
+    pub fn add_bulk(&self, objs: Vec<T>) {
+        if objs.is_empty() {
+            return;
+        }
+        let parts: Vec<(Vec<u8>, Vec<Vec<u8>>)> =
+            objs.par_iter().map(|obj| obj.split_fields()).collect();
+
+        let dynamic_bytes: Vec<u8> = parts
+            .iter()
+            .flat_map(|(_, dynamic)| dynamic.iter().flatten().copied())
+            .collect();
+
+        // The length lock serializes writers so the dynamic bytes and the
+        // slots of one batch stay in the same order.
+        let mut length = self.length.lock().unwrap();
+        let (mut offset, _) = self
+            .string_repository
+            .write_string_content_and_get_offset(dynamic_bytes);
+
+        let mut slots = Vec::with_capacity(parts.len() * Self::slot_size());
+        for (fixed, dynamic) in &parts {
+            assert_eq!(fixed.len(), T::fixed_size(), "fixed fields size mismatch");
+            slots.extend_from_slice(fixed);
+            for bytes in dynamic {
+                slots.extend_from_slice(&offset.to_le_bytes());
+                offset += bytes.len() as u64;
+                slots.extend_from_slice(&offset.to_le_bytes());
+            }
+        }
+        {
+            let file_guard = self.structure_file.lock().unwrap();
+            file_guard.write_in_file(self.slot_offset(*length), &slots);
+        }
+        *length += parts.len() as u64;
+        self.save_length(*length);
+    }
+
+    fn read_slots(&self, index: u64, count: u64) -> Vec<u8> {
+        let total = self.get_length();
+        assert!(
+            index + count <= total,
+            "index {} and count {} out of bounds for length {}",
+            index,
+            count,
+            total
+        );
+        let file_guard = self.structure_file.lock().unwrap();
+        file_guard
+            .read_in_file(self.slot_offset(index), count as usize * Self::slot_size())
+    }
+
+    pub fn read(&self, index: u64) -> T {
+        self.read_bulk(index, 1).pop().unwrap()
+    }
+
+    pub fn read_bulk(&self, index: u64, count: u64) -> Vec<T> {
+        if count == 0 {
+            return Vec::new();
+        }
+        let slots = self.read_slots(index, count);
+        let offsets: Vec<Vec<(u64, u64)>> = slots
+            .chunks_exact(Self::slot_size())
+            .map(|slot| dynamic_offsets(T::fixed_size(), slot))
+            .collect();
+
+        // Dynamic fields of consecutive records are contiguous, so the whole
+        // range is fetched with one read.
+        let first = offsets.iter().flatten().next().map(|(start, _)| *start);
+        let last = offsets.iter().flatten().last().map(|(_, end)| *end);
+        let (base, bytes) = match (first, last) {
+            (Some(start), Some(end)) => (
+                start,
+                self.string_repository
+                    .load_string_content(start, end - start),
+            ),
+            _ => (0, Vec::new()),
+        };
+
+        slots
+            .par_chunks_exact(Self::slot_size())
+            .zip(offsets)
+            .map(|(slot, offsets)| {
+                let dynamic = offsets
+                    .into_iter()
+                    .map(|(start, end)| {
+                        bytes[(start - base) as usize..(end - base) as usize].to_vec()
+                    })
+                    .collect();
+                T::join_fields(&slot[..T::fixed_size()], dynamic)
+            })
+            .collect()
+    }
+
+    /// Record `index` with only its fixed fields read; dynamic fields are
+    /// left at their default value and the string repository is not
+    /// touched.
+    pub fn read_fixed(&self, index: u64) -> T
+    where
+        T: Default,
+    {
+        let slot = self.read_slots(index, 1);
+        let mut obj = T::default();
+        obj.assign_fixed_fields(&slot[..T::fixed_size()]);
+        obj
+    }
+}
+
+/// Read-only access to a hybrid store without its Rust type, for tools
+/// that inspect files. Records are decoded through the schema in the file
+/// header.
+pub struct HybridFileReader {
+    structure_file: FileAccessService,
+    string_repository: StringRepository,
+    header: HybridHeader,
+    data_offset: u64,
+}
+
+impl HybridFileReader {
+    /// Fails with `NotFound` when either file is missing and with
+    /// `InvalidData` when the structure file has no hybrid header, such as
+    /// the structure file of another layout.
+    pub fn open(
+        structure_file_path: String,
+        string_file_path: String,
+    ) -> io::Result<Self> {
+        for path in [&structure_file_path, &string_file_path] {
+            if !std::path::Path::new(path).exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} does not exist", path),
+                ));
+            }
+        }
+        let structure_file = FileAccessService::new(structure_file_path, 0);
+        let no_hybrid_header = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has no hybrid header", structure_file.path()),
+            )
+        };
+        // Another layout's header may happen to deserialize; its slot size
+        // would not match the schema.
+        let header = match structure_file.read_header::<HybridHeader>() {
+            Ok(header) => header,
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => None,
+            Err(error) => return Err(error),
+        };
+        let header = header
+            .filter(|header| {
+                let dynamic_fields =
+                    header.schema.fields.iter().filter(|field| field.dynamic);
+                header.slot_size
+                    == (header.fixed_size() + dynamic_fields.count() * OFFSET_PAIR_SIZE)
+                        as u64
+            })
+            .ok_or_else(no_hybrid_header)?;
+        let data_offset = FileAccessService::data_offset(&header)?;
+        Ok(Self {
+            structure_file,
+            string_repository: StringRepository::new(string_file_path, 0),
+            header,
+            data_offset,
+        })
+    }
+
+    pub fn schema(&self) -> &RecordSchema {
+        &self.header.schema
+    }
+
+    pub fn slot_size(&self) -> u64 {
+        self.header.slot_size
+    }
+
+    pub fn len(&self) -> u64 {
+        read_length(&self.structure_file)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Record `index` as JSON, shaped the way `serde_json` would serialize
+    /// the original type.
+    pub fn read_value(&self, index: u64) -> io::Result<serde_json::Value> {
+        let length = self.len();
+        if index >= length {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("index {} out of bounds for length {}", index, length),
+            ));
+        }
+        let slot_size = self.header.slot_size as usize;
+        let slot = self
+            .structure_file
+            .read_in_file(self.data_offset + index * slot_size as u64, slot_size);
+        let fixed_size = self.header.fixed_size();
+        let dynamic: Vec<Vec<u8>> = dynamic_offsets(fixed_size, &slot)
+            .into_iter()
+            .map(|(start, end)| {
+                self.string_repository
+                    .load_string_content(start, end - start)
+            })
+            .collect();
+        decode_record(&self.header.schema, &slot[..fixed_size], &dynamic)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use dynamic_vector::{
+        CheckDynamicSize,
+        DynamicVector,
+        FixedString,
+    };
+    use serde::{
+        Deserialize,
+        Serialize,
+    };
+
+    use super::*;
+    use crate::{
+        test_support::TestDir,
+        vector_engine::VectorEngine,
+    };
+
+    #[derive(
+        Serialize, Deserialize, Default, Debug, Clone, PartialEq, CheckDynamicSize,
+    )]
+    pub struct MixedMessage {
+        id: u64,
+        sender: String,
+        timestamp: i64,
+        attachments: Vec<u32>,
+        read: bool,
+        status: FixedString<8>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, CheckDynamicSize)]
+    pub struct Reading(f32, char, Option<u16>, [i8; 3], (u8, f64));
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, CheckDynamicSize)]
+    pub struct Tagged<T> {
+        reading: Reading,
+        label: Option<String>,
+        value: T,
+    }
+
+    fn message(i: u64) -> MixedMessage {
+        MixedMessage {
+            id: i,
+            sender: format!("user-{}", i % 7),
+            timestamp: 1_700_000_000 + i as i64,
+            attachments: (0..(i % 4) as u32).collect(),
+            read: i.is_multiple_of(2),
+            status: FixedString::truncating(["sent", "delivered"][i as usize % 2]),
+        }
+    }
+
+    #[test]
+    fn test_split_and_join_fields() {
+        // The fixed-capacity status is stored inline with the fixed fields.
+        assert_eq!(MixedMessage::fixed_size(), 25);
+        assert_eq!(MixedMessage::dynamic_field_count(), 2);
+        let (fixed, dynamic) = message(5).split_fields();
+        assert_eq!(fixed.len(), 25);
+        assert_eq!(dynamic.len(), 2);
+        assert_eq!(MixedMessage::join_fields(&fixed, dynamic), message(5));
+        assert_eq!(
+            message(5).get_dynamic_fields(),
+            vec!["sender", "attachments"]
+        );
+    }
+
+    #[test]
+    fn test_nested_generic_record_pads_optional_fields() {
+        let dir = TestDir::new("nested_generic_record_pads_optional_fields");
+        // f32 + char + Option<u16> + [i8; 3] + (u8, f64), with Option sized as Some
+        assert_eq!(Reading::fixed_size(), 4 + 4 + 3 + 3 + 9);
+        assert_eq!(Tagged::<u32>::fixed_size(), 23 + 4);
+        assert_eq!(Tagged::<u32>::dynamic_field_count(), 1);
+
+        let (structure, data) = dir.collection();
+        let engine: HybridVectorManageService<Tagged<u32>> =
+            HybridVectorManageService::new(structure, data, 1024).unwrap();
+        let records: Vec<Tagged<u32>> = (0..20u32)
+            .map(|i| Tagged {
+                reading: Reading(
+                    i as f32 / 2.0,
+                    char::from(b'a' + i as u8),
+                    (i % 3 != 0).then_some(i as u16),
+                    [i as i8, -(i as i8), 0],
+                    (i as u8, i as f64 * 1.5),
+                ),
+                label: (i % 2 == 0).then(|| format!("label-{}", i)),
+                value: i * 10,
+            })
+            .collect();
+        engine.add_bulk(records.clone());
+        assert_eq!(engine.read_bulk(0, 20), records);
+        assert_eq!(records[3].get_dynamic_fields(), vec!["label"]);
+    }
+
+    #[test]
+    fn test_hybrid_round_trip_and_reopen() {
+        let dir = TestDir::new("hybrid_round_trip_and_reopen");
+        let (structure, data) = dir.collection();
+        {
+            let engine: HybridVectorManageService<MixedMessage> =
+                HybridVectorManageService::new(structure.clone(), data.clone(), 1024)
+                    .unwrap();
+            engine.add_bulk((0..1000).map(message).collect());
+            engine.add(message(1000));
+            engine.add(MixedMessage::default());
+        }
+        let engine: HybridVectorManageService<MixedMessage> =
+            VectorEngine::new(structure, data, 1024);
+        assert_eq!(engine.len(), 1002);
+        assert_eq!(engine.pull(999), message(999));
+        assert_eq!(engine.pull(1001), MixedMessage::default());
+        let expected: Vec<MixedMessage> = (10..1001).map(message).collect();
+        assert_eq!(engine.pullx(10, 991), expected);
+
+        let fixed = engine.read_fixed(42);
+        assert_eq!(fixed.id, 42);
+        assert_eq!(fixed.timestamp, message(42).timestamp);
+        assert!(fixed.sender.is_empty());
+        assert_eq!(fixed.status.as_str(), "sent");
+    }
+
+    #[test]
+    fn test_schema_persisted_and_readable_without_type() {
+        let dir = TestDir::new("schema_persisted_and_readable_without_type");
+        let (structure, data) = dir.collection();
+        {
+            let engine: HybridVectorManageService<MixedMessage> =
+                HybridVectorManageService::new(structure.clone(), data.clone(), 1024)
+                    .unwrap();
+            engine.add_bulk((0..10).map(message).collect());
+        }
+
+        let reader = HybridFileReader::open(structure.clone(), data.clone()).unwrap();
+        let schema = reader.schema();
+        assert_eq!(schema, &MixedMessage::schema());
+        assert_eq!(schema.name, "MixedMessage");
+        let names: Vec<&str> = schema.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["id", "sender", "timestamp", "attachments", "read", "status"]
+        );
+        assert_eq!(reader.len(), 10);
+        let value = reader.read_value(3).unwrap();
+        assert_eq!(value["sender"], "user-3");
+        assert_eq!(value["attachments"], serde_json::json!([0, 1, 2]));
+        // FixedString<8> keeps the first eight bytes.
+        assert_eq!(value["status"], "delivere");
+        assert!(reader.read_value(10).is_err());
+
+        // The structure file of a dynamic collection is not taken for one.
+        let other_dir = TestDir::new("schema_persisted_other_layout");
+        let (other_structure, other_data) = other_dir.collection();
+        crate::DynamicVectorManageService::<MixedMessage>::new(
+            other_structure.clone(),
+            other_data.clone(),
+            1024,
+        )
+        .unwrap()
+        .save(message(1));
+        let error = HybridFileReader::open(other_structure, other_data)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // Reopening with a type whose fields are laid out differently fails.
+        let other: io::Result<HybridVectorManageService<Tagged<u32>>> =
+            HybridVectorManageService::new(structure, data, 1024);
+        assert_eq!(other.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
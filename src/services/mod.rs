@@ -1,5 +1,19 @@
+pub mod backup;
+mod block_repository;
+pub mod codec;
+mod compact_index;
+pub mod compression;
 pub mod dynamic_vector_manage_service;
+pub mod encryption;
 mod file_access_service;
+mod file_swap;
+pub mod hybrid_vector_manage_service;
+pub mod raw_collection;
+pub mod repair;
+pub mod replication;
 
+pub mod shredding_vector_manage_service;
 pub mod static_vector_manage_service;
 mod string_repository;
+pub mod vector_column_service;
+pub mod versioned_vector_manage_service;
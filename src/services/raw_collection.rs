@@ -0,0 +1,516 @@
+use rayon::prelude::*;
+use serde::Serialize;
+use std::io;
+
+use crate::services::{
+    codec::CodecKind,
+    dynamic_vector_manage_service::{
+        DynamicHeader,
+        DynamicOptions,
+        DynamicVectorManageService,
+    },
+    encryption::{
+        KeyProvider,
+        PayloadCipher,
+        ENCRYPTION_OVERHEAD,
+    },
+    file_access_service::FileAccessService,
+    repair::{
+        repair_with,
+        DamagedRecord,
+        RepairOptions,
+    },
+    static_vector_manage_service::StaticHeader,
+};
+
+const LENGTH_MARKER_SIZE: usize = std::mem::size_of::<u64>();
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Static,
+    Dynamic,
+}
+
+/// Size of a file and how much of it holds data. Files grow by doubling,
+/// so the rest is preallocated slack.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileUsage {
+    pub path: String,
+    pub size: u64,
+    pub used: u64,
+    pub slack: u64,
+}
+
+impl FileUsage {
+    fn of(path: &str, used: u64) -> io::Result<Self> {
+        let size = std::fs::metadata(path)?.len();
+        Ok(Self {
+            path: path.to_string(),
+            size,
+            used,
+            slack: size.saturating_sub(used),
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CollectionInfo {
+    pub layout: Layout,
+    pub length: u64,
+    pub codec: CodecKind,
+    pub key_id: Option<String>,
+    /// Static collections only.
+    pub slot_size: Option<u64>,
+    /// Dynamic collections only.
+    pub options: Option<DynamicOptions>,
+    pub files: Vec<FileUsage>,
+}
+
+/// Problems found by [`RawCollection::verify`].
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub length: u64,
+    /// Records counted by the length marker that have no index entry or
+    /// slot.
+    pub missing_entries: u64,
+    pub damaged: Vec<DamagedRecord>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing_entries == 0 && self.damaged.is_empty()
+    }
+}
+
+struct StaticRecords {
+    file: FileAccessService,
+    header: StaticHeader,
+    cipher: Option<PayloadCipher>,
+    data_offset: u64,
+    length: u64,
+}
+
+impl StaticRecords {
+    fn slot_offset(&self, index: u64) -> u64 {
+        self.data_offset + index * self.header.slot_size
+    }
+
+    /// Slots of `index..index + count` that fit in the file.
+    fn read_slots(&self, index: u64, count: u64) -> Vec<u8> {
+        let file_size = std::fs::metadata(self.file.path())
+            .expect("Unable to get file metadata")
+            .len();
+        let available =
+            file_size.saturating_sub(self.slot_offset(index)) / self.header.slot_size;
+        let count = count.min(available);
+        self.file.read_in_file(
+            self.slot_offset(index),
+            (count * self.header.slot_size) as usize,
+        )
+    }
+
+    fn open_slot(&self, slot: &[u8]) -> io::Result<Vec<u8>> {
+        match &self.cipher {
+            Some(cipher) => cipher.open(slot),
+            None => Ok(slot.to_vec()),
+        }
+    }
+}
+
+enum Records {
+    Static(StaticRecords),
+    Dynamic(Box<DynamicVectorManageService<()>>),
+}
+
+/// Extra check run on every record by [`RawCollection::verify`].
+pub type RecordCheck<'a> = &'a (dyn Fn(&[u8]) -> io::Result<()> + Sync);
+
+/// Untyped access to a collection: the bytes each record was encoded to by
+/// the codec in its header, without deserializing them. For tools that
+/// inspect, copy or check collections without the record type. Static
+/// slots are returned whole, padding included.
+///
+/// The collection must not be written by anyone else while it is open.
+pub struct RawCollection {
+    records: Records,
+}
+
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path))
+}
+
+fn no_header(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} has no header", path),
+    )
+}
+
+impl RawCollection {
+    pub fn open_static(
+        structure_file_path: &str,
+        keys: Option<&dyn KeyProvider>,
+    ) -> io::Result<Self> {
+        if !std::path::Path::new(structure_file_path).exists() {
+            return Err(not_found(structure_file_path));
+        }
+        let file = FileAccessService::new(structure_file_path.to_string(), 1024);
+        let header = file
+            .read_header::<StaticHeader>()?
+            .ok_or_else(|| no_header(structure_file_path))?;
+        let cipher = match (&header.key_id, keys) {
+            (Some(key_id), Some(keys)) => Some(PayloadCipher::new(key_id, keys)?),
+            (Some(key_id), None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{} is encrypted with key {}", structure_file_path, key_id),
+                ))
+            }
+            (None, _) => None,
+        };
+        let data_offset = FileAccessService::data_offset(&header)?;
+        let length = u64::from_le_bytes(
+            file.read_in_file(0, LENGTH_MARKER_SIZE).try_into().unwrap(),
+        );
+        Ok(Self {
+            records: Records::Static(StaticRecords {
+                file,
+                header,
+                cipher,
+                data_offset,
+                length,
+            }),
+        })
+    }
+
+    pub fn open_dynamic(
+        structure_file_path: &str,
+        string_file_path: &str,
+        keys: Option<&dyn KeyProvider>,
+    ) -> io::Result<Self> {
+        for path in [structure_file_path, string_file_path] {
+            if !std::path::Path::new(path).exists() {
+                return Err(not_found(path));
+            }
+        }
+        FileAccessService::new(structure_file_path.to_string(), 1024)
+            .read_header::<DynamicHeader>()?
+            .ok_or_else(|| no_header(structure_file_path))?;
+        Self::dynamic(
+            structure_file_path,
+            string_file_path,
+            DynamicOptions::default(),
+            keys,
+        )
+    }
+
+    /// Creates an empty dynamic collection, encrypted when `keys` is given.
+    pub fn create_dynamic(
+        structure_file_path: &str,
+        string_file_path: &str,
+        options: DynamicOptions,
+        keys: Option<&dyn KeyProvider>,
+    ) -> io::Result<Self> {
+        if std::path::Path::new(structure_file_path).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", structure_file_path),
+            ));
+        }
+        Self::dynamic(structure_file_path, string_file_path, options, keys)
+    }
+
+    fn dynamic(
+        structure_file_path: &str,
+        string_file_path: &str,
+        options: DynamicOptions,
+        keys: Option<&dyn KeyProvider>,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            records: Records::Dynamic(Box::new(DynamicVectorManageService::open(
+                structure_file_path.to_string(),
+                string_file_path.to_string(),
+                1024,
+                options,
+                keys,
+            )?)),
+        })
+    }
+
+    pub fn layout(&self) -> Layout {
+        match &self.records {
+            Records::Static(_) => Layout::Static,
+            Records::Dynamic(_) => Layout::Dynamic,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        match &self.records {
+            Records::Static(records) => records.length,
+            Records::Dynamic(records) => records.get_length(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn codec(&self) -> CodecKind {
+        match &self.records {
+            Records::Static(records) => records.header.codec,
+            Records::Dynamic(records) => records.codec(),
+        }
+    }
+
+    pub fn info(&self) -> io::Result<CollectionInfo> {
+        Ok(match &self.records {
+            Records::Static(records) => CollectionInfo {
+                layout: Layout::Static,
+                length: records.length,
+                codec: records.header.codec,
+                key_id: records.header.key_id.clone(),
+                slot_size: Some(records.header.slot_size),
+                options: None,
+                files: vec![FileUsage::of(
+                    records.file.path(),
+                    records.slot_offset(records.length),
+                )?],
+            },
+            Records::Dynamic(records) => {
+                let (structure_used, data_used) = records.used_sizes();
+                CollectionInfo {
+                    layout: Layout::Dynamic,
+                    length: records.get_length(),
+                    codec: records.codec(),
+                    key_id: records.key_id().map(str::to_string),
+                    slot_size: None,
+                    options: Some(records.options().clone()),
+                    files: vec![
+                        FileUsage::of(&records.structure_file_path(), structure_used)?,
+                        FileUsage::of(records.data_file_path(), data_used)?,
+                    ],
+                }
+            }
+        })
+    }
+
+    /// Encoded records `index..index + count`. Panics on records that
+    /// cannot be read; check damaged collections with `verify` first.
+    pub fn read(&self, index: u64, count: u64) -> io::Result<Vec<Vec<u8>>> {
+        if index + count > self.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "records {}..{} out of range of {}",
+                    index,
+                    index + count,
+                    self.len()
+                ),
+            ));
+        }
+        match &self.records {
+            Records::Static(records) => records
+                .read_slots(index, count)
+                .par_chunks(records.header.slot_size as usize)
+                .map(|slot| records.open_slot(slot))
+                .collect(),
+            Records::Dynamic(records) => Ok(records.load_encoded_bulk(index, count)),
+        }
+    }
+
+    /// Appends records encoded with the collection's codec. Fails with
+    /// `InvalidInput`, writing nothing, when a record does not fit in a
+    /// static slot.
+    pub fn append(&mut self, records: Vec<Vec<u8>>) -> io::Result<()> {
+        match &mut self.records {
+            Records::Static(slots) => {
+                let slot_size = slots.header.slot_size as usize;
+                let payload_size = match slots.cipher {
+                    Some(_) => slot_size - ENCRYPTION_OVERHEAD,
+                    None => slot_size,
+                };
+                if let Some(record) = records.iter().find(|r| r.len() > payload_size) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "encoded record of {} bytes exceeds the {} byte slot",
+                            record.len(),
+                            payload_size
+                        ),
+                    ));
+                }
+                let buffer: Vec<u8> = records
+                    .into_par_iter()
+                    .flat_map(|mut record| {
+                        record.resize(payload_size, 0);
+                        match &slots.cipher {
+                            Some(cipher) => cipher.seal(&record),
+                            None => record,
+                        }
+                    })
+                    .collect();
+                let count = (buffer.len() / slot_size) as u64;
+                slots
+                    .file
+                    .write_in_file(slots.slot_offset(slots.length), &buffer);
+                slots.length += count;
+                slots.file.write_in_file(0, &slots.length.to_le_bytes());
+            }
+            Records::Dynamic(dynamic) => dynamic.save_encoded_bulk(records),
+        }
+        Ok(())
+    }
+
+    /// Checks that every record can be read and, when `check` is given,
+    /// that it passes `check`, typically a decode against a schema. For
+    /// dynamic collections this is a dry run of [`repair`](crate::repair).
+    pub fn verify(
+        &self,
+        keys: Option<&dyn KeyProvider>,
+        check: Option<RecordCheck>,
+    ) -> io::Result<VerifyReport> {
+        match &self.records {
+            Records::Static(records) => {
+                let slot_size = records.header.slot_size;
+                let slots = records.read_slots(0, records.length);
+                let mut damaged: Vec<DamagedRecord> = slots
+                    .par_chunks(slot_size as usize)
+                    .enumerate()
+                    .filter_map(|(index, slot)| {
+                        let result = records.open_slot(slot).and_then(|payload| {
+                            check.map_or(Ok(()), |check| check(&payload))
+                        });
+                        let start = records.slot_offset(index as u64);
+                        result.err().map(|error| DamagedRecord {
+                            index: index as u64,
+                            start,
+                            end: start + slot_size,
+                            reason: error.to_string(),
+                        })
+                    })
+                    .collect();
+                damaged.sort_by_key(|record| record.index);
+                Ok(VerifyReport {
+                    length: records.length,
+                    missing_entries: records.length - slots.len() as u64 / slot_size,
+                    damaged,
+                })
+            }
+            Records::Dynamic(records) => {
+                let decode =
+                    check.map(|check| move |_: CodecKind, bytes: &[u8]| check(bytes));
+                let report = repair_with(
+                    &records.structure_file_path(),
+                    records.data_file_path(),
+                    RepairOptions {
+                        keys,
+                        dry_run: true,
+                        renumber: false,
+                    },
+                    decode.as_ref().map(|decode| decode as _),
+                )?;
+                let mut damaged = report.truncated;
+                damaged.extend(report.quarantined);
+                damaged.sort_by_key(|record| record.index);
+                Ok(VerifyReport {
+                    length: report.length_before,
+                    missing_entries: report.missing_entries,
+                    damaged,
+                })
+            }
+        }
+    }
+
+    /// Cuts the preallocated slack off the end of the files. Returns the
+    /// number of bytes released.
+    pub fn trim(self) -> io::Result<u64> {
+        let files = self.info()?.files;
+        drop(self);
+        let mut released = 0;
+        for file in files {
+            if file.slack > 0 {
+                std::fs::OpenOptions::new()
+                    .write(true)
+                    .open(&file.path)?
+                    .set_len(file.used)?;
+                released += file.slack;
+            }
+        }
+        Ok(released)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        services::static_vector_manage_service::StaticVectorManageService,
+        test_support::TestDir,
+    };
+
+    #[test]
+    fn test_raw_copy_between_layouts() {
+        let dir = TestDir::new("raw_copy_between_layouts");
+        let temp = |name: &str| dir.path(name);
+        let (structure, data) = (temp("RawSource.bin"), temp("StringRawSource.bin"));
+        let lines: Vec<String> = (0..300).map(|i| format!("line {}", i)).collect();
+        {
+            let service = DynamicVectorManageService::<String>::new(
+                structure.clone(),
+                data.clone(),
+                1024,
+            )
+            .unwrap();
+            service.save_bulk(lines.clone());
+        }
+        let source = RawCollection::open_dynamic(&structure, &data, None).unwrap();
+        assert_eq!(source.len(), 300);
+        let info = source.info().unwrap();
+        assert_eq!(info.layout, Layout::Dynamic);
+        assert!(info
+            .files
+            .iter()
+            .all(|file| file.used + file.slack == file.size));
+
+        let (copy_structure, copy_data) =
+            (temp("RawCopy.bin"), temp("StringRawCopy.bin"));
+        let options = DynamicOptions {
+            framed: true,
+            ..Default::default()
+        };
+        let mut copy =
+            RawCollection::create_dynamic(&copy_structure, &copy_data, options, None)
+                .unwrap();
+        copy.append(source.read(0, 300).unwrap()).unwrap();
+        assert!(copy.verify(None, None).unwrap().is_ok());
+        assert!(copy.trim().unwrap() > 0);
+        let copy =
+            DynamicVectorManageService::<String>::new(copy_structure, copy_data, 1024)
+                .unwrap();
+        assert_eq!(copy.load_bulk(0, 300), lines);
+        copy.save("after trim".to_string());
+        assert_eq!(copy.load(300), "after trim");
+
+        let slots = temp("RawSlots.bin");
+        StaticVectorManageService::<u64>::new(slots.clone(), String::new(), 1024)
+            .unwrap();
+        let mut raw = RawCollection::open_static(&slots, None).unwrap();
+        let values: Vec<Vec<u8>> = (0..10u64).map(|i| i.to_le_bytes().to_vec()).collect();
+        raw.append(values.clone()).unwrap();
+        assert!(raw.append(vec![vec![0; 9]]).is_err());
+        assert_eq!(raw.read(3, 2).unwrap(), values[3..5]);
+        let fail_odd = |bytes: &[u8]| match bytes[0] % 2 {
+            0 => Ok(()),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "odd")),
+        };
+        let report = raw.verify(None, Some(&fail_odd)).unwrap();
+        assert_eq!(
+            report.damaged.iter().map(|r| r.index).collect::<Vec<_>>(),
+            vec![1, 3, 5, 7, 9]
+        );
+        drop(raw);
+        let service =
+            StaticVectorManageService::<u64>::new(slots, String::new(), 1024).unwrap();
+        assert_eq!(service.read_bulk(0, 10), (0..10).collect::<Vec<u64>>());
+    }
+}
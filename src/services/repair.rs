@@ -0,0 +1,439 @@
+use rayon::prelude::*;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use std::io::{
+    self,
+    Write,
+};
+
+use crate::services::{
+    block_repository::BlockRepository,
+    codec::CodecKind,
+    dynamic_vector_manage_service::{
+        read_index,
+        replace_structure_file,
+        DynamicHeader,
+    },
+    encryption::{
+        KeyProvider,
+        PayloadCipher,
+    },
+    file_access_service::FileAccessService,
+    string_repository::{
+        unframe_record,
+        StringRepository,
+    },
+};
+
+#[derive(Clone, Copy, Default)]
+pub struct RepairOptions<'a> {
+    /// Keys of an encrypted collection, needed to check its records.
+    pub keys: Option<&'a dyn KeyProvider>,
+    /// Only report what would change.
+    pub dry_run: bool,
+    /// Remove damaged records followed by intact ones from the index,
+    /// moving the records after them down. Without it they keep their
+    /// slots and only damaged records at the end are dropped.
+    pub renumber: bool,
+}
+
+/// A record `repair` removed from the index.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DamagedRecord {
+    /// Position of the record before the repair.
+    pub index: u64,
+    /// Location from the index entry.
+    pub start: u64,
+    pub end: u64,
+    pub reason: String,
+}
+
+/// Records `from..from + count` that renumbering moved to `to..to + count`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenumberedRange {
+    pub from: u64,
+    pub to: u64,
+    pub count: u64,
+}
+
+/// What `repair` found and, unless it was a dry run, changed.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Length marker of the structure file before the repair.
+    pub length_before: u64,
+    pub length_after: u64,
+    /// Records counted by the length marker that have no index entry.
+    pub missing_entries: u64,
+    /// Damaged records at the end of the collection, dropped.
+    pub truncated: Vec<DamagedRecord>,
+    /// Damaged records followed by intact ones. With
+    /// [`RepairOptions::renumber`] they are moved to the quarantine file
+    /// and the records after them move down; otherwise they are left in
+    /// place.
+    pub quarantined: Vec<DamagedRecord>,
+    pub quarantine_file: Option<String>,
+    /// New positions of the records moved by renumbering.
+    pub renumbered: Vec<RenumberedRange>,
+    /// Whether the files were rewritten.
+    pub applied: bool,
+}
+
+impl RepairReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing_entries == 0
+            && self.truncated.is_empty()
+            && self.quarantined.is_empty()
+    }
+}
+
+enum DataFile {
+    Plain(StringRepository),
+    Blocks(BlockRepository),
+}
+
+impl DataFile {
+    /// Raw bytes of the record at `location`, or why they cannot be read.
+    fn read(&self, index: u64, (start, end): (u64, u64)) -> Result<Vec<u8>, String> {
+        match self {
+            DataFile::Plain(strings) => {
+                if start == 0 && end == 0 && index > 0 {
+                    return Err("index entry was never written".to_string());
+                }
+                if start > end {
+                    return Err(format!(
+                        "record starts at {} after its end {}",
+                        start, end
+                    ));
+                }
+                if end > strings.end_offset() {
+                    return Err(format!(
+                        "record ends at {}, past the end of the data at {}",
+                        end,
+                        strings.end_offset()
+                    ));
+                }
+                Ok(strings.load_string_content(start, end - start))
+            }
+            DataFile::Blocks(blocks) => blocks
+                .load(&[(start, end)])
+                .map(|mut records| records.remove(0))
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Checks a dynamic collection whose length marker, index and data file
+/// may disagree after a crash, and rewrites the index so every record in
+/// it can be read. Records must decode as stored: frames must match their
+/// checksums and encrypted payloads must authenticate. Use the typed
+/// [`DynamicVectorManageService::repair`](crate::DynamicVectorManageService::repair)
+/// to also check that records deserialize.
+///
+/// Damaged records at the end are dropped. Damaged records followed by
+/// intact ones are reported but keep their slots, so no other record
+/// changes position. With [`RepairOptions::renumber`] they are removed from
+/// the index, their readable bytes are appended to `<data file>.quarantine`
+/// as [index u64][length u64][bytes] and the report maps the records that
+/// moved down. The data file itself is not changed. The collection must
+/// not be open elsewhere.
+pub fn repair(
+    structure_file_path: &str,
+    string_file_path: &str,
+    options: RepairOptions,
+) -> io::Result<RepairReport> {
+    repair_with(structure_file_path, string_file_path, options, None)
+}
+
+type DecodeCheck<'a> = &'a (dyn Fn(CodecKind, &[u8]) -> io::Result<()> + Sync);
+
+pub(crate) fn repair_with(
+    structure_file_path: &str,
+    string_file_path: &str,
+    options: RepairOptions,
+    decode: Option<DecodeCheck>,
+) -> io::Result<RepairReport> {
+    for path in [structure_file_path, string_file_path] {
+        if !std::path::Path::new(path).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} does not exist", path),
+            ));
+        }
+    }
+    let structure = FileAccessService::new(structure_file_path.to_string(), 1024);
+    let header = structure.read_header::<DynamicHeader>()?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} has no header; rebuild framed collections with rebuild_index",
+                structure_file_path
+            ),
+        )
+    })?;
+    let cipher = match (&header.key_id, options.keys) {
+        (Some(key_id), Some(keys)) => Some(PayloadCipher::new(key_id, keys)?),
+        (Some(key_id), None) => {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is encrypted with key {}", structure_file_path, key_id),
+            ))
+        }
+        (None, _) => None,
+    };
+    let (data_file, record_cipher) = match &header.options.blocks {
+        Some(blocks) => (
+            DataFile::Blocks(BlockRepository::new(
+                string_file_path.to_string(),
+                1024,
+                blocks.clone(),
+                cipher,
+            )?),
+            None,
+        ),
+        None => (
+            DataFile::Plain(StringRepository::new(string_file_path.to_string(), 1024)),
+            cipher,
+        ),
+    };
+
+    let length_before =
+        u64::from_le_bytes(structure.read_in_file(0, 8).try_into().unwrap());
+    let locations = read_index(&structure, &header, length_before)?;
+
+    let codec = header.options.codec;
+    let framed = header.options.framed;
+    let checked: Vec<Result<(), (String, Vec<u8>)>> = locations
+        .par_iter()
+        .enumerate()
+        .map(|(index, location)| {
+            let raw = data_file
+                .read(index as u64, *location)
+                .map_err(|reason| (reason, Vec::new()))?;
+            let damaged = |error: io::Error| (error.to_string(), raw.clone());
+            let payload = if framed {
+                unframe_record(&raw).map_err(damaged)?
+            } else {
+                &raw
+            };
+            let decrypted;
+            let payload = match &record_cipher {
+                Some(cipher) => {
+                    decrypted = cipher.open(payload).map_err(damaged)?;
+                    &decrypted
+                }
+                None => payload,
+            };
+            match decode {
+                Some(decode) => decode(codec, payload).map_err(damaged),
+                None => Ok(()),
+            }
+        })
+        .collect();
+
+    let intact_end = checked
+        .iter()
+        .rposition(Result::is_ok)
+        .map_or(0, |last| last + 1);
+    let mut report = RepairReport {
+        length_before,
+        missing_entries: length_before - locations.len() as u64,
+        ..Default::default()
+    };
+    let mut kept = Vec::with_capacity(locations.len());
+    let mut quarantined_bytes = Vec::new();
+    for (index, (result, &(start, end))) in
+        checked.into_iter().zip(&locations).enumerate()
+    {
+        let Err((reason, raw)) = result else {
+            if kept.len() != index {
+                let to = kept.len() as u64;
+                match report.renumbered.last_mut() {
+                    Some(range) if range.from + range.count == index as u64 => {
+                        range.count += 1
+                    }
+                    _ => report.renumbered.push(RenumberedRange {
+                        from: index as u64,
+                        to,
+                        count: 1,
+                    }),
+                }
+            }
+            kept.push((start, end));
+            continue;
+        };
+        let record = DamagedRecord {
+            index: index as u64,
+            start,
+            end,
+            reason,
+        };
+        if index >= intact_end {
+            report.truncated.push(record);
+        } else if options.renumber {
+            quarantined_bytes.extend_from_slice(&(index as u64).to_le_bytes());
+            quarantined_bytes.extend_from_slice(&(raw.len() as u64).to_le_bytes());
+            quarantined_bytes.extend_from_slice(&raw);
+            report.quarantined.push(record);
+        } else {
+            kept.push((start, end));
+            report.quarantined.push(record);
+        }
+    }
+    report.length_after = kept.len() as u64;
+    if options.renumber && !report.quarantined.is_empty() {
+        report.quarantine_file = Some(format!("{}.quarantine", string_file_path));
+    }
+
+    if !options.dry_run && report.length_after != report.length_before {
+        if let Some(path) = &report.quarantine_file {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?
+                .write_all(&quarantined_bytes)?;
+        }
+        replace_structure_file(structure_file_path, &header, &kept)?;
+        report.applied = true;
+    }
+    Ok(report)
+}
+
+/// Typed check for [`repair_with`]: the record must deserialize as `T`.
+pub(crate) fn decodes_as<T: DeserializeOwned>(
+    codec: CodecKind,
+    bytes: &[u8],
+) -> io::Result<()> {
+    codec.decode::<T>(bytes).map(|_| ())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        services::dynamic_vector_manage_service::{
+            DynamicOptions,
+            DynamicVectorManageService,
+        },
+        test_support::TestDir,
+    };
+
+    fn write_lines(structure: &str, data: &str, options: DynamicOptions, count: u64) {
+        let service = DynamicVectorManageService::<String>::with_options(
+            structure.to_string(),
+            data.to_string(),
+            1024,
+            options,
+        )
+        .unwrap();
+        service.save_bulk((0..count).map(|i| format!("line {}", i)).collect());
+    }
+
+    #[test]
+    fn test_repair_crashed_collection() {
+        let dir = TestDir::new("repair_crashed_collection");
+        let (structure, data) = dir.collection();
+        write_lines(&structure, &data, DynamicOptions::default(), 20);
+
+        // A crash after moving the length marker but before writing the
+        // index entries of the last two saves.
+        let file = FileAccessService::new(structure.clone(), 1024);
+        file.write_in_file(0, &22u64.to_le_bytes());
+        // The data end offset was not moved for the last record either.
+        let end_offset = StringRepository::new(data.clone(), 1024).end_offset() - 7;
+        FileAccessService::new(data.clone(), 1024)
+            .write_in_file(0, &end_offset.to_le_bytes());
+        drop(file);
+
+        let report = repair(
+            &structure,
+            &data,
+            RepairOptions {
+                dry_run: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(!report.applied);
+        assert_eq!(report.length_before, 22);
+        assert_eq!(
+            report.truncated.iter().map(|r| r.index).collect::<Vec<_>>(),
+            vec![19, 20, 21]
+        );
+        assert!(report.truncated[0].reason.contains("past the end"));
+
+        let report = repair(&structure, &data, RepairOptions::default()).unwrap();
+        assert!(report.applied);
+        assert_eq!(report.length_after, 19);
+        let service = DynamicVectorManageService::<String>::new(
+            structure.clone(),
+            data.clone(),
+            1024,
+        )
+        .unwrap();
+        assert_eq!(service.load_bulk(0, 19)[18], "line 18");
+        drop(service);
+        assert!(repair(&structure, &data, RepairOptions::default())
+            .unwrap()
+            .is_consistent());
+    }
+
+    #[test]
+    fn test_repair_quarantines_undecodable_records() {
+        let dir = TestDir::new("repair_quarantines_undecodable_records");
+        let (structure, data) = dir.collection();
+        write_lines(&structure, &data, DynamicOptions::default(), 10);
+        // Record 3 becomes a string length far past the end of its bytes.
+        let mut raw = std::fs::read(&data).unwrap();
+        let at = raw.windows(6).position(|w| w == b"line 3").unwrap();
+        raw[at - 8..at].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&data, raw).unwrap();
+
+        // Untyped repair cannot tell; the typed one can.
+        assert!(repair(&structure, &data, RepairOptions::default())
+            .unwrap()
+            .is_consistent());
+        let report = DynamicVectorManageService::<String>::repair(
+            &structure,
+            &data,
+            RepairOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(report.quarantined.len(), 1);
+        assert_eq!(report.quarantined[0].index, 3);
+        assert!(report.truncated.is_empty());
+        // Left in place unless renumbering was asked for.
+        assert!(!report.applied);
+        assert_eq!(report.length_after, 10);
+        assert!(report.quarantine_file.is_none());
+        assert!(report.renumbered.is_empty());
+
+        let report = DynamicVectorManageService::<String>::repair(
+            &structure,
+            &data,
+            RepairOptions {
+                renumber: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(report.applied);
+        assert_eq!(
+            report.renumbered,
+            vec![RenumberedRange {
+                from: 4,
+                to: 3,
+                count: 6
+            }]
+        );
+        let quarantine = std::fs::read(report.quarantine_file.unwrap()).unwrap();
+        assert_eq!(quarantine[..8], 3u64.to_le_bytes());
+        assert!(quarantine.ends_with(b"line 3"));
+
+        let service =
+            DynamicVectorManageService::<String>::new(structure, data, 1024).unwrap();
+        assert_eq!(service.get_length(), 9);
+        assert_eq!(service.load(3), "line 4");
+    }
+}
@@ -0,0 +1,781 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    fs::{
+        self,
+        OpenOptions,
+    },
+    io::{
+        self,
+        Read,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    net::{
+        TcpListener,
+        TcpStream,
+        ToSocketAddrs,
+    },
+    path::Path,
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        mpsc,
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::services::{
+    backup::{
+        padded_from,
+        Segment,
+    },
+    dynamic_vector_manage_service::DynamicVectorManageService,
+};
+
+/// The length marker at the start of the index file, which publishes the
+/// records an entry appends.
+const LENGTH_MARKER_SIZE: u64 = 8;
+/// Default bound on the file bytes an entry carries.
+pub const DEFAULT_MAX_ENTRY_BYTES: u64 = 16 << 20;
+/// Default bound on a message received by [`TcpTransport`]; room for an
+/// entry of [`DEFAULT_MAX_ENTRY_BYTES`] and its encoding.
+pub const DEFAULT_MAX_FRAME_SIZE: u64 = 64 << 20;
+
+/// Bytes of the index and data file tails that partial entries have
+/// carried since the last complete one.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TailProgress {
+    pub structure: u64,
+    pub data: u64,
+}
+
+/// How far a follower has applied the log: the sequence number of the
+/// last entry, 0 before the first, and the records it then holds.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplicaPosition {
+    pub sequence: u64,
+    pub length: u64,
+    /// Tails already applied past `length` when the last entry was partial.
+    #[serde(default)]
+    pub partial: TailProgress,
+}
+
+/// Byte ranges written to one file, in order, and its size after them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileChanges {
+    pub size: u64,
+    pub writes: Vec<(u64, Vec<u8>)>,
+}
+
+/// The bytes a source wrote to its index and data files while records
+/// `since_len..length` were appended: the appended tails and the regions
+/// rewritten in place, such as the length marker.
+///
+/// Tails larger than the source's entry budget are split: partial entries
+/// carry the next part of the tails and leave `length` at `since_len`, and
+/// the complete entry that ends the run carries the rest and the rewritten
+/// regions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub sequence: u64,
+    pub since_len: u64,
+    pub length: u64,
+    pub structure: FileChanges,
+    pub data: FileChanges,
+    /// Tails carried up to the end of this entry; zero when it is complete.
+    pub partial: TailProgress,
+}
+
+impl LogEntry {
+    /// Position of a follower that applied this entry.
+    pub fn position(&self) -> ReplicaPosition {
+        ReplicaPosition {
+            sequence: self.sequence,
+            length: self.length,
+            partial: self.partial,
+        }
+    }
+
+    pub fn is_partial(&self) -> bool {
+        self.partial != TailProgress::default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ReplicationMessage {
+    /// Sent by a follower when it connects, so the source resumes after
+    /// the last entry it applied.
+    Resume(ReplicaPosition),
+    Entry(LogEntry),
+}
+
+/// Carries replication messages between a source and a follower.
+pub trait Transport: Send {
+    fn send(&mut self, message: &ReplicationMessage) -> io::Result<()>;
+    /// Waits for the next message; `None` once the other end has closed.
+    fn receive(&mut self) -> io::Result<Option<ReplicationMessage>>;
+}
+
+/// In-process transport over a pair of channels.
+pub struct ChannelTransport {
+    sender: mpsc::Sender<ReplicationMessage>,
+    receiver: mpsc::Receiver<ReplicationMessage>,
+}
+
+impl ChannelTransport {
+    /// Two connected ends, one for the source and one for the follower.
+    pub fn pair() -> (Self, Self) {
+        let (to_follower, from_source) = mpsc::channel();
+        let (to_source, from_follower) = mpsc::channel();
+        (
+            Self {
+                sender: to_follower,
+                receiver: from_follower,
+            },
+            Self {
+                sender: to_source,
+                receiver: from_source,
+            },
+        )
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn send(&mut self, message: &ReplicationMessage) -> io::Result<()> {
+        self.sender.send(message.clone()).map_err(|_| {
+            io::Error::new(io::ErrorKind::BrokenPipe, "replication channel closed")
+        })
+    }
+
+    fn receive(&mut self) -> io::Result<Option<ReplicationMessage>> {
+        Ok(self.receiver.recv().ok())
+    }
+}
+
+/// Transport over a TCP connection, each message sent as
+/// [length u64][bincode message]. A received message longer than the
+/// maximum frame size fails with `InvalidData` before anything is
+/// allocated for it.
+pub struct TcpTransport {
+    stream: TcpStream,
+    max_frame_size: u64,
+}
+
+impl TcpTransport {
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::from_stream(TcpStream::connect(address)?)
+    }
+
+    /// Waits for the next connection on `listener`.
+    pub fn accept(listener: &TcpListener) -> io::Result<Self> {
+        Self::from_stream(listener.accept()?.0)
+    }
+
+    pub fn from_stream(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        })
+    }
+
+    /// Sets the largest message accepted, which must leave room for the
+    /// source's entry budget.
+    pub fn with_max_frame_size(mut self, max_frame_size: u64) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, message: &ReplicationMessage) -> io::Result<()> {
+        let bytes = bincode::serialize(message).map_err(io::Error::other)?;
+        let mut frame = Vec::with_capacity(8 + bytes.len());
+        frame.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        frame.extend_from_slice(&bytes);
+        self.stream.write_all(&frame)?;
+        self.stream.flush()
+    }
+
+    fn receive(&mut self) -> io::Result<Option<ReplicationMessage>> {
+        let mut len = [0u8; 8];
+        match self.stream.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(error) => return Err(error),
+        }
+        let len = u64::from_le_bytes(len);
+        if len > self.max_frame_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "message of {} bytes exceeds the maximum frame size {}",
+                    len, self.max_frame_size
+                ),
+            ));
+        }
+        let mut bytes = vec![0u8; len as usize];
+        self.stream.read_exact(&mut bytes)?;
+        bincode::deserialize(&bytes)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// `len` bytes of the tail of `segment` from `skip` bytes past its start,
+/// with its patches when `complete`.
+fn file_changes(
+    path: &str,
+    segment: Segment,
+    skip: u64,
+    len: u64,
+    complete: bool,
+) -> io::Result<FileChanges> {
+    let from = segment.from + skip;
+    let mut tail = Vec::with_capacity(len as usize);
+    padded_from(path, from)?.take(len).read_to_end(&mut tail)?;
+    let mut writes = vec![(from, tail)];
+    if complete {
+        writes.extend(segment.patches);
+    }
+    Ok(FileChanges {
+        size: segment.size,
+        writes,
+    })
+}
+
+fn patch_bytes(segment: &Segment) -> u64 {
+    segment
+        .patches
+        .iter()
+        .map(|(_, bytes)| bytes.len() as u64)
+        .sum()
+}
+
+/// End of the part of the tail of `segment` that later appends leave as it
+/// is: up to the first patch inside the tail.
+fn stable_end(segment: &Segment) -> u64 {
+    segment
+        .patches
+        .iter()
+        .filter(|(offset, bytes)| offset + bytes.len() as u64 > segment.from)
+        .map(|(offset, _)| *offset)
+        .fold(segment.size, u64::min)
+        .max(segment.from)
+}
+
+/// Ships the appends of a live dynamic collection to followers. Entries
+/// are cut from consistent captures, so appends continue while they are
+/// read, and each entry holds everything appended since the previous one.
+pub struct ReplicationSource<T>
+where
+    T: Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+{
+    service: Arc<DynamicVectorManageService<T>>,
+    max_entry_bytes: u64,
+}
+
+impl<T> ReplicationSource<T>
+where
+    T: Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+{
+    pub fn new(service: Arc<DynamicVectorManageService<T>>) -> Self {
+        Self {
+            service,
+            max_entry_bytes: DEFAULT_MAX_ENTRY_BYTES,
+        }
+    }
+
+    /// Sets the most file bytes an entry carries; larger tails are shipped
+    /// as several partial entries. An entry may exceed it by the regions
+    /// rewritten in place, which are never split.
+    pub fn with_max_entry_bytes(mut self, max_entry_bytes: u64) -> Self {
+        self.max_entry_bytes = max_entry_bytes.max(1);
+        self
+    }
+
+    /// The entry that follows `position`, or `None` when nothing was
+    /// appended since. Each entry comes from a fresh capture; partial
+    /// entries only carry the bytes of the tails before the regions that
+    /// appends rewrite, which stay as they are, and the complete entry
+    /// carries the rest. Fails with `InvalidInput` when the follower holds
+    /// more records than the source.
+    pub fn next_entry(&self, position: &ReplicaPosition) -> io::Result<Option<LogEntry>> {
+        let capture = self.service.capture(position.length)?;
+        if capture.length == position.length
+            && position.sequence > 0
+            && position.partial == TailProgress::default()
+        {
+            return Ok(None);
+        }
+        let done = position.partial;
+        let left = |segment: &Segment, done: u64, end: u64| {
+            (end - segment.from).saturating_sub(done)
+        };
+        let data_left = left(&capture.data, done.data, capture.data.size);
+        let structure_left =
+            left(&capture.structure, done.structure, capture.structure.size);
+        let data_stable = left(&capture.data, done.data, stable_end(&capture.data));
+        let structure_stable = left(
+            &capture.structure,
+            done.structure,
+            stable_end(&capture.structure),
+        );
+        let patches = patch_bytes(&capture.data) + patch_bytes(&capture.structure);
+        let complete = data_left + structure_left + patches <= self.max_entry_bytes
+            || data_stable + structure_stable == 0;
+        let (data_len, structure_len) = if complete {
+            (data_left, structure_left)
+        } else {
+            let data_len = data_stable.min(self.max_entry_bytes);
+            let structure_len = structure_stable.min(self.max_entry_bytes - data_len);
+            (data_len, structure_len)
+        };
+        let partial = match complete {
+            true => TailProgress::default(),
+            false => TailProgress {
+                structure: done.structure + structure_len,
+                data: done.data + data_len,
+            },
+        };
+        Ok(Some(LogEntry {
+            sequence: position.sequence + 1,
+            since_len: capture.since_len,
+            length: if complete {
+                capture.length
+            } else {
+                capture.since_len
+            },
+            structure: file_changes(
+                &self.service.structure_file_path(),
+                capture.structure,
+                done.structure,
+                structure_len,
+                complete,
+            )?,
+            data: file_changes(
+                self.service.data_file_path(),
+                capture.data,
+                done.data,
+                data_len,
+                complete,
+            )?,
+            partial,
+        }))
+    }
+
+    /// Waits for a follower to send its position, then ships a new entry
+    /// every `poll_interval` while records are appended. Once `stop` is set
+    /// it ships the records appended until then and returns the follower's
+    /// position.
+    pub fn serve(
+        &self,
+        transport: &mut dyn Transport,
+        poll_interval: Duration,
+        stop: &AtomicBool,
+    ) -> io::Result<ReplicaPosition> {
+        let mut position = match transport.receive()? {
+            Some(ReplicationMessage::Resume(position)) => position,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("expected the follower's position, got {:?}", other),
+                ))
+            }
+        };
+        loop {
+            let stopping = stop.load(Ordering::Acquire);
+            while let Some(entry) = self.next_entry(&position)? {
+                let shipped = entry.position();
+                transport.send(&ReplicationMessage::Entry(entry))?;
+                position = shipped;
+                if position.partial == TailProgress::default() {
+                    break;
+                }
+            }
+            if stopping {
+                return Ok(position);
+            }
+            std::thread::sleep(poll_interval);
+        }
+    }
+}
+
+/// Warm standby copy of a dynamic collection, built by applying the log
+/// entries of a [`ReplicationSource`]. The files open as a normal
+/// collection; readers see the records present when they opened them.
+///
+/// The position is kept in `<structure file>.replica` and moved only after
+/// an entry is on disk, so a follower restarted after a crash resumes
+/// from the last entry it applied; applying an entry again is harmless.
+pub struct Follower {
+    structure_file_path: String,
+    string_file_path: String,
+    position: ReplicaPosition,
+}
+
+impl Follower {
+    /// Opens the replica at these paths, or starts one when neither file
+    /// exists. Fails with `AlreadyExists` when the files exist but were not
+    /// written by a follower.
+    pub fn open(structure_file_path: &str, string_file_path: &str) -> io::Result<Self> {
+        let mut follower = Self {
+            structure_file_path: structure_file_path.to_string(),
+            string_file_path: string_file_path.to_string(),
+            position: ReplicaPosition::default(),
+        };
+        let state_path = follower.state_path();
+        if Path::new(&state_path).exists() {
+            follower.position = serde_json::from_slice(&fs::read(&state_path)?)?;
+            return Ok(follower);
+        }
+        for path in [structure_file_path, string_file_path] {
+            if Path::new(path).exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a replica", path),
+                ));
+            }
+        }
+        follower.save_position()?;
+        Ok(follower)
+    }
+
+    pub fn position(&self) -> ReplicaPosition {
+        self.position
+    }
+
+    fn state_path(&self) -> String {
+        format!("{}.replica", self.structure_file_path)
+    }
+
+    fn save_position(&self) -> io::Result<()> {
+        let state_path = self.state_path();
+        let partial = format!("{}.partial", state_path);
+        fs::write(&partial, serde_json::to_vec(&self.position)?)?;
+        fs::rename(&partial, &state_path)
+    }
+
+    /// Writes `changes` to the file at `path` and syncs it. The bytes that
+    /// fall in `0..deferred` are written last, after a sync of the rest.
+    fn write_changes(path: &str, changes: &FileChanges, deferred: u64) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut last = Vec::new();
+        for (offset, bytes) in &changes.writes {
+            let split = deferred.saturating_sub(*offset).min(bytes.len() as u64);
+            let (early, rest) = bytes.split_at(split as usize);
+            if !early.is_empty() {
+                last.push((*offset, early));
+            }
+            file.seek(SeekFrom::Start(offset + split))?;
+            file.write_all(rest)?;
+        }
+        file.set_len(changes.size)?;
+        file.sync_all()?;
+        if !last.is_empty() {
+            for (offset, bytes) in last {
+                file.seek(SeekFrom::Start(offset))?;
+                file.write_all(bytes)?;
+            }
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Writes `entry` to both files: the data first, then the index, then
+    /// the length marker, so a crash part way leaves a collection that
+    /// opens with the records of the previous entry. Fails with
+    /// `InvalidData` when it does not directly follow the current position.
+    pub fn apply(&mut self, entry: &LogEntry) -> io::Result<()> {
+        if entry.sequence != self.position.sequence + 1
+            || entry.since_len != self.position.length
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "entry {} from record {} does not follow entry {} at record {}",
+                    entry.sequence,
+                    entry.since_len,
+                    self.position.sequence,
+                    self.position.length
+                ),
+            ));
+        }
+        Self::write_changes(&self.string_file_path, &entry.data, 0)?;
+        Self::write_changes(
+            &self.structure_file_path,
+            &entry.structure,
+            LENGTH_MARKER_SIZE,
+        )?;
+        self.position = entry.position();
+        self.save_position()
+    }
+
+    /// Sends the current position on `transport`, then applies entries
+    /// until the source closes it. Returns the position reached.
+    pub fn follow(
+        &mut self,
+        transport: &mut dyn Transport,
+    ) -> io::Result<ReplicaPosition> {
+        transport.send(&ReplicationMessage::Resume(self.position))?;
+        while let Some(message) = transport.receive()? {
+            match message {
+                ReplicationMessage::Entry(entry) => self.apply(&entry)?,
+                ReplicationMessage::Resume(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "unexpected position from the source",
+                    ))
+                }
+            }
+        }
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        services::{
+            compression::BlockOptions,
+            dynamic_vector_manage_service::{
+                DynamicOptions,
+                IndexFormat,
+            },
+        },
+        test_support::TestDir,
+        VectorEngine,
+    };
+
+    type Record = (u64, String);
+
+    fn record(i: u64) -> Record {
+        (i, "y".repeat(i as usize % 11))
+    }
+
+    /// Source and replica file paths under `dir`.
+    fn fresh(dir: &TestDir) -> [String; 4] {
+        [
+            "Dynamic.bin",
+            "StringDynamic.bin",
+            "Replica.bin",
+            "StringReplica.bin",
+        ]
+        .map(|file| dir.path(file))
+    }
+
+    fn assert_replica(
+        source: &DynamicVectorManageService<Record>,
+        replica: &str,
+        string: &str,
+    ) {
+        let copy = <DynamicVectorManageService<Record> as VectorEngine<Record>>::new(
+            replica.to_string(),
+            string.to_string(),
+            1024,
+        );
+        assert_eq!(copy.len(), source.len());
+        assert_eq!(
+            copy.pullx(0, copy.len() as u64),
+            source.pullx(0, source.len() as u64)
+        );
+        for (source, replica) in [
+            (source.structure_file_path(), replica.to_string()),
+            (source.data_file_path().to_string(), string.to_string()),
+        ] {
+            let replica = fs::read(replica).unwrap();
+            assert_eq!(fs::read(source).unwrap()[..replica.len()], replica[..]);
+        }
+    }
+
+    #[test]
+    fn test_replicate_over_channel() {
+        let layouts = [
+            ("Pairs", DynamicOptions::default()),
+            (
+                "Blocks",
+                DynamicOptions {
+                    blocks: Some(BlockOptions {
+                        block_size: 256,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ),
+            (
+                "Compact",
+                DynamicOptions {
+                    index: IndexFormat::Compact {
+                        checkpoint_interval: 16,
+                    },
+                    ..Default::default()
+                },
+            ),
+        ];
+        // A small budget splits most entries into partial ones.
+        for ((name, options), max_entry_bytes) in layouts
+            .into_iter()
+            .flat_map(|layout| [(layout.clone(), DEFAULT_MAX_ENTRY_BYTES), (layout, 300)])
+        {
+            let dir = TestDir::new(&format!("channel_{}_{}", name, max_entry_bytes));
+            let [structure, data, replica, string] = fresh(&dir);
+            let service = Arc::new(
+                DynamicVectorManageService::<Record>::with_options(
+                    structure, data, 1024, options,
+                )
+                .unwrap(),
+            );
+            service.save_bulk((0..100).map(record).collect());
+            let first = ReplicationSource::new(service.clone())
+                .with_max_entry_bytes(max_entry_bytes)
+                .next_entry(&ReplicaPosition::default())
+                .unwrap()
+                .unwrap();
+            assert_eq!(first.is_partial(), max_entry_bytes == 300);
+
+            let (mut source_end, mut follower_end) = ChannelTransport::pair();
+            let stop = Arc::new(AtomicBool::new(false));
+            let source = {
+                let (service, stop) = (service.clone(), stop.clone());
+                std::thread::spawn(move || {
+                    ReplicationSource::new(service)
+                        .with_max_entry_bytes(max_entry_bytes)
+                        .serve(&mut source_end, Duration::from_millis(1), &stop)
+                })
+            };
+            let follower = {
+                let (replica, string) = (replica.clone(), string.clone());
+                std::thread::spawn(move || {
+                    Follower::open(&replica, &string)
+                        .unwrap()
+                        .follow(&mut follower_end)
+                })
+            };
+            for i in 100..600 {
+                service.save(record(i));
+            }
+            stop.store(true, Ordering::Release);
+            let shipped = source.join().unwrap().unwrap();
+            assert_eq!(shipped.length, 600);
+            assert_eq!(follower.join().unwrap().unwrap(), shipped);
+            assert_replica(&service, &replica, &string);
+        }
+    }
+
+    #[test]
+    fn test_follower_resumes_over_tcp() {
+        let dir = TestDir::new("tcp");
+        let [structure, data, replica, string] = fresh(&dir);
+        let service = Arc::new(
+            DynamicVectorManageService::<Record>::new(structure, data, 1024).unwrap(),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let listener = Arc::new(listener);
+
+        let mut reached = ReplicaPosition::default();
+        for session in 0..2u64 {
+            service.save_bulk((session * 200..session * 200 + 150).map(record).collect());
+            let stop = Arc::new(AtomicBool::new(false));
+            let source = {
+                let (service, stop, listener) =
+                    (service.clone(), stop.clone(), listener.clone());
+                std::thread::spawn(move || {
+                    let mut transport = TcpTransport::accept(&listener).unwrap();
+                    ReplicationSource::new(service).serve(
+                        &mut transport,
+                        Duration::from_millis(1),
+                        &stop,
+                    )
+                })
+            };
+            let mut follower = Follower::open(&replica, &string).unwrap();
+            assert_eq!(follower.position(), reached);
+            let mut transport = TcpTransport::connect(address).unwrap();
+            let following = std::thread::spawn(move || follower.follow(&mut transport));
+            service.save_bulk(
+                (session * 200 + 150..session * 200 + 200)
+                    .map(record)
+                    .collect(),
+            );
+            stop.store(true, Ordering::Release);
+            reached = source.join().unwrap().unwrap();
+            assert_eq!(reached.length, session * 200 + 200);
+            assert_eq!(following.join().unwrap().unwrap(), reached);
+            assert_replica(&service, &replica, &string);
+        }
+
+        let mut follower = Follower::open(&replica, &string).unwrap();
+        let entry = ReplicationSource::new(service.clone())
+            .next_entry(&ReplicaPosition {
+                sequence: reached.sequence + 1,
+                length: 300,
+                ..Default::default()
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            follower.apply(&entry).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            Follower::open(&service.structure_file_path(), service.data_file_path())
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::AlreadyExists
+        );
+    }
+
+    #[test]
+    fn test_tcp_refuses_oversized_frame() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sender = TcpTransport::connect(listener.local_addr().unwrap()).unwrap();
+        let mut receiver = TcpTransport::accept(&listener)
+            .unwrap()
+            .with_max_frame_size(64);
+        let entry = LogEntry {
+            sequence: 1,
+            since_len: 0,
+            length: 1,
+            structure: FileChanges {
+                size: 100,
+                writes: vec![(0, vec![7; 100])],
+            },
+            data: FileChanges {
+                size: 0,
+                writes: Vec::new(),
+            },
+            partial: TailProgress::default(),
+        };
+        sender.send(&ReplicationMessage::Entry(entry)).unwrap();
+        assert_eq!(
+            receiver.receive().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,554 @@
+use rayon::prelude::*;
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+    Serialize,
+};
+use std::{
+    collections::HashMap,
+    fs::{
+        File,
+        OpenOptions,
+    },
+    io::{
+        self,
+        Write,
+    },
+    marker::PhantomData,
+    path::Path,
+    sync::{
+        Mutex,
+        RwLock,
+    },
+};
+
+use crate::services::{
+    dynamic_vector_manage_service::DynamicVectorManageService,
+    encryption::{
+        KeyProvider,
+        PayloadCipher,
+        KEY_SIZE,
+    },
+    file_swap::sync_parent,
+};
+
+/// A record read back from a [`ShreddingVectorManageService`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubjectRecord<T> {
+    Present(T),
+    /// The subject's key was destroyed by `forget`; the record can no
+    /// longer be decrypted or traced back to its subject.
+    Erased,
+}
+
+impl<T> SubjectRecord<T> {
+    pub fn present(self) -> Option<T> {
+        match self {
+            SubjectRecord::Present(record) => Some(record),
+            SubjectRecord::Erased => None,
+        }
+    }
+}
+
+/// Random id of a subject key, stored with each record in place of the
+/// subject id.
+type SubjectHandle = [u8; HANDLE_SIZE];
+
+const HANDLE_SIZE: usize = 16;
+
+/// Stored form of every record: the handle of the subject key it was sealed
+/// with and the sealed bincode bytes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SubjectEnvelope {
+    handle: SubjectHandle,
+    payload: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SubjectKey {
+    handle: SubjectHandle,
+    key: [u8; KEY_SIZE],
+}
+
+impl SubjectKey {
+    fn generate() -> Self {
+        let mut handle = SubjectHandle::default();
+        handle.copy_from_slice(&PayloadCipher::generate_key()[..HANDLE_SIZE]);
+        Self {
+            handle,
+            key: PayloadCipher::generate_key(),
+        }
+    }
+}
+
+/// Keys of the subjects that have not been forgotten. A subject that
+/// writes again after `forget` gets a new handle, so its old records stay
+/// erased.
+#[derive(Debug, Default)]
+struct SubjectKeys {
+    keys: HashMap<String, SubjectKey>,
+    /// Key of each handle in `keys`, for reads.
+    by_handle: HashMap<SubjectHandle, [u8; KEY_SIZE]>,
+}
+
+impl SubjectKeys {
+    fn insert(&mut self, subject: String, key: SubjectKey) {
+        self.by_handle.insert(key.handle, key.key);
+        self.keys.insert(subject, key);
+    }
+
+    fn remove(&mut self, subject: &str) -> Option<SubjectKey> {
+        let key = self.keys.remove(subject)?;
+        self.by_handle.remove(&key.handle);
+        Some(key)
+    }
+}
+
+/// First frame of the key store: the id of the provider key the other
+/// frames are sealed with.
+#[derive(Serialize, Deserialize, Debug)]
+struct KeyStoreHeader {
+    key_id: String,
+}
+
+/// Appends `bytes` to `out` as a `[length u64][bytes]` frame.
+fn push_frame(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// Splits the key store into its frames. A last frame cut short by a crash
+/// during an append is left out; also returns the size of the complete
+/// frames.
+fn read_frames(bytes: &[u8]) -> (Vec<&[u8]>, usize) {
+    let mut frames = Vec::new();
+    let mut offset = 0;
+    while let Some(length) = bytes.get(offset..offset + 8) {
+        let length = u64::from_le_bytes(length.try_into().unwrap());
+        let start = offset + 8;
+        let Some(frame) = usize::try_from(length)
+            .ok()
+            .and_then(|length| bytes.get(start..start.checked_add(length)?))
+        else {
+            break;
+        };
+        frames.push(frame);
+        offset = start + frame.len();
+    }
+    (frames, offset)
+}
+
+/// Reads the key store at `path`, dropping a torn last frame from the file.
+fn read_key_store(
+    path: &str,
+    keys: &dyn KeyProvider,
+) -> io::Result<(String, SubjectKeys)> {
+    let bytes = std::fs::read(path)?;
+    let (frames, complete) = read_frames(&bytes);
+    let Some((header, entries)) = frames.split_first() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Key store has no header",
+        ));
+    };
+    let header: KeyStoreHeader = bincode::deserialize(header)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let master = PayloadCipher::new(&header.key_id, keys)?;
+    let mut subject_keys = SubjectKeys::default();
+    for entry in entries {
+        let entry: Vec<(String, SubjectKey)> = bincode::deserialize(&master.open(entry)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        for (subject, key) in entry {
+            subject_keys.insert(subject, key);
+        }
+    }
+    if complete < bytes.len() {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(complete as u64)?;
+        file.sync_all()?;
+    }
+    Ok((header.key_id, subject_keys))
+}
+
+/// Writes a key store holding only `subject_keys` and returns it opened for
+/// appends. The new file is synced before it replaces the old one in one
+/// rename, and the directory after, so a crash leaves either the old or the
+/// new set of keys and a destroyed key is gone from the file rather than
+/// superseded.
+fn write_key_store(
+    path: &str,
+    key_id: &str,
+    master: &PayloadCipher,
+    subject_keys: &SubjectKeys,
+) -> io::Result<File> {
+    let header = KeyStoreHeader {
+        key_id: key_id.to_string(),
+    };
+    let mut bytes = Vec::new();
+    push_frame(
+        &mut bytes,
+        &bincode::serialize(&header).map_err(io::Error::other)?,
+    );
+    if !subject_keys.keys.is_empty() {
+        let entry: Vec<(&String, &SubjectKey)> = subject_keys.keys.iter().collect();
+        let plain = bincode::serialize(&entry).map_err(io::Error::other)?;
+        push_frame(&mut bytes, &master.seal(&plain));
+    }
+    let tmp = format!("{}.tmp", path);
+    let mut file = File::create(&tmp)?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    sync_parent(path)?;
+    OpenOptions::new().append(true).open(path)
+}
+
+type SubjectOf<T> = Box<dyn Fn(&T) -> String + Send + Sync>;
+
+/// Record store that encrypts each record under a key of its own subject,
+/// such as the user who wrote a message, so all records of one subject can
+/// be made unreadable by destroying a single key instead of rewriting the
+/// data.
+///
+/// Subject keys live in a separate key store file, itself encrypted with a
+/// key from the [`KeyProvider`]. Records carry a random handle of their
+/// subject's key rather than the subject id, so once the key is destroyed
+/// nothing links them to the subject. Keys of new subjects are appended to
+/// the key store as one frame per batch; `forget` rewrites only the key
+/// store, so copies of it taken before (backups, snapshots) still hold the
+/// key.
+pub struct ShreddingVectorManageService<T>
+where
+    T: Serialize + DeserializeOwned + Send,
+{
+    records: DynamicVectorManageService<SubjectEnvelope>,
+    key_store_path: String,
+    master: PayloadCipher,
+    master_key_id: String,
+    subject_keys: RwLock<SubjectKeys>,
+    key_log: Mutex<File>,
+    subject_of: SubjectOf<T>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> ShreddingVectorManageService<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Opens the store. `subject_of` maps each record to its subject id. A
+    /// new key store is sealed with the current key of `keys`; an existing
+    /// one with the key recorded in it.
+    pub fn new(
+        structure_file_path: String,
+        string_file_path: String,
+        key_store_path: String,
+        initial_size_if_not_exists: u64,
+        keys: &dyn KeyProvider,
+        subject_of: impl Fn(&T) -> String + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        let records = DynamicVectorManageService::new(
+            structure_file_path,
+            string_file_path,
+            initial_size_if_not_exists,
+        )?;
+        let (master_key_id, subject_keys, key_log) = if Path::new(&key_store_path)
+            .exists()
+        {
+            let (master_key_id, subject_keys) = read_key_store(&key_store_path, keys)?;
+            let key_log = OpenOptions::new().append(true).open(&key_store_path)?;
+            (master_key_id, subject_keys, key_log)
+        } else {
+            let master_key_id = keys.current_key_id()?;
+            let subject_keys = SubjectKeys::default();
+            let key_log = write_key_store(
+                &key_store_path,
+                &master_key_id,
+                &PayloadCipher::new(&master_key_id, keys)?,
+                &subject_keys,
+            )?;
+            (master_key_id, subject_keys, key_log)
+        };
+        Ok(Self {
+            records,
+            key_store_path,
+            master: PayloadCipher::new(&master_key_id, keys)?,
+            master_key_id,
+            subject_keys: RwLock::new(subject_keys),
+            key_log: Mutex::new(key_log),
+            subject_of: Box::new(subject_of),
+            _marker: PhantomData,
+        })
+    }
+
+    /// Appends the keys of new subjects to the key store as one frame and
+    /// syncs it, so they are durable before any record sealed with them.
+    /// A failed append is cut off again so later frames stay readable.
+    fn append_keys(&self, created: &[(String, SubjectKey)]) -> io::Result<()> {
+        let plain = bincode::serialize(created).map_err(io::Error::other)?;
+        let mut frame = Vec::new();
+        push_frame(&mut frame, &self.master.seal(&plain));
+        let mut key_log = self.key_log.lock().unwrap();
+        let length = key_log.metadata()?.len();
+        let result = key_log.write_all(&frame).and_then(|_| key_log.sync_data());
+        if result.is_err() {
+            let _ = key_log.set_len(length);
+        }
+        result
+    }
+
+    /// Keys of `subjects`, creating and persisting the missing ones.
+    fn keys_for<'a>(
+        &self,
+        subjects: impl IntoIterator<Item = &'a String>,
+    ) -> HashMap<String, SubjectKey> {
+        let mut found = HashMap::new();
+        let mut missing = Vec::new();
+        {
+            let subject_keys = self.subject_keys.read().unwrap();
+            for subject in subjects {
+                match subject_keys.keys.get(subject) {
+                    Some(key) => {
+                        found.insert(subject.clone(), key.clone());
+                    }
+                    None => missing.push(subject.clone()),
+                }
+            }
+        }
+        if !missing.is_empty() {
+            let mut subject_keys = self.subject_keys.write().unwrap();
+            let mut created = Vec::new();
+            for subject in missing {
+                match subject_keys.keys.get(&subject) {
+                    Some(key) => {
+                        found.insert(subject, key.clone());
+                    }
+                    None if found.contains_key(&subject) => {}
+                    None => {
+                        let key = SubjectKey::generate();
+                        found.insert(subject.clone(), key.clone());
+                        created.push((subject, key));
+                    }
+                }
+            }
+            if !created.is_empty() {
+                self.append_keys(&created)
+                    .expect("Unable to persist subject keys");
+                for (subject, key) in created {
+                    subject_keys.insert(subject, key);
+                }
+            }
+        }
+        found
+    }
+
+    fn seal(&self, obj: &T, key: &SubjectKey) -> SubjectEnvelope {
+        let bytes = bincode::serialize(obj).expect("Serialization failed");
+        SubjectEnvelope {
+            handle: key.handle,
+            payload: PayloadCipher::from_key(&key.key).seal(&bytes),
+        }
+    }
+
+    pub fn get_length(&self) -> u64 {
+        self.records.get_length()
+    }
+
+    pub fn save(&self, obj: T) {
+        let subject = (self.subject_of)(&obj);
+        let key = self.keys_for([&subject]).remove(&subject).unwrap();
+        self.records.save(self.seal(&obj, &key));
+    }
+
+    pub fn save_bulk(&self, objs: Vec<T>) {
+        if objs.is_empty() {
+            return;
+        }
+        let subjects: Vec<String> =
+            objs.iter().map(|obj| (self.subject_of)(obj)).collect();
+        let keys = self.keys_for(&subjects);
+        let envelopes = objs
+            .par_iter()
+            .zip(&subjects)
+            .map(|(obj, subject)| self.seal(obj, &keys[subject]))
+            .collect();
+        self.records.save_bulk(envelopes);
+    }
+
+    fn open(&self, envelope: SubjectEnvelope) -> SubjectRecord<T> {
+        let key = self
+            .subject_keys
+            .read()
+            .unwrap()
+            .by_handle
+            .get(&envelope.handle)
+            .copied();
+        match key {
+            Some(key) => {
+                let bytes = PayloadCipher::from_key(&key)
+                    .open(&envelope.payload)
+                    .expect("Decryption failed");
+                SubjectRecord::Present(
+                    bincode::deserialize(&bytes).expect("Deserialization failed"),
+                )
+            }
+            None => SubjectRecord::Erased,
+        }
+    }
+
+    pub fn load(&self, index: u64) -> SubjectRecord<T> {
+        self.open(self.records.load(index))
+    }
+
+    pub fn load_bulk(&self, index: u64, count: u64) -> Vec<SubjectRecord<T>> {
+        if count == 0 {
+            return Vec::new();
+        }
+        self.records
+            .load_bulk(index, count)
+            .into_par_iter()
+            .map(|envelope| self.open(envelope))
+            .collect()
+    }
+
+    /// Destroys the key of `subject`, after which its records read back as
+    /// [`SubjectRecord::Erased`]. Returns whether the subject had a key.
+    pub fn forget(&self, subject: &str) -> io::Result<bool> {
+        let mut subject_keys = self.subject_keys.write().unwrap();
+        if subject_keys.remove(subject).is_none() {
+            return Ok(false);
+        }
+        *self.key_log.lock().unwrap() = write_key_store(
+            &self.key_store_path,
+            &self.master_key_id,
+            &self.master,
+            &subject_keys,
+        )?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        services::encryption::LocalKeyProvider,
+        test_support::TestDir,
+    };
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct ChatMessage {
+        sender: String,
+        content: String,
+    }
+
+    fn message(sender: &str, i: usize) -> ChatMessage {
+        ChatMessage {
+            sender: sender.to_string(),
+            content: format!("message {} from {}", i, sender),
+        }
+    }
+
+    fn open(
+        paths: &[String; 3],
+        keys: &LocalKeyProvider,
+    ) -> ShreddingVectorManageService<ChatMessage> {
+        ShreddingVectorManageService::new(
+            paths[0].clone(),
+            paths[1].clone(),
+            paths[2].clone(),
+            1024,
+            keys,
+            |message: &ChatMessage| message.sender.clone(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_forget_subject() {
+        let dir = TestDir::new("forget_subject");
+        let paths = ["structure.bin", "data.bin", "keys.bin"].map(|name| dir.path(name));
+        let keys =
+            LocalKeyProvider::new("master", [("master".to_string(), [9u8; 32])]).unwrap();
+        let senders = ["alice", "bob", "carol"];
+        let messages: Vec<ChatMessage> =
+            (0..30).map(|i| message(senders[i % 3], i)).collect();
+        {
+            let service = open(&paths, &keys);
+            service.save_bulk(messages[..20].to_vec());
+            for message in &messages[20..] {
+                service.save(message.clone());
+            }
+            assert!(service.forget("bob").unwrap());
+            assert!(!service.forget("bob").unwrap());
+            assert!(!service.forget("dave").unwrap());
+            assert_eq!(service.load(1), SubjectRecord::Erased);
+            // bob writes again under a new key; older records stay erased.
+            service.save(message("bob", 30));
+        }
+
+        let service = open(&paths, &keys);
+        assert_eq!(service.get_length(), 31);
+        let loaded = service.load_bulk(0, 31);
+        for (i, record) in loaded.iter().enumerate().take(30) {
+            match record {
+                SubjectRecord::Present(record) => {
+                    assert_ne!(i % 3, 1);
+                    assert_eq!(record, &messages[i]);
+                }
+                SubjectRecord::Erased => assert_eq!(i % 3, 1),
+            }
+        }
+        assert_eq!(loaded[30].clone().present(), Some(message("bob", 30)));
+
+        let other =
+            LocalKeyProvider::new("other", [("other".to_string(), [1u8; 32])]).unwrap();
+        assert!(ShreddingVectorManageService::<ChatMessage>::new(
+            paths[0].clone(),
+            paths[1].clone(),
+            paths[2].clone(),
+            1024,
+            &other,
+            |message: &ChatMessage| message.sender.clone(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_key_log_drops_torn_append() {
+        let dir = TestDir::new("key_log_torn");
+        let paths = ["structure.bin", "data.bin", "keys.bin"].map(|name| dir.path(name));
+        let keys =
+            LocalKeyProvider::new("master", [("master".to_string(), [9u8; 32])]).unwrap();
+        {
+            let service = open(&paths, &keys);
+            service.save_bulk(vec![message("alice", 0), message("bob", 1)]);
+            service.save(message("carol", 2));
+        }
+        let complete = std::fs::metadata(&paths[2]).unwrap().len();
+        // A crash in the middle of appending the next frame.
+        let mut file = OpenOptions::new().append(true).open(&paths[2]).unwrap();
+        file.write_all(&100u64.to_le_bytes()).unwrap();
+        file.write_all(&[7u8; 30]).unwrap();
+        drop(file);
+
+        {
+            let service = open(&paths, &keys);
+            assert_eq!(std::fs::metadata(&paths[2]).unwrap().len(), complete);
+            for i in 0..3 {
+                assert!(matches!(service.load(i), SubjectRecord::Present(_)));
+            }
+            service.save(message("dave", 3));
+            assert!(service.forget("alice").unwrap());
+            // Rewritten as the header and a single frame of remaining keys.
+            let bytes = std::fs::read(&paths[2]).unwrap();
+            let (frames, complete) = read_frames(&bytes);
+            assert_eq!((frames.len(), complete), (2, bytes.len()));
+        }
+
+        let service = open(&paths, &keys);
+        assert_eq!(service.load(0), SubjectRecord::Erased);
+        for i in 1..4 {
+            assert!(matches!(service.load(i), SubjectRecord::Present(_)));
+        }
+    }
+}
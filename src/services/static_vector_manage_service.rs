@@ -1,3 +1,4 @@
+use dynamic_vector::MaxEncodedSize;
 use rayon::prelude::*;
 use serde::{
     Deserialize,
@@ -15,31 +16,181 @@ use std::{
     },
 };
 
-use crate::services::file_access_service::FileAccessService;
+use crate::services::{
+    codec::CodecKind,
+    encryption::{
+        KeyProvider,
+        PayloadCipher,
+        ENCRYPTION_OVERHEAD,
+    },
+    file_access_service::FileAccessService,
+    file_swap::sync_parent,
+};
 
 const LENGTH_MARKER_SIZE: usize = size_of::<u64>();
+/// Records per read/write batch while rewriting a collection.
+const REWRITE_BATCH: u64 = 4096;
+
+/// Layout and codec of the slots, stored after the length marker so a file
+/// is never read back with a different record layout.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct StaticHeader {
+    pub(crate) slot_size: u64,
+    pub(crate) codec: CodecKind,
+    /// Key the slots are encrypted with, `None` when they are plaintext.
+    pub(crate) key_id: Option<String>,
+}
 
+/// Record store with one fixed-width slot per record. Slots are sized by
+/// `MaxEncodedSize`, the largest bincode encoding `T` can produce, widened
+/// to the bound of the codec in use. Encrypted slots hold the record padded
+/// to that bound and sealed with ChaCha20-Poly1305, so every slot grows by
+/// a nonce and a tag.
+///
+/// File layout: [length u64][magic u32][version u32][header size u64]
+/// [bincode header][fixed-size slots]. Files in the headerless `[length][slots]`
+/// layout of earlier versions are refused with `InvalidData`; convert them
+/// with [`convert_static_headerless`].
 pub struct StaticVectorManageService<T>
 where
     T: Serialize + for<'de> Deserialize<'de> + Send,
 {
     length: Arc<Mutex<u64>>,
     structure_file: Mutex<FileAccessService>,
-
+    slot_size: usize,
+    codec: CodecKind,
+    key_id: Option<String>,
+    cipher: Option<PayloadCipher>,
+    data_offset: u64,
     _marker: PhantomData<T>,
 }
 
 impl<T: Send + Sync> StaticVectorManageService<T>
 where
-    T: Serialize + for<'de> Deserialize<'de> + 'static + std::fmt::Debug,
+    T: Serialize + for<'de> Deserialize<'de> + MaxEncodedSize + 'static + std::fmt::Debug,
 {
+    /// Opens the store with bincode records, writing the slot layout of `T`
+    /// into a new file. An existing file keeps the codec it was created
+    /// with; reopening it for a type with a different slot size fails with
+    /// `InvalidData`.
     pub fn new(
+        structure_file_path: String,
+        string_file_path: String,
+        initial_size_if_not_exists: u64,
+    ) -> io::Result<Self> {
+        Self::with_codec(
+            structure_file_path,
+            string_file_path,
+            initial_size_if_not_exists,
+            CodecKind::Bincode,
+        )
+    }
+
+    /// Opens the store, recording `codec` in the header of a new file.
+    /// Codecs without a bounded encoding size, such as JSON, are rejected
+    /// with `InvalidInput`. Encrypted files fail with `PermissionDenied`;
+    /// open them with [`with_encryption`](Self::with_encryption).
+    pub fn with_codec(
+        structure_file_path: String,
+        _string_file_path: String,
+        initial_size_if_not_exists: u64,
+        codec: CodecKind,
+    ) -> io::Result<Self> {
+        Self::open(structure_file_path, initial_size_if_not_exists, codec, None)
+    }
+
+    /// Opens an encrypted store. A new file is encrypted with the current
+    /// key of `keys`; an existing one with the key recorded in its header,
+    /// which `keys` must still provide. Plaintext files fail with
+    /// `InvalidInput`; encrypt them with [`reencrypt`](Self::reencrypt).
+    pub fn with_encryption(
         structure_file_path: String,
         _string_file_path: String,
         initial_size_if_not_exists: u64,
+        codec: CodecKind,
+        keys: &dyn KeyProvider,
     ) -> io::Result<Self> {
+        let service = Self::open(
+            structure_file_path,
+            initial_size_if_not_exists,
+            codec,
+            Some(keys),
+        )?;
+        if service.key_id.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "collection is not encrypted",
+            ));
+        }
+        Ok(service)
+    }
+
+    fn open(
+        structure_file_path: String,
+        initial_size_if_not_exists: u64,
+        codec: CodecKind,
+        keys: Option<&dyn KeyProvider>,
+    ) -> io::Result<Self> {
+        let slot_size = |codec: CodecKind, encrypted: bool| {
+            let payload_size =
+                codec.max_encoded_size(T::MAX_ENCODED_SIZE).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{:?} records have no fixed slot size", codec),
+                    )
+                })?;
+            Ok::<_, io::Error>(if encrypted {
+                payload_size + ENCRYPTION_OVERHEAD
+            } else {
+                payload_size
+            })
+        };
+
         let structure_file_access =
             FileAccessService::new(structure_file_path, initial_size_if_not_exists);
+        let header = match structure_file_access.read_header::<StaticHeader>()? {
+            Some(existing) => {
+                let expected =
+                    slot_size(existing.codec, existing.key_id.is_some())? as u64;
+                if existing.slot_size != expected {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{} was created with {} byte slots but {} needs {}",
+                            structure_file_access.path(),
+                            existing.slot_size,
+                            std::any::type_name::<T>(),
+                            expected
+                        ),
+                    ));
+                }
+                existing
+            }
+            None => {
+                let header = StaticHeader {
+                    slot_size: slot_size(codec, keys.is_some())? as u64,
+                    codec,
+                    key_id: keys.map(|keys| keys.current_key_id()).transpose()?,
+                };
+                structure_file_access.write_header(&header)?;
+                header
+            }
+        };
+        let cipher = match (&header.key_id, keys) {
+            (Some(key_id), Some(keys)) => Some(PayloadCipher::new(key_id, keys)?),
+            (Some(key_id), None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!(
+                        "{} is encrypted with key {}",
+                        structure_file_access.path(),
+                        key_id
+                    ),
+                ));
+            }
+            (None, _) => None,
+        };
+        let data_offset = FileAccessService::data_offset(&header)?;
 
         let length = {
             let buffer = structure_file_access.read_in_file(0, LENGTH_MARKER_SIZE);
@@ -53,11 +204,74 @@ where
         Ok(Self {
             length,
             structure_file: Mutex::new(structure_file_access),
-
+            slot_size: header.slot_size as usize,
+            codec: header.codec,
+            key_id: header.key_id,
+            cipher,
+            data_offset,
             _marker: PhantomData,
         })
     }
 
+    /// Codec recorded in the file header.
+    pub fn codec(&self) -> CodecKind {
+        self.codec
+    }
+
+    /// Id of the key the collection is encrypted with.
+    pub fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    /// Rewrites a collection so it is encrypted with the current key of
+    /// `keys`, decrypting it with the key in its header; plaintext
+    /// collections become encrypted. The collection must not be open
+    /// elsewhere. Returns the number of records written; on error the
+    /// original file is left in place.
+    pub fn reencrypt(
+        structure_file_path: &str,
+        keys: &dyn KeyProvider,
+    ) -> io::Result<u64> {
+        if !std::path::Path::new(structure_file_path).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} does not exist", structure_file_path),
+            ));
+        }
+        let source = Self::open(
+            structure_file_path.to_string(),
+            1024,
+            CodecKind::default(),
+            Some(keys),
+        )?;
+        let length = source.get_length();
+        let structure_tmp = format!("{}.reencrypting", structure_file_path);
+        if std::path::Path::new(&structure_tmp).exists() {
+            std::fs::remove_file(&structure_tmp)?;
+        }
+        let result = Self::open(structure_tmp.clone(), 1024, source.codec, Some(keys))
+            .map(|target| {
+                let mut index = 0;
+                while index < length {
+                    let count = REWRITE_BATCH.min(length - index);
+                    target.add_bulk(source.read_bulk(index, count));
+                    index += count;
+                }
+            });
+        if let Err(error) = result {
+            let _ = std::fs::remove_file(&structure_tmp);
+            return Err(error);
+        }
+        std::fs::File::open(&structure_tmp)?.sync_all()?;
+        std::fs::rename(&structure_tmp, structure_file_path)?;
+        sync_parent(structure_file_path)?;
+        Ok(length)
+    }
+
+    fn slot_offset(&self, index: u64) -> u64 {
+        self.data_offset + index * self.slot_size as u64
+    }
+
     pub fn get_length(&self) -> u64 {
         let structure_file_guard = self.structure_file.lock().unwrap();
         let buffer = structure_file_guard.read_in_file(0, LENGTH_MARKER_SIZE);
@@ -73,39 +287,70 @@ where
         file_guard.write_in_file(0, &buffer);
     }
 
-    fn serialize_object(obj: &T) -> Vec<u8> {
-        bincode::serialize(obj).expect("Serialization failed")
+    fn serialize_object(&self, obj: &T) -> Vec<u8> {
+        let mut data = self.codec.encode(obj).expect("Serialization failed");
+        match &self.cipher {
+            Some(cipher) => {
+                // Padding to the full payload keeps every sealed slot the
+                // same size.
+                let payload_size = self.slot_size - ENCRYPTION_OVERHEAD;
+                assert!(
+                    data.len() <= payload_size,
+                    "encoded record of {} bytes exceeds the {} byte slot",
+                    data.len(),
+                    payload_size
+                );
+                data.resize(payload_size, 0);
+                cipher.seal(&data)
+            }
+            None => data,
+        }
     }
 
-    fn deserialize_object(data: &[u8]) -> T {
-        bincode::deserialize(data).expect("Deserialization failed")
+    fn deserialize_object(&self, data: &[u8]) -> T {
+        match &self.cipher {
+            Some(cipher) => {
+                let data = cipher.open(data).expect("Decryption failed");
+                self.codec.decode(&data).expect("Deserialization failed")
+            }
+            None => self.codec.decode(data).expect("Deserialization failed"),
+        }
     }
 
     fn write_index(&self, index: u64, obj: T) {
-        let size_of_object = size_of::<T>();
-        let data = Self::serialize_object(&obj);
-
-        println!("bytes length of data to write once: {} ", &data.len());
+        let data = self.serialize_object(&obj);
+        assert!(
+            data.len() <= self.slot_size,
+            "encoded record of {} bytes exceeds the {} byte slot",
+            data.len(),
+            self.slot_size
+        );
 
-        let offset = (size_of_object * index as usize + LENGTH_MARKER_SIZE) as u64;
+        let offset = self.slot_offset(index);
 
         let file = self.structure_file.lock().unwrap();
         file.write_in_file(offset, &data);
     }
 
     fn bulk_write_index(&self, index: u64, objs: Vec<T>) {
-        let size_of_object = size_of::<T>();
+        let size_of_object = self.slot_size;
         let count = objs.len();
         let mut buffer: Vec<u8> = vec![0; size_of_object * count];
 
         let serialized_objs: Vec<Vec<u8>> = objs
             .par_iter()
-            .map(|obj| Self::serialize_object(obj))
+            .map(|obj| self.serialize_object(obj))
             .collect::<Vec<Vec<u8>>>();
 
         let mut current_position = 0;
         for serialized_obj in &serialized_objs {
             let serialized_size = serialized_obj.len();
+            assert!(
+                serialized_size <= size_of_object,
+                "encoded record of {} bytes exceeds the {} byte slot",
+                serialized_size,
+                size_of_object
+            );
             let white_space = size_of_object - serialized_size;
 
             buffer[current_position..current_position + serialized_size]
@@ -115,7 +360,7 @@ where
             current_position += serialized_size;
         }
 
-        let offset = (size_of_object * index as usize + LENGTH_MARKER_SIZE) as u64;
+        let offset = self.slot_offset(index);
 
         let file_guard = self.structure_file.lock().unwrap();
 
@@ -148,22 +393,22 @@ where
     }
 
     pub fn read(&self, index: u64) -> T {
-        let size_of_object = size_of::<T>();
-        let offset = (size_of_object * index as usize + LENGTH_MARKER_SIZE) as u64;
+        let size_of_object = self.slot_size;
+        let offset = self.slot_offset(index);
 
         let length = size_of_object;
 
         let file_guard = self.structure_file.lock().unwrap();
         let data: Vec<u8> = file_guard.read_in_file(offset, length);
 
-        let obj: T = bincode::deserialize(&data).expect("Deserialization failed");
+        let obj: T = self.deserialize_object(&data);
 
         obj
     }
 
     pub fn read_bulk(&self, index: u64, count: u64) -> Vec<T> {
-        let size_of_object = size_of::<T>();
-        let offset = (size_of_object * index as usize + LENGTH_MARKER_SIZE) as u64;
+        let size_of_object = self.slot_size;
+        let offset = self.slot_offset(index);
 
         let length = count as usize * size_of_object;
 
@@ -172,19 +417,143 @@ where
 
         let objs: Vec<T> = data
             .par_chunks(size_of_object)
-            .map(|data| Self::deserialize_object(data))
+            .map(|data| self.deserialize_object(data))
             .collect();
 
         objs
     }
 }
 
+/// Adds a header to a static file in the original headerless layout,
+/// `[length u64][slots]`, which opening refuses with `InvalidData`.
+/// `from_slot_size` is the slot width the file was written with, the
+/// `size_of::<T>()` of its record type, and `codec` the encoding of its
+/// records, bincode for files in that layout. The records are moved into
+/// `slot_size` byte slots; pass the slot size `T` needs now,
+/// `codec.max_encoded_size(T::MAX_ENCODED_SIZE)`, for
+/// `StaticVectorManageService<T>` to open the result. Narrower slots fail
+/// with `InvalidData` if they would cut off a non-zero byte. The collection
+/// must not be open elsewhere. Returns the number of records converted; on
+/// error the original file is left in place.
+pub fn convert_static_headerless(
+    structure_file_path: &str,
+    from_slot_size: u64,
+    slot_size: u64,
+    codec: CodecKind,
+) -> io::Result<u64> {
+    if !std::path::Path::new(structure_file_path).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not exist", structure_file_path),
+        ));
+    }
+    if from_slot_size == 0 || slot_size == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "slot sizes must be positive",
+        ));
+    }
+    if codec.max_encoded_size(0).is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} records have no fixed slot size", codec),
+        ));
+    }
+    let source = FileAccessService::new(structure_file_path.to_string(), 1024);
+    if source.has_header()? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} already has a header", structure_file_path),
+        ));
+    }
+    let file_size = std::fs::metadata(structure_file_path)?.len();
+    if file_size < LENGTH_MARKER_SIZE as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no length marker", structure_file_path),
+        ));
+    }
+    let length = u64::from_le_bytes(
+        source
+            .read_in_file(0, LENGTH_MARKER_SIZE)
+            .try_into()
+            .unwrap(),
+    );
+    if length > (file_size - LENGTH_MARKER_SIZE as u64) / from_slot_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} is too short for the {} records its length marker claims",
+                structure_file_path, length
+            ),
+        ));
+    }
+
+    let header = StaticHeader {
+        slot_size,
+        codec,
+        key_id: None,
+    };
+    let tmp = format!("{}.upgrading", structure_file_path);
+    if std::path::Path::new(&tmp).exists() {
+        std::fs::remove_file(&tmp)?;
+    }
+    let target = FileAccessService::new(tmp.clone(), 1024);
+    let result = target.write_header(&header).and_then(|()| {
+        let data_offset = FileAccessService::data_offset(&header)?;
+        let kept = from_slot_size.min(slot_size) as usize;
+        let mut index = 0;
+        while index < length {
+            let count = REWRITE_BATCH.min(length - index);
+            let old_slots = source.read_in_file(
+                LENGTH_MARKER_SIZE as u64 + index * from_slot_size,
+                (count * from_slot_size) as usize,
+            );
+            let mut slots = vec![0u8; (count * slot_size) as usize];
+            for (offset, (old_slot, slot)) in old_slots
+                .chunks_exact(from_slot_size as usize)
+                .zip(slots.chunks_exact_mut(slot_size as usize))
+                .enumerate()
+            {
+                if old_slot[kept..].iter().any(|byte| *byte != 0) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "record {} does not fit in {} byte slots",
+                            index + offset as u64,
+                            slot_size
+                        ),
+                    ));
+                }
+                slot[..kept].copy_from_slice(&old_slot[..kept]);
+            }
+            target.write_in_file(data_offset + index * slot_size, &slots);
+            index += count;
+        }
+        target.write_in_file(0, &length.to_le_bytes());
+        std::fs::File::open(&tmp)?.sync_all()
+    });
+    if let Err(error) = result {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(error);
+    }
+    std::fs::rename(&tmp, structure_file_path)?;
+    sync_parent(structure_file_path)?;
+    Ok(length)
+}
+
 #[cfg(test)]
 mod test {
+    use dynamic_vector::{
+        FixedString,
+        FixedVec,
+    };
+
     use super::*;
+    use crate::test_support::TestDir;
     const COUNT: usize = 1000000;
 
-    #[derive(Serialize, Deserialize, Default, Debug, Clone)]
+    #[derive(Serialize, Deserialize, Default, Debug, Clone, MaxEncodedSize)]
     pub struct StaticStruct {
         my_usize: usize,
         my_u64: u64,
@@ -238,4 +607,287 @@ mod test {
         my_service.add_bulk(objs);
         my_service.read_bulk(0, COUNT as u64);
     }
+
+    #[derive(
+        Serialize, Deserialize, Default, Debug, Clone, PartialEq, MaxEncodedSize,
+    )]
+    pub struct SensorReading {
+        sensor: u32,
+        value: Option<f64>,
+        tags: [u8; 4],
+    }
+
+    #[derive(
+        Serialize, Deserialize, Default, Debug, Clone, PartialEq, MaxEncodedSize,
+    )]
+    pub struct OtherLayout {
+        sensor: u64,
+    }
+
+    #[derive(
+        Serialize, Deserialize, Default, Debug, Clone, PartialEq, MaxEncodedSize,
+    )]
+    pub struct MessageStatus {
+        sender: FixedString<16>,
+        status_codes: FixedVec<u16, 4>,
+    }
+
+    #[test]
+    fn test_slots_sized_by_max_encoded_size() {
+        let dir = TestDir::new("slots_sized_by_max_encoded_size");
+        assert_eq!(SensorReading::MAX_ENCODED_SIZE, 4 + 9 + 4);
+        let path = dir.path("slot_size.bin");
+        let readings: Vec<SensorReading> = (0..100)
+            .map(|i| SensorReading {
+                sensor: i,
+                // `None` encodes shorter than `Some`, leaving a gap in the slot.
+                value: (i % 3 != 0).then_some(i as f64 / 10.0),
+                tags: [i as u8; 4],
+            })
+            .collect();
+        {
+            let service = StaticVectorManageService::<SensorReading>::new(
+                path.clone(),
+                String::new(),
+                1024,
+            )
+            .unwrap();
+            service.add_bulk(readings[..50].to_vec());
+            for reading in &readings[50..] {
+                service.add(reading.clone());
+            }
+        }
+        let service = StaticVectorManageService::<SensorReading>::new(
+            path.clone(),
+            String::new(),
+            1024,
+        )
+        .unwrap();
+        assert_eq!(service.get_length(), 100);
+        assert_eq!(service.read_bulk(0, 100), readings);
+
+        let mismatch =
+            StaticVectorManageService::<OtherLayout>::new(path, String::new(), 1024);
+        assert_eq!(mismatch.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_fixed_capacity_fields() {
+        let dir = TestDir::new("fixed_capacity_fields");
+        assert_eq!(MessageStatus::MAX_ENCODED_SIZE, 16 + 4 + 4 * 2);
+        let path = dir.path("fixed_capacity.bin");
+        let service =
+            StaticVectorManageService::<MessageStatus>::new(path, String::new(), 1024)
+                .unwrap();
+        let statuses: Vec<MessageStatus> = (0..10)
+            .map(|i| MessageStatus {
+                sender: FixedString::truncating(&format!(
+                    "sender-{}-with-a-long-suffix",
+                    i
+                )),
+                status_codes: FixedVec::truncating(&vec![200; i]),
+            })
+            .collect();
+        service.add_bulk(statuses.clone());
+        assert_eq!(service.read_bulk(0, 10), statuses);
+        assert_eq!(service.read(3).sender.as_str(), "sender-3-with-a-");
+        assert_eq!(service.read(9).status_codes.len(), 4);
+    }
+
+    #[test]
+    fn test_varint_codec_and_json_rejected() {
+        let dir = TestDir::new("varint_codec_and_json_rejected");
+        let path = dir.path("varint_codec.bin");
+        let readings: Vec<SensorReading> = (0..20)
+            .map(|i| SensorReading {
+                sensor: i * 1000,
+                value: (i % 2 == 0).then_some(i as f64),
+                tags: [i as u8; 4],
+            })
+            .collect();
+        {
+            let service = StaticVectorManageService::<SensorReading>::with_codec(
+                path.clone(),
+                String::new(),
+                1024,
+                CodecKind::Varint,
+            )
+            .unwrap();
+            service.add_bulk(readings[..10].to_vec());
+            for reading in &readings[10..] {
+                service.add(reading.clone());
+            }
+        }
+        let service =
+            StaticVectorManageService::<SensorReading>::new(path, String::new(), 1024)
+                .unwrap();
+        assert_eq!(service.codec(), CodecKind::Varint);
+        assert_eq!(service.read_bulk(0, 20), readings);
+        assert_eq!(service.read(13), readings[13]);
+
+        let json = StaticVectorManageService::<SensorReading>::with_codec(
+            dir.path("json_codec.bin"),
+            String::new(),
+            1024,
+            CodecKind::Json,
+        );
+        assert_eq!(json.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_encrypted_slots() {
+        let dir = TestDir::new("encrypted_slots");
+        use crate::services::encryption::LocalKeyProvider;
+
+        let path = dir.path("encrypted.bin");
+        let keys = LocalKeyProvider::new("k1", [("k1".to_string(), [7u8; 32])]).unwrap();
+        let readings: Vec<SensorReading> = (0..30)
+            .map(|i| SensorReading {
+                sensor: i,
+                value: (i % 2 == 0).then_some(i as f64),
+                tags: [0xab; 4],
+            })
+            .collect();
+        {
+            let service = StaticVectorManageService::<SensorReading>::new(
+                path.clone(),
+                String::new(),
+                1024,
+            )
+            .unwrap();
+            service.add_bulk(readings[..20].to_vec());
+            let encrypted = StaticVectorManageService::<SensorReading>::with_encryption(
+                path.clone(),
+                String::new(),
+                1024,
+                CodecKind::Bincode,
+                &keys,
+            );
+            assert_eq!(encrypted.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+        }
+        assert_eq!(
+            StaticVectorManageService::<SensorReading>::reencrypt(&path, &keys).unwrap(),
+            20
+        );
+        let raw = std::fs::read(&path).unwrap();
+        assert!(!raw.windows(4).any(|w| w == [0xab; 4]));
+
+        let service = StaticVectorManageService::<SensorReading>::with_encryption(
+            path.clone(),
+            String::new(),
+            1024,
+            CodecKind::Bincode,
+            &keys,
+        )
+        .unwrap();
+        for reading in &readings[20..] {
+            service.add(reading.clone());
+        }
+        assert_eq!(service.key_id(), Some("k1"));
+        assert_eq!(service.read_bulk(0, 30), readings);
+        assert_eq!(service.read(25), readings[25]);
+        let plain =
+            StaticVectorManageService::<SensorReading>::new(path, String::new(), 1024);
+        assert_eq!(plain.err().unwrap().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_headerless_and_unknown_files_refused() {
+        let dir = TestDir::new("headerless_and_unknown_files_refused");
+        let open = |path: &str| {
+            StaticVectorManageService::<SensorReading>::new(
+                path.to_string(),
+                String::new(),
+                1024,
+            )
+        };
+        let path = dir.path("headered.bin");
+        open(&path).unwrap().add(SensorReading::default());
+        let headered = std::fs::read(&path).unwrap();
+
+        // The original layout: [length][slots]. A first slot whose bytes
+        // would read as a huge header size must not reach a file read.
+        let mut headerless = 1u64.to_le_bytes().to_vec();
+        headerless.extend_from_slice(&u64::MAX.to_le_bytes());
+        headerless.resize(1024, 0);
+        let mut unknown_version = headered.clone();
+        unknown_version[12] = 2;
+        let mut oversized_header = headered.clone();
+        oversized_header[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        for (name, bytes) in [
+            ("headerless.bin", headerless),
+            ("version.bin", unknown_version),
+            ("oversized.bin", oversized_header),
+        ] {
+            let path = dir.path(name);
+            std::fs::write(&path, &bytes).unwrap();
+            assert_eq!(
+                open(&path).err().unwrap().kind(),
+                io::ErrorKind::InvalidData
+            );
+            assert_eq!(std::fs::read(&path).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_convert_static_headerless() {
+        let dir = TestDir::new("convert_static_headerless");
+        let path = dir.path("headerless.bin");
+        let readings: Vec<SensorReading> = (0..10)
+            .map(|i| SensorReading {
+                sensor: i,
+                value: (i % 2 == 0).then_some(i as f64 / 4.0),
+                tags: [i as u8; 4],
+            })
+            .collect();
+        // The original layout: size_of::<T>() slots holding zero-padded
+        // bincode records.
+        let from_slot_size = size_of::<SensorReading>();
+        let mut headerless = (readings.len() as u64).to_le_bytes().to_vec();
+        for reading in &readings {
+            let mut slot = bincode::serialize(reading).unwrap();
+            slot.resize(from_slot_size, 0);
+            headerless.extend_from_slice(&slot);
+        }
+        headerless.resize(1024, 0);
+        std::fs::write(&path, &headerless).unwrap();
+        let slot_size = CodecKind::Bincode
+            .max_encoded_size(SensorReading::MAX_ENCODED_SIZE)
+            .unwrap() as u64;
+
+        // Slots too narrow for the records leave the file as it was.
+        let narrow = convert_static_headerless(
+            &path,
+            from_slot_size as u64,
+            8,
+            CodecKind::Bincode,
+        );
+        assert_eq!(narrow.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(std::fs::read(&path).unwrap(), headerless);
+
+        assert_eq!(
+            convert_static_headerless(
+                &path,
+                from_slot_size as u64,
+                slot_size,
+                CodecKind::Bincode
+            )
+            .unwrap(),
+            10
+        );
+        let service = StaticVectorManageService::<SensorReading>::new(
+            path.clone(),
+            String::new(),
+            1024,
+        )
+        .unwrap();
+        assert_eq!(service.read_bulk(0, 10), readings);
+        let again = convert_static_headerless(
+            &path,
+            from_slot_size as u64,
+            slot_size,
+            CodecKind::Bincode,
+        );
+        assert_eq!(again.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
 }
@@ -1,5 +1,14 @@
 use std::{
     convert::TryInto,
+    fs::File,
+    io::{
+        self,
+        BufRead,
+        BufReader,
+        Read,
+        Seek,
+        SeekFrom,
+    },
     sync::{
         Arc,
         Mutex,
@@ -10,6 +19,138 @@ use crate::services::file_access_service::FileAccessService;
 
 const END_OFFSET_SIZE: usize = std::mem::size_of::<u64>();
 
+/// Starts every framed record, so a scan can find the next frame after a
+/// damaged one.
+const SYNC_MARKER: [u8; 4] = [0xd3, b'R', b'E', b'C'];
+/// Sync marker, payload length u32, CRC-32 of the payload u32.
+const FRAME_HEADER_SIZE: usize = 12;
+/// Bytes `recover_frames` reads at a time.
+const SCAN_CHUNK_SIZE: usize = 1 << 20;
+
+/// Wraps `payload` in a frame: [sync marker][length u32][crc32 u32][payload].
+///
+/// Panics if the payload is 4 GiB or larger, which the length field cannot
+/// hold.
+pub fn frame_record(payload: &[u8]) -> Vec<u8> {
+    let len = u32::try_from(payload.len())
+        .expect("Record payload does not fit in a frame (4 GiB or larger)");
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    frame.extend_from_slice(&SYNC_MARKER);
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Payload of a frame written by [`frame_record`]. Fails with `InvalidData`
+/// when the frame is truncated or its checksum does not match.
+pub fn unframe_record(frame: &[u8]) -> io::Result<&[u8]> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+    if frame.len() < FRAME_HEADER_SIZE || frame[..4] != SYNC_MARKER {
+        return Err(invalid("missing record frame"));
+    }
+    let len = u32::from_le_bytes(frame[4..8].try_into().unwrap()) as usize;
+    let crc = u32::from_le_bytes(frame[8..12].try_into().unwrap());
+    let payload = frame
+        .get(FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + len)
+        .ok_or_else(|| invalid("record frame is truncated"))?;
+    if crc32fast::hash(payload) != crc {
+        return Err(invalid("record checksum mismatch"));
+    }
+    Ok(payload)
+}
+
+/// Up to `len` bytes of `file` from `offset`, fewer at the end of the file.
+fn read_at(file: &mut File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    file.take(len).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// End of the intact frame at `start`, checking its payload checksum
+/// through a buffered reader rather than loading the payload whole.
+fn intact_frame_end(
+    file: &mut File,
+    data_start: u64,
+    data_size: u64,
+    start: u64,
+) -> io::Result<Option<u64>> {
+    let header = read_at(file, data_start + start, FRAME_HEADER_SIZE as u64)?;
+    if header.len() < FRAME_HEADER_SIZE {
+        return Ok(None);
+    }
+    let len = u32::from_le_bytes(header[4..8].try_into().unwrap()) as u64;
+    let crc = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    let end = start + FRAME_HEADER_SIZE as u64 + len;
+    if end > data_size {
+        return Ok(None);
+    }
+    let mut reader = BufReader::with_capacity(
+        SCAN_CHUNK_SIZE.min(len as usize).max(1),
+        (&mut *file).take(len),
+    );
+    let mut hasher = crc32fast::Hasher::new();
+    loop {
+        let bytes = reader.fill_buf()?;
+        if bytes.is_empty() {
+            break;
+        }
+        hasher.update(bytes);
+        let used = bytes.len();
+        reader.consume(used);
+    }
+    Ok((hasher.finalize() == crc).then_some(end))
+}
+
+/// (start, end) offsets, relative to `data_start`, of the intact frames in
+/// `file`. The file is scanned `chunk_size` bytes at a time; a sync marker
+/// cut by a chunk boundary is found in the next chunk.
+fn scan_frames(
+    file: &mut File,
+    data_start: u64,
+    chunk_size: usize,
+) -> io::Result<Vec<(u64, u64)>> {
+    assert!(chunk_size > FRAME_HEADER_SIZE, "Scan chunk too small");
+    let data_size = file.metadata()?.len().saturating_sub(data_start);
+    let mut frames = Vec::new();
+    // `chunk` holds the data from offset `base` on.
+    let mut chunk = Vec::new();
+    let mut base = 0;
+    let mut position = 0;
+    while position < data_size {
+        let chunk_end = base + chunk.len() as u64;
+        if position + FRAME_HEADER_SIZE as u64 > chunk_end && chunk_end < data_size {
+            chunk = read_at(file, data_start + position, chunk_size as u64)?;
+            base = position;
+        }
+        let from = (position - base) as usize;
+        let Some(found) = chunk[from..]
+            .windows(SYNC_MARKER.len())
+            .position(|window| window == SYNC_MARKER)
+        else {
+            let chunk_end = base + chunk.len() as u64;
+            if chunk_end >= data_size {
+                break;
+            }
+            // Keep the bytes that may begin a marker cut by the boundary.
+            position = position.max(chunk_end - (SYNC_MARKER.len() - 1) as u64);
+            chunk.clear();
+            base = position;
+            continue;
+        };
+        let start = position + found as u64;
+        match intact_frame_end(file, data_start, data_size, start)? {
+            Some(end) => {
+                frames.push((start, end));
+                position = end;
+            }
+            None => position = start + 1,
+        }
+    }
+    Ok(frames)
+}
+
 pub struct StringRepository {
     file_access: FileAccessService,
     file_end_offset: Arc<Mutex<u64>>,
@@ -85,6 +226,58 @@ impl StringRepository {
         (current_offset - bytes_vector.len() as u64, current_offset)
     }
 
+    pub fn path(&self) -> &str {
+        self.file_access.path()
+    }
+
+    /// Bytes of the file in use, from the start of the file.
+    pub fn used_size(&self) -> u64 {
+        END_OFFSET_SIZE as u64 + 1 + self.end_offset()
+    }
+
+    /// End of the written data, relative to the start of the records.
+    pub fn end_offset(&self) -> u64 {
+        *self.file_end_offset.lock().unwrap()
+    }
+
+    /// File offset of the record data at `offset`.
+    pub fn file_offset(&self, offset: u64) -> u64 {
+        END_OFFSET_SIZE as u64 + 1 + offset
+    }
+
+    /// Byte ranges that appends rewrite in place, with their current
+    /// contents: the end offset marker.
+    pub fn rewritable_regions(&self) -> Vec<(u64, Vec<u8>)> {
+        vec![(0, self.end_offset().to_le_bytes().to_vec())]
+    }
+
+    /// Drops the data past `end_offset` and cuts the file there.
+    pub fn truncate(&self, end_offset: u64) -> io::Result<()> {
+        let mut current = self.file_end_offset.lock().unwrap();
+        *current = end_offset.min(*current);
+        self.file_access.write_in_file(0, &current.to_le_bytes());
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(self.file_access.path())?
+            .set_len(self.file_offset(*current))
+    }
+
+    /// Scans the whole file for intact frames and returns their (start, end)
+    /// offsets, skipping damaged bytes up to the next sync marker. The end
+    /// offset marker is not trusted, so frames written after a crash that
+    /// lost it are found too; it is reset to the end of the last frame.
+    /// The file is read in chunks, never whole.
+    pub fn recover_frames(&self) -> Vec<(u64, u64)> {
+        let mut file = File::open(self.file_access.path()).expect("Unable to open file");
+        let frames = scan_frames(&mut file, self.file_offset(0), SCAN_CHUNK_SIZE)
+            .expect("Unable to read file");
+
+        let mut end_offset = self.file_end_offset.lock().unwrap();
+        *end_offset = frames.last().map_or(0, |frame| frame.1);
+        self.file_access.write_in_file(0, &end_offset.to_le_bytes());
+        frames
+    }
+
     pub fn load_string_content(&self, offset: u64, length: u64) -> Vec<u8> {
         let offset = offset + 1;
 
@@ -99,6 +292,7 @@ impl StringRepository {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_support::TestDir;
 
     #[test]
     fn test_write_dynamic_repository() {
@@ -118,4 +312,54 @@ mod test {
             String::from_utf8(string_bytes.clone()).expect("Invalid UTF-8 sequence");
         println!("result: {}", result);
     }
+
+    #[test]
+    fn test_recover_frames() {
+        let dir = TestDir::new("recover_frames");
+        let path = dir.path("test_recover_frames.bin");
+        let repository = StringRepository::new(path.clone(), 1024);
+        let records: Vec<Vec<u8>> = (0..20)
+            .map(|i| format!("record {}", i).repeat(i + 1).into_bytes())
+            .collect();
+        let locations: Vec<(u64, u64)> = records
+            .iter()
+            .map(|record| {
+                repository.write_string_content_and_get_offset(frame_record(record))
+            })
+            .collect();
+
+        // Damage record 7's payload and record 12's sync marker, and lose
+        // the end offset.
+        let mut file = std::fs::read(&path).unwrap();
+        let data_start = END_OFFSET_SIZE + 1;
+        file[data_start + locations[7].1 as usize - 1] ^= 0xff;
+        file[data_start + locations[12].0 as usize] = 0;
+        file[..END_OFFSET_SIZE].fill(0);
+        std::fs::write(&path, file).unwrap();
+
+        let repository = StringRepository::new(path, 1024);
+        let frames = repository.recover_frames();
+        let expected: Vec<(u64, u64)> = locations
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != 7 && *i != 12)
+            .map(|(_, location)| *location)
+            .collect();
+        assert_eq!(frames, expected);
+        // Chunk boundaries at every position within markers and headers.
+        let mut file = File::open(repository.path()).unwrap();
+        for chunk_size in FRAME_HEADER_SIZE + 1..FRAME_HEADER_SIZE + 40 {
+            assert_eq!(
+                scan_frames(&mut file, data_start as u64, chunk_size).unwrap(),
+                expected
+            );
+        }
+        let (start, end) = frames[10];
+        let frame = repository.load_string_content(start, end - start);
+        assert_eq!(unframe_record(&frame).unwrap(), records[11]);
+        assert_eq!(
+            repository.write_string_content_and_get_offset(vec![1]).0,
+            locations[19].1
+        );
+    }
 }
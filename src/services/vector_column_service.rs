@@ -0,0 +1,797 @@
+use rayon::prelude::*;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    io,
+    mem::size_of,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use crate::{
+    search::{
+        Metric,
+        Neighbor,
+        SearchFilter,
+        TopK,
+    },
+    services::file_access_service::FileAccessService,
+};
+
+const LENGTH_MARKER_SIZE: usize = size_of::<u64>();
+const HEADER_SIZE_MARKER: usize = size_of::<u64>();
+/// Number of slots read from disk at once during a scan.
+const SCAN_CHUNK: u64 = 8192;
+
+/// On-disk representation of each vector component.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum VectorEncoding {
+    F32,
+    F16,
+    /// One byte per component, linearly mapped from `min[i]..=max[i]`.
+    Int8 {
+        min: Vec<f32>,
+        max: Vec<f32>,
+    },
+    /// One bit per component (set when positive), searched by Hamming
+    /// distance.
+    Binary,
+}
+
+impl VectorEncoding {
+    /// Int8 encoding with per-dimension ranges taken from `samples`.
+    pub fn int8_from_samples(samples: &[Vec<f32>]) -> Self {
+        assert!(!samples.is_empty(), "cannot derive ranges without samples");
+        let dimension = samples[0].len();
+        let mut min = vec![f32::INFINITY; dimension];
+        let mut max = vec![f32::NEG_INFINITY; dimension];
+        for sample in samples {
+            for (i, x) in sample.iter().enumerate() {
+                min[i] = min[i].min(*x);
+                max[i] = max[i].max(*x);
+            }
+        }
+        VectorEncoding::Int8 { min, max }
+    }
+
+    pub fn slot_size(&self, dimension: usize) -> usize {
+        match self {
+            VectorEncoding::F32 => dimension * 4,
+            VectorEncoding::F16 => dimension * 2,
+            VectorEncoding::Int8 { .. } => dimension,
+            VectorEncoding::Binary => dimension.div_ceil(8),
+        }
+    }
+
+    fn encode(&self, vector: &[f32], out: &mut [u8]) {
+        match self {
+            VectorEncoding::F32 => {
+                for (x, bytes) in vector.iter().zip(out.chunks_exact_mut(4)) {
+                    bytes.copy_from_slice(&x.to_le_bytes());
+                }
+            }
+            VectorEncoding::F16 => {
+                for (x, bytes) in vector.iter().zip(out.chunks_exact_mut(2)) {
+                    bytes.copy_from_slice(&f32_to_f16(*x).to_le_bytes());
+                }
+            }
+            VectorEncoding::Int8 { min, max } => {
+                for (i, x) in vector.iter().enumerate() {
+                    let range = max[i] - min[i];
+                    out[i] = if range > 0.0 {
+                        ((x - min[i]) / range * 255.0).round().clamp(0.0, 255.0) as u8
+                    } else {
+                        0
+                    };
+                }
+            }
+            VectorEncoding::Binary => {
+                out.fill(0);
+                for (i, x) in vector.iter().enumerate() {
+                    if *x > 0.0 {
+                        out[i / 8] |= 1 << (i % 8);
+                    }
+                }
+            }
+        }
+    }
+
+    fn decode(&self, dimension: usize, bytes: &[u8]) -> Vec<f32> {
+        match self {
+            VectorEncoding::F32 => bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect(),
+            VectorEncoding::F16 => bytes
+                .chunks_exact(2)
+                .map(|b| f16_to_f32(u16::from_le_bytes(b.try_into().unwrap())))
+                .collect(),
+            VectorEncoding::Int8 { min, max } => bytes
+                .iter()
+                .enumerate()
+                .map(|(i, q)| min[i] + *q as f32 / 255.0 * (max[i] - min[i]))
+                .collect(),
+            VectorEncoding::Binary => (0..dimension)
+                .map(|i| {
+                    if bytes[i / 8] & (1 << (i % 8)) != 0 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct ColumnHeader {
+    dimension: usize,
+    encoding: VectorEncoding,
+}
+
+/// Fixed-width embedding column. Slot `i` holds the embedding of record `i`
+/// of the main collection, so the column can sit next to either engine and
+/// be addressed with the same record index.
+pub struct VectorColumnService {
+    length: Arc<Mutex<u64>>,
+    file: Mutex<FileAccessService>,
+    header: ColumnHeader,
+    slot_size: usize,
+    data_offset: u64,
+}
+
+impl VectorColumnService {
+    /// Opens the column at `path`, creating it with the given layout when it
+    /// does not exist yet. Reopening with a different dimension or encoding
+    /// fails with `InvalidData`.
+    pub fn new(
+        path: String,
+        dimension: usize,
+        encoding: VectorEncoding,
+        initial_size_if_not_exists: u64,
+    ) -> io::Result<Self> {
+        assert!(dimension > 0, "dimension must be positive");
+        if let VectorEncoding::Int8 { min, max } = &encoding {
+            assert!(
+                min.len() == dimension && max.len() == dimension,
+                "int8 ranges must cover every dimension"
+            );
+        }
+        let header = ColumnHeader {
+            dimension,
+            encoding,
+        };
+        let file_access = FileAccessService::new(path, initial_size_if_not_exists);
+        let existing = Self::read_header(&file_access)?;
+        match existing {
+            Some(existing) if existing != header => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "vector column was created as {:?} but opened as {:?}",
+                        existing, header
+                    ),
+                ));
+            }
+            Some(_) => {}
+            None => {
+                let bytes = bincode::serialize(&header).map_err(io::Error::other)?;
+                file_access.write_in_file(
+                    LENGTH_MARKER_SIZE as u64,
+                    &(bytes.len() as u64).to_le_bytes(),
+                );
+                file_access.write_in_file(
+                    (LENGTH_MARKER_SIZE + HEADER_SIZE_MARKER) as u64,
+                    &bytes,
+                );
+            }
+        }
+        Ok(Self::with_header(file_access, header))
+    }
+
+    /// Opens an existing column, taking dimension and encoding from its
+    /// header.
+    pub fn open(path: String) -> io::Result<Self> {
+        if !std::path::Path::new(&path).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("vector column {} does not exist", path),
+            ));
+        }
+        let file_access = FileAccessService::new(path, 0);
+        let header = Self::read_header(&file_access)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "vector column has no header")
+        })?;
+        Ok(Self::with_header(file_access, header))
+    }
+
+    fn with_header(file_access: FileAccessService, header: ColumnHeader) -> Self {
+        let length =
+            u64::from_le_bytes(file_access.read_in_file(0, 8).try_into().unwrap());
+        let header_size = bincode::serialized_size(&header).unwrap();
+        Self {
+            length: Arc::new(Mutex::new(length)),
+            file: Mutex::new(file_access),
+            slot_size: header.encoding.slot_size(header.dimension),
+            data_offset: (LENGTH_MARKER_SIZE + HEADER_SIZE_MARKER) as u64 + header_size,
+            header,
+        }
+    }
+
+    fn read_header(file_access: &FileAccessService) -> io::Result<Option<ColumnHeader>> {
+        let prefix_size = LENGTH_MARKER_SIZE + HEADER_SIZE_MARKER;
+        if std::fs::metadata(file_access.path())?.len() < prefix_size as u64 {
+            return Ok(None);
+        }
+        let prefix = file_access.read_in_file(0, prefix_size);
+        let header_size = u64::from_le_bytes(prefix[8..16].try_into().unwrap());
+        if header_size == 0 {
+            return Ok(None);
+        }
+        let bytes = file_access.read_in_file(prefix_size as u64, header_size as usize);
+        bincode::deserialize(&bytes)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.header.dimension
+    }
+
+    pub fn encoding(&self) -> &VectorEncoding {
+        &self.header.encoding
+    }
+
+    pub fn get_length(&self) -> u64 {
+        *self.length.lock().unwrap()
+    }
+
+    fn save_length(&self, length: u64) {
+        let file_guard = self.file.lock().unwrap();
+        file_guard.write_in_file(0, &length.to_le_bytes());
+    }
+
+    fn slot_offset(&self, index: u64) -> u64 {
+        self.data_offset + index * self.slot_size as u64
+    }
+
+    fn encode_all(&self, vectors: &[Vec<f32>]) -> Vec<u8> {
+        let mut buffer = vec![0u8; self.slot_size * vectors.len()];
+        buffer
+            .par_chunks_mut(self.slot_size)
+            .zip(vectors.par_iter())
+            .for_each(|(slot, vector)| {
+                assert_eq!(
+                    vector.len(),
+                    self.header.dimension,
+                    "vector dimension mismatch"
+                );
+                self.header.encoding.encode(vector, slot);
+            });
+        buffer
+    }
+
+    pub fn add(&self, vector: &[f32]) -> u64 {
+        self.add_bulk(&[vector.to_vec()])
+    }
+
+    /// Appends `vectors` and returns the index of the first one.
+    pub fn add_bulk(&self, vectors: &[Vec<f32>]) -> u64 {
+        let buffer = self.encode_all(vectors);
+        let mut length = self.length.lock().unwrap();
+        let index = *length;
+        self.file
+            .lock()
+            .unwrap()
+            .write_in_file(self.slot_offset(index), &buffer);
+        *length += vectors.len() as u64;
+        self.save_length(*length);
+        index
+    }
+
+    /// Writes the embedding of record `index`, growing the column when the
+    /// index is past its end. Skipped slots read back as zero vectors.
+    pub fn write(&self, index: u64, vector: &[f32]) {
+        self.write_bulk(index, &[vector.to_vec()]);
+    }
+
+    /// Writes `vectors` as records `first_index..first_index + vectors.len()`.
+    pub fn write_bulk(&self, first_index: u64, vectors: &[Vec<f32>]) {
+        let buffer = self.encode_all(vectors);
+        let mut length = self.length.lock().unwrap();
+        self.file
+            .lock()
+            .unwrap()
+            .write_in_file(self.slot_offset(first_index), &buffer);
+        let end = first_index + vectors.len() as u64;
+        if end > *length {
+            *length = end;
+            self.save_length(*length);
+        }
+    }
+
+    pub fn read(&self, index: u64) -> Vec<f32> {
+        self.read_bulk(index, 1).pop().unwrap()
+    }
+
+    /// Decoded (de-quantized) vectors `index..index + count`.
+    pub fn read_bulk(&self, index: u64, count: u64) -> Vec<Vec<f32>> {
+        self.read_raw(index, count)
+            .par_chunks(self.slot_size)
+            .map(|slot| self.header.encoding.decode(self.header.dimension, slot))
+            .collect()
+    }
+
+    fn read_raw(&self, index: u64, count: u64) -> Vec<u8> {
+        assert!(
+            index + count <= self.get_length(),
+            "index {} and count {} out of bounds for column length {}",
+            index,
+            count,
+            self.get_length()
+        );
+        let file_guard = self.file.lock().unwrap();
+        file_guard.read_in_file(self.slot_offset(index), count as usize * self.slot_size)
+    }
+
+    /// Exact `k` nearest neighbors by a full scan. Binary columns always
+    /// rank by Hamming distance and ignore `metric`.
+    pub fn knn(&self, query: &[f32], k: usize, metric: Metric) -> Vec<Neighbor> {
+        self.knn_filtered(query, k, metric, &SearchFilter::All)
+    }
+
+    /// Exact `k` nearest neighbors among the records accepted by `filter`.
+    /// Selective filters read only the matching slots; otherwise the column
+    /// is scanned and rejected slots are skipped, so the result always holds
+    /// `k` neighbors when at least `k` records match.
+    pub fn knn_filtered(
+        &self,
+        query: &[f32],
+        k: usize,
+        metric: Metric,
+        filter: &SearchFilter,
+    ) -> Vec<Neighbor> {
+        assert_eq!(
+            query.len(),
+            self.header.dimension,
+            "query dimension mismatch"
+        );
+        let length = self.get_length();
+        let mut encoded_query = vec![0u8; self.slot_size];
+        self.header.encoding.encode(query, &mut encoded_query);
+
+        if filter.prefers_pre_filter(length) {
+            return filter
+                .matching(length)
+                .into_par_iter()
+                .fold(
+                    || TopK::new(k),
+                    |mut top, index| {
+                        let slot = self.read_raw(index, 1);
+                        top.push(
+                            index,
+                            self.distance(query, &encoded_query, &slot, metric),
+                        );
+                        top
+                    },
+                )
+                .reduce(|| TopK::new(k), TopK::merge)
+                .into_sorted_vec();
+        }
+
+        let mut top = TopK::new(k);
+        let mut start = 0;
+        while start < length {
+            let count = SCAN_CHUNK.min(length - start);
+            let raw = self.read_raw(start, count);
+            let chunk_top = raw
+                .par_chunks(self.slot_size)
+                .enumerate()
+                .fold(
+                    || TopK::new(k),
+                    |mut top, (offset, slot)| {
+                        let index = start + offset as u64;
+                        if filter.matches(index) {
+                            top.push(
+                                index,
+                                self.distance(query, &encoded_query, slot, metric),
+                            );
+                        }
+                        top
+                    },
+                )
+                .reduce(|| TopK::new(k), TopK::merge);
+            top = top.merge(chunk_top);
+            start += count;
+        }
+        top.into_sorted_vec()
+    }
+
+    /// Exact `k` nearest neighbors of every query in `queries`, computed in
+    /// a single pass over the column. Each chunk is read and decoded once and
+    /// then compared against all queries in parallel.
+    pub fn knn_batch(
+        &self,
+        queries: &[Vec<f32>],
+        k: usize,
+        metric: Metric,
+    ) -> Vec<Vec<Neighbor>> {
+        let encoded_queries = self.encode_queries(queries);
+        let mut tops: Vec<TopK> = queries.iter().map(|_| TopK::new(k)).collect();
+        self.scan(|start, raw, decoded| {
+            tops.par_iter_mut()
+                .zip(queries.par_iter().zip(&encoded_queries))
+                .for_each(|(top, (query, encoded_query))| {
+                    for offset in 0..raw.len() / self.slot_size {
+                        top.push(
+                            start + offset as u64,
+                            self.chunk_distance(
+                                query,
+                                encoded_query,
+                                raw,
+                                decoded,
+                                offset,
+                                metric,
+                            ),
+                        );
+                    }
+                });
+        });
+        tops.into_iter().map(TopK::into_sorted_vec).collect()
+    }
+
+    /// Every record within `radius` of `query`, nearest first. The radius is
+    /// expressed in the distance of `metric` (squared for L2), or in
+    /// differing bits for binary columns.
+    pub fn range_search(
+        &self,
+        query: &[f32],
+        radius: f32,
+        metric: Metric,
+    ) -> Vec<Neighbor> {
+        let encoded_query = self.encode_queries(&[query.to_vec()]).pop().unwrap();
+        let mut neighbors = Vec::new();
+        self.scan(|start, raw, decoded| {
+            neighbors.par_extend(
+                (0..raw.len() / self.slot_size)
+                    .into_par_iter()
+                    .filter_map(|offset| {
+                        let distance = self.chunk_distance(
+                            query,
+                            &encoded_query,
+                            raw,
+                            decoded,
+                            offset,
+                            metric,
+                        );
+                        (distance <= radius).then_some(Neighbor {
+                            index: start + offset as u64,
+                            distance,
+                        })
+                    }),
+            );
+        });
+        neighbors.sort();
+        neighbors
+    }
+
+    fn encode_queries(&self, queries: &[Vec<f32>]) -> Vec<Vec<u8>> {
+        queries
+            .iter()
+            .map(|query| {
+                assert_eq!(
+                    query.len(),
+                    self.header.dimension,
+                    "query dimension mismatch"
+                );
+                let mut encoded_query = vec![0u8; self.slot_size];
+                self.header.encoding.encode(query, &mut encoded_query);
+                encoded_query
+            })
+            .collect()
+    }
+
+    /// Reads the column chunk by chunk, passing each chunk's first index, its
+    /// raw slots and, unless the column is binary, the decoded vectors.
+    fn scan<F>(&self, mut visit: F)
+    where
+        F: FnMut(u64, &[u8], &[Vec<f32>]),
+    {
+        let length = self.get_length();
+        let mut start = 0;
+        while start < length {
+            let count = SCAN_CHUNK.min(length - start);
+            let raw = self.read_raw(start, count);
+            let decoded: Vec<Vec<f32>> = match self.header.encoding {
+                VectorEncoding::Binary => Vec::new(),
+                _ => raw
+                    .par_chunks(self.slot_size)
+                    .map(|slot| self.header.encoding.decode(self.header.dimension, slot))
+                    .collect(),
+            };
+            visit(start, &raw, &decoded);
+            start += count;
+        }
+    }
+
+    fn chunk_distance(
+        &self,
+        query: &[f32],
+        encoded_query: &[u8],
+        raw: &[u8],
+        decoded: &[Vec<f32>],
+        offset: usize,
+        metric: Metric,
+    ) -> f32 {
+        match self.header.encoding {
+            VectorEncoding::Binary => hamming(
+                encoded_query,
+                &raw[offset * self.slot_size..(offset + 1) * self.slot_size],
+            ) as f32,
+            _ => metric.distance(query, &decoded[offset]),
+        }
+    }
+
+    fn distance(
+        &self,
+        query: &[f32],
+        encoded_query: &[u8],
+        slot: &[u8],
+        metric: Metric,
+    ) -> f32 {
+        match self.header.encoding {
+            VectorEncoding::Binary => hamming(encoded_query, slot) as f32,
+            _ => metric.distance(
+                query,
+                &self.header.encoding.decode(self.header.dimension, slot),
+            ),
+        }
+    }
+}
+
+fn hamming(a: &[u8], b: &[u8]) -> u32 {
+    a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+}
+
+/// IEEE 754 half precision, rounding to nearest even.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    let (full, shift, base) = if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        (mantissa | 0x80_0000, (14 - half_exponent) as u32, 0)
+    } else {
+        (mantissa, 13, (half_exponent as u32) << 10)
+    };
+    let half = base | (full >> shift);
+    let remainder = full & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    let rounded = if remainder > halfway || (remainder == halfway && half & 1 == 1) {
+        half + 1
+    } else {
+        half
+    };
+    sign | rounded as u16
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+    match exponent {
+        0 => {
+            let magnitude = mantissa as f32 * 2f32.powi(-24);
+            if sign != 0 {
+                -magnitude
+            } else {
+                magnitude
+            }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 127 - 15) << 23) | (mantissa << 13)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::TestDir;
+
+    fn vectors(count: usize) -> Vec<Vec<f32>> {
+        (0..count)
+            .map(|i| {
+                (0..8)
+                    .map(|j| ((i * 7 + j * 3) % 17) as f32 - 8.0)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_f16_round_trip() {
+        for x in [0.0f32, 1.0, -2.5, 0.333, 65504.0, 1e-6, -0.0] {
+            let y = f16_to_f32(f32_to_f16(x));
+            assert!((x - y).abs() <= x.abs() * 1e-3 + 1e-7, "{} -> {}", x, y);
+        }
+        assert!(f16_to_f32(f32_to_f16(1e6)).is_infinite());
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+    }
+
+    #[test]
+    fn test_encodings_round_trip() {
+        let dir = TestDir::new("encodings_round_trip");
+        let data = vectors(100);
+        for (name, encoding, tolerance) in [
+            ("test_column_f32", VectorEncoding::F32, 0.0),
+            ("test_column_f16", VectorEncoding::F16, 0.01),
+            (
+                "test_column_int8",
+                VectorEncoding::int8_from_samples(&data),
+                0.04,
+            ),
+        ] {
+            let column =
+                VectorColumnService::new(dir.path(name), 8, encoding, 1024).unwrap();
+            column.add_bulk(&data);
+            assert_eq!(column.get_length(), 100);
+            for (original, decoded) in data.iter().zip(column.read_bulk(0, 100)) {
+                for (x, y) in original.iter().zip(decoded) {
+                    assert!((x - y).abs() <= tolerance, "{} vs {} in {}", x, y, name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_knn_and_reopen() {
+        let dir = TestDir::new("knn_and_reopen");
+        let path = dir.path("knn.bin");
+        let data = vectors(500);
+        {
+            let column =
+                VectorColumnService::new(path.clone(), 8, VectorEncoding::F16, 1024)
+                    .unwrap();
+            column.add_bulk(&data);
+        }
+        let column = VectorColumnService::open(path.clone()).unwrap();
+        assert_eq!(column.encoding(), &VectorEncoding::F16);
+        let result = column.knn(&data[42], 3, Metric::L2);
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].distance, 0.0);
+        assert_eq!(data[result[0].index as usize], data[42]);
+
+        let mismatch = VectorColumnService::new(path, 16, VectorEncoding::F16, 1024);
+        assert_eq!(mismatch.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_filtered_knn() {
+        let dir = TestDir::new("filtered_knn");
+        let column = VectorColumnService::new(
+            dir.path("filtered.bin"),
+            8,
+            VectorEncoding::F32,
+            1024,
+        )
+        .unwrap();
+        let data = vectors(2000);
+        column.add_bulk(&data);
+
+        let odd = SearchFilter::predicate(|index| index % 2 == 1);
+        let result = column.knn_filtered(&data[42], 10, Metric::L2, &odd);
+        assert_eq!(result.len(), 10);
+        assert!(result.iter().all(|n| n.index % 2 == 1));
+        let expected: Vec<Neighbor> = column
+            .knn(&data[42], 2000, Metric::L2)
+            .into_iter()
+            .filter(|n| n.index % 2 == 1)
+            .take(10)
+            .collect();
+        assert_eq!(result, expected);
+
+        let candidates = SearchFilter::candidates(vec![7, 1999, 3]);
+        let result = column.knn_filtered(&data[42], 10, Metric::L2, &candidates);
+        let mut indices: Vec<u64> = result.iter().map(|n| n.index).collect();
+        indices.sort();
+        assert_eq!(indices, vec![3, 7, 1999]);
+
+        let result = column.knn_filtered(
+            &data[0],
+            5,
+            Metric::L2,
+            &SearchFilter::Range(1990..3000),
+        );
+        assert_eq!(result.len(), 5);
+        assert!(result.iter().all(|n| n.index >= 1990));
+    }
+
+    #[test]
+    fn test_knn_batch_and_range_search() {
+        let dir = TestDir::new("knn_batch_and_range_search");
+        let column =
+            VectorColumnService::new(dir.path("batch.bin"), 8, VectorEncoding::F32, 1024)
+                .unwrap();
+        let data = vectors(20000);
+        column.add_bulk(&data);
+
+        let queries = vec![data[3].clone(), data[12345].clone(), vec![0.5; 8]];
+        let batch = column.knn_batch(&queries, 5, Metric::L2);
+        assert_eq!(batch.len(), 3);
+        for (query, result) in queries.iter().zip(&batch) {
+            assert_eq!(result, &column.knn(query, 5, Metric::L2));
+        }
+
+        let within = column.range_search(&data[3], 30.0, Metric::L2);
+        let expected: Vec<Neighbor> = column
+            .knn(&data[3], 20000, Metric::L2)
+            .into_iter()
+            .filter(|n| n.distance <= 30.0)
+            .collect();
+        assert!(!within.is_empty());
+        assert_eq!(within, expected);
+    }
+
+    #[test]
+    fn test_binary_hamming_search() {
+        let dir = TestDir::new("binary_hamming_search");
+        let column = VectorColumnService::new(
+            dir.path("binary.bin"),
+            12,
+            VectorEncoding::Binary,
+            1024,
+        )
+        .unwrap();
+        assert_eq!(VectorEncoding::Binary.slot_size(12), 2);
+        let mut first = vec![-1.0; 12];
+        first[0] = 1.0;
+        let second = vec![1.0; 12];
+        column.add_bulk(&[first.clone(), second.clone()]);
+        column.write(5, &first);
+        assert_eq!(column.get_length(), 6);
+
+        let result = column.knn(&second, 2, Metric::L2);
+        assert_eq!(
+            result[0],
+            Neighbor {
+                index: 1,
+                distance: 0.0
+            }
+        );
+        assert_eq!(
+            result[1],
+            Neighbor {
+                index: 0,
+                distance: 11.0
+            }
+        );
+        let result = column.knn(&first, 6, Metric::L2);
+        assert_eq!(result[0].index, 0);
+        assert_eq!(result[1].index, 5);
+        assert_eq!(
+            result[5],
+            Neighbor {
+                index: 1,
+                distance: 11.0
+            }
+        );
+        assert_eq!(column.read(0), first);
+    }
+}
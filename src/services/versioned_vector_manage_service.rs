@@ -0,0 +1,516 @@
+use rayon::prelude::*;
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+    Serialize,
+};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io,
+    marker::PhantomData,
+};
+
+use crate::services::{
+    dynamic_vector_manage_service::{
+        DynamicOptions,
+        DynamicVectorManageService,
+    },
+    file_swap::swap_in,
+};
+
+/// Records per read/write batch while migrating a collection.
+const MIGRATION_BATCH: u64 = 4096;
+
+/// Record type with a schema version. Bump `VERSION` whenever the
+/// serialized layout changes and register an upgrade from the previous
+/// version in [`Migrations`].
+pub trait Versioned: Serialize + DeserializeOwned {
+    const VERSION: u32;
+}
+
+/// Stored form of every record: the version it was written with and its
+/// bincode bytes at that version.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Envelope {
+    version: u32,
+    bytes: Vec<u8>,
+}
+
+type Upgrade = Box<dyn Fn(&[u8]) -> io::Result<(u32, Vec<u8>)> + Send + Sync>;
+
+fn invalid_data(error: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+/// Upgrade steps between record versions, keyed by the version they read.
+/// Steps are chained, so registering 1 -> 2 and 2 -> 3 lets version 1
+/// records be read as version 3.
+#[derive(Default)]
+pub struct Migrations {
+    steps: HashMap<u32, Upgrade>,
+}
+
+impl Migrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers how to turn a record written as `Old` into `New`.
+    pub fn register<Old, New>(
+        mut self,
+        upgrade: impl Fn(Old) -> New + Send + Sync + 'static,
+    ) -> Self
+    where
+        Old: Versioned,
+        New: Versioned,
+    {
+        assert!(
+            Old::VERSION < New::VERSION,
+            "upgrade must go from an older version to a newer one, got {} -> {}",
+            Old::VERSION,
+            New::VERSION
+        );
+        assert!(
+            !self.steps.contains_key(&Old::VERSION),
+            "an upgrade from version {} is already registered",
+            Old::VERSION
+        );
+        self.steps.insert(
+            Old::VERSION,
+            Box::new(move |bytes| {
+                let old: Old = bincode::deserialize(bytes).map_err(invalid_data)?;
+                let new = bincode::serialize(&upgrade(old)).map_err(invalid_data)?;
+                Ok((New::VERSION, new))
+            }),
+        );
+        self
+    }
+
+    /// Decodes `bytes` written at `version` as `T`, applying upgrades until
+    /// the record reaches `T::VERSION`.
+    pub fn upgrade<T: Versioned>(&self, version: u32, bytes: &[u8]) -> io::Result<T> {
+        let mut version = version;
+        let mut bytes = Cow::Borrowed(bytes);
+        while version != T::VERSION {
+            if version > T::VERSION {
+                return Err(invalid_data(format!(
+                    "record version {} is newer than {} version {}",
+                    version,
+                    std::any::type_name::<T>(),
+                    T::VERSION
+                )));
+            }
+            let step = self.steps.get(&version).ok_or_else(|| {
+                invalid_data(format!(
+                    "no upgrade registered from version {} towards {}",
+                    version,
+                    T::VERSION
+                ))
+            })?;
+            let (next, upgraded) = step(&bytes)?;
+            version = next;
+            bytes = Cow::Owned(upgraded);
+        }
+        bincode::deserialize(&bytes).map_err(invalid_data)
+    }
+}
+
+/// Record store that tags every record with the version of `T` it was
+/// written with, so older records stay readable after the type changes.
+/// Records are upgraded through the registered [`Migrations`] on read;
+/// [`migrate`](Self::migrate) rewrites a whole collection at the current
+/// version.
+///
+/// The files use the dynamic layout, each record holding its version
+/// followed by its bincode bytes.
+pub struct VersionedVectorManageService<T>
+where
+    T: Versioned + Send,
+{
+    records: DynamicVectorManageService<Envelope>,
+    migrations: Migrations,
+    _marker: PhantomData<T>,
+}
+
+impl<T> VersionedVectorManageService<T>
+where
+    T: Versioned + Send + Sync,
+{
+    pub fn new(
+        structure_file_path: String,
+        string_file_path: String,
+        initial_size_if_not_exists: u64,
+        migrations: Migrations,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            records: DynamicVectorManageService::new(
+                structure_file_path,
+                string_file_path,
+                initial_size_if_not_exists,
+            )?,
+            migrations,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn get_length(&self) -> u64 {
+        self.records.get_length()
+    }
+
+    fn envelope(obj: &T) -> Envelope {
+        Envelope {
+            version: T::VERSION,
+            bytes: bincode::serialize(obj).expect("Serialization failed"),
+        }
+    }
+
+    pub fn save(&self, obj: T) {
+        self.records.save(Self::envelope(&obj));
+    }
+
+    pub fn save_bulk(&self, objs: Vec<T>) {
+        if objs.is_empty() {
+            return;
+        }
+        self.records
+            .save_bulk(objs.par_iter().map(Self::envelope).collect());
+    }
+
+    fn open(&self, envelope: &Envelope) -> T {
+        self.migrations
+            .upgrade(envelope.version, &envelope.bytes)
+            .expect("Unable to upgrade record")
+    }
+
+    pub fn load(&self, index: u64) -> T {
+        self.open(&self.records.load(index))
+    }
+
+    pub fn load_bulk(&self, index: u64, count: u64) -> Vec<T> {
+        if count == 0 {
+            return Vec::new();
+        }
+        self.records
+            .load_bulk(index, count)
+            .par_iter()
+            .map(|envelope| self.open(envelope))
+            .collect()
+    }
+
+    /// Version record `index` was written with.
+    pub fn record_version(&self, index: u64) -> u32 {
+        self.records.load(index).version
+    }
+
+    /// Rewrites a versioned collection so every record is stored at
+    /// `T::VERSION`. The collection must not be open elsewhere. Returns the
+    /// number of records written; on error the original files are left in
+    /// place.
+    pub fn migrate(
+        structure_file_path: &str,
+        string_file_path: &str,
+        migrations: &Migrations,
+    ) -> io::Result<u64> {
+        let source: DynamicVectorManageService<Envelope> =
+            DynamicVectorManageService::new(
+                structure_file_path.to_string(),
+                string_file_path.to_string(),
+                1024,
+            )?;
+        Self::rewrite(
+            structure_file_path,
+            string_file_path,
+            source.get_length(),
+            source.options(),
+            |index, count| {
+                source
+                    .load_bulk(index, count)
+                    .par_iter()
+                    .map(|envelope| migrations.upgrade(envelope.version, &envelope.bytes))
+                    .collect()
+            },
+        )
+    }
+
+    /// Converts a collection written by `DynamicVectorManageService<Old>`,
+    /// which has no version tags, into a versioned collection of `T`.
+    pub fn migrate_unversioned<Old>(
+        structure_file_path: &str,
+        string_file_path: &str,
+        migrations: &Migrations,
+    ) -> io::Result<u64>
+    where
+        Old: Versioned + std::fmt::Debug + Clone + Send + Sync + 'static,
+    {
+        let source: DynamicVectorManageService<Old> = DynamicVectorManageService::new(
+            structure_file_path.to_string(),
+            string_file_path.to_string(),
+            1024,
+        )?;
+        Self::rewrite(
+            structure_file_path,
+            string_file_path,
+            source.get_length(),
+            source.options(),
+            |index, count| {
+                source
+                    .load_bulk(index, count)
+                    .par_iter()
+                    .map(|old| {
+                        let bytes = bincode::serialize(old).map_err(invalid_data)?;
+                        migrations.upgrade(Old::VERSION, &bytes)
+                    })
+                    .collect()
+            },
+        )
+    }
+
+    /// Writes the upgraded records into sibling files, created with the
+    /// storage options of the source, and swaps them in once every record
+    /// has been converted. A swap interrupted between its two renames is
+    /// finished by the next open.
+    fn rewrite(
+        structure_file_path: &str,
+        string_file_path: &str,
+        length: u64,
+        options: &DynamicOptions,
+        read: impl Fn(u64, u64) -> io::Result<Vec<T>>,
+    ) -> io::Result<u64> {
+        let structure_tmp = format!("{}.migrating", structure_file_path);
+        let string_tmp = format!("{}.migrating", string_file_path);
+        for path in [&structure_tmp, &string_tmp] {
+            if std::path::Path::new(path).exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        let result = (|| {
+            let target = Self {
+                records: DynamicVectorManageService::with_options(
+                    structure_tmp.clone(),
+                    string_tmp.clone(),
+                    1024,
+                    options.clone(),
+                )?,
+                migrations: Migrations::new(),
+                _marker: PhantomData,
+            };
+            let mut index = 0;
+            while index < length {
+                let count = MIGRATION_BATCH.min(length - index);
+                target.save_bulk(read(index, count)?);
+                index += count;
+            }
+            Ok(())
+        })();
+        if let Err(error) = result {
+            let _ = std::fs::remove_file(&structure_tmp);
+            let _ = std::fs::remove_file(&string_tmp);
+            return Err(error);
+        }
+        swap_in(structure_file_path, string_file_path, ".migrating")?;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        test_support::TestDir,
+        vector_engine::VectorEngine,
+    };
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct ChatMessageV1 {
+        sender: String,
+        content: String,
+    }
+
+    impl Versioned for ChatMessageV1 {
+        const VERSION: u32 = 1;
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct ChatMessageV2 {
+        sender: String,
+        content: String,
+        room: String,
+    }
+
+    impl Versioned for ChatMessageV2 {
+        const VERSION: u32 = 2;
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct ChatMessage {
+        sender: String,
+        content: String,
+        room: String,
+        edited: bool,
+    }
+
+    impl Versioned for ChatMessage {
+        const VERSION: u32 = 3;
+    }
+
+    fn migrations() -> Migrations {
+        Migrations::new()
+            .register(|old: ChatMessageV1| ChatMessageV2 {
+                sender: old.sender,
+                content: old.content,
+                room: "general".to_string(),
+            })
+            .register(|old: ChatMessageV2| ChatMessage {
+                sender: old.sender,
+                content: old.content,
+                room: old.room,
+                edited: false,
+            })
+    }
+
+    fn v1(i: usize) -> ChatMessageV1 {
+        ChatMessageV1 {
+            sender: format!("user-{}", i % 3),
+            content: format!("message {}", i),
+        }
+    }
+
+    fn upgraded(i: usize) -> ChatMessage {
+        ChatMessage {
+            sender: format!("user-{}", i % 3),
+            content: format!("message {}", i),
+            room: "general".to_string(),
+            edited: false,
+        }
+    }
+
+    #[test]
+    fn test_upgrade_on_read_and_migrate() {
+        let dir = TestDir::new("upgrade_on_read_and_migrate");
+        let (structure, data) = dir.collection();
+        {
+            let old: VersionedVectorManageService<ChatMessageV1> =
+                VersionedVectorManageService::new(
+                    structure.clone(),
+                    data.clone(),
+                    1024,
+                    Migrations::new(),
+                )
+                .unwrap();
+            old.save_bulk((0..10).map(v1).collect());
+        }
+
+        {
+            let engine: VersionedVectorManageService<ChatMessage> =
+                VersionedVectorManageService::new(
+                    structure.clone(),
+                    data.clone(),
+                    1024,
+                    migrations(),
+                )
+                .unwrap();
+            assert_eq!(engine.load(4), upgraded(4));
+            engine.save(upgraded(10));
+            let expected: Vec<ChatMessage> = (0..11).map(upgraded).collect();
+            assert_eq!(engine.load_bulk(0, 11), expected);
+            assert_eq!(engine.record_version(0), 1);
+            assert_eq!(engine.record_version(10), 3);
+
+            // Without the upgrade steps the old records cannot be read.
+            let err = Migrations::new()
+                .upgrade::<ChatMessage>(1, &[])
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        let written = VersionedVectorManageService::<ChatMessage>::migrate(
+            &structure,
+            &data,
+            &migrations(),
+        )
+        .unwrap();
+        assert_eq!(written, 11);
+        let engine: VersionedVectorManageService<ChatMessage> =
+            VectorEngine::new(structure, data, 1024);
+        assert_eq!(engine.len(), 11);
+        assert!((0..11).all(|i| engine.record_version(i) == 3));
+        assert_eq!(engine.pull(7), upgraded(7));
+    }
+
+    #[test]
+    fn test_migrate_unversioned_collection() {
+        let dir = TestDir::new("migrate_unversioned_collection");
+        let (structure, data) = dir.collection();
+        {
+            let legacy: DynamicVectorManageService<ChatMessageV1> =
+                DynamicVectorManageService::new(structure.clone(), data.clone(), 1024)
+                    .unwrap();
+            legacy.save_bulk((0..5).map(v1).collect());
+        }
+
+        // A missing step fails and leaves the legacy files untouched.
+        let partial = Migrations::new().register(|old: ChatMessageV1| ChatMessageV2 {
+            sender: old.sender,
+            content: old.content,
+            room: String::new(),
+        });
+        assert!(
+            VersionedVectorManageService::<ChatMessage>::migrate_unversioned::<
+                ChatMessageV1,
+            >(&structure, &data, &partial)
+            .is_err()
+        );
+
+        VersionedVectorManageService::<ChatMessage>::migrate_unversioned::<ChatMessageV1>(
+            &structure,
+            &data,
+            &migrations(),
+        )
+        .unwrap();
+        let engine: VersionedVectorManageService<ChatMessage> =
+            VersionedVectorManageService::new(structure, data, 1024, Migrations::new())
+                .unwrap();
+        let expected: Vec<ChatMessage> = (0..5).map(upgraded).collect();
+        assert_eq!(engine.load_bulk(0, 5), expected);
+    }
+
+    #[test]
+    fn test_interrupted_migration_finished_on_open() {
+        let dir = TestDir::new("interrupted_migration_finished_on_open");
+        let (structure, data) = dir.collection();
+        let copy = |path: &str| format!("{}.migrating", path);
+        {
+            let old: VersionedVectorManageService<ChatMessageV1> =
+                VersionedVectorManageService::new(
+                    structure.clone(),
+                    data.clone(),
+                    1024,
+                    Migrations::new(),
+                )
+                .unwrap();
+            old.save_bulk((0..5).map(v1).collect());
+            let migrated: VersionedVectorManageService<ChatMessage> =
+                VersionedVectorManageService::new(
+                    copy(&structure),
+                    copy(&data),
+                    1024,
+                    Migrations::new(),
+                )
+                .unwrap();
+            migrated.save_bulk((0..5).map(upgraded).collect());
+        }
+
+        // Stopped after the data file was renamed over the original.
+        std::fs::write(format!("{}.swapping", structure), ".migrating").unwrap();
+        std::fs::rename(copy(&data), &data).unwrap();
+        let engine: VersionedVectorManageService<ChatMessage> =
+            VersionedVectorManageService::new(structure, data, 1024, Migrations::new())
+                .unwrap();
+        assert!((0..5).all(|i| engine.record_version(i) == 3));
+        assert_eq!(
+            engine.load_bulk(0, 5),
+            (0..5).map(upgraded).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{
+        AtomicU64,
+        Ordering,
+    },
+};
+
+static NEXT_DIR: AtomicU64 = AtomicU64::new(0);
+
+/// Scratch directory for one test, named after the process and a counter
+/// so tests running in parallel, or from another checkout, never share
+/// files. Removed with its contents on drop.
+pub(crate) struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "vector-db-core-{}-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("Unable to create test directory");
+        Self { path }
+    }
+
+    /// Path of `file` in the directory.
+    pub(crate) fn path(&self, file: &str) -> String {
+        self.path.join(file).to_string_lossy().to_string()
+    }
+
+    /// Structure and data file paths of a collection in the directory.
+    pub(crate) fn collection(&self) -> (String, String) {
+        (self.path("structure.bin"), self.path("data.bin"))
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -1,11 +1,40 @@
-use crate::services::{
-    dynamic_vector_manage_service::DynamicVectorManageService,
-    static_vector_manage_service::StaticVectorManageService,
+#[cfg(feature = "arrow")]
+use crate::arrow_export::{
+    self,
+    ArrowOptions,
+};
+use crate::{
+    interchange::{
+        self,
+        ImportReport,
+    },
+    services::{
+        dynamic_vector_manage_service::DynamicVectorManageService,
+        hybrid_vector_manage_service::HybridVectorManageService,
+        static_vector_manage_service::StaticVectorManageService,
+        versioned_vector_manage_service::{
+            Migrations,
+            Versioned,
+            VersionedVectorManageService,
+        },
+    },
+};
+use dynamic_vector::{
+    HybridRecord,
+    MaxEncodedSize,
 };
 use serde::{
     Deserialize,
     Serialize,
 };
+use std::{
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    ops::Range,
+};
 
 pub trait VectorEngine<T>
 where
@@ -51,6 +80,50 @@ where
             None
         }
     }
+
+    /// Writes records `range` to `writer` as JSON Lines, one record per
+    /// line, reading them in batches. Returns the number written.
+    fn export_jsonl<W: Write>(&self, writer: W, range: Range<u64>) -> io::Result<u64> {
+        interchange::export_jsonl(self, writer, range)
+    }
+
+    /// Appends the records of a JSON Lines stream through `pushx`, a batch
+    /// at a time. Blank lines are skipped; malformed lines are reported by
+    /// line number and skipped. Fails only when `reader` does.
+    fn import_jsonl<R: Read>(&self, reader: R) -> io::Result<ImportReport> {
+        interchange::import_jsonl(self, reader)
+    }
+
+    /// Writes records `range` to `writer` as CSV. Nested maps are flattened
+    /// into dotted columns such as `author.name`; arrays are JSON text.
+    /// The columns are those of all records in the first batch read, so an
+    /// optional struct absent from the first record keeps its columns.
+    /// Records with fields outside them, such as enums with data, fail with
+    /// `InvalidData` and are better exported as JSON Lines.
+    fn export_csv<W: Write>(&self, writer: W, range: Range<u64>) -> io::Result<u64> {
+        interchange::export_csv(self, writer, range)
+    }
+
+    /// Reverse of `export_csv`, with the same batching and error reporting
+    /// as `import_jsonl`.
+    fn import_csv<R: Read>(&self, reader: R) -> io::Result<ImportReport> {
+        interchange::import_csv(self, reader)
+    }
+
+    /// Writes records `range` to `writer` as an Arrow IPC file or stream,
+    /// `options.batch_size` records per record batch. Struct records get a
+    /// column per field, `Vec` fields become list columns and `SystemTime`
+    /// or chrono `DateTime` fields nanosecond UTC timestamps. Returns the
+    /// number of records written.
+    #[cfg(feature = "arrow")]
+    fn export_arrow<W: Write>(
+        &self,
+        writer: W,
+        range: Range<u64>,
+        options: &ArrowOptions,
+    ) -> io::Result<u64> {
+        arrow_export::export_arrow(self, writer, range, options)
+    }
 }
 
 impl<T> VectorEngine<T> for DynamicVectorManageService<T>
@@ -96,7 +169,8 @@ where
 
 impl<T> VectorEngine<T> for StaticVectorManageService<T>
 where
-    T: Serialize
+    T: MaxEncodedSize
+        + Serialize
         + for<'de> Deserialize<'de>
         + 'static
         + std::fmt::Debug
@@ -134,3 +208,90 @@ where
         self.get_length() as usize
     }
 }
+
+impl<T> VectorEngine<T> for HybridVectorManageService<T>
+where
+    T: HybridRecord
+        + Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+{
+    fn new(
+        structural_repository: String,
+        dynamic_repository: String,
+        initial_file_size: u64,
+    ) -> Self {
+        HybridVectorManageService::<T>::new(
+            structural_repository,
+            dynamic_repository,
+            initial_file_size,
+        )
+        .unwrap()
+    }
+
+    fn push(&self, obj: T) {
+        self.add(obj);
+    }
+    fn pushx(&self, objs: Vec<T>) {
+        self.add_bulk(objs);
+    }
+
+    fn pull(&self, index: u64) -> T {
+        self.read(index)
+    }
+    fn pullx(&self, index: u64, count: u64) -> Vec<T> {
+        self.read_bulk(index, count)
+    }
+    fn len(&self) -> usize {
+        self.get_length() as usize
+    }
+}
+
+/// Opens without upgrade steps, so only records at `T::VERSION` can be read;
+/// use `VersionedVectorManageService::new` to register migrations.
+impl<T> VectorEngine<T> for VersionedVectorManageService<T>
+where
+    T: Versioned
+        + Serialize
+        + for<'de> Deserialize<'de>
+        + 'static
+        + std::fmt::Debug
+        + Clone
+        + Send
+        + Sync,
+{
+    fn new(
+        structural_repository: String,
+        dynamic_repository: String,
+        initial_file_size: u64,
+    ) -> Self {
+        VersionedVectorManageService::<T>::new(
+            structural_repository,
+            dynamic_repository,
+            initial_file_size,
+            Migrations::new(),
+        )
+        .unwrap()
+    }
+
+    fn push(&self, obj: T) {
+        self.save(obj);
+    }
+    fn pushx(&self, objs: Vec<T>) {
+        self.save_bulk(objs);
+    }
+
+    fn pull(&self, index: u64) -> T {
+        self.load(index)
+    }
+    fn pullx(&self, index: u64, count: u64) -> Vec<T> {
+        self.load_bulk(index, count)
+    }
+    fn len(&self) -> usize {
+        self.get_length() as usize
+    }
+}
@@ -11,6 +11,7 @@ use std::{
     time::Instant,
 };
 use vector_db_core::{
+    MaxEncodedSize,
     ReadableCache,
     StaticVectorManageService,
     VectorEngine,
@@ -20,7 +21,7 @@ use vector_db_core::{
 const COUNT: usize = 1000;
 const TURNS: usize = 10;
 
-#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone, MaxEncodedSize)]
 pub struct StaticStruct {
     my_usize: usize,
     my_u64: u64,
@@ -2,5 +2,5 @@ mod cache_engine_performance_test;
 mod cache_performance_test;
 mod mix_data_test;
 mod sample_data_test;
+mod vector_macro_test;
 mod vector_performance_test;
-// mod vector_macro_test;
@@ -26,11 +26,14 @@ use std::{
     },
 };
 use uuid::Uuid;
-use vector_db_core::StaticVectorManageService;
+use vector_db_core::{
+    MaxEncodedSize,
+    StaticVectorManageService,
+};
 
 const COUNT: u64 = 1000;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, MaxEncodedSize)]
 struct TestStruct {
     // 时间类型
     time_duration: Duration,
@@ -1,5 +1,10 @@
 #[cfg(test)]
 mod test {
+    use chrono::{
+        DateTime,
+        NaiveDate,
+        Utc,
+    };
     use serde::{
         Deserialize,
         Serialize,
@@ -15,6 +20,68 @@ mod test {
         // my_boolean: bool,
     }
 
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, CheckDynamicSize)]
+    pub struct Point(f64, f64);
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, CheckDynamicSize)]
+    pub enum Shape {
+        Empty,
+        Circle { center: Point, radius: f32 },
+        Polygon(Vec<Point>),
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, CheckDynamicSize)]
+    pub struct Drawing {
+        origin: Point,
+        created: DateTime<Utc>,
+        due: Option<NaiveDate>,
+        shape: Shape,
+    }
+
+    #[test]
+    fn test_dynamic_macro_nested_and_enum_fields() {
+        const {
+            assert!(!<Point as DynamicSized>::DYNAMIC);
+            assert!(<Shape as DynamicSized>::DYNAMIC);
+        }
+        assert_eq!(<Point as DynamicSized>::ENCODED_SIZE, 16);
+
+        let circle = Shape::Circle {
+            center: Point(1.0, 2.0),
+            radius: 3.0,
+        };
+        assert!(!circle.is_dynamic_structure());
+        let polygon = Shape::Polygon(vec![Point(0.0, 0.0), Point(1.0, 1.0)]);
+        assert_eq!(polygon.get_dynamic_fields(), vec!["Polygon::0"]);
+        // u32 element count followed by two points
+        assert_eq!(polygon.get_dynamic_values()[0].len(), 4 + 32);
+        let points =
+            Vec::<Point>::from_bytes_vector(&polygon.get_dynamic_values()[0]).unwrap();
+        assert_eq!(points, vec![Point(0.0, 0.0), Point(1.0, 1.0)]);
+        assert!(Shape::Empty.get_dynamic_values().is_empty());
+
+        let drawing = Drawing {
+            origin: Point(0.5, -0.5),
+            created: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            due: NaiveDate::from_ymd_opt(2024, 5, 1),
+            shape: polygon,
+        };
+        assert_eq!(
+            drawing.get_dynamic_fields(),
+            vec!["created", "due", "shape"]
+        );
+        assert_eq!(Drawing::fixed_size(), 16);
+        let (fixed, dynamic) = drawing.split_fields();
+        assert_eq!(Drawing::join_fields(&fixed, dynamic), drawing);
+
+        let bytes = drawing.to_bytes_vector();
+        assert_eq!(Drawing::from_bytes_vector(&bytes).unwrap(), drawing);
+        assert_eq!(
+            Shape::from_bytes_vector(&circle.to_bytes_vector()).unwrap(),
+            circle
+        );
+    }
+
     #[test]
     fn test_dynamic_macro() {
         let i = 10;
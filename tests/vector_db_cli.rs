@@ -0,0 +1,231 @@
+use std::{
+    path::PathBuf,
+    process::{
+        Command,
+        Output,
+    },
+};
+
+use vector_db_core::{
+    DynamicOptions,
+    DynamicVectorManageService,
+    StaticVectorManageService,
+};
+
+type Reading = (u32, u64);
+type Message = (u32, String);
+
+/// Directory under the system temp dir, removed on drop.
+struct Scratch {
+    path: PathBuf,
+}
+
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "vector-db-cli-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    fn path(&self, file: &str) -> String {
+        self.path.join(file).to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+fn vector_db(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_vector-db"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Runs `vector-db` and returns its stdout, failing on a non-zero exit.
+fn vector_db_ok(args: &[&str]) -> String {
+    let output = vector_db(args);
+    assert!(
+        output.status.success(),
+        "vector-db {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn reading(i: u64) -> Reading {
+    (i as u32 % 7, i * 1000)
+}
+
+fn message(i: u64) -> Message {
+    (i as u32, format!("message {:04}", i))
+}
+
+fn save_messages(structure: &str, data: &str, options: DynamicOptions) {
+    let service = DynamicVectorManageService::<Message>::with_options(
+        structure.to_string(),
+        data.to_string(),
+        1024,
+        options,
+    )
+    .unwrap();
+    service.save_bulk((0..100).map(message).collect());
+}
+
+#[test]
+fn test_export_import_static() {
+    let dir = Scratch::new("export_import_static");
+    let (source, target, export) = (dir.path("a.bin"), dir.path("b.bin"), dir.path("e"));
+    StaticVectorManageService::<Reading>::new(source.clone(), String::new(), 1024)
+        .unwrap()
+        .add_bulk((0..100).map(reading).collect());
+    StaticVectorManageService::<Reading>::new(target.clone(), String::new(), 1024)
+        .unwrap();
+
+    vector_db_ok(&["export", &source, "--out", &export]);
+    let imported = vector_db_ok(&["import", &target, "--from", &export]);
+    assert!(imported.contains("\"imported\": 100"), "{}", imported);
+
+    let service =
+        StaticVectorManageService::<Reading>::new(target, String::new(), 1024).unwrap();
+    assert_eq!(service.get_length(), 100);
+    assert_eq!(
+        service.read_bulk(0, 100),
+        (0..100).map(reading).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_export_import_dynamic() {
+    let dir = Scratch::new("export_import_dynamic");
+    let (structure, data) = (dir.path("a.index"), dir.path("a.data"));
+    let (copy_structure, copy_data) = (dir.path("b.index"), dir.path("b.data"));
+    let export = dir.path("e");
+    save_messages(&structure, &data, DynamicOptions::default());
+
+    vector_db_ok(&["export", &structure, &data, "--out", &export]);
+    // The missing copy is created with the codec recorded in the export.
+    vector_db_ok(&["import", &copy_structure, &copy_data, "--from", &export]);
+
+    let service =
+        DynamicVectorManageService::<Message>::new(copy_structure, copy_data, 1024)
+            .unwrap();
+    assert_eq!(service.get_length(), 100);
+    assert_eq!(
+        service.load_bulk(0, 100),
+        (0..100).map(message).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_verify_fails_on_damaged_file() {
+    let dir = Scratch::new("verify_fails_on_damaged_file");
+    let (structure, data) = (dir.path("a.index"), dir.path("a.data"));
+    let options = DynamicOptions {
+        framed: true,
+        ..Default::default()
+    };
+    save_messages(&structure, &data, options);
+    vector_db_ok(&["verify", &structure, &data]);
+
+    let mut bytes = std::fs::read(&data).unwrap();
+    let at = bytes
+        .windows(12)
+        .position(|window| window == b"message 0042")
+        .unwrap();
+    bytes[at] ^= 0xff;
+    std::fs::write(&data, bytes).unwrap();
+
+    let output = vector_db(&["verify", &structure, &data]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\"index\": 42"), "{}", stdout);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 damaged records"));
+}
+
+#[test]
+fn test_range_is_clamped_to_length() {
+    let dir = Scratch::new("range_is_clamped_to_length");
+    let (structure, data) = (dir.path("a.index"), dir.path("a.data"));
+    save_messages(&structure, &data, DynamicOptions::default());
+
+    let lines = |range: &str| {
+        vector_db_ok(&["dump", &structure, &data, "--range", range])
+            .lines()
+            .count()
+    };
+    assert_eq!(lines("95..200"), 5);
+    assert_eq!(lines("150.."), 0);
+    assert_eq!(lines("..3"), 3);
+    assert_eq!(lines(".."), 100);
+
+    let export = dir.path("e");
+    let exported = vector_db_ok(&[
+        "export", &structure, &data, "--out", &export, "--range", "90..1000",
+    ]);
+    assert!(exported.contains("\"exported\": 10"), "{}", exported);
+}
+
+#[test]
+fn test_upgrade_static() {
+    let dir = Scratch::new("upgrade_static");
+    let path = dir.path("a.bin");
+    // The original layout: size_of::<T>() slots holding zero-padded bincode.
+    let mut headerless = 5u64.to_le_bytes().to_vec();
+    for i in 0..5 {
+        let mut slot = bincode::serialize(&reading(i)).unwrap();
+        slot.resize(size_of::<Reading>(), 0);
+        headerless.extend_from_slice(&slot);
+    }
+    std::fs::write(&path, headerless).unwrap();
+    assert_eq!(vector_db(&["info", &path]).status.code(), Some(1));
+
+    let slot_size = size_of::<Reading>().to_string();
+    let upgraded = vector_db_ok(&[
+        "upgrade",
+        &path,
+        "--slot-size",
+        &slot_size,
+        "--new-slot-size",
+        "12",
+    ]);
+    assert!(
+        upgraded.contains("\"converted_records\": 5"),
+        "{}",
+        upgraded
+    );
+    let service =
+        StaticVectorManageService::<Reading>::new(path, String::new(), 1024).unwrap();
+    assert_eq!(
+        service.read_bulk(0, 5),
+        (0..5).map(reading).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_unknown_option_is_rejected() {
+    let dir = Scratch::new("unknown_option_is_rejected");
+    let (structure, data) = (dir.path("a.index"), dir.path("a.data"));
+    save_messages(&structure, &data, DynamicOptions::default());
+    let before = std::fs::read(&structure).unwrap();
+
+    let output = vector_db(&["repair", &structure, &data, "--dryrun"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unknown option --dryrun"), "{}", stderr);
+    assert!(stderr.contains("usage: vector-db"));
+    assert_eq!(std::fs::read(&structure).unwrap(), before);
+
+    // Options of other commands are unknown too.
+    let output = vector_db(&["info", &structure, &data, "--out", "x"]);
+    assert_eq!(output.status.code(), Some(2));
+}
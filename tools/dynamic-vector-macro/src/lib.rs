@@ -1,201 +1,637 @@
 use proc_macro::TokenStream; // 用于定义过程宏输入和输出的类型
-use quote::quote; // 用于将 Rust 代码片段转换成可编译的代码块
-use syn::{parse_macro_input, Data, DeriveInput, Fields}; // 解析 Rust 语法树和类型
-
-
-
-// 定义一个过程宏 `CheckDynamicSize`，它自动为标记的结构体生成代码，检查每个字段是否是动态大小类型
-#[proc_macro_derive(CheckDynamicSize)]
-pub fn check_dynamic_size_derive(input: TokenStream) -> TokenStream {
-    // 解析输入的语法树，将过程宏输入（TokenStream）解析为 DeriveInput 数据结构
-    let input = parse_macro_input!(input as DeriveInput);
-
-    // 获取结构体的名称
-    let name = input.ident;
-    
-    let dynamic_field_map = if let Data::Struct(data) = input.data.clone() {
-    if let Fields::Named(fields) = data.fields {
-        // 生成动态字段名称，只收集动态大小字段
-        let names: Vec<_> = fields.named.iter().map(|f| {
-            let field_name = &f.ident;
-            let field_type = &f.ty; // 获取字段类型
-            // let vec_type = Type::Verbatim(quote! { Vec<usize> });
-
-
-            quote! {
-
-            // if stringify!(#field_type) == stringify!(#vec_type) {
-            if is_dynamic_size::<#field_type>(&self.#field_name) {
-
-                // println!("type:{:?}", stringify!(#field_type));
-                // let value = self.#field_name.to_bytes_vector().clone();
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use proc_macro_crate::{crate_name, FoundCrate};
+use quote::{format_ident, quote}; // 用于将 Rust 代码片段转换成可编译的代码块
+use syn::{
+    parse_macro_input, parse_quote_spanned, spanned::Spanned, Data, DeriveInput, Fields,
+    GenericArgument, Generics, Ident, Member, PathArguments, Type,
+}; // 解析 Rust 语法树和类型
+
+// 生成代码中引用特征的路径：直接依赖 dynamic-vector 时为 `::dynamic_vector`，
+// 只依赖 vector-db-core 时使用其重新导出的 `::vector_db_core`
+fn crate_path() -> TokenStream2 {
+    for name in ["dynamic-vector", "vector-db-core"] {
+        match crate_name(name) {
+            Ok(FoundCrate::Itself) => return quote!(crate),
+            Ok(FoundCrate::Name(name)) => {
+                let ident = Ident::new(&name, Span::call_site());
+                return quote!(::#ident);
+            }
+            Err(_) => {}
+        }
+    }
+    quote!(::dynamic_vector)
+}
 
+// 单个字段：`member` 用于访问，`binding` 为解构时的局部变量，`name` 为对外报告的字段名，
+// `label` 为字段自身的名称（枚举字段不带变体前缀）
+struct FieldInfo {
+    member: Member,
+    binding: Ident,
+    name: String,
+    label: String,
+    ty: Type,
+}
 
-(stringify!(#field_name), self.#field_name.to_bytes_vector())
-// (stringify!(#field_name),value )
-// self.#field_name.to_bytes_vector()
-            } else {
-                // "".to_string()
-                // (&#field_name, self.#field_name.to_bytes_vector().clone())
-                ("None", vec![0 as u8])
+// 结构体只有一个形状；枚举的每个变体各是一个形状
+struct Shape {
+    variant: Option<Ident>,
+    fields: Vec<FieldInfo>,
+}
 
+fn shape_of(variant: Option<&Ident>, fields: &Fields) -> Shape {
+    let fields = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let member = match &field.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(i.into()),
+            };
+            let field_name = match &field.ident {
+                Some(ident) => ident.to_string(),
+                None => i.to_string(),
+            };
+            FieldInfo {
+                member,
+                binding: format_ident!("__field{}", i),
+                name: match variant {
+                    Some(variant) => format!("{}::{}", variant, field_name),
+                    None => field_name.clone(),
+                },
+                label: field_name,
+                ty: field.ty.clone(),
             }
-        }
-            // }
         })
         .collect();
-
-        quote! { vec![#(#names),*] } // 返回一个字符串数组
-    } else {
-        quote! { vec![] } // 如果没有具名字段，返回空数组
+    Shape {
+        variant: variant.cloned(),
+        fields,
     }
-} else {
-    quote! { vec![] } // 如果输入数据不是结构体，返回空数组
-};
-
-
+}
 
+// 借用、裸指针、函数指针、特征对象等字段无法编码后再还原，返回指向该字段类型的错误
+fn check_field_type(ty: &Type) -> syn::Result<()> {
+    let reason = match ty {
+        Type::Array(array) => return check_field_type(&array.elem),
+        Type::Group(group) => return check_field_type(&group.elem),
+        Type::Paren(paren) => return check_field_type(&paren.elem),
+        Type::Tuple(tuple) => return tuple.elems.iter().try_for_each(check_field_type),
+        Type::Path(path) => {
+            if let Some(qself) = &path.qself {
+                check_field_type(&qself.ty)?;
+            }
+            for segment in &path.path.segments {
+                if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                    for arg in &args.args {
+                        if let GenericArgument::Type(ty) = arg {
+                            check_field_type(ty)?;
+                        }
+                    }
+                }
+            }
+            return Ok(());
+        }
+        Type::Macro(_) | Type::Verbatim(_) => return Ok(()),
+        Type::Reference(_) => {
+            "borrowed fields cannot be decoded back; use an owned type such as `String` or `Vec<T>`"
+        }
+        Type::Ptr(_) => "raw pointer fields cannot be stored",
+        Type::BareFn(_) => "function pointer fields cannot be stored",
+        Type::TraitObject(_) | Type::ImplTrait(_) => {
+            "trait object fields have no known layout; use a concrete type"
+        }
+        Type::Slice(_) => "unsized slice fields are not supported; use `Vec<T>` or `[T; N]`",
+        Type::Never(_) => "`!` fields cannot be stored",
+        _ => "unsupported field type",
+    };
+    Err(syn::Error::new_spanned(
+        ty,
+        format!("CheckDynamicSize: {}", reason),
+    ))
+}
 
-    let dynamic_field_values = if let Data::Struct(data) = input.data.clone() {
-    if let Fields::Named(fields) = data.fields {
-        // 生成动态字段名称，只收集动态大小字段
-        let names: Vec<_> = fields.named.iter().map(|f| {
-            let field_name = &f.ident;
-            let field_type = &f.ty; // 获取字段类型
-            // let vec_type = Type::Verbatim(quote! { Vec<usize> });
+// 泛型类型为每个字段类型加上 `#ty: #bounds` 约束；非泛型类型保持原样
+fn bounded_generics(generics: &Generics, field_types: &[&Type], bounds: TokenStream2) -> Generics {
+    let mut generics = generics.clone();
+    if generics.type_params().next().is_some() {
+        let where_clause = generics.make_where_clause();
+        for ty in field_types {
+            where_clause
+                .predicates
+                .push(parse_quote_spanned!(ty.span()=> #ty: #bounds));
+        }
+    }
+    generics
+}
 
+// 解构 `self` 并对每个字段生成语句；`prefix` 为每个形状在字段语句之前插入的语句
+fn visit_fields(
+    shapes: &[Shape],
+    prefix: impl Fn(usize) -> TokenStream2,
+    visit: impl Fn(&FieldInfo) -> TokenStream2,
+) -> TokenStream2 {
+    if shapes.is_empty() {
+        return quote! { match *self {} };
+    }
+    let arms = shapes.iter().enumerate().map(|(index, shape)| {
+        let path = match &shape.variant {
+            Some(variant) => quote!(Self::#variant),
+            None => quote!(Self),
+        };
+        let members = shape.fields.iter().map(|field| &field.member);
+        let bindings = shape.fields.iter().map(|field| &field.binding);
+        let prefix = prefix(index);
+        let body = shape.fields.iter().map(&visit);
+        quote! {
+            #path { #(#members: #bindings),* } => {
+                #prefix
+                #(#body)*
+            }
+        }
+    });
+    quote! {
+        match self {
+            #(#arms)*
+        }
+    }
+}
 
-            quote! {
+// 定义一个过程宏 `CheckDynamicSize`，为结构体或枚举生成 `DynamicSized`、`VectorCandidate`、
+// `FromBytesVector`、`Describe` 与 `DynamicVector` 实现，结构体另外生成 `HybridRecord` 实现。
+// 生成的代码只通过完整路径引用特征，不向调用处引入任何名字。
+#[proc_macro_derive(CheckDynamicSize)]
+pub fn check_dynamic_size_derive(input: TokenStream) -> TokenStream {
+    // 解析输入的语法树，将过程宏输入（TokenStream）解析为 DeriveInput 数据结构
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_check_dynamic_size(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
 
-            // if stringify!(#field_type) == stringify!(#vec_type) {
-            if is_dynamic_size::<#field_type>(&self.#field_name) {
+fn expand_check_dynamic_size(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let krate = crate_path();
+    let name = &input.ident;
+    let is_enum = matches!(input.data, Data::Enum(_));
+
+    let shapes: Vec<Shape> = match &input.data {
+        Data::Struct(data) => vec![shape_of(None, &data.fields)],
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|variant| shape_of(Some(&variant.ident), &variant.fields))
+            .collect(),
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "CheckDynamicSize cannot be derived for unions",
+            ));
+        }
+    };
 
-                // println!("type:{:?}", stringify!(#field_type));
+    // 汇总所有不支持的字段，一次报告
+    let mut errors: Option<syn::Error> = None;
+    for field in shapes.iter().flat_map(|shape| &shape.fields) {
+        if let Err(error) = check_field_type(&field.ty) {
+            match &mut errors {
+                Some(errors) => errors.combine(error),
+                None => errors = Some(error),
+            }
+        }
+    }
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
 
+    let field_types: Vec<&Type> = shapes
+        .iter()
+        .flat_map(|shape| shape.fields.iter().map(|field| &field.ty))
+        .collect();
+    let dynamic_sized = quote!(#krate::DynamicSized);
+
+    // DYNAMIC：任一字段为变长即为变长；ENCODED_SIZE：结构体为各字段之和，
+    // 枚举为 4 字节的变体标签加上最大的变体
+    let encoded_size = if is_enum {
+        let variant_sizes = shapes.iter().map(|shape| {
+            let types = shape.fields.iter().map(|field| &field.ty);
+            quote! { 0 #(+ <#types as #dynamic_sized>::ENCODED_SIZE)* }
+        });
+        quote! {
+            {
+                let mut max = 0;
+                #(
+                    let size = #variant_sizes;
+                    if size > max {
+                        max = size;
+                    }
+                )*
+                4 + max
+            }
+        }
+    } else {
+        quote! { 0 #(+ <#field_types as #dynamic_sized>::ENCODED_SIZE)* }
+    };
+    let generics = bounded_generics(&input.generics, &field_types, quote!(#dynamic_sized));
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let dynamic_sized_impl = quote! {
+        impl #impl_generics #dynamic_sized for #name #type_generics #where_clause {
+            const DYNAMIC: bool = false #(|| <#field_types as #dynamic_sized>::DYNAMIC)*;
+            const ENCODED_SIZE: usize = if Self::DYNAMIC { 0 } else { #encoded_size };
+        }
+    };
 
-self.#field_name.to_bytes_vector()
+    // 字节表示为各字段字节的拼接，枚举以 u32 变体序号开头
+    let to_bytes = visit_fields(
+        &shapes,
+        |index| {
+            if is_enum {
+                let index = index as u32;
+                quote! { bytes.extend_from_slice(&#index.to_le_bytes()); }
             } else {
+                quote! {}
+            }
+        },
+        |field| {
+            let binding = &field.binding;
+            quote! { bytes.extend(#krate::VectorCandidate::to_bytes_vector(#binding)); }
+        },
+    );
+    let generics = bounded_generics(
+        &input.generics,
+        &field_types,
+        quote!(#krate::VectorCandidate),
+    );
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let vector_candidate_impl = quote! {
+        impl #impl_generics #krate::VectorCandidate for #name #type_generics #where_clause {
+            #[allow(unused_variables)]
+            fn to_bytes_vector(&self) -> ::std::vec::Vec<u8> {
+                let mut bytes = ::std::vec::Vec::new();
+                #to_bytes
+                bytes
+            }
+        }
+    };
 
-                vec![0 as u8]
-
+    // 按 to_bytes_vector 的顺序逐个字段解码，枚举先读取 u32 变体序号
+    let decode = quote!(#krate::FromBytesVector);
+    let construct = |shape: &Shape| {
+        let path = match &shape.variant {
+            Some(variant) => quote!(Self::#variant),
+            None => quote!(Self),
+        };
+        let members = shape.fields.iter().map(|field| &field.member);
+        let types = shape.fields.iter().map(|field| &field.ty);
+        quote! {
+            #path {
+                #(
+                    #members: {
+                        let (value, used) =
+                            <#types as #decode>::from_bytes_prefix(&bytes[offset..])?;
+                        offset += used;
+                        value
+                    },
+                )*
             }
         }
-            // }
-        })
-        .filter( |item|  !item.is_empty())
-        .collect();
+    };
+    let from_bytes = if is_enum {
+        let indices = (0..shapes.len() as u32).collect::<Vec<_>>();
+        let values = shapes.iter().map(construct);
+        quote! {
+            let (index, used) = <u32 as #decode>::from_bytes_prefix(bytes)?;
+            offset += used;
+            let value = match index {
+                #(#indices => #values,)*
+                _ => {
+                    return ::std::result::Result::Err(
+                        #krate::BytesDecodeError::InvalidValue("unknown enum variant"),
+                    );
+                }
+            };
+        }
+    } else {
+        let value = construct(&shapes[0]);
+        quote! { let value = #value; }
+    };
+    let generics = bounded_generics(&input.generics, &field_types, quote!(#decode));
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let from_bytes_vector_impl = quote! {
+        impl #impl_generics #decode for #name #type_generics #where_clause {
+            #[allow(unused_mut, unreachable_code)]
+            fn from_bytes_prefix(
+                bytes: &[u8],
+            ) -> ::std::result::Result<(Self, usize), #krate::BytesDecodeError> {
+                let mut offset = 0;
+                #from_bytes
+                ::std::result::Result::Ok((value, offset))
+            }
+        }
+    };
 
-        quote! { vec![#(#names),*] } // 返回一个字符串数组
+    // 结构描述：字段名称、类型、顺序与定长/动态分类
+    let describe = quote!(#krate::Describe);
+    let field_schemas = |shape: &Shape| {
+        let labels = shape.fields.iter().map(|field| &field.label);
+        let types: Vec<_> = shape.fields.iter().map(|field| &field.ty).collect();
+        quote! {
+            ::std::vec![
+                #(
+                    #krate::FieldSchema {
+                        name: ::std::string::String::from(#labels),
+                        ty: <#types as #describe>::describe(),
+                        dynamic: <#types as #dynamic_sized>::DYNAMIC,
+                        encoded_size: <#types as #dynamic_sized>::ENCODED_SIZE,
+                    }
+                ),*
+            ]
+        }
+    };
+    let type_name = name.to_string();
+    // 枚举没有 HybridRecord 实现，也就不需要记录级的描述
+    let record_schema = if is_enum {
+        quote! {}
     } else {
-        quote! { vec![] } // 如果没有具名字段，返回空数组
-    }
-} else {
-    quote! { vec![] } // 如果输入数据不是结构体，返回空数组
-};
+        let fields = field_schemas(&shapes[0]);
+        quote! {
+            #krate::RecordSchema {
+                name: ::std::string::String::from(#type_name),
+                fields: #fields,
+            }
+        }
+    };
+    let description = if is_enum {
+        let variant_names = shapes
+            .iter()
+            .map(|shape| shape.variant.as_ref().unwrap().to_string());
+        let variant_fields = shapes.iter().map(field_schemas);
+        quote! {
+            #krate::FieldType::Enum {
+                name: ::std::string::String::from(#type_name),
+                variants: ::std::vec![
+                    #(
+                        #krate::VariantSchema {
+                            name: ::std::string::String::from(#variant_names),
+                            fields: #variant_fields,
+                        }
+                    ),*
+                ],
+            }
+        }
+    } else {
+        quote! { #krate::FieldType::Struct(#record_schema) }
+    };
+    let generics = bounded_generics(
+        &input.generics,
+        &field_types,
+        quote!(#describe + #dynamic_sized),
+    );
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let describe_impl = quote! {
+        impl #impl_generics #describe for #name #type_generics #where_clause {
+            fn describe() -> #krate::FieldType {
+                #description
+            }
+        }
+    };
+
+    // 动态字段的名称与字节，枚举只报告当前变体的字段
+    let dynamic_names = visit_fields(
+        &shapes,
+        |_| quote! {},
+        |field| {
+            let (ty, field_name) = (&field.ty, &field.name);
+            quote! {
+                if <#ty as #dynamic_sized>::DYNAMIC {
+                    fields.push(::std::string::String::from(#field_name));
+                }
+            }
+        },
+    );
+    let dynamic_values = visit_fields(
+        &shapes,
+        |_| quote! {},
+        |field| {
+            let (ty, binding) = (&field.ty, &field.binding);
+            quote! {
+                if <#ty as #dynamic_sized>::DYNAMIC {
+                    values.push(#krate::VectorCandidate::to_bytes_vector(#binding));
+                }
+            }
+        },
+    );
+    let dynamic_map = visit_fields(
+        &shapes,
+        |_| quote! {},
+        |field| {
+            let (ty, binding, field_name) = (&field.ty, &field.binding, &field.name);
+            quote! {
+                if <#ty as #dynamic_sized>::DYNAMIC {
+                    map.insert(#field_name, #krate::VectorCandidate::to_bytes_vector(#binding));
+                }
+            }
+        },
+    );
+    let generics = bounded_generics(
+        &input.generics,
+        &field_types,
+        quote!(#dynamic_sized + #krate::VectorCandidate),
+    );
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let dynamic_vector_impl = quote! {
+        impl #impl_generics #krate::DynamicVector for #name #type_generics #where_clause {
+            fn is_dynamic_structure(&self) -> bool {
+                !#krate::DynamicVector::get_dynamic_fields(self).is_empty()
+            }
 
+            #[allow(unused_mut, unused_variables)]
+            fn get_dynamic_fields(&self) -> ::std::vec::Vec<::std::string::String> {
+                let mut fields = ::std::vec::Vec::new();
+                #dynamic_names
+                fields
+            }
 
+            #[allow(unused_mut, unused_variables)]
+            fn get_dynamic_values(&self) -> ::std::vec::Vec<::std::vec::Vec<u8>> {
+                let mut values = ::std::vec::Vec::new();
+                #dynamic_values
+                values
+            }
+        }
 
+        impl #impl_generics #name #type_generics #where_clause {
+            #[allow(unused_mut, unused_variables, dead_code)]
+            fn get_dynamic_map(
+                &self,
+            ) -> ::std::collections::HashMap<&'static str, ::std::vec::Vec<u8>> {
+                let mut map = ::std::collections::HashMap::new();
+                #dynamic_map
+                map
+            }
+        }
+    };
 
-    let dynamic_field_names = if let Data::Struct(data) = input.data {
-    if let Fields::Named(fields) = data.fields {
-        // 生成动态字段名称，只收集动态大小字段
-        let names: Vec<_> = fields.named.iter().map(|f| {
-            let field_name = &f.ident;
-            let field_type = &f.ty; // 获取字段类型
-            // 检查字段是否为动态大小类型
+    // 为结构体生成 HybridRecord 实现：定长字段补齐后写入定长槽位，动态字段单独存放
+    let hybrid_record_impl = match shapes.as_slice() {
+        [shape] if !is_enum => {
+            let members: Vec<_> = shape.fields.iter().map(|field| &field.member).collect();
+            let names: Vec<_> = shape.fields.iter().map(|field| &field.name).collect();
+            let codec = quote!(#krate::FieldCodec);
+            let generics = bounded_generics(
+                &input.generics,
+                &field_types,
+                quote!(#dynamic_sized + #codec + #describe),
+            );
+            let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
             quote! {
-            if is_dynamic_size::<#field_type>(&self.#field_name) {
-                // Some(quote! { stringify!(#field_name).to_string() }) // 只有动态大小字段的名称被收集
-                stringify!(#field_name).to_string() 
-            } else {
-                "".to_string() 
+                impl #impl_generics #krate::HybridRecord for #name #type_generics #where_clause {
+                    fn fixed_size() -> usize {
+                        0 #(+ <#field_types as #dynamic_sized>::ENCODED_SIZE)*
+                    }
+
+                    fn dynamic_field_count() -> usize {
+                        0 #(+ <#field_types as #dynamic_sized>::DYNAMIC as usize)*
+                    }
+
+                    fn schema() -> #krate::RecordSchema {
+                        #record_schema
+                    }
+
+                    #[allow(unused_mut)]
+                    fn split_fields(
+                        &self,
+                    ) -> (::std::vec::Vec<u8>, ::std::vec::Vec<::std::vec::Vec<u8>>) {
+                        let mut fixed = ::std::vec::Vec::with_capacity(
+                            <Self as #krate::HybridRecord>::fixed_size(),
+                        );
+                        let mut dynamic = ::std::vec::Vec::with_capacity(
+                            <Self as #krate::HybridRecord>::dynamic_field_count(),
+                        );
+                        #(
+                            if <#field_types as #dynamic_sized>::DYNAMIC {
+                                dynamic.push(
+                                    <#field_types as #codec>::encode_field(&self.#members),
+                                );
+                            } else {
+                                let size = <#field_types as #dynamic_sized>::ENCODED_SIZE;
+                                let mut bytes =
+                                    <#field_types as #codec>::encode_field(&self.#members);
+                                assert!(
+                                    bytes.len() <= size,
+                                    "field `{}` encodes to {} bytes, more than its {} byte slot",
+                                    #names,
+                                    bytes.len(),
+                                    size
+                                );
+                                bytes.resize(size, 0);
+                                fixed.extend(bytes);
+                            }
+                        )*
+                        (fixed, dynamic)
+                    }
+
+                    #[allow(unused_mut, unused_variables)]
+                    fn join_fields(
+                        fixed: &[u8],
+                        dynamic: ::std::vec::Vec<::std::vec::Vec<u8>>,
+                    ) -> Self {
+                        let mut fixed_offset = 0;
+                        let mut dynamic = dynamic.into_iter();
+                        Self {
+                            #(
+                                #members: if <#field_types as #dynamic_sized>::DYNAMIC {
+                                    <#field_types as #codec>::decode_field(
+                                        &dynamic.next().expect("missing dynamic field"),
+                                    )
+                                } else {
+                                    let size = <#field_types as #dynamic_sized>::ENCODED_SIZE;
+                                    fixed_offset += size;
+                                    <#field_types as #codec>::decode_field(
+                                        &fixed[fixed_offset - size..fixed_offset],
+                                    )
+                                },
+                            )*
+                        }
+                    }
+
+                    #[allow(unused_mut, unused_variables)]
+                    fn assign_fixed_fields(&mut self, fixed: &[u8]) {
+                        let mut fixed_offset = 0;
+                        #(
+                            if !<#field_types as #dynamic_sized>::DYNAMIC {
+                                let size = <#field_types as #dynamic_sized>::ENCODED_SIZE;
+                                fixed_offset += size;
+                                self.#members = <#field_types as #codec>::decode_field(
+                                    &fixed[fixed_offset - size..fixed_offset],
+                                );
+                            }
+                        )*
+                    }
+                }
             }
         }
-        })
-        .filter( |item|  !item.is_empty())
-        .collect();
+        _ => quote! {},
+    };
 
-        quote! { vec![#(#names),*] } // 返回一个字符串数组
-    } else {
-        quote! { vec![] } // 如果没有具名字段，返回空数组
-    }
-} else {
-    quote! { vec![] } // 如果输入数据不是结构体，返回空数组
-};
-
-
-
-    // 使用 `quote!` 宏生成最终代码：为结构体实现 `check_dynamic_fields` 方法
-    let expanded = quote! {
-            use std::collections::HashMap;
-        trait DynamicSized {fn is_dynamic_size(&self) -> bool;}
-
-impl DynamicSized for bool {fn is_dynamic_size(&self) -> bool {false}}
-        impl DynamicSized for usize {fn is_dynamic_size(&self) -> bool {false}}
-        impl DynamicSized for u8 {fn is_dynamic_size(&self) -> bool {false}}
-        impl DynamicSized for u16 {fn is_dynamic_size(&self) -> bool {false}}
-        impl DynamicSized for u32 {fn is_dynamic_size(&self) -> bool {false}}
-        impl DynamicSized for u64 {fn is_dynamic_size(&self) -> bool {false}}
-        impl DynamicSized for i8 {fn is_dynamic_size(&self) -> bool {false}}
-        impl DynamicSized for i16 {fn is_dynamic_size(&self) -> bool {false}}
-        impl DynamicSized for i32 {fn is_dynamic_size(&self) -> bool {false}}
-        impl DynamicSized for i64 {fn is_dynamic_size(&self) -> bool {false}}
-
-        impl DynamicSized for str {fn is_dynamic_size(&self) -> bool {true}}
-        impl DynamicSized for &str {fn is_dynamic_size(&self) -> bool {true}}
-        impl DynamicSized for String {fn is_dynamic_size(&self) -> bool {true}}
-
-        impl<T> DynamicSized for Vec<T> {fn is_dynamic_size(&self) -> bool {true}}
-        impl<T> DynamicSized for [T] {fn is_dynamic_size(&self) -> bool {true}}
-        impl<T> DynamicSized for &[T] {fn is_dynamic_size(&self) -> bool {true}}
-
-        fn is_dynamic_size<T: DynamicSized>(value: &T) -> bool {
-    value.is_dynamic_size()
+    Ok(quote! {
+        #dynamic_sized_impl
+        #vector_candidate_impl
+        #from_bytes_vector_impl
+        #describe_impl
+        #dynamic_vector_impl
+        #hybrid_record_impl
+    })
 }
 
+// 定义过程宏 `MaxEncodedSize`：记录的最大编码长度为各字段之和，
+// 枚举为 4 字节的变体标签加上最大的变体。任何字段未实现 MaxEncodedSize 时编译失败。
+#[proc_macro_derive(MaxEncodedSize)]
+pub fn max_encoded_size_derive(input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+    let krate = crate_path();
+    let name = input.ident.clone();
 
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(syn::parse_quote!(#krate::MaxEncodedSize));
+    }
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
 
+    let fields_size = |fields: &Fields| {
+        let field_types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+        quote! { 0 #(+ <#field_types as #krate::MaxEncodedSize>::MAX_ENCODED_SIZE)* }
+    };
 
-        impl DynamicVector for #name {
-
-            fn is_dynamic_structure(&self) -> bool {
-                let check = self.get_dynamic_fields();
-                if   check.len() != 0 {
-                    return true;
-                } else {
-                    return false ;
+    let size = match &input.data {
+        Data::Struct(data) => fields_size(&data.fields),
+        Data::Enum(data) => {
+            let variant_sizes: Vec<_> = data
+                .variants
+                .iter()
+                .map(|variant| fields_size(&variant.fields))
+                .collect();
+            quote! {
+                {
+                    let mut max = 0;
+                    #(
+                        let size = #variant_sizes;
+                        if size > max {
+                            max = size;
+                        }
+                    )*
+                    4 + max
                 }
             }
-
-fn get_dynamic_fields(&self) -> Vec<String> {
-#dynamic_field_names.iter()
-.filter( |item| !item.is_empty())
-.cloned()
-.collect()
-}
-
-fn get_dynamic_values(&self) -> Vec<Vec<u8>> {
-#dynamic_field_values
-.iter()
-.filter( |item| !item.is_empty())
-.cloned()
-.collect()
-}
         }
-        
-        impl #name{
-fn get_dynamic_map(&self) -> HashMap<&str, Vec<u8>> {
-    #dynamic_field_map
-    .iter()
-    .cloned()
-    .collect::<HashMap<&str, Vec<u8>>>()
-}
-
+        Data::Union(_) => {
+            return syn::Error::new_spanned(&input.ident, "MaxEncodedSize cannot be derived for unions")
+                .to_compile_error()
+                .into();
         }
     };
 
-    // 将生成的代码转换为 TokenStream，以便 Rust 编译器进一步处理
-    TokenStream::from(expanded)
+    TokenStream::from(quote! {
+        impl #impl_generics #krate::MaxEncodedSize for #name #type_generics #where_clause {
+            const MAX_ENCODED_SIZE: usize = #size;
+        }
+    })
 }
-
-
@@ -0,0 +1,492 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    ops::Deref,
+};
+
+use serde::{
+    de::{
+        self,
+        SeqAccess,
+        Visitor,
+    },
+    ser::SerializeTuple,
+    Deserialize,
+    Deserializer,
+    Serialize,
+    Serializer,
+};
+
+use crate::traits::{
+    BytesDecodeError,
+    DynamicSized,
+    FromBytesVector,
+    MaxEncodedSize,
+    VectorCandidate,
+};
+
+/// 超出容量时的处理方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// 截断到容量以内（字符串按字符边界截断）。
+    Truncate,
+    /// 返回错误：`FixedVec` 为 `CapacityError`，`FixedString` 为
+    /// `FixedStringError`。
+    Reject,
+}
+
+/// 内容超出定长类型的容量。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapacityError {
+    pub capacity: usize,
+    pub length: usize,
+}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "value of length {} exceeds the fixed capacity of {}",
+            self.length, self.capacity
+        )
+    }
+}
+
+impl std::error::Error for CapacityError {}
+
+/// `FixedString` 无法保存给定内容的原因。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FixedStringError {
+    /// 内容超出容量。
+    Capacity(CapacityError),
+    /// 内容在字节位置 `position` 处包含 `'\0'`，而 `'\0'` 用作填充。
+    ContainsNul { position: usize },
+}
+
+impl fmt::Display for FixedStringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixedStringError::Capacity(error) => fmt::Display::fmt(error, f),
+            FixedStringError::ContainsNul { position } => write!(
+                f,
+                "value contains a NUL byte at {}, which fixed strings use as padding",
+                position
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FixedStringError {}
+
+impl From<CapacityError> for FixedStringError {
+    fn from(error: CapacityError) -> Self {
+        FixedStringError::Capacity(error)
+    }
+}
+
+/// 最多 N 字节 UTF-8 的定长字符串，编码后恰好 N 字节：内容之后以 0 填充，
+/// 因此内容中不能包含 `'\0'`。
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FixedString<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FixedString<N> {
+    /// 超出容量或包含 `'\0'` 时返回错误。
+    pub fn new(value: &str) -> Result<Self, FixedStringError> {
+        Self::with_policy(value, OverflowPolicy::Reject)
+    }
+
+    /// 超出容量时按字符边界截断，遇到 `'\0'` 时在其之前截断。
+    pub fn truncating(value: &str) -> Self {
+        Self::with_policy(value, OverflowPolicy::Truncate).unwrap()
+    }
+
+    pub fn with_policy(
+        value: &str,
+        policy: OverflowPolicy,
+    ) -> Result<Self, FixedStringError> {
+        let mut end = value.find('\0').unwrap_or(value.len());
+        if end < value.len() && policy == OverflowPolicy::Reject {
+            return Err(FixedStringError::ContainsNul { position: end });
+        }
+        if end > N {
+            if policy == OverflowPolicy::Reject {
+                return Err(CapacityError {
+                    capacity: N,
+                    length: value.len(),
+                }
+                .into());
+            }
+            end = N;
+            while !value.is_char_boundary(end) {
+                end -= 1;
+            }
+        }
+        let mut bytes = [0u8; N];
+        bytes[..end].copy_from_slice(&value.as_bytes()[..end]);
+        Ok(Self { bytes, len: end })
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.bytes[..self.len]).unwrap()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> Default for FixedString<N> {
+    fn default() -> Self {
+        Self {
+            bytes: [0u8; N],
+            len: 0,
+        }
+    }
+}
+
+impl<const N: usize> Deref for FixedString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> fmt::Debug for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> fmt::Display for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> TryFrom<&str> for FixedString<N> {
+    type Error = FixedStringError;
+
+    fn try_from(value: &str) -> Result<Self, FixedStringError> {
+        Self::new(value)
+    }
+}
+
+impl<const N: usize> TryFrom<String> for FixedString<N> {
+    type Error = FixedStringError;
+
+    fn try_from(value: String) -> Result<Self, FixedStringError> {
+        Self::new(&value)
+    }
+}
+
+impl<const N: usize> Serialize for FixedString<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(N)?;
+        for byte in &self.bytes {
+            tuple.serialize_element(byte)?;
+        }
+        tuple.end()
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for FixedString<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FixedStringVisitor<const N: usize>;
+
+        impl<'de, const N: usize> Visitor<'de> for FixedStringVisitor<N> {
+            type Value = FixedString<N>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{} bytes of zero-padded UTF-8", N)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut bytes = [0u8; N];
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+                }
+                let len = bytes.iter().position(|b| *b == 0).unwrap_or(N);
+                std::str::from_utf8(&bytes[..len]).map_err(de::Error::custom)?;
+                Ok(FixedString { bytes, len })
+            }
+        }
+
+        deserializer.deserialize_tuple(N, FixedStringVisitor::<N>)
+    }
+}
+
+impl<const N: usize> MaxEncodedSize for FixedString<N> {
+    const MAX_ENCODED_SIZE: usize = N;
+}
+
+impl<const N: usize> DynamicSized for FixedString<N> {
+    const DYNAMIC: bool = false;
+    const ENCODED_SIZE: usize = N;
+}
+
+impl<const N: usize> VectorCandidate for FixedString<N> {
+    fn to_bytes_vector(&self) -> Vec<u8> {
+        self.bytes.to_vec()
+    }
+}
+
+impl<const N: usize> FromBytesVector for FixedString<N> {
+    fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+        let raw = bytes.get(..N).ok_or(BytesDecodeError::UnexpectedEnd {
+            needed: N,
+            remaining: bytes.len(),
+        })?;
+        let mut padded = [0u8; N];
+        padded.copy_from_slice(raw);
+        let len = padded.iter().position(|b| *b == 0).unwrap_or(N);
+        std::str::from_utf8(&padded[..len])
+            .map_err(|_| BytesDecodeError::InvalidValue("string is not valid UTF-8"))?;
+        Ok((FixedString { bytes: padded, len }, N))
+    }
+}
+
+/// 最多 N 个元素的定长数组，编码为 u32 长度加 N 个元素（不足部分以
+/// `T::default()` 填充），因此编码长度不超过 `MAX_ENCODED_SIZE`。
+#[derive(Clone, PartialEq, Eq, Hash, Default)]
+pub struct FixedVec<T, const N: usize> {
+    items: Vec<T>,
+}
+
+impl<T: Clone, const N: usize> FixedVec<T, N> {
+    /// 元素超过 N 个时返回错误。
+    pub fn new(items: &[T]) -> Result<Self, CapacityError> {
+        Self::with_policy(items, OverflowPolicy::Reject)
+    }
+
+    /// 只保留前 N 个元素。
+    pub fn truncating(items: &[T]) -> Self {
+        Self::with_policy(items, OverflowPolicy::Truncate).unwrap()
+    }
+
+    pub fn with_policy(
+        items: &[T],
+        policy: OverflowPolicy,
+    ) -> Result<Self, CapacityError> {
+        if items.len() > N && policy == OverflowPolicy::Reject {
+            return Err(CapacityError {
+                capacity: N,
+                length: items.len(),
+            });
+        }
+        Ok(Self {
+            items: items[..items.len().min(N)].to_vec(),
+        })
+    }
+
+    pub fn push(&mut self, item: T) -> Result<(), CapacityError> {
+        if self.items.len() == N {
+            return Err(CapacityError {
+                capacity: N,
+                length: N + 1,
+            });
+        }
+        self.items.push(item);
+        Ok(())
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.items
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize> Deref for FixedVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.items
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for FixedVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.items, f)
+    }
+}
+
+impl<T: Clone, const N: usize> TryFrom<Vec<T>> for FixedVec<T, N> {
+    type Error = CapacityError;
+
+    fn try_from(items: Vec<T>) -> Result<Self, CapacityError> {
+        Self::new(&items)
+    }
+}
+
+impl<T: Serialize + Default, const N: usize> Serialize for FixedVec<T, N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(N + 1)?;
+        tuple.serialize_element(&(self.items.len() as u32))?;
+        for item in &self.items {
+            tuple.serialize_element(item)?;
+        }
+        let padding = T::default();
+        for _ in self.items.len()..N {
+            tuple.serialize_element(&padding)?;
+        }
+        tuple.end()
+    }
+}
+
+impl<'de, T: Deserialize<'de>, const N: usize> Deserialize<'de> for FixedVec<T, N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FixedVecVisitor<T, const N: usize>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>, const N: usize> Visitor<'de> for FixedVecVisitor<T, N> {
+            type Value = FixedVec<T, N>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a length followed by {} elements", N)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                let len: u32 = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                if len as usize > N {
+                    return Err(de::Error::invalid_length(len as usize, &self));
+                }
+                let mut items = Vec::with_capacity(len as usize);
+                for i in 0..N {
+                    let item: T = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(i + 1, &self))?;
+                    if i < len as usize {
+                        items.push(item);
+                    }
+                }
+                Ok(FixedVec { items })
+            }
+        }
+
+        deserializer.deserialize_tuple(N + 1, FixedVecVisitor::<T, N>(PhantomData))
+    }
+}
+
+impl<T: MaxEncodedSize, const N: usize> MaxEncodedSize for FixedVec<T, N> {
+    const MAX_ENCODED_SIZE: usize = 4 + N * T::MAX_ENCODED_SIZE;
+}
+
+impl<T: DynamicSized, const N: usize> DynamicSized for FixedVec<T, N> {
+    const DYNAMIC: bool = false;
+    const ENCODED_SIZE: usize = if T::DYNAMIC {
+        panic!("FixedVec elements must be fixed-size")
+    } else {
+        4 + N * T::ENCODED_SIZE
+    };
+}
+
+impl<T: VectorCandidate + Default, const N: usize> VectorCandidate for FixedVec<T, N> {
+    fn to_bytes_vector(&self) -> Vec<u8> {
+        let mut bytes = (self.items.len() as u32).to_bytes_vector();
+        for item in &self.items {
+            bytes.extend(item.to_bytes_vector());
+        }
+        let padding = T::default().to_bytes_vector();
+        for _ in self.items.len()..N {
+            bytes.extend(&padding);
+        }
+        bytes
+    }
+}
+
+impl<T: FromBytesVector, const N: usize> FromBytesVector for FixedVec<T, N> {
+    fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+        let (len, mut offset) = u32::from_bytes_prefix(bytes)?;
+        if len as usize > N {
+            return Err(BytesDecodeError::InvalidValue("FixedVec length exceeds capacity"));
+        }
+        // 填充元素同样需要读过，但不保留
+        let mut items = Vec::with_capacity(len as usize);
+        for i in 0..N {
+            let (item, used) = T::from_bytes_prefix(&bytes[offset..])?;
+            offset += used;
+            if i < len as usize {
+                items.push(item);
+            }
+        }
+        Ok((FixedVec { items }, offset))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fixed_string_policies() {
+        let sender = FixedString::<8>::new("alice").unwrap();
+        assert_eq!(sender.as_str(), "alice");
+        assert_eq!(bincode::serialize(&sender).unwrap(), b"alice\0\0\0");
+        assert_eq!(
+            FixedString::<4>::new("alice"),
+            Err(FixedStringError::Capacity(CapacityError {
+                capacity: 4,
+                length: 5
+            }))
+        );
+        // "你" is three bytes and must not be split.
+        assert_eq!(FixedString::<4>::truncating("a你好").as_str(), "a你");
+
+        let decoded: FixedString<8> =
+            bincode::deserialize(&bincode::serialize(&sender).unwrap()).unwrap();
+        assert_eq!(decoded, sender);
+        assert_eq!(FixedString::<8>::MAX_ENCODED_SIZE, 8);
+    }
+
+    #[test]
+    fn test_fixed_string_rejects_nul() {
+        let error = FixedString::<8>::new("a\0b").unwrap_err();
+        assert_eq!(error, FixedStringError::ContainsNul { position: 1 });
+        assert!(error.to_string().contains("NUL byte at 1"));
+        // Even when the value would also be too long, the NUL is reported.
+        assert_eq!(
+            FixedString::<2>::new("abc\0"),
+            Err(FixedStringError::ContainsNul { position: 3 })
+        );
+        assert_eq!(FixedString::<8>::truncating("a\0b").as_str(), "a");
+    }
+
+    #[test]
+    fn test_fixed_vec_round_trip() {
+        let codes = FixedVec::<u16, 4>::new(&[200, 404]).unwrap();
+        let bytes = bincode::serialize(&codes).unwrap();
+        assert_eq!(bytes.len(), FixedVec::<u16, 4>::MAX_ENCODED_SIZE);
+        let decoded: FixedVec<u16, 4> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.as_slice(), &[200, 404]);
+        assert_eq!(codes.to_bytes_vector(), bytes);
+        assert_eq!(FixedVec::<u16, 4>::from_bytes_vector(&bytes).unwrap(), codes);
+
+        assert!(FixedVec::<u16, 1>::new(&[1, 2]).is_err());
+        let mut truncated = FixedVec::<u16, 1>::truncating(&[1, 2]);
+        assert_eq!(truncated.as_slice(), &[1]);
+        assert!(truncated.push(3).is_err());
+    }
+}
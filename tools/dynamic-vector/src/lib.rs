@@ -1,5 +1,24 @@
+pub mod fixed;
+pub mod schema;
 pub mod traits;
 
+pub use fixed::CapacityError;
+pub use fixed::FixedString;
+pub use fixed::FixedStringError;
+pub use fixed::FixedVec;
+pub use fixed::OverflowPolicy;
+pub use schema::Describe;
+pub use schema::FieldSchema;
+pub use schema::FieldType;
+pub use schema::RecordSchema;
+pub use schema::VariantSchema;
+pub use traits::BytesDecodeError;
+pub use traits::DynamicSized;
 pub use traits::DynamicVector;
+pub use traits::FieldCodec;
+pub use traits::FromBytesVector;
+pub use traits::HybridRecord;
+pub use traits::MaxEncodedSize;
 pub use traits::VectorCandidate;
 pub use dynamic_vector_macro::CheckDynamicSize;
+pub use dynamic_vector_macro::MaxEncodedSize;
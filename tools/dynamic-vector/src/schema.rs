@@ -0,0 +1,180 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::fixed::{
+    FixedString,
+    FixedVec,
+};
+
+/// 字段类型描述，足以在没有 Rust 类型的情况下按 bincode 规则解码字节。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F32,
+    F64,
+    Char,
+    Unit,
+    /// UTF-8 字符串，bincode 编码为 u64 长度加内容。
+    String,
+    /// `Vec<T>`，bincode 编码为 u64 元素个数加各元素。
+    Seq(Box<FieldType>),
+    /// `[T; N]`，N 个元素依次排列。
+    Array(Box<FieldType>, usize),
+    /// 1 字节标签，之后为 Some 的值。
+    Option(Box<FieldType>),
+    Tuple(Vec<FieldType>),
+    /// `Duration`：u64 秒加 u32 纳秒。
+    Duration,
+    /// `FixedString<N>`：N 字节，内容之后以 0 填充。
+    FixedString(usize),
+    /// `FixedVec<T, N>`：u32 长度加 N 个元素。
+    FixedVec(Box<FieldType>, usize),
+    Struct(RecordSchema),
+    /// 4 字节变体序号，之后为该变体的字段。
+    Enum {
+        name: String,
+        variants: Vec<VariantSchema>,
+    },
+    /// `SystemTime`：i64 秒（向下取整，纪元之前为负）加 u32 纳秒，
+    /// 表示距 UNIX 纪元的时间点。
+    Timestamp,
+    /// chrono 的 `DateTime`：编码同 `String`，内容为 RFC 3339 时间。
+    DateTime,
+}
+
+/// 记录中的一个字段。`encoded_size` 为定长字段在定长槽位中占用的字节数，
+/// 动态字段为 0。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FieldSchema {
+    pub name: String,
+    pub ty: FieldType,
+    pub dynamic: bool,
+    pub encoded_size: usize,
+}
+
+/// 结构体的字段按声明顺序排列；元组结构体的字段名为 "0"、"1" 等。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecordSchema {
+    pub name: String,
+    pub fields: Vec<FieldSchema>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VariantSchema {
+    pub name: String,
+    pub fields: Vec<FieldSchema>,
+}
+
+/// 类型的结构描述，由 `CheckDynamicSize` 为结构体和枚举生成。
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` has no schema descriptor",
+    note = "derive CheckDynamicSize on the field type or implement Describe for it"
+)]
+pub trait Describe {
+    fn describe() -> FieldType;
+}
+
+macro_rules! impl_describe {
+    ($($t:ty => $variant:ident),*) => {
+        $(
+            impl Describe for $t {
+                fn describe() -> FieldType {
+                    FieldType::$variant
+                }
+            }
+        )*
+    };
+}
+
+impl_describe!(
+    bool => Bool, u8 => U8, u16 => U16, u32 => U32, u64 => U64, u128 => U128,
+    i8 => I8, i16 => I16, i32 => I32, i64 => I64, i128 => I128,
+    f32 => F32, f64 => F64, char => Char, () => Unit, String => String,
+    std::time::Duration => Duration, std::time::SystemTime => Timestamp
+);
+
+// bincode 把 usize/isize 编码为 8 字节
+impl_describe!(usize => U64, isize => I64);
+
+impl<T: Describe> Describe for Vec<T> {
+    fn describe() -> FieldType {
+        FieldType::Seq(Box::new(T::describe()))
+    }
+}
+
+impl<T: Describe> Describe for Box<T> {
+    fn describe() -> FieldType {
+        T::describe()
+    }
+}
+
+impl<T: Describe> Describe for Option<T> {
+    fn describe() -> FieldType {
+        FieldType::Option(Box::new(T::describe()))
+    }
+}
+
+impl<T: Describe, const N: usize> Describe for [T; N] {
+    fn describe() -> FieldType {
+        FieldType::Array(Box::new(T::describe()), N)
+    }
+}
+
+macro_rules! impl_describe_for_tuples {
+    ($(($($t:ident),+)),*) => {
+        $(
+            impl<$($t: Describe),+> Describe for ($($t,)+) {
+                fn describe() -> FieldType {
+                    FieldType::Tuple(vec![$($t::describe()),+])
+                }
+            }
+        )*
+    };
+}
+
+impl_describe_for_tuples!((A), (A, B), (A, B, C), (A, B, C, D), (A, B, C, D, E));
+
+impl<const N: usize> Describe for FixedString<N> {
+    fn describe() -> FieldType {
+        FieldType::FixedString(N)
+    }
+}
+
+impl<T: Describe, const N: usize> Describe for FixedVec<T, N> {
+    fn describe() -> FieldType {
+        FieldType::FixedVec(Box::new(T::describe()), N)
+    }
+}
+
+// chrono 的 serde 实现把时间编码为字符串
+#[cfg(feature = "chrono")]
+mod chrono_impls {
+    use super::{
+        Describe,
+        FieldType,
+    };
+
+    impl<Tz: chrono::TimeZone> Describe for chrono::DateTime<Tz> {
+        fn describe() -> FieldType {
+            FieldType::DateTime
+        }
+    }
+
+    impl_describe!(
+        chrono::NaiveDate => String,
+        chrono::NaiveDateTime => String,
+        chrono::NaiveTime => String
+    );
+}
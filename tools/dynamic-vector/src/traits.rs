@@ -1,9 +1,255 @@
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use std::fmt;
+
+use crate::schema::RecordSchema;
+
 pub trait DynamicVector {
     fn is_dynamic_structure(&self) -> bool;
     fn get_dynamic_fields(&self) -> Vec<String>;
     fn get_dynamic_values(&self) -> Vec<Vec<u8>>;
     }
     
+/// 拆分为定长部分与动态部分的记录，由 `CheckDynamicSize` 为结构体生成。
+/// 定长字段按声明顺序拼接，每个字段补齐到其 `DynamicSized::ENCODED_SIZE`，
+/// 因此每条记录恰好 `fixed_size()` 字节；
+/// 每个动态字段（`String`、`Vec` 等）单独编码为一段字节。
+pub trait HybridRecord: Sized {
+    fn fixed_size() -> usize;
+    fn dynamic_field_count() -> usize;
+    fn split_fields(&self) -> (Vec<u8>, Vec<Vec<u8>>);
+    /// 由 `split_fields` 的结果还原记录；`dynamic` 必须包含全部动态字段。
+    fn join_fields(fixed: &[u8], dynamic: Vec<Vec<u8>>) -> Self;
+    /// 用定长部分覆盖记录中的定长字段，动态字段保持不变。
+    fn assign_fixed_fields(&mut self, fixed: &[u8]);
+    /// 字段名称、类型、顺序与定长/动态分类，写入引擎的文件头。
+    fn schema() -> RecordSchema;
+}
+
+/// 字段的存储分类，`CheckDynamicSize` 据此决定字段放入定长槽位还是单独存放。
+/// `ENCODED_SIZE` 为定长类型经 bincode 编码后的最大字节数，变长类型为 0。
+/// 派生 `CheckDynamicSize` 的类型自身也实现该特征，因此可以作为其他记录的字段。
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a supported CheckDynamicSize field type",
+    label = "field type does not implement DynamicSized",
+    note = "derive CheckDynamicSize on the field type or implement DynamicSized for it"
+)]
+pub trait DynamicSized {
+    const DYNAMIC: bool;
+    const ENCODED_SIZE: usize;
+}
+
+macro_rules! impl_fixed_sized {
+    ($($t:ty),*) => {
+        $(
+            impl DynamicSized for $t {
+                const DYNAMIC: bool = false;
+                const ENCODED_SIZE: usize = <$t as MaxEncodedSize>::MAX_ENCODED_SIZE;
+            }
+        )*
+    };
+}
+
+impl_fixed_sized!(
+    bool, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, char, (),
+    std::time::Duration, std::time::SystemTime
+);
+
+impl DynamicSized for String {
+    const DYNAMIC: bool = true;
+    const ENCODED_SIZE: usize = 0;
+}
+
+impl<T> DynamicSized for Vec<T> {
+    const DYNAMIC: bool = true;
+    const ENCODED_SIZE: usize = 0;
+}
+
+impl<T: DynamicSized> DynamicSized for Box<T> {
+    const DYNAMIC: bool = T::DYNAMIC;
+    const ENCODED_SIZE: usize = T::ENCODED_SIZE;
+}
+
+// None 只编码 1 字节的标签，定长槽位按 Some 的长度预留
+impl<T: DynamicSized> DynamicSized for Option<T> {
+    const DYNAMIC: bool = T::DYNAMIC;
+    const ENCODED_SIZE: usize = if T::DYNAMIC { 0 } else { 1 + T::ENCODED_SIZE };
+}
+
+impl<T: DynamicSized, const N: usize> DynamicSized for [T; N] {
+    const DYNAMIC: bool = T::DYNAMIC;
+    const ENCODED_SIZE: usize = if T::DYNAMIC { 0 } else { N * T::ENCODED_SIZE };
+}
+
+macro_rules! impl_dynamic_sized_for_tuples {
+    ($(($($t:ident),+)),*) => {
+        $(
+            impl<$($t: DynamicSized),+> DynamicSized for ($($t,)+) {
+                const DYNAMIC: bool = false $(|| $t::DYNAMIC)+;
+                const ENCODED_SIZE: usize =
+                    if Self::DYNAMIC { 0 } else { 0 $(+ $t::ENCODED_SIZE)+ };
+            }
+        )*
+    };
+}
+
+impl_dynamic_sized_for_tuples!((A), (A, B), (A, B, C), (A, B, C, D), (A, B, C, D, E));
+
+// chrono 的 serde 实现把时间编码为字符串，因此按变长字段存放
+#[cfg(feature = "chrono")]
+mod chrono_impls {
+    use super::{
+        BytesDecodeError,
+        DynamicSized,
+        FromBytesVector,
+        VectorCandidate,
+    };
+    use chrono::{
+        DateTime,
+        FixedOffset,
+        NaiveDate,
+        NaiveDateTime,
+        NaiveTime,
+        TimeZone,
+        Utc,
+    };
+
+    impl<Tz: TimeZone> DynamicSized for DateTime<Tz> {
+        const DYNAMIC: bool = true;
+        const ENCODED_SIZE: usize = 0;
+    }
+
+    impl<Tz: TimeZone> VectorCandidate for DateTime<Tz>
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        fn to_bytes_vector(&self) -> Vec<u8> {
+            self.to_rfc3339().to_bytes_vector()
+        }
+    }
+
+    fn parse_prefix<T: std::str::FromStr>(
+        bytes: &[u8],
+    ) -> Result<(T, usize), BytesDecodeError> {
+        let (text, used) = String::from_bytes_prefix(bytes)?;
+        let value = text
+            .parse()
+            .map_err(|_| BytesDecodeError::InvalidValue("malformed chrono value"))?;
+        Ok((value, used))
+    }
+
+    impl FromBytesVector for DateTime<FixedOffset> {
+        fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+            let (text, used) = String::from_bytes_prefix(bytes)?;
+            let value = DateTime::parse_from_rfc3339(&text)
+                .map_err(|_| BytesDecodeError::InvalidValue("malformed RFC 3339 timestamp"))?;
+            Ok((value, used))
+        }
+    }
+
+    impl FromBytesVector for DateTime<Utc> {
+        fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+            let (value, used) = DateTime::<FixedOffset>::from_bytes_prefix(bytes)?;
+            Ok((value.with_timezone(&Utc), used))
+        }
+    }
+
+    macro_rules! impl_naive {
+        ($($t:ty),*) => {
+            $(
+                impl DynamicSized for $t {
+                    const DYNAMIC: bool = true;
+                    const ENCODED_SIZE: usize = 0;
+                }
+
+                impl VectorCandidate for $t {
+                    fn to_bytes_vector(&self) -> Vec<u8> {
+                        self.to_string().to_bytes_vector()
+                    }
+                }
+
+                impl FromBytesVector for $t {
+                    fn from_bytes_prefix(
+                        bytes: &[u8],
+                    ) -> Result<(Self, usize), BytesDecodeError> {
+                        parse_prefix(bytes)
+                    }
+                }
+            )*
+        };
+    }
+
+    impl_naive!(NaiveDate, NaiveDateTime, NaiveTime);
+}
+
+/// 单个字段的 bincode 编解码，供派生宏生成的代码使用。
+pub trait FieldCodec: Sized {
+    fn encode_field(&self) -> Vec<u8>;
+    fn decode_field(bytes: &[u8]) -> Self;
+}
+
+impl<T: Serialize + DeserializeOwned> FieldCodec for T {
+    fn encode_field(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Serialization failed")
+    }
+
+    fn decode_field(bytes: &[u8]) -> Self {
+        bincode::deserialize(bytes).expect("Deserialization failed")
+    }
+}
+
+/// 类型经 bincode 编码后的最大字节数，用于定长槽位的大小计算。
+/// 只有编码长度有上限的类型才实现该特征，因此 `String`、`Vec` 等动态字段
+/// 会在编译期被 `#[derive(MaxEncodedSize)]` 拒绝。
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` has no bounded encoded size",
+    label = "dynamically sized field cannot be stored in a fixed-width slot",
+    note = "store this record with DynamicVectorManageService or HybridVectorManageService"
+)]
+pub trait MaxEncodedSize {
+    const MAX_ENCODED_SIZE: usize;
+}
+
+macro_rules! impl_max_encoded_size {
+    ($($t:ty => $size:expr),*) => {
+        $(
+            impl MaxEncodedSize for $t {
+                const MAX_ENCODED_SIZE: usize = $size;
+            }
+        )*
+    };
+}
+
+// bincode 默认使用定长整数编码，usize 按 u64 编码，char 按 UTF-8 最多 4 字节
+impl_max_encoded_size!(
+    bool => 1, u8 => 1, u16 => 2, u32 => 4, u64 => 8, u128 => 16, usize => 8,
+    i8 => 1, i16 => 2, i32 => 4, i64 => 8, i128 => 16, isize => 8,
+    f32 => 4, f64 => 8, char => 4, () => 0,
+    std::time::Duration => 12, std::time::SystemTime => 12
+);
+
+impl<T: MaxEncodedSize, const N: usize> MaxEncodedSize for [T; N] {
+    const MAX_ENCODED_SIZE: usize = T::MAX_ENCODED_SIZE * N;
+}
+
+impl<T: MaxEncodedSize> MaxEncodedSize for Option<T> {
+    const MAX_ENCODED_SIZE: usize = 1 + T::MAX_ENCODED_SIZE;
+}
+
+macro_rules! impl_max_encoded_size_for_tuples {
+    ($(($($t:ident),+)),*) => {
+        $(
+            impl<$($t: MaxEncodedSize),+> MaxEncodedSize for ($($t,)+) {
+                const MAX_ENCODED_SIZE: usize = 0 $(+ $t::MAX_ENCODED_SIZE)+;
+            }
+        )*
+    };
+}
+
+impl_max_encoded_size_for_tuples!((A), (A, B), (A, B, C), (A, B, C, D), (A, B, C, D, E));
+
     pub trait VectorCandidate {
     /// 将类型转换为小端字节数组，返回一个 Vec<u8>
     fn to_bytes_vector(&self) -> Vec<u8>;
@@ -37,7 +283,101 @@ macro_rules! impl_to_bytes_for_integers {
 }
 
 // 批量实现 u64, u32, u16, u8, i64, i32, i16, i8 的特征
-impl_to_bytes_for_integers!(u64, u32, u16, u8, i64, i32, i16, i8);
+impl_to_bytes_for_integers!(u64, u32, u16, u8, i64, i32, i16, i8, u128, i128);
+
+// 浮点数同样按小端字节序转换
+impl_to_bytes_for_integers!(f32, f64);
+
+// char 按其 Unicode 标量值转换
+impl VectorCandidate for char {
+    fn to_bytes_vector(&self) -> Vec<u8> {
+        (*self as u32).to_le_bytes().to_vec()
+    }
+}
+
+impl VectorCandidate for () {
+    fn to_bytes_vector(&self) -> Vec<u8> {
+        Vec::new()
+    }
+}
+
+// Duration 转换为秒数 (u64) 加纳秒 (u32)
+impl VectorCandidate for std::time::Duration {
+    fn to_bytes_vector(&self) -> Vec<u8> {
+        let mut bytes = self.as_secs().to_bytes_vector();
+        bytes.extend(self.subsec_nanos().to_bytes_vector());
+        bytes
+    }
+}
+
+// SystemTime 编码为距 UNIX 纪元的 i64 秒（向下取整）加 u32 纳秒，
+// 纪元之前的时间点秒数为负；纪元之后的编码与 Duration 相同
+impl VectorCandidate for std::time::SystemTime {
+    fn to_bytes_vector(&self) -> Vec<u8> {
+        let (secs, nanos) = match self.duration_since(std::time::UNIX_EPOCH) {
+            Ok(after) => (after.as_secs() as i64, after.subsec_nanos()),
+            Err(before) => {
+                let before = before.duration();
+                match before.subsec_nanos() {
+                    0 => (-(before.as_secs() as i64), 0),
+                    nanos => (-(before.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+                }
+            }
+        };
+        let mut bytes = secs.to_bytes_vector();
+        bytes.extend(nanos.to_bytes_vector());
+        bytes
+    }
+}
+
+// Option 以 1 字节标签开头，Some 之后紧跟其值
+impl<T: VectorCandidate> VectorCandidate for Option<T> {
+    fn to_bytes_vector(&self) -> Vec<u8> {
+        match self {
+            Some(value) => {
+                let mut bytes = vec![1];
+                bytes.extend(value.to_bytes_vector());
+                bytes
+            }
+            None => vec![0],
+        }
+    }
+}
+
+impl<T: VectorCandidate + ?Sized> VectorCandidate for Box<T> {
+    fn to_bytes_vector(&self) -> Vec<u8> {
+        (**self).to_bytes_vector()
+    }
+}
+
+// 定长数组的长度由类型确定，不带长度前缀
+impl<T: VectorCandidate, const N: usize> VectorCandidate for [T; N] {
+    fn to_bytes_vector(&self) -> Vec<u8> {
+        self.iter().flat_map(|item| item.to_bytes_vector()).collect()
+    }
+}
+
+macro_rules! impl_to_bytes_for_tuples {
+    ($(($($t:ident $i:tt),+)),*) => {
+        $(
+            impl<$($t: VectorCandidate),+> VectorCandidate for ($($t,)+) {
+                fn to_bytes_vector(&self) -> Vec<u8> {
+                    let mut bytes = Vec::new();
+                    $(bytes.extend(self.$i.to_bytes_vector());)+
+                    bytes
+                }
+            }
+        )*
+    };
+}
+
+impl_to_bytes_for_tuples!(
+    (A 0),
+    (A 0, B 1),
+    (A 0, B 1, C 2),
+    (A 0, B 1, C 2, D 3),
+    (A 0, B 1, C 2, D 3, E 4)
+);
 
 // 为布尔类型实现特征
 impl VectorCandidate for bool {
@@ -46,39 +386,336 @@ impl VectorCandidate for bool {
     }
 }
 
+// 字符串与集合以 u32 长度前缀开头，拼接后的字节仍能逐个读回
+fn length_prefix(len: usize) -> Vec<u8> {
+    u32::try_from(len)
+        .expect("length exceeds u32::MAX")
+        .to_le_bytes()
+        .to_vec()
+}
+
 // 为 Vec<T> 实现特征，要求 T 实现 VectorCandidate 特征
 impl<T: VectorCandidate> VectorCandidate for Vec<T> {
     fn to_bytes_vector(&self) -> Vec<u8> {
-        // 将 Vec<T> 中的每个元素转换为字节数组，然后合并到一个 Vec<u8>
-        self.iter()
-            .flat_map(|item| item.to_bytes_vector()) // 展开每个元素的 Vec<u8>
-            .collect()
+        self.as_slice().to_bytes_vector()
     }
 }
 
 
-// 为 String 实现 VectorCandidate
+// 为 String 实现 VectorCandidate：字节长度前缀加 UTF-8 内容
 impl VectorCandidate for String {
     fn to_bytes_vector(&self) -> Vec<u8> {
-        self.as_bytes().to_vec() // 将字符串的字节数组直接返回
+        self.as_str().to_bytes_vector()
+    }
+}
+
+impl VectorCandidate for str {
+    fn to_bytes_vector(&self) -> Vec<u8> {
+        let mut bytes = length_prefix(self.len());
+        bytes.extend_from_slice(self.as_bytes());
+        bytes
     }
 }
 
-// 为 [T] 实现 VectorCandidate，要求 T 实现 VectorCandidate
+// 为 [T] 实现 VectorCandidate：元素个数前缀加各元素的字节
 impl<T: VectorCandidate> VectorCandidate for [T] {
     fn to_bytes_vector(&self) -> Vec<u8> {
-        self.iter()
-            .flat_map(|item| item.to_bytes_vector()) // 对每个元素调用 to_bytes_vector
-            .collect()
+        let mut bytes = length_prefix(self.len());
+        for item in self {
+            bytes.extend(item.to_bytes_vector());
+        }
+        bytes
     }
 }
 
 // 为 &[T] 实现 VectorCandidate，要求 T 实现 VectorCandidate
 impl<T: VectorCandidate> VectorCandidate for &[T] {
     fn to_bytes_vector(&self) -> Vec<u8> {
-        self.iter()
-            .flat_map(|item| item.to_bytes_vector()) // 对每个元素调用 to_bytes_vector
-            .collect()
+        (**self).to_bytes_vector()
+    }
+}
+
+/// `FromBytesVector` 解码失败的原因。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BytesDecodeError {
+    /// 字节在值结束之前耗尽。
+    UnexpectedEnd { needed: usize, remaining: usize },
+    /// 字节不是该类型的合法编码。
+    InvalidValue(&'static str),
+    /// 解码出完整的值之后仍有剩余字节。
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for BytesDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BytesDecodeError::UnexpectedEnd { needed, remaining } => write!(
+                f,
+                "unexpected end of bytes: needed {} more, {} remaining",
+                needed, remaining
+            ),
+            BytesDecodeError::InvalidValue(reason) => write!(f, "invalid value: {}", reason),
+            BytesDecodeError::TrailingBytes(count) => {
+                write!(f, "{} trailing bytes after value", count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BytesDecodeError {}
+
+/// `VectorCandidate` 的解码对应：从 `to_bytes_vector` 的结果还原值。
+/// 字符串与集合带有 u32 长度前缀，因此多个值拼接后仍能按顺序逐个读回，
+/// 字段级的字节可以直接作为存储内容或索引键。
+pub trait FromBytesVector: Sized {
+    /// 从 `bytes` 开头解码一个值，返回该值与消耗的字节数。
+    fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError>;
+
+    /// 解码恰好占满 `bytes` 的一个值。
+    fn from_bytes_vector(bytes: &[u8]) -> Result<Self, BytesDecodeError> {
+        let (value, used) = Self::from_bytes_prefix(bytes)?;
+        if used != bytes.len() {
+            return Err(BytesDecodeError::TrailingBytes(bytes.len() - used));
+        }
+        Ok(value)
+    }
+}
+
+fn take(bytes: &[u8], len: usize) -> Result<&[u8], BytesDecodeError> {
+    bytes.get(..len).ok_or(BytesDecodeError::UnexpectedEnd {
+        needed: len,
+        remaining: bytes.len(),
+    })
+}
+
+// 为整数与浮点数实现：按小端字节序读取固定字节数
+macro_rules! impl_from_bytes_for_numbers {
+    ($($t:ty),*) => {
+        $(
+            impl FromBytesVector for $t {
+                fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+                    const SIZE: usize = std::mem::size_of::<$t>();
+                    let raw = take(bytes, SIZE)?;
+                    Ok((<$t>::from_le_bytes(raw.try_into().unwrap()), SIZE))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_bytes_for_numbers!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+impl FromBytesVector for bool {
+    fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+        match take(bytes, 1)?[0] {
+            0 => Ok((false, 1)),
+            1 => Ok((true, 1)),
+            _ => Err(BytesDecodeError::InvalidValue("bool must be 0 or 1")),
+        }
+    }
+}
+
+impl FromBytesVector for char {
+    fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+        let (code, used) = u32::from_bytes_prefix(bytes)?;
+        let value = char::from_u32(code)
+            .ok_or(BytesDecodeError::InvalidValue("not a Unicode scalar value"))?;
+        Ok((value, used))
+    }
+}
+
+impl FromBytesVector for () {
+    fn from_bytes_prefix(_bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+        Ok(((), 0))
+    }
+}
+
+impl FromBytesVector for String {
+    fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+        let (len, prefix) = u32::from_bytes_prefix(bytes)?;
+        let raw = take(&bytes[prefix..], len as usize)?;
+        let value = String::from_utf8(raw.to_vec())
+            .map_err(|_| BytesDecodeError::InvalidValue("string is not valid UTF-8"))?;
+        Ok((value, prefix + raw.len()))
+    }
+}
+
+impl<T: FromBytesVector> FromBytesVector for Vec<T> {
+    fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+        let (count, mut offset) = u32::from_bytes_prefix(bytes)?;
+        // 长度前缀来自外部数据，预分配的容量不超过剩余字节数
+        let mut items = Vec::with_capacity((count as usize).min(bytes.len() - offset));
+        for _ in 0..count {
+            let (item, used) = T::from_bytes_prefix(&bytes[offset..])?;
+            items.push(item);
+            offset += used;
+        }
+        Ok((items, offset))
+    }
+}
+
+impl<T: FromBytesVector, const N: usize> FromBytesVector for [T; N] {
+    fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+        let mut items = Vec::with_capacity(N);
+        let mut offset = 0;
+        for _ in 0..N {
+            let (item, used) = T::from_bytes_prefix(&bytes[offset..])?;
+            items.push(item);
+            offset += used;
+        }
+        match items.try_into() {
+            Ok(array) => Ok((array, offset)),
+            Err(_) => unreachable!("decoded exactly N items"),
+        }
+    }
+}
+
+impl<T: FromBytesVector> FromBytesVector for Option<T> {
+    fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+        match take(bytes, 1)?[0] {
+            0 => Ok((None, 1)),
+            1 => {
+                let (value, used) = T::from_bytes_prefix(&bytes[1..])?;
+                Ok((Some(value), 1 + used))
+            }
+            _ => Err(BytesDecodeError::InvalidValue("Option tag must be 0 or 1")),
+        }
+    }
+}
+
+impl<T: FromBytesVector> FromBytesVector for Box<T> {
+    fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+        let (value, used) = T::from_bytes_prefix(bytes)?;
+        Ok((Box::new(value), used))
+    }
+}
+
+macro_rules! impl_from_bytes_for_tuples {
+    ($(($($t:ident),+)),*) => {
+        $(
+            impl<$($t: FromBytesVector),+> FromBytesVector for ($($t,)+) {
+                fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+                    let mut offset = 0;
+                    let value = ($(
+                        {
+                            let (item, used) = $t::from_bytes_prefix(&bytes[offset..])?;
+                            offset += used;
+                            item
+                        },
+                    )+);
+                    Ok((value, offset))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_bytes_for_tuples!((A), (A, B), (A, B, C), (A, B, C, D), (A, B, C, D, E));
+
+impl FromBytesVector for std::time::Duration {
+    fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+        let (secs, secs_len) = u64::from_bytes_prefix(bytes)?;
+        let (nanos, nanos_len) = u32::from_bytes_prefix(&bytes[secs_len..])?;
+        if nanos >= 1_000_000_000 {
+            return Err(BytesDecodeError::InvalidValue("Duration nanoseconds out of range"));
+        }
+        Ok((std::time::Duration::new(secs, nanos), secs_len + nanos_len))
+    }
+}
+
+impl FromBytesVector for std::time::SystemTime {
+    fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, usize), BytesDecodeError> {
+        let (secs, secs_len) = i64::from_bytes_prefix(bytes)?;
+        let (nanos, nanos_len) = u32::from_bytes_prefix(&bytes[secs_len..])?;
+        if nanos >= 1_000_000_000 {
+            return Err(BytesDecodeError::InvalidValue(
+                "SystemTime nanoseconds out of range",
+            ));
+        }
+        let epoch = std::time::UNIX_EPOCH;
+        let time = if secs >= 0 {
+            epoch.checked_add(std::time::Duration::new(secs as u64, nanos))
+        } else {
+            epoch
+                .checked_sub(std::time::Duration::from_secs(secs.unsigned_abs()))
+                .and_then(|time| {
+                    time.checked_add(std::time::Duration::from_nanos(nanos as u64))
+                })
+        };
+        let time =
+            time.ok_or(BytesDecodeError::InvalidValue("SystemTime out of range"))?;
+        Ok((time, secs_len + nanos_len))
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip<T: VectorCandidate + FromBytesVector + PartialEq + std::fmt::Debug>(
+        value: T,
+    ) {
+        let bytes = value.to_bytes_vector();
+        assert_eq!(T::from_bytes_vector(&bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn test_round_trip_keeps_element_boundaries() {
+        round_trip(vec![String::from("ab"), String::new(), String::from("c")]);
+        round_trip(vec![vec![1u16, 2], vec![], vec![3]]);
+        round_trip((Some(1.5f32), None::<f64>, 'é', -7i128));
+        round_trip([Some(String::from("x")), None]);
+        round_trip(std::time::Duration::new(5, 42));
+    }
+
+    #[test]
+    fn test_system_time_before_epoch() {
+        use std::time::{
+            Duration,
+            UNIX_EPOCH,
+        };
+
+        let before = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(before.to_bytes_vector()[..8], (-1i64).to_le_bytes());
+        round_trip(before);
+        round_trip(UNIX_EPOCH - Duration::new(1, 250_000_000));
+        round_trip(UNIX_EPOCH);
+
+        // 纪元之后的编码与 Duration 相同
+        let after = UNIX_EPOCH + Duration::new(7, 9);
+        assert_eq!(
+            after.to_bytes_vector(),
+            Duration::new(7, 9).to_bytes_vector()
+        );
+        round_trip(after);
+
+        // 两个值拼接后仍能按顺序读回
+        let mut bytes = String::from("key").to_bytes_vector();
+        bytes.extend(9u64.to_bytes_vector());
+        let (key, used) = String::from_bytes_prefix(&bytes).unwrap();
+        assert_eq!(key, "key");
+        assert_eq!(u64::from_bytes_vector(&bytes[used..]).unwrap(), 9);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let bytes = String::from("hello").to_bytes_vector();
+        assert_eq!(
+            String::from_bytes_vector(&bytes[..4]),
+            Err(BytesDecodeError::UnexpectedEnd {
+                needed: 5,
+                remaining: 0
+            })
+        );
+        assert_eq!(
+            u8::from_bytes_vector(&[1, 2]),
+            Err(BytesDecodeError::TrailingBytes(1))
+        );
+        assert!(matches!(
+            bool::from_bytes_vector(&[2]),
+            Err(BytesDecodeError::InvalidValue(_))
+        ));
+    }
+}